@@ -0,0 +1,22 @@
+//! Stamps the build with the git commit and build time, reported by the
+//! GetVersion SDK command.
+use std::process::Command;
+
+fn main() {
+    let git_hash = Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|out| out.status.success())
+        .and_then(|out| String::from_utf8(out.stdout).ok())
+        .map(|hash| hash.trim().to_string())
+        .unwrap_or_else(|| "unknown".to_string());
+    let build_time = chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ");
+
+    println!("cargo:rustc-env=HUIDU_GIT_HASH={git_hash}");
+    println!("cargo:rustc-env=HUIDU_BUILD_TIME={build_time}");
+    // Re-stamp when the checked-out commit changes, not on every build
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=../.git/HEAD");
+    println!("cargo:rerun-if-changed=../.git/refs/heads");
+}
@@ -1,5 +1,9 @@
 use std::path::PathBuf;
 
+use crate::program::model::Program;
+use crate::render::color::ColorCorrection;
+use crate::render::testpattern::TestPattern;
+
 /// Top-level player configuration
 #[derive(Debug, Clone)]
 pub struct PlayerConfig {
@@ -8,19 +12,85 @@ pub struct PlayerConfig {
     pub fps: u32,
     pub program_dir: PathBuf,
     pub port: u16,
-    pub output_mode: OutputMode,
+    /// Every rendered frame goes to each of these outputs
+    pub output_modes: Vec<OutputMode>,
     pub output_path: PathBuf,
+    /// Seconds between periodic NTP syncs
+    pub ntp_interval_secs: u64,
+    /// Channel and on/off threshold used by `OutputMode::Mono`
+    pub mono_channel: MonoChannel,
+    pub mono_threshold: u8,
+    /// Byte order of the RGB565 words sent to the panel by `OutputMode::Framebuffer`
+    pub rgb565_byte_order: ByteOrder,
+    /// Framebuffer devices `OutputMode::Framebuffer` writes every frame to
+    pub framebuffer_devices: Vec<PathBuf>,
+    /// GPIO pin numbers driving relay outputs 0, 1, ...
+    pub relay_gpios: Vec<u32>,
+    /// When set, render this pattern instead of any programs
+    pub test_pattern: Option<TestPattern>,
+    /// Time for a brightness change to fade in (0 = instant)
+    pub brightness_fade_ms: u64,
+    /// Cross-fade between programs over this long (0 = cut)
+    pub program_transition_ms: u64,
+    /// Keep the boot logo up at least this long after startup, even once
+    /// programs are loaded (0 = only until the first program)
+    pub splash_ms: u64,
+    /// Fade from the boot logo into the first program over this long (0 = cut)
+    pub splash_fade_ms: u64,
+    /// Applied to every frame to correct the panel's color cast
+    pub color_correction: ColorCorrection,
+    /// Idle program shown while no network/USB programs are loaded
+    pub fallback_program: Option<Program>,
+    /// Render this many frames, saving each one, then return from the render loop
+    pub max_frames: Option<u64>,
+    /// Panel resolution when it differs from the rendered `width` x `height`;
+    /// every output gets the frame scaled to this size
+    pub output_size: Option<(u32, u32)>,
+}
+
+#[cfg(test)]
+impl PlayerConfig {
+    /// Small raw-output config rooted at `dir`
+    pub fn for_test(dir: &std::path::Path) -> Self {
+        PlayerConfig {
+            width: 32,
+            height: 16,
+            fps: 10,
+            program_dir: dir.to_path_buf(),
+            port: 0,
+            output_modes: Vec::new(),
+            output_path: dir.join("output.png"),
+            ntp_interval_secs: 3600,
+            mono_channel: MonoChannel::default(),
+            mono_threshold: 128,
+            rgb565_byte_order: ByteOrder::default(),
+            framebuffer_devices: Vec::new(),
+            relay_gpios: Vec::new(),
+            test_pattern: None,
+            brightness_fade_ms: 0,
+            program_transition_ms: 0,
+            splash_ms: 0,
+            splash_fade_ms: 0,
+            color_correction: ColorCorrection::default(),
+            fallback_program: None,
+            max_frames: None,
+            output_size: None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Default)]
 pub enum OutputMode {
-    /// Save each frame as PNG (for testing)
+    /// Save frames to `output_path` (PNG, JPEG or BMP by extension)
     #[default]
     Png,
-    /// Render to DRM/KMS framebuffer (production)
+    /// Write frames to each of `framebuffer_devices` in its pixel format (production)
     Framebuffer,
     /// Output raw pixels to stdout (for piping)
     Raw,
+    /// 1-bit thresholded frames for monochrome panels, written to `output_path`
+    /// (or stdout when the path is "-"); don't combine with Png, they share the path
+    Mono,
 }
 
 impl std::str::FromStr for OutputMode {
@@ -30,7 +100,52 @@ impl std::str::FromStr for OutputMode {
             "png" => Ok(OutputMode::Png),
             "framebuffer" | "fb" | "drm" => Ok(OutputMode::Framebuffer),
             "raw" | "stdout" => Ok(OutputMode::Raw),
+            "mono" => Ok(OutputMode::Mono),
             _ => Err(format!("Unknown output mode: {s}")),
         }
     }
 }
+
+/// Which part of the pixel drives a mono panel
+#[derive(Debug, Clone, Copy, Default)]
+pub enum MonoChannel {
+    /// Red-only modules (the common case)
+    #[default]
+    Red,
+    Green,
+    Blue,
+    /// Perceived brightness of the full color
+    Luma,
+}
+
+impl std::str::FromStr for MonoChannel {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "red" | "r" => Ok(MonoChannel::Red),
+            "green" | "g" => Ok(MonoChannel::Green),
+            "blue" | "b" => Ok(MonoChannel::Blue),
+            "luma" | "gray" | "grey" => Ok(MonoChannel::Luma),
+            _ => Err(format!("Unknown mono channel: {s}")),
+        }
+    }
+}
+
+/// Byte order of 16-bit panel words
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ByteOrder {
+    #[default]
+    Little,
+    Big,
+}
+
+impl std::str::FromStr for ByteOrder {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "little" | "le" => Ok(ByteOrder::Little),
+            "big" | "be" => Ok(ByteOrder::Big),
+            _ => Err(format!("Unknown byte order: {s}")),
+        }
+    }
+}
@@ -0,0 +1,60 @@
+/// Wall-clock time source.
+/// Schedules, clock content and time queries read the local time through a
+/// `Clock` instead of calling `chrono::Local::now()`, so tests can freeze it
+/// or step it to just the moment they need.
+use chrono::{DateTime, Local};
+use std::sync::Arc;
+
+pub trait Clock: Send + Sync {
+    /// The current local date and time
+    fn now(&self) -> DateTime<Local>;
+}
+
+/// Shared handle to a clock, as held by services and renderers
+pub type SharedClock = Arc<dyn Clock>;
+
+/// The system clock
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Local> {
+        Local::now()
+    }
+}
+
+/// The system clock as a shared handle
+pub fn system() -> SharedClock {
+    Arc::new(SystemClock)
+}
+
+/// A clock that stays wherever it was last set. Clones share the same time.
+#[cfg(test)]
+#[derive(Clone)]
+pub struct MockClock(Arc<std::sync::Mutex<DateTime<Local>>>);
+
+#[cfg(test)]
+impl MockClock {
+    pub fn new(now: DateTime<Local>) -> Self {
+        Self(Arc::new(std::sync::Mutex::new(now)))
+    }
+
+    pub fn set(&self, now: DateTime<Local>) {
+        *self.0.lock().unwrap() = now;
+    }
+
+    pub fn advance(&self, by: chrono::Duration) {
+        *self.0.lock().unwrap() += by;
+    }
+
+    /// A shared handle that follows this clock
+    pub fn shared(&self) -> SharedClock {
+        Arc::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+impl Clock for MockClock {
+    fn now(&self) -> DateTime<Local> {
+        *self.0.lock().unwrap()
+    }
+}
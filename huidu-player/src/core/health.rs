@@ -0,0 +1,143 @@
+/// Liveness/readiness probes for orchestration (`--health-port`).
+/// The render loop records every tick here; `/healthz` fails once ticks stop
+/// and `/readyz` fails while no program is loaded.
+use anyhow::Result;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::net::TcpListener;
+use tracing::info;
+
+use crate::protocol::http::{self, Request, Response};
+
+/// A render loop that hasn't ticked for this long counts as stalled
+pub const STALL_TIMEOUT_MS: u64 = 5000;
+
+/// `last_tick_ms` before the first tick
+const NEVER: u64 = u64::MAX;
+
+/// Render loop status, shared between the player and the probe server
+pub struct Health {
+    /// Times are measured from here. The clock is monotonic, so setting the
+    /// wall clock back (NTP, SetTimeInfo) can't make a stalled loop look live.
+    start: Instant,
+    /// Time of the last render loop tick in ms since `start`; `NEVER` before the first
+    last_tick_ms: AtomicU64,
+    program_count: AtomicUsize,
+}
+
+impl Default for Health {
+    fn default() -> Self {
+        Self {
+            start: Instant::now(),
+            last_tick_ms: AtomicU64::new(NEVER),
+            program_count: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl Health {
+    /// Called by the render loop on every tick
+    pub fn record_tick(&self, program_count: usize) {
+        self.last_tick_ms.store(self.elapsed_ms(), Ordering::Relaxed);
+        self.program_count.store(program_count, Ordering::Relaxed);
+    }
+
+    fn elapsed_ms(&self) -> u64 {
+        self.start.elapsed().as_millis() as u64
+    }
+
+    /// Status at `now_ms` since `start`. `lastFrameMs` is also since `start`
+    /// (null before the first frame).
+    fn status(&self, now_ms: u64) -> (bool, bool, String) {
+        let last = self.last_tick_ms.load(Ordering::Relaxed);
+        let programs = self.program_count.load(Ordering::Relaxed);
+        let (live, last_json, age) = match last {
+            NEVER => (false, "null".to_string(), now_ms),
+            last => {
+                let age = now_ms.saturating_sub(last);
+                (age <= STALL_TIMEOUT_MS, last.to_string(), age)
+            }
+        };
+        let json = format!(
+            "{{\"live\":{live},\"ready\":{},\"lastFrameMs\":{last_json},\"ageMs\":{age},\
+             \"programs\":{programs}}}",
+            programs > 0
+        );
+        (live, programs > 0, json)
+    }
+
+    fn respond(&self, request: &Request) -> Response {
+        let (live, ready, json) = self.status(self.elapsed_ms());
+        let ok = match request.path.as_str() {
+            "/healthz" => live,
+            "/readyz" => live && ready,
+            _ => return Response::not_found(),
+        };
+        Response::json(if ok { 200 } else { 503 }, json)
+    }
+}
+
+/// Serve `/healthz` and `/readyz` on `port`
+pub async fn run(port: u16, health: Arc<Health>) -> Result<()> {
+    let listener = TcpListener::bind(("0.0.0.0", port)).await?;
+    info!("Health probes listening on 0.0.0.0:{}", port);
+    serve(listener, health).await
+}
+
+async fn serve(listener: TcpListener, health: Arc<Health>) -> Result<()> {
+    http::serve(listener, move |request| {
+        let health = health.clone();
+        async move { health.respond(&request) }
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+
+    async fn get(addr: std::net::SocketAddr, path: &str) -> String {
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        let request = format!("GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        client.write_all(request.as_bytes()).await.unwrap();
+        let mut reply = String::new();
+        client.read_to_string(&mut reply).await.unwrap();
+        reply
+    }
+
+    #[tokio::test]
+    async fn test_healthz_follows_render_loop() {
+        let health = Arc::new(Health::default());
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(serve(listener, health.clone()));
+
+        // Nothing rendered yet
+        let reply = get(addr, "/healthz").await;
+        assert!(reply.starts_with("HTTP/1.1 503"));
+        assert!(reply.contains("\"lastFrameMs\":null"));
+
+        health.record_tick(0);
+        let reply = get(addr, "/healthz").await;
+        assert!(reply.starts_with("HTTP/1.1 200 OK"));
+        assert!(reply.contains("\"lastFrameMs\":"));
+        assert!(get(addr, "/readyz").await.starts_with("HTTP/1.1 503"));
+
+        health.record_tick(2);
+        assert!(get(addr, "/readyz").await.starts_with("HTTP/1.1 200 OK"));
+
+        server.abort();
+    }
+
+    #[test]
+    fn test_stalled_loop_is_not_live() {
+        let health = Health::default();
+        health.record_tick(1);
+        let last = health.last_tick_ms.load(Ordering::Relaxed);
+        assert!(health.status(last + STALL_TIMEOUT_MS).0);
+        assert!(!health.status(last + STALL_TIMEOUT_MS + 1).0);
+    }
+}
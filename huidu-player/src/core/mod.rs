@@ -1 +1,6 @@
+pub mod clock;
+pub mod health;
+pub mod output;
 pub mod player;
+pub mod playlist;
+pub mod preview;
@@ -0,0 +1,515 @@
+/// Frame outputs — where each rendered frame goes (snapshots, stdout, panel).
+use anyhow::{Context, Result};
+use image::imageops::{self, FilterType};
+use image::RgbaImage;
+use std::collections::HashMap;
+use std::io::{Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use tracing::{debug, info, warn};
+
+use crate::config::{ByteOrder, MonoChannel, OutputMode, PlayerConfig};
+use crate::render::{export, mono, rgb565};
+
+/// Receives every rendered frame (premultiplied RGBA, row-major). Built-in sinks
+/// come from `OutputMode` via `sink_for`; custom ones go through `Player::add_output`.
+pub trait OutputSink: Send {
+    fn write_frame(&mut self, pixels: &[u8], width: u32, height: u32) -> Result<()>;
+}
+
+/// Build the sink for one configured output mode, scaling frames to
+/// `config.output_size` when it differs from the render size
+pub fn sink_for(mode: &OutputMode, config: &PlayerConfig) -> Box<dyn OutputSink> {
+    let sink = mode_sink(mode, config);
+    match config.output_size {
+        Some(size) if size != (config.width, config.height) => {
+            Box::new(ScaledSink::new(sink, size.0, size.1))
+        }
+        _ => sink,
+    }
+}
+
+fn mode_sink(mode: &OutputMode, config: &PlayerConfig) -> Box<dyn OutputSink> {
+    match mode {
+        OutputMode::Png if config.max_frames.is_some() => {
+            Box::new(PngSink::numbered(config.output_path.clone()))
+        }
+        OutputMode::Png => Box::new(PngSink::new(config.output_path.clone(), config.fps)),
+        OutputMode::Raw => Box::new(RawSink),
+        OutputMode::Mono => Box::new(MonoSink {
+            path: config.output_path.clone(),
+            channel: config.mono_channel,
+            threshold: config.mono_threshold,
+        }),
+        OutputMode::Framebuffer => Box::new(FramebufferSink::new(
+            config.rgb565_byte_order,
+            &config.framebuffer_devices,
+        )),
+    }
+}
+
+/// Scales each frame to the panel resolution before passing it on
+pub struct ScaledSink {
+    inner: Box<dyn OutputSink>,
+    width: u32,
+    height: u32,
+}
+
+impl ScaledSink {
+    pub fn new(inner: Box<dyn OutputSink>, width: u32, height: u32) -> Self {
+        Self {
+            inner,
+            width: width.max(1),
+            height: height.max(1),
+        }
+    }
+}
+
+impl OutputSink for ScaledSink {
+    fn write_frame(&mut self, pixels: &[u8], width: u32, height: u32) -> Result<()> {
+        let frame = RgbaImage::from_raw(width, height, pixels.to_vec())
+            .context("Frame size doesn't match its dimensions")?;
+        // Triangle widens with the scale factor, so each output pixel averages
+        // the source pixels it covers. Filtering premultiplied pixels is exact.
+        let scaled = imageops::resize(&frame, self.width, self.height, FilterType::Triangle);
+        self.inner.write_frame(scaled.as_raw(), self.width, self.height)
+    }
+}
+
+/// Periodic snapshot to an image file (format from the extension)
+pub struct PngSink {
+    path: PathBuf,
+    /// Save one frame out of this many (every 5 seconds)
+    every: u64,
+    frames: u64,
+    /// Save each frame to its own file, numbered from 0
+    numbered: bool,
+}
+
+impl PngSink {
+    pub fn new(path: PathBuf, fps: u32) -> Self {
+        Self {
+            path,
+            every: (fps as u64 * 5).max(1),
+            frames: 0,
+            numbered: false,
+        }
+    }
+
+    /// Save every frame, frame N of `out.png` to `out-000N.png` (`--frames`)
+    pub fn numbered(path: PathBuf) -> Self {
+        Self {
+            path,
+            every: 1,
+            frames: 0,
+            numbered: true,
+        }
+    }
+
+    fn frame_path(&self) -> PathBuf {
+        if !self.numbered {
+            return self.path.clone();
+        }
+        let stem = self.path.file_stem().unwrap_or_default().to_string_lossy();
+        let name = match self.path.extension() {
+            Some(ext) => format!("{stem}-{:04}.{}", self.frames, ext.to_string_lossy()),
+            None => format!("{stem}-{:04}", self.frames),
+        };
+        self.path.with_file_name(name)
+    }
+}
+
+impl OutputSink for PngSink {
+    fn write_frame(&mut self, pixels: &[u8], width: u32, height: u32) -> Result<()> {
+        if self.frames.is_multiple_of(self.every) {
+            let path = self.frame_path();
+            export::save_frame(&path, pixels, width, height).context("Failed to save frame")?;
+            debug!("Saved frame {}", self.frames);
+        }
+        self.frames += 1;
+        Ok(())
+    }
+}
+
+/// Raw RGBA frames to stdout (for piping)
+pub struct RawSink;
+
+impl OutputSink for RawSink {
+    fn write_frame(&mut self, pixels: &[u8], _width: u32, _height: u32) -> Result<()> {
+        std::io::stdout().write_all(pixels).ok();
+        Ok(())
+    }
+}
+
+/// 1-bit packed frames for monochrome panels, to a file or stdout ("-")
+pub struct MonoSink {
+    path: PathBuf,
+    channel: MonoChannel,
+    threshold: u8,
+}
+
+impl OutputSink for MonoSink {
+    fn write_frame(&mut self, pixels: &[u8], width: u32, height: u32) -> Result<()> {
+        let bits = mono::pack(pixels, width, height, self.channel, self.threshold);
+        if self.path.as_os_str() == "-" {
+            std::io::stdout().write_all(&bits).ok();
+        } else {
+            std::fs::write(&self.path, &bits).context("Failed to write mono frame")?;
+        }
+        Ok(())
+    }
+}
+
+/// A device the framebuffer sink writes converted frames to
+pub trait FrameDevice: Send {
+    /// Replace the device contents with one frame
+    fn write_frame_bytes(&mut self, bytes: &[u8]) -> std::io::Result<()>;
+}
+
+impl FrameDevice for std::fs::File {
+    fn write_frame_bytes(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        self.seek(SeekFrom::Start(0))?;
+        self.write_all(bytes)
+    }
+}
+
+/// Line length and pixel size of a framebuffer, as the kernel reports them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FbLayout {
+    /// Bytes from the start of one line to the next, padding included
+    pub stride: usize,
+    /// 16 (RGB565), 24 (BGR) or 32 (BGRX)
+    pub bits_per_pixel: usize,
+}
+
+impl FbLayout {
+    /// RGB565 lines of `width` pixels with no padding, for devices without
+    /// sysfs attributes
+    fn packed(width: u32) -> Self {
+        Self {
+            stride: width as usize * 2,
+            bits_per_pixel: 16,
+        }
+    }
+
+    /// Read from /sys/class/graphics/fbN for the device at `path`
+    fn from_sysfs(path: &Path) -> Option<Self> {
+        let device = std::fs::canonicalize(path).ok()?;
+        let dir = Path::new("/sys/class/graphics").join(device.file_name()?);
+        let read = |attr: &str| std::fs::read_to_string(dir.join(attr)).ok()?.trim().parse().ok();
+        Some(Self {
+            stride: read("stride")?,
+            bits_per_pixel: read("bits_per_pixel")?,
+        })
+    }
+
+    fn is_supported(&self) -> bool {
+        matches!(self.bits_per_pixel, 16 | 24 | 32)
+    }
+}
+
+/// One framebuffer the sink writes to
+pub struct FbDevice {
+    path: PathBuf,
+    device: Box<dyn FrameDevice>,
+    /// None when unknown: frames are written as packed RGB565
+    layout: Option<FbLayout>,
+    /// The last write failed (logged once until it recovers)
+    failing: bool,
+}
+
+impl FbDevice {
+    pub fn new(path: PathBuf, device: Box<dyn FrameDevice>, layout: Option<FbLayout>) -> Self {
+        Self {
+            path,
+            device,
+            layout,
+            failing: false,
+        }
+    }
+}
+
+/// Panel output. Each frame is converted once per framebuffer layout and the
+/// same buffer is written to every device with that layout, so identical
+/// signs can be driven as mirrors.
+pub struct FramebufferSink {
+    byte_order: ByteOrder,
+    devices: Vec<FbDevice>,
+    /// Scratch buffers reused between frames
+    words: Vec<u16>,
+    bytes: Vec<u8>,
+    frames: HashMap<FbLayout, Vec<u8>>,
+}
+
+impl FramebufferSink {
+    /// Open each framebuffer device in `paths` and read its layout. One that
+    /// can't be opened or uses an unsupported pixel format is skipped with a
+    /// warning so the others still get frames.
+    pub fn new(byte_order: ByteOrder, paths: &[PathBuf]) -> Self {
+        let mut devices = Vec::new();
+        for path in paths {
+            let file = match std::fs::OpenOptions::new().write(true).open(path) {
+                Ok(file) => file,
+                Err(e) => {
+                    warn!("Skipping framebuffer device {}: {}", path.display(), e);
+                    continue;
+                }
+            };
+            let layout = FbLayout::from_sysfs(path);
+            match layout {
+                Some(layout) if !layout.is_supported() => {
+                    warn!(
+                        "Skipping framebuffer device {}: {} bits per pixel is not supported",
+                        path.display(),
+                        layout.bits_per_pixel
+                    );
+                    continue;
+                }
+                Some(layout) => info!(
+                    "Framebuffer output to {} ({} bpp, {} bytes per line)",
+                    path.display(),
+                    layout.bits_per_pixel,
+                    layout.stride
+                ),
+                None => info!("Framebuffer output to {} (packed RGB565)", path.display()),
+            }
+            devices.push(FbDevice::new(path.clone(), Box::new(file), layout));
+        }
+        Self::with_devices(byte_order, devices)
+    }
+
+    pub fn with_devices(byte_order: ByteOrder, devices: Vec<FbDevice>) -> Self {
+        Self {
+            byte_order,
+            devices,
+            words: Vec::new(),
+            bytes: Vec::new(),
+            frames: HashMap::new(),
+        }
+    }
+
+    /// Lay out a frame for `layout` in `out`: each line at its stride, cut
+    /// off where the framebuffer line ends
+    fn encode(&mut self, pixels: &[u8], width: u32, height: u32, layout: FbLayout) {
+        let out = self.frames.entry(layout).or_default();
+        out.clear();
+        out.resize(layout.stride * height as usize, 0);
+        let pixel_bytes = layout.bits_per_pixel / 8;
+        let columns = (width as usize).min(layout.stride / pixel_bytes);
+        let rows = pixels.chunks_exact(width as usize * 4);
+        for (y, (row, line)) in rows.zip(out.chunks_exact_mut(layout.stride)).enumerate() {
+            let line = &mut line[..columns * pixel_bytes];
+            match layout.bits_per_pixel {
+                16 => {
+                    let start = y * width as usize * 2;
+                    line.copy_from_slice(&self.bytes[start..start + columns * 2]);
+                }
+                // Premultiplied over black, like RGB565: channels as stored
+                _ => {
+                    for (px, out) in row.chunks_exact(4).zip(line.chunks_exact_mut(pixel_bytes)) {
+                        out[..3].copy_from_slice(&[px[2], px[1], px[0]]);
+                        if pixel_bytes == 4 {
+                            out[3] = 0xff;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl OutputSink for FramebufferSink {
+    fn write_frame(&mut self, pixels: &[u8], width: u32, height: u32) -> Result<()> {
+        let layout_of = |device: &FbDevice| device.layout.unwrap_or(FbLayout::packed(width));
+        let mut layouts: Vec<FbLayout> = self.devices.iter().map(layout_of).collect();
+        layouts.sort_by_key(|layout| (layout.stride, layout.bits_per_pixel));
+        layouts.dedup();
+        if layouts.iter().any(|layout| layout.bits_per_pixel == 16) {
+            self.words.resize((width * height) as usize, 0);
+            rgb565::convert_rgba_to_rgb565(pixels, &mut self.words);
+            rgb565::to_bytes(&self.words, self.byte_order, &mut self.bytes);
+        }
+        for layout in layouts {
+            self.encode(pixels, width, height, layout);
+        }
+
+        // A failed write is retried with the next frame rather than failing
+        // the render loop for the other devices
+        for device in &mut self.devices {
+            let bytes = &self.frames[&layout_of(device)];
+            match device.device.write_frame_bytes(bytes) {
+                Ok(()) if device.failing => {
+                    info!("Framebuffer device {} accepts frames again", device.path.display());
+                    device.failing = false;
+                }
+                Ok(()) => {}
+                Err(e) if !device.failing => {
+                    warn!("Failed to write to framebuffer device {}: {}", device.path.display(), e);
+                    device.failing = true;
+                }
+                Err(_) => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_png_sink_writes_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("frame.png");
+        let mut sink = PngSink::new(path.clone(), 10);
+
+        let pixels: Vec<u8> = [255, 0, 0, 255].repeat(4 * 2);
+        sink.write_frame(&pixels, 4, 2).unwrap();
+
+        let img = image::open(&path).unwrap().to_rgba8();
+        assert_eq!(img.dimensions(), (4, 2));
+        assert_eq!(img.get_pixel(3, 1).0, [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn test_png_sink_saves_every_five_seconds() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("frame.png");
+        let mut sink = PngSink::new(path.clone(), 10);
+        let pixels = vec![0u8; 4];
+
+        sink.write_frame(&pixels, 1, 1).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        // Frames 1..49 are skipped, frame 50 (5s at 10fps) is saved
+        for _ in 1..50 {
+            sink.write_frame(&pixels, 1, 1).unwrap();
+        }
+        assert!(!path.exists());
+        sink.write_frame(&pixels, 1, 1).unwrap();
+        assert!(path.exists());
+    }
+
+    #[test]
+    fn test_sink_for_each_mode() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = PlayerConfig::for_test(dir.path());
+        config.output_path = dir.path().join("out.bin");
+        let pixels = [255u8, 255, 255, 255];
+
+        // Mono writes the packed bits to the output path
+        sink_for(&OutputMode::Mono, &config).write_frame(&pixels, 1, 1).unwrap();
+        assert_eq!(std::fs::read(&config.output_path).unwrap(), vec![0b1000_0000]);
+
+        sink_for(&OutputMode::Framebuffer, &config).write_frame(&pixels, 1, 1).unwrap();
+    }
+
+    /// Records every frame written to it
+    struct MockDevice(std::sync::Arc<std::sync::Mutex<Vec<Vec<u8>>>>);
+
+    impl FrameDevice for MockDevice {
+        fn write_frame_bytes(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+            self.0.lock().unwrap().push(bytes.to_vec());
+            Ok(())
+        }
+    }
+
+    /// Fails its first write, then records frames like `MockDevice`
+    struct FlakyDevice(bool, MockDevice);
+
+    impl FrameDevice for FlakyDevice {
+        fn write_frame_bytes(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+            if !std::mem::replace(&mut self.0, true) {
+                return Err(std::io::Error::other("device busy"));
+            }
+            self.1.write_frame_bytes(bytes)
+        }
+    }
+
+    #[test]
+    fn test_framebuffer_follows_device_layout() {
+        type Frames = std::sync::Arc<std::sync::Mutex<Vec<Vec<u8>>>>;
+        let padded = Frames::default();
+        let bgrx = Frames::default();
+        let device = |frames: &Frames, stride, bits_per_pixel| {
+            let layout = FbLayout { stride, bits_per_pixel };
+            FbDevice::new("/dev/fb0".into(), Box::new(MockDevice(frames.clone())), Some(layout))
+        };
+        let mut sink = FramebufferSink::with_devices(
+            ByteOrder::Little,
+            vec![device(&padded, 8, 16), device(&bgrx, 4, 32)],
+        );
+
+        // 2x2: red, blue / white, black
+        let pixels = [
+            [255, 0, 0, 255], [0, 0, 255, 255],
+            [255, 255, 255, 255], [0, 0, 0, 255],
+        ]
+        .concat();
+        sink.write_frame(&pixels, 2, 2).unwrap();
+
+        // Two RGB565 pixels per 8-byte line, then padding
+        let padded = padded.lock().unwrap();
+        assert_eq!(padded[0], [0x00, 0xf8, 0x1f, 0x00, 0, 0, 0, 0, 0xff, 0xff, 0, 0, 0, 0, 0, 0]);
+        // Only one 32-bit pixel fits each line; the rest is cut off
+        assert_eq!(bgrx.lock().unwrap()[0], [0, 0, 255, 255, 255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn test_framebuffer_keeps_device_after_write_error() {
+        let frames = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let device = FlakyDevice(false, MockDevice(frames.clone()));
+        let mut sink = FramebufferSink::with_devices(
+            ByteOrder::default(),
+            vec![FbDevice::new("/dev/fb0".into(), Box::new(device), None)],
+        );
+
+        sink.write_frame(&[255, 0, 0, 255], 1, 1).unwrap();
+        assert!(frames.lock().unwrap().is_empty());
+        sink.write_frame(&[255, 0, 0, 255], 1, 1).unwrap();
+        assert_eq!(frames.lock().unwrap().len(), 1);
+        assert!(!sink.devices[0].failing);
+    }
+
+    #[test]
+    fn test_framebuffer_mirrors_to_every_device() {
+        let first = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let second = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut sink = FramebufferSink::with_devices(
+            ByteOrder::default(),
+            vec![
+                FbDevice::new("/dev/fb0".into(), Box::new(MockDevice(first.clone())), None),
+                FbDevice::new("/dev/fb1".into(), Box::new(MockDevice(second.clone())), None),
+            ],
+        );
+
+        sink.write_frame(&[255, 0, 0, 255, 0, 0, 255, 255], 2, 1).unwrap();
+        sink.write_frame(&[0, 255, 0, 255, 0, 255, 0, 255], 2, 1).unwrap();
+        let first = first.lock().unwrap();
+        assert_eq!(first.len(), 2);
+        assert_eq!(first[0].len(), 4);
+        assert_ne!(first[0], first[1]);
+        assert_eq!(*first, *second.lock().unwrap());
+
+        // Devices that can't be opened are skipped
+        let dir = tempfile::tempdir().unwrap();
+        let sink = FramebufferSink::new(ByteOrder::default(), &[dir.path().join("missing/fb0")]);
+        assert!(sink.devices.is_empty());
+    }
+
+    #[test]
+    fn test_output_scaled_to_panel_size() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = PlayerConfig::for_test(dir.path());
+        config.width = 256;
+        config.height = 128;
+        config.output_size = Some((128, 64));
+
+        let pixels: Vec<u8> = [0, 255, 0, 255].repeat(256 * 128);
+        sink_for(&OutputMode::Png, &config).write_frame(&pixels, 256, 128).unwrap();
+
+        let img = image::open(&config.output_path).unwrap().to_rgba8();
+        assert_eq!(img.dimensions(), (128, 64));
+        assert_eq!(img.get_pixel(64, 32).0, [0, 255, 0, 255]);
+    }
+}
@@ -2,25 +2,93 @@
 use anyhow::{Context, Result};
 use std::path::Path;
 use std::sync::Arc;
-use tokio::sync::{mpsc, RwLock};
-use tokio::time::{self, Duration};
+use tokio::sync::{broadcast, mpsc, oneshot, RwLock};
+use tokio::time::{self, Duration, Instant, MissedTickBehavior};
 use tracing::{debug, info, warn};
 
-use crate::config::{OutputMode, PlayerConfig};
-use crate::program::model::{Program, Screen};
+use crate::config::PlayerConfig;
+use crate::core::clock::SharedClock;
+use crate::core::health::Health;
+use crate::core::output::{self, OutputSink};
+use crate::core::playlist::{Playlist, Slot};
+use crate::program::model::{ContentKind, Program, Screen};
 use crate::program::parser;
-use crate::render::engine::RenderEngine;
+use crate::render::engine::{PreloadedMedia, RenderEngine, RenderTiming};
+use crate::render::plugins::clock::ClockRenderer;
+use crate::render::testpattern::TestPattern;
+use crate::services::boot_logo::BootLogoService;
 use crate::services::manager::ServicesState;
+use crate::services::storage::StorageService;
+
+/// How often per-content-type render times are logged (at debug level)
+const TIMING_LOG_INTERVAL_MS: u64 = 60_000;
 
 /// Commands sent from the protocol server to the player
 #[derive(Debug)]
 pub enum PlayerCommand {
     /// Load a new screen (replaces all programs)
     LoadScreen(Screen),
+    /// Like `LoadScreen`, but if the displayed program is still in the new
+    /// screen it keeps playing, restarting only the areas that changed
+    UpdateScreen(Screen),
+    /// Remove a single program by GUID, leaving the others playing
+    RemoveProgram(String),
+    /// Jump the rotation to the program at this index; replies false if out of range
+    SetCurrentProgram(usize, oneshot::Sender<bool>),
+    /// Reply with (current index, program count)
+    GetCurrentProgram(oneshot::Sender<(usize, usize)>),
+    /// Reply with (current index, summary of every loaded program)
+    GetProgramList(oneshot::Sender<(usize, Vec<ProgramSummary>)>),
+    /// Reply with (width, height, RGBA pixels) of the last rendered frame
+    CaptureFrame(oneshot::Sender<(u32, u32, Vec<u8>)>),
+    /// Reply with the render time spent per content type, ordered by type name
+    GetRenderTimings(oneshot::Sender<Vec<(ContentKind, RenderTiming)>>),
+    /// Blank one area of a program until the next program update
+    ClearArea { program_guid: String, area_guid: String },
     /// Set brightness (0-100)
     SetBrightness(u8),
     /// Turn screen on/off
     ScreenPower(bool),
+    /// Show one solid color instead of programs, for spotting dead pixels
+    FillColor(u8, u8, u8),
+    /// End `FillColor` and resume normal playback
+    ClearFill,
+    /// Follow a time-of-day playlist instead of rotating (empty: rotate again)
+    SetPlaylist(Playlist),
+    /// A file upload finished; rebroadcast as [`PlayerEvent::FileReceived`]
+    FileReceived(ReceivedFile),
+    /// Program media decoded off the render loop, ready to cache
+    MediaPreloaded(PreloadedMedia),
+}
+
+/// What the protocol side needs to know about a loaded program
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProgramSummary {
+    pub guid: String,
+    pub name: String,
+    pub area_count: usize,
+    /// Play time before rotating to the next program
+    pub duration_secs: u32,
+}
+
+/// Notifications published by the player for supervisors and status reporters
+#[derive(Debug, Clone, PartialEq)]
+pub enum PlayerEvent {
+    /// The displayed program changed (rotation, new screen or explicit selection)
+    ProgramChanged { index: usize, guid: String, name: String },
+    /// A client finished uploading a file into the program directory
+    FileReceived(ReceivedFile),
+}
+
+/// Audit record of a completed file transfer
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReceivedFile {
+    pub filename: String,
+    pub size: u64,
+    /// MD5 of the bytes written, lowercase hex
+    pub md5: String,
+    /// Address of the uploading client
+    pub peer: String,
 }
 
 pub struct Player {
@@ -28,31 +96,78 @@ pub struct Player {
     engine: RenderEngine,
     programs: Vec<Program>,
     current_program: usize,
-    /// Time (in frames) when current program started
-    program_start_frame: u64,
+    /// Time (ms on the player clock) when current program started
+    program_start_ms: u64,
     command_rx: mpsc::Receiver<PlayerCommand>,
     command_tx: mpsc::Sender<PlayerCommand>,
     screen_on: bool,
+    /// Every rendered frame is handed to each of these
+    sinks: Vec<Box<dyn OutputSink>>,
     /// Shared services state
     services: Arc<RwLock<ServicesState>>,
+    /// Broadcasts player events; sends with no subscribers are dropped
+    events: broadcast::Sender<PlayerEvent>,
+    /// Render loop liveness, read by the health probes
+    health: Arc<Health>,
+    /// Logo shown from startup until the first program plays
+    boot_logo: BootLogoService,
+    /// No program has played yet
+    booting: bool,
+    /// The last frame rendered was the boot logo
+    showing_splash: bool,
+    /// The engine's area state belongs to the fallback program
+    showing_fallback: bool,
+    /// Solid color shown over everything until `ClearFill`
+    fill_color: Option<TestPattern>,
+    /// Time-of-day slots overriding the rotation
+    playlist: Playlist,
+    /// The playlist slot now in force is blank
+    playlist_blank: bool,
+    /// Wall-clock time for the playlist
+    clock: SharedClock,
 }
 
 impl Player {
     pub fn new(config: PlayerConfig) -> Self {
         let (tx, rx) = mpsc::channel(64);
-        let engine = RenderEngine::new(config.width, config.height, config.fps);
-        let services = Arc::new(RwLock::new(ServicesState::new(config.program_dir.clone())));
+        let mut engine = RenderEngine::new(config.width, config.height);
+        engine.set_brightness_fade(config.brightness_fade_ms);
+        engine.set_program_transition(config.program_transition_ms);
+        engine.set_color_correction(&config.color_correction);
+        let services = ServicesState::new(&config);
+        // Clock content and the playlist keep the services' time
+        let clock = services.clock.clone();
+        engine.set_renderer(ContentKind::Clock, Box::new(ClockRenderer::with_clock(clock.clone())));
+        let boot_logo = services.boot_logo.clone();
+        let services = Arc::new(RwLock::new(services));
+        let sinks = config
+            .output_modes
+            .iter()
+            .map(|mode| output::sink_for(mode, &config))
+            .collect();
+        let (events, _) = broadcast::channel(16);
 
         Self {
             config,
             engine,
             programs: Vec::new(),
             current_program: 0,
-            program_start_frame: 0,
+            program_start_ms: 0,
             command_rx: rx,
             command_tx: tx,
             screen_on: true,
+            sinks,
             services,
+            events,
+            health: Arc::new(Health::default()),
+            boot_logo,
+            booting: true,
+            showing_splash: false,
+            showing_fallback: false,
+            fill_color: None,
+            playlist: Playlist::default(),
+            playlist_blank: false,
+            clock,
         }
     }
 
@@ -60,10 +175,25 @@ impl Player {
         self.command_tx.clone()
     }
 
+    /// Add an extra output (network, HTTP preview, ...) alongside the configured ones
+    pub fn add_output(&mut self, sink: Box<dyn OutputSink>) {
+        self.sinks.push(sink);
+    }
+
     pub fn services(&self) -> Arc<RwLock<ServicesState>> {
         self.services.clone()
     }
 
+    /// Render loop status for the health probes
+    pub fn health(&self) -> Arc<Health> {
+        self.health.clone()
+    }
+
+    /// Subscribe to player events such as program changes
+    pub fn subscribe(&self) -> broadcast::Receiver<PlayerEvent> {
+        self.events.subscribe()
+    }
+
     /// Load programs from a directory
     pub fn load_programs_from_dir(&mut self, dir: &str) -> Result<()> {
         let path = Path::new(dir);
@@ -71,11 +201,19 @@ impl Player {
             anyhow::bail!("Program directory does not exist: {}", dir);
         }
 
+        // Programs received over the SDK first, then any files placed here
         let mut loaded = 0;
+        if let Some(screen) = StorageService::new(path.to_path_buf()).load_current_program() {
+            info!("Restored {} saved program(s)", screen.programs.len());
+            loaded += screen.programs.len();
+            self.programs.extend(screen.programs);
+        }
         for entry in std::fs::read_dir(path)? {
             let entry = entry?;
             let file_path = entry.path();
-            if file_path.extension().is_some_and(|e| e == "xml") {
+            if file_path.extension().is_some_and(|e| e == "xml")
+                || parser::is_json_program(&file_path)
+            {
                 match parser::parse_program_file(&file_path) {
                     Ok(screen) => {
                         info!(
@@ -83,7 +221,11 @@ impl Player {
                             screen.programs.len(),
                             file_path.display()
                         );
-                        self.programs.extend(screen.programs);
+                        // A saved program wins over a file copy of it
+                        let known: Vec<String> =
+                            self.programs.iter().map(|p| p.guid.clone()).collect();
+                        let new = screen.programs.into_iter().filter(|p| !known.contains(&p.guid));
+                        self.programs.extend(new);
                         loaded += 1;
                     }
                     Err(e) => {
@@ -94,89 +236,319 @@ impl Player {
         }
 
         if loaded == 0 {
-            anyhow::bail!("No program XML files found in {}", dir);
+            anyhow::bail!("No program XML or JSON files found in {}", dir);
         }
 
-        // Initialize rendering for first program
-        if !self.programs.is_empty() {
-            self.engine.reset_for_program(&self.programs[0]);
+        // Initialize rendering for the boot program
+        self.preload_media();
+        if let Some(index) = boot_program_index(&self.programs) {
+            self.activate_program(index, 0);
         }
 
         info!("Loaded {} total programs from {}", self.programs.len(), dir);
         Ok(())
     }
 
+    /// Load the programs of one program XML document read from `reader`
+    /// (e.g. stdin), replacing any loaded so far
+    pub fn load_programs_from_reader(&mut self, mut reader: impl std::io::Read) -> Result<()> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).context("Failed to read program XML")?;
+        let screen = parser::parse_program_xml(&parser::decode_xml_bytes(&bytes))?;
+        if screen.programs.is_empty() {
+            anyhow::bail!("Program XML contains no programs");
+        }
+
+        info!("Loaded {} program(s) from stdin", screen.programs.len());
+        self.programs = screen.programs;
+        self.preload_media();
+        if let Some(index) = boot_program_index(&self.programs) {
+            self.activate_program(index, 0);
+        }
+        Ok(())
+    }
+
     /// Main render loop
     pub async fn run(&mut self) -> Result<()> {
         let frame_duration = Duration::from_millis(1000 / self.config.fps as u64);
         let mut interval = time::interval(frame_duration);
-        let mut frames_rendered: u64 = 0;
+        // A slow frame should cost us frames, not a burst of catch-up ticks
+        interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        // Animation and rotation timing runs off the wall clock; the engine's
+        // frame count is only kept for diagnostics
+        let clock_start = Instant::now();
+        let mut frames_dropped: u64 = 0;
+        let mut ticks: u64 = 0;
+        let mut timings_logged_ms: u64 = 0;
 
         info!(
             "Starting render loop: {}x{} @ {}fps, output: {:?}",
-            self.config.width, self.config.height, self.config.fps, self.config.output_mode
+            self.config.width, self.config.height, self.config.fps, self.config.output_modes
         );
 
-        if self.programs.is_empty() {
-            info!("No programs loaded, waiting for program from network...");
+        if let Some(pattern) = &self.config.test_pattern {
+            info!("Showing test pattern {:?}; programs are ignored", pattern);
+        } else if !self.has_enabled_program() {
+            if self.config.fallback_program.is_some() {
+                info!("No programs to play, showing fallback program until one arrives");
+            } else {
+                info!("No programs loaded, waiting for program from network...");
+            }
         }
 
         loop {
             tokio::select! {
                 _ = interval.tick() => {
+                    let now_ms = clock_start.elapsed().as_millis() as u64;
+
                     // Process pending commands
                     while let Ok(cmd) = self.command_rx.try_recv() {
-                        self.handle_command(cmd, frames_rendered);
+                        self.handle_command(cmd, now_ms);
                     }
+                    self.health.record_tick(self.programs.len());
+                    let scheduled = self.apply_playlist(self.clock.now().time(), now_ms);
 
                     // Render frame
-                    if self.screen_on && !self.programs.is_empty() {
-                        let program_dir = self.config.program_dir.clone();
-                        let program = &self.programs[self.current_program];
-                        self.engine.render_frame(program, &program_dir);
-
-                        match self.config.output_mode {
-                            OutputMode::Png => {
-                                // Save every 5 seconds
-                                if frames_rendered == 0
-                                    || frames_rendered % (self.config.fps as u64 * 5) == 0
-                                {
-                                    let output_path = self.config.output_path.clone();
-                                    self.engine
-                                        .save_png(&output_path)
-                                        .context("Failed to save PNG")?;
-                                    debug!("Saved frame {}", frames_rendered);
-                                }
-                            }
-                            OutputMode::Raw => {
-                                use std::io::Write;
-                                std::io::stdout().write_all(self.engine.pixels()).ok();
-                            }
-                            OutputMode::Framebuffer => {
-                                // TODO: DRM/KMS output
-                            }
+                    let has_content = self.fill_color.is_some()
+                        || self.config.test_pattern.is_some()
+                        || self.playlist_blank
+                        || self.has_enabled_program()
+                        || self.config.fallback_program.is_some()
+                        || self.pending_boot_logo(now_ms).is_some();
+                    if self.screen_on && has_content {
+                        let render_start = Instant::now();
+                        self.render_and_output(now_ms);
+
+                        // Frames whose tick we slept through are skipped; the clock
+                        // keeps running, so animations stay time-correct
+                        let elapsed = render_start.elapsed().as_millis();
+                        let dropped = (elapsed / frame_duration.as_millis().max(1)) as u64;
+                        if dropped > 0 {
+                            frames_dropped += dropped;
+                            warn!(
+                                "Render overran frame budget, dropped {} frame(s) ({} total)",
+                                dropped, frames_dropped
+                            );
                         }
 
-                        frames_rendered += 1;
+                        // Program rotation based on play control, unless the
+                        // playlist decides what plays right now
+                        if !scheduled {
+                            self.check_program_rotation(now_ms);
+                        }
+                    }
+
+                    if now_ms >= timings_logged_ms + TIMING_LOG_INTERVAL_MS {
+                        timings_logged_ms = now_ms;
+                        self.log_render_timings();
+                    }
 
-                        // Program rotation based on play control
-                        self.check_program_rotation(frames_rendered);
+                    ticks += 1;
+                    if self.config.max_frames.is_some_and(|max| ticks >= max) {
+                        info!("Rendered {} frame(s), stopping", ticks);
+                        return Ok(());
                     }
                 }
             }
         }
     }
 
-    fn handle_command(&mut self, cmd: PlayerCommand, current_frame: u64) {
+    /// Render the current program and hand the frame to every output sink. A
+    /// sink that fails is logged and skipped for this frame, so it can't stop
+    /// the others (e.g. a full disk halting the panel).
+    fn render_and_output(&mut self, now_ms: u64) {
+        if let Some(pattern) = self.fill_color.as_ref().or(self.config.test_pattern.as_ref()) {
+            self.engine.render_test_pattern(pattern, now_ms);
+        } else if self.playlist_blank {
+            self.engine.render_blank(now_ms);
+        } else if let Some(logo) = self.pending_boot_logo(now_ms) {
+            self.engine.render_boot_logo(&logo, &self.config.program_dir, now_ms);
+            self.showing_splash = true;
+        } else if !self.has_enabled_program() {
+            let Some(fallback) = &self.config.fallback_program else {
+                return;
+            };
+            if !self.showing_fallback {
+                self.engine.begin_program_transition(now_ms);
+                self.engine.reset_for_program(fallback);
+                self.showing_fallback = true;
+            }
+            self.engine.render_frame(fallback, &self.config.program_dir, now_ms);
+        } else {
+            if self.showing_splash {
+                self.end_splash(now_ms);
+            }
+            let program = &self.programs[self.current_program];
+            self.engine.render_frame(program, &self.config.program_dir, now_ms);
+        }
+
+        let (width, height) = (self.engine.width(), self.engine.height());
+        for sink in &mut self.sinks {
+            if let Err(e) = sink.write_frame(self.engine.pixels(), width, height) {
+                warn!("Failed to output frame: {}", e);
+            }
+        }
+    }
+
+    /// Render time per content type, ordered by type name
+    fn render_timings(&self) -> Vec<(ContentKind, RenderTiming)> {
+        let mut timings: Vec<_> =
+            self.engine.render_timings().iter().map(|(&kind, &t)| (kind, t)).collect();
+        timings.sort_by_key(|(kind, _)| kind.name());
+        timings
+    }
+
+    fn log_render_timings(&self) {
+        if !tracing::enabled!(tracing::Level::DEBUG) {
+            return;
+        }
+        for (kind, timing) in self.render_timings() {
+            debug!(
+                "Render time for {}: {} call(s), avg {:?}, max {:?}",
+                kind.name(),
+                timing.calls,
+                timing.average(),
+                timing.max
+            );
+        }
+    }
+
+    /// At least one loaded program is allowed to play
+    fn has_enabled_program(&self) -> bool {
+        self.programs.iter().any(is_enabled)
+    }
+
+    /// The boot logo, while it should still be showing: until the first
+    /// program plays, and for at least `splash_ms` after startup
+    fn pending_boot_logo(&self, now_ms: u64) -> Option<String> {
+        let waiting = self.booting && !self.has_enabled_program();
+        if waiting || now_ms < self.config.splash_ms {
+            self.boot_logo.get()
+        } else {
+            None
+        }
+    }
+
+    /// The boot logo has made way for the current program: start the program
+    /// afresh, as it has been hidden behind the logo, and fade into it. Without
+    /// a splash fade the usual program transition applies.
+    fn end_splash(&mut self, now_ms: u64) {
+        self.showing_splash = false;
+        self.program_start_ms = now_ms;
+        self.engine.reset_for_program(&self.programs[self.current_program]);
+        if self.config.splash_fade_ms > 0 {
+            self.engine.begin_transition(now_ms, self.config.splash_fade_ms);
+        }
+    }
+
+    /// Decode the media of the loaded programs on a worker thread; the result
+    /// comes back to the render loop as `MediaPreloaded`
+    fn preload_media(&self) {
+        let preload = self.engine.media_preload(&self.programs, &self.config.program_dir);
+        if preload.is_empty() {
+            return;
+        }
+        let tx = self.command_tx.clone();
+        std::thread::spawn(move || {
+            let _ = tx.blocking_send(PlayerCommand::MediaPreloaded(preload.decode()));
+        });
+    }
+
+    fn handle_command(&mut self, cmd: PlayerCommand, now_ms: u64) {
         match cmd {
             PlayerCommand::LoadScreen(screen) => {
                 info!("Loading new screen with {} program(s)", screen.programs.len());
+                for warning in parser::blank_program_warnings(&screen) {
+                    warn!("Loaded {}; it will show black", warning);
+                }
                 self.programs = screen.programs;
-                self.current_program = 0;
-                self.program_start_frame = current_frame;
-                if !self.programs.is_empty() {
-                    self.engine.reset_for_program(&self.programs[0]);
+                self.preload_media();
+                self.engine.restore_areas();
+                match boot_program_index(&self.programs) {
+                    Some(index) => self.activate_program(index, now_ms),
+                    None => self.current_program = 0,
+                }
+            }
+            PlayerCommand::UpdateScreen(screen) => {
+                let current = self
+                    .programs
+                    .get(self.current_program)
+                    .filter(|_| !self.booting && !self.showing_fallback);
+                let updated = current.and_then(|old| {
+                    let index = screen.programs.iter().position(|p| p.guid == old.guid)?;
+                    is_enabled(&screen.programs[index]).then_some(index)
+                });
+                let Some(index) = updated else {
+                    self.handle_command(PlayerCommand::LoadScreen(screen), now_ms);
+                    return;
+                };
+                info!("Updating screen with {} program(s) in place", screen.programs.len());
+                let old = std::mem::replace(&mut self.programs, screen.programs);
+                self.preload_media();
+                self.engine.restore_areas();
+                self.engine.update_program(&old[self.current_program], &self.programs[index]);
+                self.current_program = index;
+            }
+            PlayerCommand::RemoveProgram(guid) => {
+                let Some(index) = self.programs.iter().position(|p| p.guid == guid) else {
+                    warn!("RemoveProgram: no program with GUID {}", guid);
+                    return;
+                };
+                let removed = self.programs.remove(index);
+                info!("Removed program '{}' ({})", removed.name, guid);
+
+                if self.programs.is_empty() {
+                    self.current_program = 0;
+                } else if index < self.current_program {
+                    self.current_program -= 1;
+                } else if index == self.current_program {
+                    // The displayed program went away; show whatever slid into its slot
+                    let slot = self.current_program % self.programs.len();
+                    match next_enabled_index(&self.programs, slot) {
+                        Some(next) => self.activate_program(next, now_ms),
+                        None => self.current_program = slot,
+                    }
+                }
+            }
+            PlayerCommand::SetCurrentProgram(index, reply) => {
+                let ok = self.set_current_program(index, now_ms);
+                reply.send(ok).ok();
+            }
+            PlayerCommand::GetCurrentProgram(reply) => {
+                reply.send((self.current_program, self.programs.len())).ok();
+            }
+            PlayerCommand::GetProgramList(reply) => {
+                let summaries = self
+                    .programs
+                    .iter()
+                    .map(|p| ProgramSummary {
+                        guid: p.guid.clone(),
+                        name: p.name.clone(),
+                        area_count: p.areas.len(),
+                        duration_secs: program_duration_secs(p),
+                    })
+                    .collect();
+                reply.send((self.current_program, summaries)).ok();
+            }
+            PlayerCommand::CaptureFrame(reply) => {
+                let frame = self.engine.pixels().to_vec();
+                reply.send((self.engine.width(), self.engine.height(), frame)).ok();
+            }
+            PlayerCommand::GetRenderTimings(reply) => {
+                reply.send(self.render_timings()).ok();
+            }
+            PlayerCommand::ClearArea { program_guid, area_guid } => {
+                let found = self
+                    .programs
+                    .iter()
+                    .filter(|p| p.guid == program_guid)
+                    .any(|p| p.areas.iter().any(|a| a.guid == area_guid));
+                if !found {
+                    warn!("ClearArea: no area {} in program {}", area_guid, program_guid);
+                    return;
                 }
+                info!("Clearing area {} of program {}", area_guid, program_guid);
+                self.engine.clear_area(&program_guid, &area_guid);
             }
             PlayerCommand::SetBrightness(level) => {
                 info!("Brightness: {}", level);
@@ -186,33 +558,101 @@ impl Player {
                 info!("Screen: {}", if on { "ON" } else { "OFF" });
                 self.screen_on = on;
             }
+            PlayerCommand::FillColor(r, g, b) => {
+                info!("Filling screen with #{:02X}{:02X}{:02X}", r, g, b);
+                self.fill_color = Some(TestPattern::Solid(r, g, b));
+            }
+            PlayerCommand::ClearFill => {
+                if self.fill_color.take().is_some() {
+                    info!("Screen fill cleared, resuming playback");
+                }
+            }
+            PlayerCommand::SetPlaylist(playlist) => {
+                info!("Playlist set: {} slot(s)", playlist.entries().len());
+                self.playlist = playlist;
+                self.playlist_blank = false;
+            }
+            PlayerCommand::FileReceived(file) => {
+                let _ = self.events.send(PlayerEvent::FileReceived(file));
+            }
+            PlayerCommand::MediaPreloaded(media) => self.engine.insert_preloaded(media),
         }
     }
 
-    /// Check if it's time to rotate to the next program
-    fn check_program_rotation(&mut self, current_frame: u64) {
-        if self.programs.len() <= 1 {
-            return;
+    /// Switch straight to a program, restarting its effects and play duration
+    fn set_current_program(&mut self, index: usize, now_ms: u64) -> bool {
+        if index >= self.programs.len() {
+            warn!(
+                "SetCurrentProgram: index {} out of range ({} programs)",
+                index,
+                self.programs.len()
+            );
+            return false;
         }
+        self.activate_program(index, now_ms);
+        info!(
+            "Program {}/{}: '{}' (selected)",
+            index + 1,
+            self.programs.len(),
+            self.programs[index].name
+        );
+        true
+    }
 
-        let program = &self.programs[self.current_program];
+    /// Make `index` the displayed program, restart its timing and announce the change
+    fn activate_program(&mut self, index: usize, now_ms: u64) {
+        self.current_program = index;
+        self.program_start_ms = now_ms;
+        self.booting = false;
+        self.showing_fallback = false;
+        let program = &self.programs[index];
+        self.engine.begin_program_transition(now_ms);
+        self.engine.reset_for_program(program);
+        let _ = self.events.send(PlayerEvent::ProgramChanged {
+            index,
+            guid: program.guid.clone(),
+            name: program.name.clone(),
+        });
+    }
 
-        // Use play control duration if specified, otherwise default 10s
-        let duration_secs = if let Some(ref pc) = program.play_control {
-            parse_duration_secs(&pc.duration).unwrap_or(10)
-        } else {
-            10
+    /// Follow the playlist slot covering `time`: switch to its program or
+    /// blank the screen. Returns false when no slot applies (or its program
+    /// isn't loaded) and the normal rotation should run.
+    fn apply_playlist(&mut self, time: chrono::NaiveTime, now_ms: u64) -> bool {
+        let slot = self.playlist.slot_at(time);
+        self.playlist_blank = slot == Slot::Blank;
+        let guid = match slot {
+            Slot::Rotate => return false,
+            Slot::Blank => return true,
+            Slot::Program(guid) => guid,
+        };
+        let Some(index) = self.programs.iter().position(|p| p.guid == guid && is_enabled(p))
+        else {
+            debug!("Playlist program {} is not loaded, rotating instead", guid);
+            return false;
         };
+        if index != self.current_program || self.booting || self.showing_fallback {
+            self.activate_program(index, now_ms);
+            info!("Program '{}' (playlist)", self.programs[index].name);
+        }
+        true
+    }
+
+    /// Check if it's time to rotate to the next program
+    fn check_program_rotation(&mut self, now_ms: u64) {
+        if self.programs.len() <= 1 {
+            return;
+        }
 
-        let frames_per_program = duration_secs as u64 * self.config.fps as u64;
-        let elapsed = current_frame - self.program_start_frame;
+        let duration_secs = program_duration_secs(&self.programs[self.current_program]);
+        let elapsed = now_ms.saturating_sub(self.program_start_ms);
 
-        if elapsed >= frames_per_program {
-            let next = (self.current_program + 1) % self.programs.len();
-            if next != self.current_program {
-                self.current_program = next;
-                self.program_start_frame = current_frame;
-                self.engine.reset_for_program(&self.programs[next]);
+        if elapsed >= duration_secs as u64 * 1000 {
+            let after = (self.current_program + 1) % self.programs.len();
+            if let Some(next) = next_enabled_index(&self.programs, after)
+                && next != self.current_program
+            {
+                self.activate_program(next, now_ms);
                 info!(
                     "Program {}/{}: '{}'",
                     self.current_program + 1,
@@ -224,6 +664,51 @@ impl Player {
     }
 }
 
+/// Built-in idle program: a full-screen digital clock
+pub fn fallback_clock_program(width: u32, height: u32) -> Result<Program> {
+    let xml = format!(
+        r##"<screen><program guid="fallback-clock" name="Clock">
+             <area guid="fallback-clock-area"><rectangle x="0" y="0" width="{width}" height="{height}"/>
+               <resources><clock guid="fallback-clock-item" type="digital">
+                 <date format="1" color="#FFFFFF" display="true"/>
+                 <time format="2" color="#FFFFFF" display="true"/>
+               </clock></resources>
+             </area></program></screen>"##
+    );
+    let screen = parser::parse_program_xml(&xml)?;
+    Ok(screen.programs.into_iter().next().expect("built-in clock program"))
+}
+
+/// Programs marked `disabled` in their play control stay loaded but never play
+fn is_enabled(program: &Program) -> bool {
+    !program.play_control.as_ref().is_some_and(|pc| pc.disabled)
+}
+
+/// The enabled program flagged as default plays first; otherwise the first
+/// enabled one. None when nothing may play.
+fn boot_program_index(programs: &[Program]) -> Option<usize> {
+    programs
+        .iter()
+        .position(|p| p.flag.is_default() && is_enabled(p))
+        .or_else(|| programs.iter().position(is_enabled))
+}
+
+/// First enabled program at or after `start`, wrapping around
+fn next_enabled_index(programs: &[Program], start: usize) -> Option<usize> {
+    (0..programs.len())
+        .map(|offset| (start + offset) % programs.len())
+        .find(|&i| is_enabled(&programs[i]))
+}
+
+/// Play control duration if specified, otherwise default 10s
+fn program_duration_secs(program: &Program) -> u32 {
+    program
+        .play_control
+        .as_ref()
+        .and_then(|pc| parse_duration_secs(&pc.duration))
+        .unwrap_or(10)
+}
+
 /// Parse "HH:MM:SS" duration to seconds
 fn parse_duration_secs(s: &str) -> Option<u32> {
     let parts: Vec<&str> = s.split(':').collect();
@@ -236,3 +721,375 @@ fn parse_duration_secs(s: &str) -> Option<u32> {
         None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn two_program_screen() -> Screen {
+        parser::parse_program_xml(
+            r#"<screen>
+              <program guid="p1" name="One"><area guid="a1"><rectangle width="32" height="16"/><resources/></area></program>
+              <program guid="p2" name="Two"><area guid="a2"><rectangle width="32" height="16"/><resources/></area></program>
+            </screen>"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_fill_color_overrides_programs() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut player = Player::new(PlayerConfig::for_test(dir.path()));
+        player.handle_command(PlayerCommand::LoadScreen(two_program_screen()), 0);
+
+        player.handle_command(PlayerCommand::FillColor(0, 0, 255), 0);
+        player.render_and_output(0);
+        assert!(player.engine.pixels().chunks_exact(4).all(|px| px == [0, 0, 255, 255]));
+
+        // Programs come back once the fill is cleared (these ones are empty: black)
+        player.handle_command(PlayerCommand::ClearFill, 100);
+        player.render_and_output(100);
+        assert!(player.engine.pixels().chunks_exact(4).all(|px| px == [0, 0, 0, 255]));
+    }
+
+    #[test]
+    fn test_playlist_overrides_rotation() {
+        use crate::core::playlist::PlaylistEntry;
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut player = Player::new(PlayerConfig::for_test(dir.path()));
+        player.handle_command(PlayerCommand::LoadScreen(two_program_screen()), 0);
+        let playlist = Playlist::new(vec![
+            PlaylistEntry::new("09:00", "09:05", Some("p2".to_string())).unwrap(),
+            PlaylistEntry::new("09:05", "09:10", Some("p1".to_string())).unwrap(),
+            PlaylistEntry::new("09:10", "09:15", None).unwrap(),
+            PlaylistEntry::new("09:15", "09:20", Some("missing".to_string())).unwrap(),
+        ]);
+        player.handle_command(PlayerCommand::SetPlaylist(playlist), 0);
+        let at = |h, m| chrono::NaiveTime::from_hms_opt(h, m, 0).unwrap();
+
+        assert!(player.apply_playlist(at(9, 2), 0));
+        assert_eq!(player.programs[player.current_program].guid, "p2");
+        assert!(player.apply_playlist(at(9, 7), 1000));
+        assert_eq!(player.programs[player.current_program].guid, "p1");
+
+        // A blank slot shows black
+        assert!(player.apply_playlist(at(9, 12), 2000));
+        player.render_and_output(2000);
+        assert!(player.engine.pixels().chunks_exact(4).all(|px| px == [0, 0, 0, 255]));
+
+        // Unknown programs and uncovered times leave it to the rotation
+        assert!(!player.apply_playlist(at(9, 17), 3000));
+        assert!(!player.apply_playlist(at(12, 0), 3000));
+        assert!(!player.playlist_blank);
+    }
+
+    #[test]
+    fn test_remove_one_of_two_programs() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut player = Player::new(PlayerConfig::for_test(dir.path()));
+        player.handle_command(PlayerCommand::LoadScreen(two_program_screen()), 0);
+
+        player.handle_command(PlayerCommand::RemoveProgram("p1".to_string()), 1000);
+        assert_eq!(player.programs.len(), 1);
+        assert_eq!(player.programs[0].guid, "p2");
+        assert_eq!(player.current_program, 0);
+
+        // Unknown GUIDs are ignored
+        player.handle_command(PlayerCommand::RemoveProgram("missing".to_string()), 2000);
+        assert_eq!(player.programs.len(), 1);
+    }
+
+    /// Counts the frames it receives
+    struct CountingSink(Arc<std::sync::atomic::AtomicUsize>);
+
+    impl OutputSink for CountingSink {
+        fn write_frame(&mut self, pixels: &[u8], width: u32, height: u32) -> Result<()> {
+            assert_eq!(pixels.len(), (width * height * 4) as usize);
+            self.0.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    /// Fails every frame, like a snapshot sink on a full disk
+    struct FailingSink;
+
+    impl OutputSink for FailingSink {
+        fn write_frame(&mut self, _pixels: &[u8], _width: u32, _height: u32) -> Result<()> {
+            anyhow::bail!("No space left on device")
+        }
+    }
+
+    #[test]
+    fn test_every_sink_receives_frame() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut player = Player::new(PlayerConfig::for_test(dir.path()));
+        player.handle_command(PlayerCommand::LoadScreen(two_program_screen()), 0);
+
+        let first = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let second = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        player.add_output(Box::new(CountingSink(first.clone())));
+        player.add_output(Box::new(FailingSink));
+        player.add_output(Box::new(CountingSink(second.clone())));
+
+        player.render_and_output(0);
+        player.render_and_output(100);
+        assert_eq!(first.load(std::sync::atomic::Ordering::SeqCst), 2);
+        assert_eq!(second.load(std::sync::atomic::Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_default_flag_plays_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut player = Player::new(PlayerConfig::for_test(dir.path()));
+        let screen = parser::parse_program_xml(
+            r#"<screen>
+              <program guid="p1" name="One"><area guid="a1"><rectangle width="32" height="16"/><resources/></area></program>
+              <program guid="p2" name="Two" flag="default"><area guid="a2"><rectangle width="32" height="16"/><resources/></area></program>
+            </screen>"#,
+        )
+        .unwrap();
+        player.handle_command(PlayerCommand::LoadScreen(screen), 0);
+        assert_eq!(player.current_program, 1);
+
+        // Without a flagged program the first one plays
+        player.handle_command(PlayerCommand::LoadScreen(two_program_screen()), 0);
+        assert_eq!(player.current_program, 0);
+    }
+
+    #[test]
+    fn test_set_current_program() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut player = Player::new(PlayerConfig::for_test(dir.path()));
+        player.handle_command(PlayerCommand::LoadScreen(two_program_screen()), 0);
+
+        let (tx, mut rx) = oneshot::channel();
+        player.handle_command(PlayerCommand::SetCurrentProgram(1, tx), 1500);
+        assert_eq!(rx.try_recv(), Ok(true));
+        assert_eq!(player.current_program, 1);
+        assert_eq!(player.program_start_ms, 1500);
+
+        let (tx, mut rx) = oneshot::channel();
+        player.handle_command(PlayerCommand::GetCurrentProgram(tx), 1600);
+        assert_eq!(rx.try_recv(), Ok((1, 2)));
+
+        let (tx, mut rx) = oneshot::channel();
+        player.handle_command(PlayerCommand::SetCurrentProgram(0, tx), 2000);
+        assert_eq!(rx.try_recv(), Ok(true));
+        assert_eq!(player.current_program, 0);
+
+        // Out of range leaves the current program alone
+        let (tx, mut rx) = oneshot::channel();
+        player.handle_command(PlayerCommand::SetCurrentProgram(2, tx), 3000);
+        assert_eq!(rx.try_recv(), Ok(false));
+        assert_eq!(player.current_program, 0);
+        assert_eq!(player.program_start_ms, 2000);
+    }
+
+    #[test]
+    fn test_boot_logo_shows_until_first_program() {
+        let dir = tempfile::tempdir().unwrap();
+        let logo = image::RgbaImage::from_pixel(2, 1, image::Rgba([255, 0, 0, 255]));
+        logo.save(dir.path().join("logo.png")).unwrap();
+
+        let mut player = Player::new(PlayerConfig::for_test(dir.path()));
+        player.boot_logo.set("logo.png").unwrap();
+        player.render_and_output(0);
+        // 2:1 logo fills the 32x16 screen
+        let pixels = player.engine.pixels();
+        assert_eq!(&pixels[..4], &[255, 0, 0, 255]);
+        assert_eq!(&pixels[pixels.len() - 4..], &[255, 0, 0, 255]);
+
+        player.handle_command(PlayerCommand::LoadScreen(two_program_screen()), 100);
+        assert!(player.pending_boot_logo(100).is_none());
+        player.render_and_output(200);
+        assert_eq!(&player.engine.pixels()[..4], &[0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn test_splash_holds_then_fades_into_program() {
+        let dir = tempfile::tempdir().unwrap();
+        let logo = image::RgbaImage::from_pixel(2, 1, image::Rgba([255, 0, 0, 255]));
+        logo.save(dir.path().join("logo.png")).unwrap();
+        let config = PlayerConfig {
+            splash_ms: 300,
+            splash_fade_ms: 200,
+            ..PlayerConfig::for_test(dir.path())
+        };
+        let mut player = Player::new(config);
+        player.boot_logo.set("logo.png").unwrap();
+        player.handle_command(PlayerCommand::LoadScreen(two_program_screen()), 0);
+
+        // The logo holds for 300ms of frames although a program is loaded
+        for now_ms in [0, 100, 200] {
+            player.render_and_output(now_ms);
+            assert_eq!(&player.engine.pixels()[..4], &[255, 0, 0, 255], "at {now_ms}ms");
+        }
+
+        // Then fades into the (black) program, which starts from the top
+        player.render_and_output(300);
+        assert_eq!(player.program_start_ms, 300);
+        player.render_and_output(400);
+        assert_eq!(&player.engine.pixels()[..4], &[128, 0, 0, 255]);
+        player.render_and_output(500);
+        assert_eq!(&player.engine.pixels()[..4], &[0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn test_fallback_program_renders_when_idle() {
+        let dir = tempfile::tempdir().unwrap();
+        let green = image::RgbaImage::from_pixel(4, 4, image::Rgba([0, 255, 0, 255]));
+        green.save(dir.path().join("idle.png")).unwrap();
+        let idle = parser::parse_program_xml(
+            r#"<screen><program guid="idle"><area guid="a"><rectangle width="32" height="16"/>
+              <resources><image guid="i" fit="stretch"><file name="idle.png"/></image></resources></area></program></screen>"#,
+        )
+        .unwrap();
+
+        let mut config = PlayerConfig::for_test(dir.path());
+        config.fallback_program = idle.programs.into_iter().next();
+        let mut player = Player::new(config);
+        player.render_and_output(0);
+        assert_eq!(&player.engine.pixels()[..4], &[0, 255, 0, 255]);
+
+        // Real programs take over, and the fallback returns once they're gone
+        player.handle_command(PlayerCommand::LoadScreen(two_program_screen()), 100);
+        player.render_and_output(200);
+        assert_eq!(&player.engine.pixels()[..4], &[0, 0, 0, 255]);
+
+        player.handle_command(PlayerCommand::LoadScreen(Screen {
+            timestamps: String::new(),
+            programs: Vec::new(),
+        }), 300);
+        player.render_and_output(400);
+        assert_eq!(&player.engine.pixels()[..4], &[0, 255, 0, 255]);
+
+        assert_eq!(fallback_clock_program(32, 16).unwrap().areas.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_frames_limit_stops_run_loop() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = PlayerConfig::for_test(dir.path());
+        config.output_modes = vec![crate::config::OutputMode::Png];
+        config.max_frames = Some(3);
+        let mut player = Player::new(config);
+        player.handle_command(PlayerCommand::LoadScreen(two_program_screen()), 0);
+
+        tokio::time::timeout(Duration::from_secs(5), player.run())
+            .await
+            .expect("run loop should stop after 3 frames")
+            .unwrap();
+        for frame in 0..3 {
+            assert!(dir.path().join(format!("output-{frame:04}.png")).exists(), "frame {frame}");
+        }
+        assert!(!dir.path().join("output-0003.png").exists());
+    }
+
+    #[test]
+    fn test_load_programs_from_stdin() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut player = Player::new(PlayerConfig::for_test(dir.path()));
+        let xml = r#"<screen><program guid="piped" name="Piped">
+            <area guid="a"><rectangle width="32" height="16"/><resources/></area></program></screen>"#;
+        player.load_programs_from_reader(xml.as_bytes()).unwrap();
+        assert_eq!(player.programs.len(), 1);
+        assert_eq!(player.programs[0].guid, "piped");
+        player.render_and_output(0);
+
+        assert!(player.load_programs_from_reader("<screen/>".as_bytes()).is_err());
+        assert!(player.load_programs_from_reader("not xml".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_load_screen_preloads_media() {
+        let dir = tempfile::tempdir().unwrap();
+        let red = image::RgbaImage::from_pixel(4, 4, image::Rgba([255, 0, 0, 255]));
+        red.save(dir.path().join("red.png")).unwrap();
+        let mut file = std::fs::File::create(dir.path().join("green.gif")).unwrap();
+        let mut encoder = gif::Encoder::new(&mut file, 1, 1, &[0, 255, 0, 0, 0, 0]).unwrap();
+        encoder.write_frame(&gif::Frame::from_indexed_pixels(1, 1, vec![0], None)).unwrap();
+        drop(encoder);
+        let screen = parser::parse_program_xml(
+            r#"<screen><program guid="p">
+              <area guid="a"><rectangle x="0" y="0" width="16" height="16"/>
+                <resources><image guid="i" fit="stretch"><file name="red.png"/></image></resources></area>
+              <area guid="b"><rectangle x="16" y="0" width="16" height="16"/>
+                <resources><gif guid="g"><file name="green.gif"/></gif></resources></area>
+            </program></screen>"#,
+        )
+        .unwrap();
+
+        let mut player = Player::new(PlayerConfig::for_test(dir.path()));
+        player.handle_command(PlayerCommand::LoadScreen(screen), 0);
+
+        // Decoded on a worker thread and handed back as a command
+        let preloaded = player.command_rx.blocking_recv().unwrap();
+        assert!(matches!(preloaded, PlayerCommand::MediaPreloaded(_)));
+        player.handle_command(preloaded, 0);
+
+        // The first frame doesn't need the files
+        std::fs::remove_file(dir.path().join("red.png")).unwrap();
+        std::fs::remove_file(dir.path().join("green.gif")).unwrap();
+        player.render_and_output(0);
+        let pixels = player.engine.pixels();
+        assert_eq!(&pixels[..4], &[255, 0, 0, 255]);
+        let i = (8 * 32 + 24) * 4;
+        assert_eq!(&pixels[i..i + 4], &[0, 255, 0, 255]);
+    }
+
+    #[test]
+    fn test_disabled_program_never_plays() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = PlayerConfig::for_test(dir.path());
+        config.fallback_program = Some(fallback_clock_program(32, 16).unwrap());
+        let mut player = Player::new(config);
+        let screen = parser::parse_program_xml(
+            r#"<screen>
+              <program guid="off" name="Off"><playControl duration="00:00:05" disabled="true"/>
+                <area guid="a1"><rectangle width="32" height="16"/><resources/></area></program>
+              <program guid="on" name="On"><playControl duration="00:00:05"/>
+                <area guid="a2"><rectangle width="32" height="16"/><resources/></area></program>
+            </screen>"#,
+        )
+        .unwrap();
+        player.handle_command(PlayerCommand::LoadScreen(screen.clone()), 0);
+        assert_eq!(player.programs[player.current_program].guid, "on");
+
+        // Rotation skips the disabled program, so the enabled one keeps playing
+        let mut events = player.subscribe();
+        player.check_program_rotation(5_000);
+        player.check_program_rotation(10_000);
+        assert_eq!(player.programs[player.current_program].guid, "on");
+        assert!(events.try_recv().is_err());
+
+        // With every program disabled the fallback shows instead
+        let mut all_off = screen;
+        all_off.programs.retain(|p| p.guid == "off");
+        player.handle_command(PlayerCommand::LoadScreen(all_off), 20_000);
+        player.render_and_output(20_000);
+        assert!(player.showing_fallback);
+    }
+
+    #[test]
+    fn test_rotation_emits_program_changed() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut player = Player::new(PlayerConfig::for_test(dir.path()));
+        player.handle_command(PlayerCommand::LoadScreen(two_program_screen()), 0);
+        let mut events = player.subscribe();
+
+        // Not due yet: nothing changes
+        player.check_program_rotation(5_000);
+        assert!(events.try_recv().is_err());
+
+        player.check_program_rotation(10_000);
+        assert_eq!(
+            events.try_recv().unwrap(),
+            PlayerEvent::ProgramChanged {
+                index: 1,
+                guid: "p2".to_string(),
+                name: "Two".to_string(),
+            }
+        );
+    }
+}
@@ -0,0 +1,114 @@
+/// Time-of-day playlist: an explicit timeline of which program plays when.
+/// While a slot covers the current time it decides what is shown (a program,
+/// or a blank screen) instead of the normal duration-based rotation. Outside
+/// every slot the rotation carries on as usual.
+use chrono::NaiveTime;
+
+/// One slot on the daily timeline
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlaylistEntry {
+    pub start: NaiveTime,
+    /// End of the slot (exclusive). Earlier than `start` means the slot runs
+    /// past midnight; equal to it means all day.
+    pub end: NaiveTime,
+    /// GUID of the program to play; None blanks the screen
+    pub program: Option<String>,
+}
+
+impl PlaylistEntry {
+    /// Parse a slot from "HH:MM[:SS]" times; None if either time is invalid
+    pub fn new(start: &str, end: &str, program: Option<String>) -> Option<Self> {
+        Some(Self {
+            start: parse_time(start)?,
+            end: parse_time(end)?,
+            program: program.filter(|guid| !guid.is_empty()),
+        })
+    }
+
+    fn covers(&self, time: NaiveTime) -> bool {
+        if self.end < self.start {
+            time >= self.start || time < self.end
+        } else {
+            self.start == self.end || (time >= self.start && time < self.end)
+        }
+    }
+}
+
+/// What the playlist wants on screen at a given time
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Slot<'a> {
+    /// Play the program with this GUID
+    Program(&'a str),
+    /// Show nothing
+    Blank,
+    /// No slot covers this time; rotate programs as usual
+    Rotate,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Playlist {
+    entries: Vec<PlaylistEntry>,
+}
+
+impl Playlist {
+    pub fn new(entries: Vec<PlaylistEntry>) -> Self {
+        Self { entries }
+    }
+
+    pub fn entries(&self) -> &[PlaylistEntry] {
+        &self.entries
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The slot in force at `time`. Where slots overlap, the one listed first wins.
+    pub fn slot_at(&self, time: NaiveTime) -> Slot<'_> {
+        match self.entries.iter().find(|entry| entry.covers(time)) {
+            Some(PlaylistEntry { program: Some(guid), .. }) => Slot::Program(guid),
+            Some(_) => Slot::Blank,
+            None => Slot::Rotate,
+        }
+    }
+}
+
+/// Parse HH:MM:SS, or HH:MM
+fn parse_time(s: &str) -> Option<NaiveTime> {
+    let s = s.trim();
+    NaiveTime::parse_from_str(s, "%H:%M:%S")
+        .or_else(|_| NaiveTime::parse_from_str(s, "%H:%M"))
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(h: u32, m: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(h, m, 0).unwrap()
+    }
+
+    #[test]
+    fn test_slot_at_times_of_day() {
+        let playlist = Playlist::new(vec![
+            PlaylistEntry::new("09:00", "09:05", Some("a".to_string())).unwrap(),
+            PlaylistEntry::new("09:05:00", "09:10:00", Some("b".to_string())).unwrap(),
+            PlaylistEntry::new("09:10", "09:15", None).unwrap(),
+            PlaylistEntry::new("23:00", "01:00", Some("night".to_string())).unwrap(),
+        ]);
+        assert_eq!(playlist.slot_at(at(9, 0)), Slot::Program("a"));
+        assert_eq!(playlist.slot_at(at(9, 4)), Slot::Program("a"));
+        assert_eq!(playlist.slot_at(at(9, 5)), Slot::Program("b"));
+        assert_eq!(playlist.slot_at(at(9, 12)), Slot::Blank);
+        assert_eq!(playlist.slot_at(at(9, 15)), Slot::Rotate);
+        assert_eq!(playlist.slot_at(at(8, 59)), Slot::Rotate);
+
+        // Past midnight
+        assert_eq!(playlist.slot_at(at(23, 30)), Slot::Program("night"));
+        assert_eq!(playlist.slot_at(at(0, 30)), Slot::Program("night"));
+        assert_eq!(playlist.slot_at(at(1, 0)), Slot::Rotate);
+
+        assert!(PlaylistEntry::new("9am", "10:00", None).is_none());
+    }
+}
@@ -0,0 +1,105 @@
+/// Offline rendering and checking of a program file, without the network
+/// server or output sinks (`huidu-player preview` / `validate`).
+use anyhow::{Context, Result};
+use std::path::Path;
+
+use crate::program::parser::{self, ValidationWarning};
+use crate::render::engine::RenderEngine;
+use crate::render::export;
+
+/// Render frame `frame` (at `fps`) of program `program_index` in the XML file
+/// at `path`. Media is looked up next to the file. Every earlier frame is
+/// rendered too, so effects reach the state they would have on the sign.
+pub fn render_program_frame(
+    path: &Path,
+    program_index: usize,
+    width: u32,
+    height: u32,
+    fps: u32,
+    frame: u64,
+) -> Result<RenderEngine> {
+    let screen = parser::parse_program_file(path)?;
+    let program = screen.programs.get(program_index).with_context(|| {
+        format!(
+            "{} has {} program(s), no index {}",
+            path.display(),
+            screen.programs.len(),
+            program_index
+        )
+    })?;
+    let program_dir = path.parent().unwrap_or(Path::new("."));
+
+    let mut engine = RenderEngine::new(width, height);
+    engine.reset_for_program(program);
+    let frame_ms = 1000 / fps.max(1) as u64;
+    for n in 0..=frame {
+        engine.render_frame(program, program_dir, n * frame_ms);
+    }
+    Ok(engine)
+}
+
+/// Render one frame of a program file and save it (format from `out`'s extension)
+pub fn write_preview(
+    path: &Path,
+    out: &Path,
+    program_index: usize,
+    width: u32,
+    height: u32,
+    fps: u32,
+    frame: u64,
+) -> Result<()> {
+    let engine = render_program_frame(path, program_index, width, height, fps, frame)?;
+    export::save_frame(out, engine.pixels(), engine.width(), engine.height())
+}
+
+/// Parse and validate a program file against a `width` x `height` display
+pub fn validate_file(path: &Path, width: u32, height: u32) -> Result<Vec<ValidationWarning>> {
+    let bytes = std::fs::read(path)
+        .with_context(|| format!("Failed to read program file: {}", path.display()))?;
+    let xml = parser::decode_xml_bytes(&bytes);
+    let program_dir = path.parent().unwrap_or(Path::new("."));
+    parser::validate_program_xml(&xml, program_dir, width, height)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROGRAM: &str = r##"<screen>
+      <program guid="p1" name="Preview">
+        <area guid="a1" bgColor="#0000FF">
+          <rectangle x="0" y="0" width="16" height="8"/>
+          <resources/>
+        </area>
+      </program>
+    </screen>"##;
+
+    #[test]
+    fn test_preview_writes_png() {
+        let dir = tempfile::tempdir().unwrap();
+        let program = dir.path().join("program.xml");
+        std::fs::write(&program, PROGRAM).unwrap();
+        let out = dir.path().join("out.png");
+
+        write_preview(&program, &out, 0, 32, 16, 10, 5).unwrap();
+        let img = image::open(&out).unwrap().to_rgba8();
+        assert_eq!(img.dimensions(), (32, 16));
+        assert_eq!(img.get_pixel(4, 4).0, [0, 0, 255, 255]);
+
+        assert!(write_preview(&program, &out, 3, 32, 16, 10, 0).is_err());
+    }
+
+    #[test]
+    fn test_validate_reports_out_of_bounds_area() {
+        let dir = tempfile::tempdir().unwrap();
+        let program = dir.path().join("program.xml");
+        std::fs::write(&program, PROGRAM).unwrap();
+
+        assert!(validate_file(&program, 32, 16).unwrap().iter().all(|w| !matches!(
+            w,
+            ValidationWarning::AreaOutOfBounds { .. }
+        )));
+        let warnings = validate_file(&program, 8, 8).unwrap();
+        assert!(warnings.iter().any(|w| matches!(w, ValidationWarning::AreaOutOfBounds { .. })));
+    }
+}
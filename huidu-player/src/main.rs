@@ -1,7 +1,8 @@
 #![allow(dead_code)]
 
-use anyhow::Result;
-use clap::Parser;
+use anyhow::{Context, Result};
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
 use tracing::{info, warn};
 
 mod config;
@@ -12,13 +13,70 @@ mod render;
 mod services;
 
 use crate::core::player::Player;
+use crate::core::preview;
 
 #[derive(Parser, Debug)]
-#[command(name = "huidu-player", about = "Huidu LED sign player - Rust reproduction of BoxPlayer")]
-struct Args {
-    /// Program directory path
-    #[arg(short, long, default_value = "programs")]
-    program_dir: String,
+#[command(
+    name = "huidu-player",
+    about = "Huidu LED sign player - Rust reproduction of BoxPlayer",
+    args_conflicts_with_subcommands = true,
+    after_help = "Every option of `run` (listed above) can also be set through the HUIDU_* \
+                  environment variable shown with it (e.g. HUIDU_PORT); command-line flags take \
+                  precedence. The `preview` and `validate` options are flags only."
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Options for `run`, which is also what happens without a subcommand
+    #[command(flatten)]
+    run: RunArgs,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Run the player: serve HDPlayer connections and drive the outputs (default)
+    Run(Box<RunArgs>),
+    /// Render one frame of a program file to an image
+    Preview(PreviewArgs),
+    /// Parse a program file and print structural warnings
+    Validate(ValidateArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct PreviewArgs {
+    /// Program XML file; media is looked up next to it
+    program: PathBuf,
+
+    /// Frame number to render (from the start of the program, at --fps)
+    #[arg(long, default_value_t = 0)]
+    frame: u64,
+
+    /// Which program in the file to render
+    #[arg(long, default_value_t = 0)]
+    program_index: usize,
+
+    /// Output image (.png, .jpg or .bmp)
+    #[arg(short, long, default_value = "preview.png")]
+    output: PathBuf,
+
+    /// Display width in pixels
+    #[arg(long, default_value_t = 128)]
+    width: u32,
+
+    /// Display height in pixels
+    #[arg(long, default_value_t = 64)]
+    height: u32,
+
+    /// Frames per second used to turn --frame into a time
+    #[arg(long, default_value_t = 30)]
+    fps: u32,
+}
+
+#[derive(clap::Args, Debug)]
+struct ValidateArgs {
+    /// Program XML file; media is looked up next to it
+    program: PathBuf,
 
     /// Display width in pixels
     #[arg(long, default_value_t = 128)]
@@ -27,43 +85,218 @@ struct Args {
     /// Display height in pixels
     #[arg(long, default_value_t = 64)]
     height: u32,
+}
+
+#[derive(clap::Args, Debug)]
+struct RunArgs {
+    /// Program directory path
+    #[arg(short, long, default_value = "programs", env = "HUIDU_PROGRAM_DIR")]
+    program_dir: String,
+
+    /// Read one program XML from stdin at startup instead of scanning
+    /// --program-dir (takes precedence over the directory)
+    #[arg(long, env = "HUIDU_PROGRAM_STDIN")]
+    program_stdin: bool,
+
+    /// Display width in pixels
+    #[arg(long, default_value_t = 128, env = "HUIDU_WIDTH")]
+    width: u32,
+
+    /// Display height in pixels
+    #[arg(long, default_value_t = 64, env = "HUIDU_HEIGHT")]
+    height: u32,
 
     /// TCP listen port for HDPlayer connections
-    #[arg(long, default_value_t = 10001)]
+    #[arg(long, default_value_t = 10001, env = "HUIDU_PORT")]
     port: u16,
 
     /// Target FPS
-    #[arg(long, default_value_t = 30)]
+    #[arg(long, default_value_t = 30, env = "HUIDU_FPS")]
     fps: u32,
 
-    /// Output mode: png, raw, framebuffer
-    #[arg(long, default_value = "png")]
-    output: String,
+    /// Output modes, comma separated: png, raw, mono, framebuffer
+    #[arg(long, default_value = "png", value_delimiter = ',', env = "HUIDU_OUTPUT")]
+    output: Vec<String>,
 
-    /// Output file path (for png mode)
-    #[arg(long, default_value = "output.png")]
+    /// Output file path for png mode (.png, .jpg or .bmp selects the format)
+    /// and mono mode ("-" writes mono frames to stdout)
+    #[arg(long, default_value = "output.png", env = "HUIDU_OUTPUT_PATH")]
     output_path: String,
 
+    /// Panel width when smaller than --width: frames are rendered at --width
+    /// and scaled down for every output (defaults to --width)
+    #[arg(long, env = "HUIDU_OUTPUT_WIDTH")]
+    output_width: Option<u32>,
+
+    /// Panel height when smaller than --height (defaults to --height)
+    #[arg(long, env = "HUIDU_OUTPUT_HEIGHT")]
+    output_height: Option<u32>,
+
+    /// Render this many frames and exit. In png mode every frame is saved,
+    /// numbered: output.png becomes output-0000.png, output-0001.png, ...
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..), env = "HUIDU_FRAMES")]
+    frames: Option<u64>,
+
+    /// Mono output: channel compared against the threshold (red, green, blue, luma)
+    #[arg(long, default_value = "red", env = "HUIDU_MONO_CHANNEL")]
+    mono_channel: String,
+
+    /// Mono output: channel level (0-255) at which a pixel is lit
+    #[arg(long, default_value_t = 128, env = "HUIDU_MONO_THRESHOLD")]
+    mono_threshold: u8,
+
+    /// Framebuffer output: byte order of the RGB565 panel words (little, big)
+    #[arg(long, default_value = "little", env = "HUIDU_RGB565_BYTE_ORDER")]
+    rgb565_byte_order: String,
+
+    /// Framebuffer output: devices to write frames to, comma separated; each
+    /// gets the same frame, for mirrored signs
+    #[arg(long, default_value = "/dev/fb0", value_delimiter = ',', env = "HUIDU_FB_DEVICE")]
+    fb_device: Vec<PathBuf>,
+
+    /// GPIO pins for relay outputs, in output order (e.g. 17,27)
+    #[arg(long, value_delimiter = ',', env = "HUIDU_RELAY_GPIO")]
+    relay_gpio: Vec<u32>,
+
+    /// Seconds between periodic NTP time syncs
+    #[arg(
+        long,
+        default_value_t = services::time_sync::DEFAULT_SYNC_INTERVAL_SECS,
+        env = "HUIDU_NTP_INTERVAL"
+    )]
+    ntp_interval: u64,
+
     /// Device ID for network discovery
-    #[arg(long, default_value = "RUST-001")]
+    #[arg(long, default_value = "RUST-001", env = "HUIDU_DEVICE_ID")]
     device_id: String,
 
+    /// Show a commissioning pattern instead of programs:
+    /// bars, grid, gradient or solid:#rrggbb
+    #[arg(long, env = "HUIDU_TEST_PATTERN")]
+    test_pattern: Option<String>,
+
+    /// Milliseconds over which brightness changes fade in (0 = instant)
+    #[arg(
+        long,
+        default_value_t = render::engine::DEFAULT_BRIGHTNESS_FADE_MS,
+        env = "HUIDU_BRIGHTNESS_FADE_MS"
+    )]
+    brightness_fade_ms: u64,
+
+    /// Milliseconds to cross-fade from one program to the next (0 = cut)
+    #[arg(long, default_value_t = 0, env = "HUIDU_PROGRAM_TRANSITION_MS")]
+    program_transition_ms: u64,
+
+    /// Milliseconds the boot logo stays up after startup before loaded
+    /// programs replace it (0 = until the first program)
+    #[arg(long, default_value_t = 0, env = "HUIDU_SPLASH_MS")]
+    splash_ms: u64,
+
+    /// Milliseconds to fade from the boot logo into the first program (0 = cut)
+    #[arg(long, default_value_t = 0, env = "HUIDU_SPLASH_FADE_MS")]
+    splash_fade_ms: u64,
+
+    /// Correct the panel's color cast: gain:R,G,B (e.g. gain:1,0.9,0.8)
+    /// or matrix:9 row-major values
+    #[arg(long, env = "HUIDU_COLOR_CORRECTION")]
+    color_correction: Option<String>,
+
+    /// Idle program shown while no programs are loaded: a program XML path,
+    /// or "clock" for a built-in full-screen clock
+    #[arg(long, env = "HUIDU_FALLBACK_PROGRAM")]
+    fallback_program: Option<String>,
+
+    /// Honor the FactoryReset SDK command, which wipes programs, files and
+    /// saved settings
+    #[arg(long, env = "HUIDU_ALLOW_FACTORY_RESET")]
+    allow_factory_reset: bool,
+
+    /// Log every TCP/UDP packet and SDK command under the `protocol_trace`
+    /// tracing target (file contents are logged by size only)
+    #[arg(long, env = "HUIDU_PROTOCOL_TRACE")]
+    protocol_trace: bool,
+
+    /// Largest SDK command (bytes) accepted from a client; longer ones are
+    /// refused before being buffered
+    #[arg(
+        long,
+        default_value_t = protocol::session::DEFAULT_MAX_XML_LEN,
+        env = "HUIDU_MAX_XML_LEN"
+    )]
+    max_xml_len: usize,
+
+    /// Largest file (bytes) accepted over a file transfer; bigger ones are
+    /// refused before any data is buffered
+    #[arg(
+        long,
+        default_value_t = protocol::session::DEFAULT_MAX_FILE_SIZE,
+        env = "HUIDU_MAX_FILE_SIZE"
+    )]
+    max_file_size: u64,
+
+    /// Serve /healthz and /readyz probes over HTTP on this port
+    #[arg(long, env = "HUIDU_HEALTH_PORT")]
+    health_port: Option<u16>,
+
+    /// Serve a browser status page with the current frame, the program list
+    /// and program upload on this port
+    #[arg(long, env = "HUIDU_WEB_PORT")]
+    web_port: Option<u16>,
+
+    /// Address the web UI listens on; loopback keeps it off the network
+    #[arg(long, default_value = "127.0.0.1", env = "HUIDU_WEB_BIND")]
+    web_bind: std::net::IpAddr,
+
     /// Log level
-    #[arg(long, default_value = "info")]
+    #[arg(long, default_value = "info", env = "HUIDU_LOG_LEVEL")]
     log_level: String,
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let args = Args::parse();
+    let cli = Cli::parse();
+    let log_level = match &cli.command {
+        Some(Command::Run(args)) => args.log_level.clone(),
+        Some(_) => "warn".to_string(),
+        None => cli.run.log_level.clone(),
+    };
 
     tracing_subscriber::fmt()
         .with_env_filter(
             tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| args.log_level.parse().unwrap_or_default()),
+                .unwrap_or_else(|_| log_level.parse().unwrap_or_default()),
         )
         .init();
 
+    match cli.command {
+        Some(Command::Run(args)) => run(*args).await,
+        Some(Command::Preview(args)) => {
+            preview::write_preview(
+                &args.program,
+                &args.output,
+                args.program_index,
+                args.width,
+                args.height,
+                args.fps,
+                args.frame,
+            )?;
+            println!("Wrote frame {} to {}", args.frame, args.output.display());
+            Ok(())
+        }
+        Some(Command::Validate(args)) => {
+            let warnings = preview::validate_file(&args.program, args.width, args.height)?;
+            for warning in &warnings {
+                println!("warning: {warning}");
+            }
+            println!("{}: {} warning(s)", args.program.display(), warnings.len());
+            Ok(())
+        }
+        None => run(cli.run).await,
+    }
+}
+
+/// Server mode: load programs, then serve the network protocol and render until stopped
+async fn run(args: RunArgs) -> Result<()> {
     info!(
         "huidu-player v{} starting ({}x{} @ {}fps, device={})",
         env!("CARGO_PKG_VERSION"),
@@ -73,33 +306,95 @@ async fn main() -> Result<()> {
         args.device_id,
     );
 
+    let test_pattern = match &args.test_pattern {
+        Some(pattern) => Some(pattern.parse().map_err(anyhow::Error::msg)?),
+        None => None,
+    };
+
+    let color_correction = match &args.color_correction {
+        Some(correction) => correction.parse().map_err(anyhow::Error::msg)?,
+        None => Default::default(),
+    };
+
+    let fallback_program = match args.fallback_program.as_deref() {
+        Some("clock") => Some(core::player::fallback_clock_program(args.width, args.height)?),
+        Some(path) => {
+            let screen = program::parser::parse_program_file(std::path::Path::new(path))
+                .with_context(|| format!("Failed to load fallback program {path}"))?;
+            let program = screen.programs.into_iter().next();
+            Some(program.with_context(|| format!("No program in {path}"))?)
+        }
+        None => None,
+    };
+    let fallback_media: Vec<String> = fallback_program
+        .iter()
+        .flat_map(|program| program.media_files())
+        .map(String::from)
+        .collect();
+
     let mut player = Player::new(config::PlayerConfig {
         width: args.width,
         height: args.height,
         fps: args.fps,
         program_dir: args.program_dir.clone().into(),
         port: args.port,
-        output_mode: args.output.parse().unwrap_or_default(),
+        output_modes: args
+            .output
+            .iter()
+            .map(|o| o.parse())
+            .collect::<Result<_, _>>()
+            .map_err(anyhow::Error::msg)?,
         output_path: args.output_path.clone().into(),
+        ntp_interval_secs: args.ntp_interval,
+        mono_channel: args.mono_channel.parse().map_err(anyhow::Error::msg)?,
+        mono_threshold: args.mono_threshold,
+        rgb565_byte_order: args.rgb565_byte_order.parse().map_err(anyhow::Error::msg)?,
+        framebuffer_devices: args.fb_device.clone(),
+        relay_gpios: args.relay_gpio.clone(),
+        test_pattern,
+        brightness_fade_ms: args.brightness_fade_ms,
+        program_transition_ms: args.program_transition_ms,
+        splash_ms: args.splash_ms,
+        splash_fade_ms: args.splash_fade_ms,
+        color_correction,
+        fallback_program,
+        max_frames: args.frames,
+        output_size: (args.output_width.is_some() || args.output_height.is_some()).then(|| {
+            (args.output_width.unwrap_or(args.width), args.output_height.unwrap_or(args.height))
+        }),
     });
 
-    // Load any existing programs from disk
-    if let Err(e) = player.load_programs_from_dir(&args.program_dir) {
-        warn!("No programs loaded from {}: {}", args.program_dir, e);
+    // Load any existing programs from stdin or disk (a test pattern replaces them)
+    if args.test_pattern.is_none() {
+        if args.program_stdin {
+            player
+                .load_programs_from_reader(std::io::stdin().lock())
+                .context("Failed to load program from stdin")?;
+        } else if let Err(e) = player.load_programs_from_dir(&args.program_dir) {
+            warn!("No programs loaded from {}: {}", args.program_dir, e);
+        }
     }
 
     let services = player.services();
 
     // Start the TCP protocol server
     let protocol_handle = {
-        let tx = player.program_sender();
         let port = args.port;
-        let dir = args.program_dir.clone();
-        let svc = services.clone();
-        let w = args.width;
-        let h = args.height;
+        let ctx = protocol::command::HandlerContext {
+            player_tx: player.program_sender(),
+            program_dir: args.program_dir.clone(),
+            services: services.clone(),
+            screen_width: args.width,
+            screen_height: args.height,
+            device_id: args.device_id.clone(),
+            allow_factory_reset: args.allow_factory_reset,
+            protocol_trace: args.protocol_trace,
+            max_xml_len: args.max_xml_len,
+            max_file_size: args.max_file_size,
+            fallback_media,
+        };
         tokio::spawn(async move {
-            if let Err(e) = protocol::server::run(port, tx, dir, svc, w, h).await {
+            if let Err(e) = protocol::server::run(port, ctx).await {
                 tracing::error!("Protocol server error: {}", e);
             }
         })
@@ -113,15 +408,39 @@ async fn main() -> Result<()> {
             ip_address: ip,
             screen_width: args.width as u16,
             screen_height: args.height as u16,
-            player_name: "BoxPlayer".to_string(),
         };
+        let name = services.read().await.device_name.clone();
+        let lock = services.read().await.lock.clone();
+        let trace = args.protocol_trace;
         tokio::spawn(async move {
-            if let Err(e) = protocol::discovery::run(device_info).await {
+            if let Err(e) = protocol::discovery::run(device_info, name, lock, trace).await {
                 tracing::error!("UDP discovery error: {}", e);
             }
         })
     };
 
+    // Health probes for orchestration
+    if let Some(port) = args.health_port {
+        let health = player.health();
+        tokio::spawn(async move {
+            if let Err(e) = core::health::run(port, health).await {
+                tracing::error!("Health probe server error: {}", e);
+            }
+        });
+    }
+
+    // Browser UI for operators without HDPlayer
+    if let Some(port) = args.web_port {
+        let addr = std::net::SocketAddr::new(args.web_bind, port);
+        let player_tx = player.program_sender();
+        let services = services.clone();
+        tokio::spawn(async move {
+            if let Err(e) = protocol::web::run(addr, player_tx, services).await {
+                tracing::error!("Web UI server error: {}", e);
+            }
+        });
+    }
+
     // Start background services (scheduling, NTP, USB disk)
     let program_dir = args.program_dir.clone().into();
     services::manager::start_services(services, player.program_sender(), program_dir).await;
@@ -134,3 +453,64 @@ async fn main() -> Result<()> {
     info!("huidu-player shutdown");
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_env_bindings() {
+        use clap::CommandFactory;
+
+        // Inspect the bindings instead of setting process-wide variables,
+        // which would race with other tests
+        let cli = Cli::command();
+        let env = |id: &str| {
+            let arg = cli.get_arguments().find(|a| a.get_id() == id).unwrap();
+            arg.get_env().map(|e| e.to_string_lossy().into_owned())
+        };
+        assert_eq!(env("port").as_deref(), Some("HUIDU_PORT"));
+        assert_eq!(env("output").as_deref(), Some("HUIDU_OUTPUT"));
+        assert_eq!(env("device_id").as_deref(), Some("HUIDU_DEVICE_ID"));
+        assert_eq!(env("program_stdin").as_deref(), Some("HUIDU_PROGRAM_STDIN"));
+        assert_eq!(env("frames").as_deref(), Some("HUIDU_FRAMES"));
+        // As the help promises for every `run` option
+        let unbound: Vec<_> =
+            cli.get_arguments().filter(|a| a.get_env().is_none()).map(|a| a.get_id()).collect();
+        assert!(unbound.is_empty(), "no HUIDU_* variable: {unbound:?}");
+
+        let args = Cli::try_parse_from(["huidu-player", "--port", "10005", "--output", "raw,mono"])
+            .unwrap()
+            .run;
+        assert_eq!(args.port, 10005);
+        assert_eq!(args.output, ["raw", "mono"]);
+        assert_eq!(args.width, 128);
+    }
+
+    #[test]
+    fn test_frames_must_be_positive() {
+        // Zero frames would still render one before the limit is checked
+        assert!(Cli::try_parse_from(["huidu-player", "--frames", "0"]).is_err());
+        let args = Cli::try_parse_from(["huidu-player", "--frames", "3"]).unwrap().run;
+        assert_eq!(args.frames, Some(3));
+    }
+
+    #[test]
+    fn test_subcommands_parse() {
+        let cli = Cli::try_parse_from(["huidu-player", "run", "--width", "64"]).unwrap();
+        assert!(matches!(cli.command, Some(Command::Run(ref args)) if args.width == 64));
+
+        let cli = Cli::try_parse_from([
+            "huidu-player", "preview", "program.xml", "--frame", "5", "-o", "out.png",
+        ])
+        .unwrap();
+        let Some(Command::Preview(args)) = cli.command else {
+            panic!("expected preview");
+        };
+        assert_eq!(args.frame, 5);
+        assert_eq!(args.output, PathBuf::from("out.png"));
+
+        // Server options belong to run, not to the other subcommands
+        assert!(Cli::try_parse_from(["huidu-player", "--port", "1", "validate", "p.xml"]).is_err());
+    }
+}
@@ -0,0 +1,282 @@
+/// Packed program bundles (`.hdp`).
+/// Some HDPlayer versions export a program as one file: a ZIP archive holding
+/// the program XML and the media it references. Bundles are unpacked into the
+/// program directory, flattened, so the XML finds its media by plain filename.
+use anyhow::{bail, Context, Result};
+use flate2::read::DeflateDecoder;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use tracing::{debug, info};
+
+use super::model::Screen;
+use super::parser;
+
+/// Every bundle starts with a ZIP local file header
+const LOCAL_HEADER_MAGIC: &[u8; 4] = b"PK\x03\x04";
+const CENTRAL_HEADER_MAGIC: u32 = 0x0201_4b50;
+const END_OF_DIRECTORY_MAGIC: u32 = 0x0605_4b50;
+/// Size of the end-of-central-directory record without its comment
+const END_OF_DIRECTORY_LEN: usize = 22;
+
+/// Compression methods HDPlayer uses
+const METHOD_STORED: u16 = 0;
+const METHOD_DEFLATE: u16 = 8;
+
+/// Most a single bundle may unpack to, guarding against ZIP bombs
+const MAX_UNPACKED_SIZE: u64 = 512 * 1024 * 1024;
+
+/// Whether `path` is a program bundle: a `.hdp` (or `.zip`) file that starts
+/// with the ZIP magic
+pub fn is_bundle(path: &Path) -> bool {
+    let by_extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case("hdp") || e.eq_ignore_ascii_case("zip"));
+    if !by_extension {
+        return false;
+    }
+    let mut magic = [0u8; 4];
+    std::fs::File::open(path).and_then(|mut f| f.read_exact(&mut magic)).is_ok()
+        && &magic == LOCAL_HEADER_MAGIC
+}
+
+/// Unpack `bundle` into `program_dir`, overwriting files of the same name.
+/// Returns the paths written.
+pub fn unpack(bundle: &Path, program_dir: &Path) -> Result<Vec<PathBuf>> {
+    let data = std::fs::read(bundle)
+        .with_context(|| format!("Failed to read bundle: {}", bundle.display()))?;
+    let entries = read_entries(&data)
+        .with_context(|| format!("Invalid program bundle: {}", bundle.display()))?;
+
+    std::fs::create_dir_all(program_dir)?;
+    let mut written = Vec::new();
+    let mut unpacked: u64 = 0;
+    for entry in entries {
+        let Some(name) = entry.file_name() else {
+            debug!("Skipping bundle entry {}", entry.name);
+            continue;
+        };
+        unpacked += entry.size;
+        if unpacked > MAX_UNPACKED_SIZE {
+            bail!("Bundle {} unpacks to more than {} bytes", bundle.display(), MAX_UNPACKED_SIZE);
+        }
+        let contents = entry.contents(&data)?;
+        let dest = program_dir.join(name);
+        std::fs::write(&dest, contents)
+            .with_context(|| format!("Failed to write {}", dest.display()))?;
+        written.push(dest);
+    }
+    info!("Unpacked {} file(s) from {}", written.len(), bundle.display());
+    Ok(written)
+}
+
+/// Unpack `bundle` into `program_dir` and parse the program XML it contained.
+/// Returns the programs with the XML they were parsed from.
+pub fn load(bundle: &Path, program_dir: &Path) -> Result<(Screen, String)> {
+    let files = unpack(bundle, program_dir)?;
+    let path = files
+        .iter()
+        .find(|f| f.extension().is_some_and(|e| e.eq_ignore_ascii_case("xml")))
+        .with_context(|| format!("No program XML in bundle {}", bundle.display()))?;
+    let bytes = std::fs::read(path)
+        .with_context(|| format!("Failed to read program file: {}", path.display()))?;
+    let xml = parser::decode_xml_bytes(&bytes);
+    Ok((parser::parse_program_xml(&xml)?, xml))
+}
+
+/// One file in the archive's central directory
+struct Entry {
+    name: String,
+    method: u16,
+    crc32: u32,
+    compressed_size: u64,
+    size: u64,
+    local_header_offset: usize,
+}
+
+impl Entry {
+    /// Name to unpack as: the last path component. None for directories and
+    /// names that can't be a plain file.
+    fn file_name(&self) -> Option<&str> {
+        if self.name.ends_with('/') {
+            return None;
+        }
+        let name = self.name.rsplit(['/', '\\']).next()?;
+        let plain = !name.is_empty()
+            && name != "."
+            && name != ".."
+            && !name.chars().any(char::is_control);
+        plain.then_some(name)
+    }
+
+    /// Decompressed contents, checked against the stored size and CRC
+    fn contents(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let header = data.get(self.local_header_offset..).context("Entry offset out of range")?;
+        if !header.starts_with(LOCAL_HEADER_MAGIC) || header.len() < 30 {
+            bail!("Bad local header for {}", self.name);
+        }
+        let start = 30 + le_u16(header, 26) as usize + le_u16(header, 28) as usize;
+        let compressed = header
+            .get(start..start + self.compressed_size as usize)
+            .with_context(|| format!("Truncated data for {}", self.name))?;
+
+        let contents = match self.method {
+            METHOD_STORED => compressed.to_vec(),
+            METHOD_DEFLATE => {
+                let mut out = Vec::new();
+                DeflateDecoder::new(compressed)
+                    .take(self.size + 1)
+                    .read_to_end(&mut out)
+                    .with_context(|| format!("Failed to inflate {}", self.name))?;
+                out
+            }
+            method => bail!("Unsupported compression method {} for {}", method, self.name),
+        };
+        if contents.len() as u64 != self.size {
+            bail!("Size mismatch for {}", self.name);
+        }
+        let mut crc = flate2::Crc::new();
+        crc.update(&contents);
+        if crc.sum() != self.crc32 {
+            bail!("CRC mismatch for {}", self.name);
+        }
+        Ok(contents)
+    }
+}
+
+/// Read the central directory at the end of the archive
+fn read_entries(data: &[u8]) -> Result<Vec<Entry>> {
+    if data.len() < END_OF_DIRECTORY_LEN {
+        bail!("Too short for a ZIP archive");
+    }
+    // The end record sits before a comment of up to 64 KiB
+    let earliest = data.len().saturating_sub(END_OF_DIRECTORY_LEN + u16::MAX as usize);
+    let end = (earliest..=data.len() - END_OF_DIRECTORY_LEN)
+        .rev()
+        .find(|&i| le_u32(data, i) == END_OF_DIRECTORY_MAGIC)
+        .context("No ZIP end of central directory")?;
+    let count = le_u16(data, end + 10) as usize;
+    let mut pos = le_u32(data, end + 16) as usize;
+
+    let mut entries = Vec::with_capacity(count);
+    for _ in 0..count {
+        if pos + 46 > data.len() || le_u32(data, pos) != CENTRAL_HEADER_MAGIC {
+            bail!("Corrupt central directory");
+        }
+        let name_len = le_u16(data, pos + 28) as usize;
+        let extra_len = le_u16(data, pos + 30) as usize;
+        let comment_len = le_u16(data, pos + 32) as usize;
+        let name = data.get(pos + 46..pos + 46 + name_len).context("Truncated entry name")?;
+        entries.push(Entry {
+            name: String::from_utf8_lossy(name).into_owned(),
+            method: le_u16(data, pos + 10),
+            crc32: le_u32(data, pos + 16),
+            compressed_size: le_u32(data, pos + 20) as u64,
+            size: le_u32(data, pos + 24) as u64,
+            local_header_offset: le_u32(data, pos + 42) as usize,
+        });
+        pos += 46 + name_len + extra_len + comment_len;
+    }
+    Ok(entries)
+}
+
+fn le_u16(data: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([data[at], data[at + 1]])
+}
+
+fn le_u32(data: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(data[at..at + 4].try_into().unwrap())
+}
+
+/// Minimal ZIP writer for tests: (name, contents, deflate?) entries
+#[cfg(test)]
+pub fn zip(files: &[(&str, &[u8], bool)]) -> Vec<u8> {
+    use std::io::Write;
+
+    let mut out = Vec::new();
+    let mut central = Vec::new();
+    for &(name, contents, deflate) in files {
+        let body = if deflate {
+            let mut encoder =
+                flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(contents).unwrap();
+            encoder.finish().unwrap()
+        } else {
+            contents.to_vec()
+        };
+        let mut crc = flate2::Crc::new();
+        crc.update(contents);
+        let method: u16 = if deflate { METHOD_DEFLATE } else { METHOD_STORED };
+        let offset = out.len() as u32;
+
+        // Fields shared by the local and central headers, from "method" on
+        let mut common = Vec::new();
+        common.extend_from_slice(&method.to_le_bytes());
+        common.extend_from_slice(&[0; 4]); // mod time and date
+        common.extend_from_slice(&crc.sum().to_le_bytes());
+        common.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        common.extend_from_slice(&(contents.len() as u32).to_le_bytes());
+        common.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        common.extend_from_slice(&[0; 2]); // extra length
+
+        out.extend_from_slice(LOCAL_HEADER_MAGIC);
+        out.extend_from_slice(&[20, 0, 0, 0]); // version, flags
+        out.extend_from_slice(&common);
+        out.extend_from_slice(name.as_bytes());
+        out.extend_from_slice(&body);
+
+        central.extend_from_slice(&CENTRAL_HEADER_MAGIC.to_le_bytes());
+        central.extend_from_slice(&[20, 0, 20, 0, 0, 0]); // versions, flags
+        central.extend_from_slice(&common);
+        central.extend_from_slice(&[0; 6]); // comment length, disk, internal attrs
+        central.extend_from_slice(&[0; 4]); // external attrs
+        central.extend_from_slice(&offset.to_le_bytes());
+        central.extend_from_slice(name.as_bytes());
+    }
+    let central_offset = out.len() as u32;
+    out.extend_from_slice(&central);
+    out.extend_from_slice(&END_OF_DIRECTORY_MAGIC.to_le_bytes());
+    out.extend_from_slice(&[0; 4]); // disk numbers
+    out.extend_from_slice(&(files.len() as u16).to_le_bytes());
+    out.extend_from_slice(&(files.len() as u16).to_le_bytes());
+    out.extend_from_slice(&(central.len() as u32).to_le_bytes());
+    out.extend_from_slice(&central_offset.to_le_bytes());
+    out.extend_from_slice(&[0; 2]); // comment length
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unpack_bundle_into_program_dir() {
+        let xml = br#"<screen><program guid="b1" name="Bundled"><area guid="a">
+            <rectangle width="32" height="16"/>
+            <resources><image guid="i"><file name="logo.png"/></image></resources>
+            </area></program></screen>"#;
+        let src = tempfile::tempdir().unwrap();
+        let bundle = src.path().join("sale.hdp");
+        let data = zip(&[
+            ("program/program.xml", xml, true),
+            ("program/media/", b"", false),
+            ("program/media/logo.png", b"\x89PNG fake", false),
+            ("../escape.txt", b"flattened", false),
+        ]);
+        std::fs::write(&bundle, data).unwrap();
+        assert!(is_bundle(&bundle));
+
+        let dir = tempfile::tempdir().unwrap();
+        let (screen, loaded) = load(&bundle, dir.path()).unwrap();
+        assert_eq!(screen.programs[0].guid, "b1");
+        assert_eq!(loaded.as_bytes(), xml);
+        assert_eq!(std::fs::read(dir.path().join("program.xml")).unwrap(), xml);
+        assert_eq!(std::fs::read(dir.path().join("logo.png")).unwrap(), b"\x89PNG fake");
+        assert_eq!(std::fs::read(dir.path().join("escape.txt")).unwrap(), b"flattened");
+
+        // Plain files with a bundle extension aren't mistaken for bundles
+        let fake = src.path().join("notes.hdp");
+        std::fs::write(&fake, "<screen/>").unwrap();
+        assert!(!is_bundle(&fake));
+    }
+}
@@ -1,2 +1,3 @@
+pub mod bundle;
 pub mod model;
 pub mod parser;
@@ -1,6 +1,6 @@
 /// Data model for Huidu program format.
 /// Based on reverse-engineering of BoxPlayer binaries and firmware XML analysis.
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 /// Root element — a screen contains one or more programs
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -21,12 +21,15 @@ pub struct Program {
     #[serde(rename = "@type", default = "default_program_type")]
     pub program_type: String,
     #[serde(rename = "@flag", default)]
-    pub flag: String,
-    #[serde(rename = "border")]
+    pub flag: ProgramFlags,
+    /// Whole-program opacity (0-255), applied to the composited frame
+    #[serde(rename = "@alpha", default = "default_alpha")]
+    pub alpha: u8,
+    #[serde(rename = "border", skip_serializing_if = "Option::is_none")]
     pub border: Option<Border>,
-    #[serde(rename = "backgroundMusic")]
+    #[serde(rename = "backgroundMusic", skip_serializing_if = "Option::is_none")]
     pub background_music: Option<BackgroundMusic>,
-    #[serde(rename = "playControl")]
+    #[serde(rename = "playControl", skip_serializing_if = "Option::is_none")]
     pub play_control: Option<PlayControl>,
     #[serde(rename = "area", default)]
     pub areas: Vec<Area>,
@@ -36,6 +39,86 @@ fn default_program_type() -> String {
     "normal".to_string()
 }
 
+impl Program {
+    /// Names of the files in the program directory this program uses: content
+    /// media, area background images and background music
+    pub fn media_files(&self) -> impl Iterator<Item = &str> {
+        let content = self.areas.iter().flat_map(|area| {
+            let items = area.resources.items.iter().filter_map(|item| item.file());
+            items.map(|f| f.name.as_str()).chain(area.bg_image.as_deref())
+        });
+        let music = self.background_music.iter().flat_map(|m| &m.files);
+        content.chain(music.map(|f| f.name.as_str()))
+    }
+}
+
+/// Program `@flag` property bits. HDPlayer writes either a number or names
+/// ("default", "locked") separated by commas, pipes or spaces. The raw value is
+/// kept so the attribute round-trips unchanged.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProgramFlags {
+    raw: String,
+    bits: u32,
+}
+
+impl ProgramFlags {
+    /// Boot/default program: displayed first on startup
+    pub const DEFAULT: u32 = 0x1;
+    /// Locked against editing from the panel
+    pub const LOCKED: u32 = 0x2;
+
+    pub fn parse(raw: &str) -> Self {
+        let trimmed = raw.trim();
+        let numeric = match trimmed.strip_prefix("0x").or_else(|| trimmed.strip_prefix("0X")) {
+            Some(hex) => u32::from_str_radix(hex, 16).ok(),
+            None => trimmed.parse().ok(),
+        };
+        let bits = numeric.unwrap_or_else(|| {
+            trimmed
+                .split([',', '|', ' '])
+                .map(|name| match name.to_lowercase().as_str() {
+                    "default" | "boot" => Self::DEFAULT,
+                    "locked" | "lock" => Self::LOCKED,
+                    _ => 0,
+                })
+                .fold(0, |acc, bit| acc | bit)
+        });
+        Self {
+            raw: raw.to_string(),
+            bits,
+        }
+    }
+
+    pub fn contains(&self, bit: u32) -> bool {
+        self.bits & bit != 0
+    }
+
+    pub fn is_default(&self) -> bool {
+        self.contains(Self::DEFAULT)
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.contains(Self::LOCKED)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.raw
+    }
+}
+
+impl Serialize for ProgramFlags {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.raw)
+    }
+}
+
+impl<'de> Deserialize<'de> for ProgramFlags {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let raw = String::deserialize(deserializer)?;
+        Ok(Self::parse(&raw))
+    }
+}
+
 /// Border/neon effect around the display
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Border {
@@ -50,10 +133,31 @@ pub struct Border {
 /// Background music track list
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BackgroundMusic {
+    /// Volume (0-100) for this program's music; None uses the device volume
+    #[serde(rename = "@volume", default, skip_serializing_if = "Option::is_none")]
+    pub volume: Option<u32>,
+    /// Start the track list over when it ends, rather than playing it once
+    #[serde(rename = "@loop", default = "default_music_loop")]
+    pub loop_playback: bool,
     #[serde(rename = "file", default)]
     pub files: Vec<FileRef>,
 }
 
+/// Highest background music volume
+pub const MAX_MUSIC_VOLUME: u32 = 100;
+
+fn default_music_loop() -> bool {
+    true
+}
+
+impl BackgroundMusic {
+    /// Volume to play at: the program's own (capped at `MAX_MUSIC_VOLUME`),
+    /// falling back to the device's `global` volume
+    pub fn effective_volume(&self, global: u32) -> u32 {
+        self.volume.map_or(global, |v| v.min(MAX_MUSIC_VOLUME))
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FileRef {
     #[serde(rename = "@name")]
@@ -69,11 +173,11 @@ pub struct PlayControl {
     pub count: u32,
     #[serde(rename = "@disabled", default)]
     pub disabled: bool,
-    #[serde(rename = "date")]
+    #[serde(rename = "date", skip_serializing_if = "Option::is_none")]
     pub date: Option<DateRange>,
-    #[serde(rename = "time")]
+    #[serde(rename = "time", skip_serializing_if = "Option::is_none")]
     pub time: Option<TimeRange>,
-    #[serde(rename = "week")]
+    #[serde(rename = "week", skip_serializing_if = "Option::is_none")]
     pub week: Option<WeekFilter>,
 }
 
@@ -108,8 +212,26 @@ pub struct Area {
     pub name: String,
     #[serde(rename = "@alpha", default = "default_alpha")]
     pub alpha: u8,
+    /// Compositing order; higher values are drawn on top, ties keep document order
+    #[serde(rename = "@zIndex", default)]
+    pub z_index: i32,
+    /// Animates the opacity: none, blink (on/off) or pulse (smooth fade)
+    #[serde(rename = "@alphaEffect", default, skip_serializing_if = "Option::is_none")]
+    pub alpha_effect: Option<String>,
+    /// Alpha effect cycles per 10 seconds (default 10, one per second)
+    #[serde(rename = "@alphaSpeed", default, skip_serializing_if = "Option::is_none")]
+    pub alpha_speed: Option<u32>,
+    /// Static backdrop (#RRGGBB) drawn under the content
+    #[serde(rename = "@bgColor", default, skip_serializing_if = "Option::is_none")]
+    pub bg_color: Option<String>,
+    /// Static backdrop image, stretched to the area, drawn over `bg_color`
+    #[serde(rename = "@bgImage", default, skip_serializing_if = "Option::is_none")]
+    pub bg_image: Option<String>,
+    /// Radius (px) of rounded corners; the area is clipped to the rounded shape
+    #[serde(rename = "@cornerRadius", default, skip_serializing_if = "Option::is_none")]
+    pub corner_radius: Option<u32>,
     pub rectangle: Rectangle,
-    #[serde(rename = "border")]
+    #[serde(rename = "border", skip_serializing_if = "Option::is_none")]
     pub border: Option<Border>,
     pub resources: Resources,
 }
@@ -132,13 +254,29 @@ pub struct Rectangle {
 }
 
 /// Container for content items within an area
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize)]
 pub struct Resources {
     #[serde(rename = "$value", default)]
     pub items: Vec<ContentItem>,
 }
 
+/// Resources as written, before text `<line>` lists are split into messages
+#[derive(Deserialize)]
+struct RawResources {
+    #[serde(rename = "$value", default)]
+    items: Vec<ContentItem>,
+}
+
+impl<'de> Deserialize<'de> for Resources {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let raw = RawResources::deserialize(deserializer)?;
+        let items = raw.items.into_iter().flat_map(ContentItem::split_lines).collect();
+        Ok(Self { items })
+    }
+}
+
 /// A content item — the actual thing displayed in an area
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub enum ContentItem {
@@ -154,6 +292,115 @@ pub enum ContentItem {
     Gif(GifContent),
 }
 
+/// Content type of an item, without its data
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ContentKind {
+    Image,
+    Video,
+    Text,
+    Clock,
+    Gif,
+}
+
+impl ContentKind {
+    /// XML element name of items of this kind
+    pub fn name(self) -> &'static str {
+        match self {
+            ContentKind::Image => "image",
+            ContentKind::Video => "video",
+            ContentKind::Text => "text",
+            ContentKind::Clock => "clock",
+            ContentKind::Gif => "gif",
+        }
+    }
+}
+
+impl ContentItem {
+    pub fn kind(&self) -> ContentKind {
+        match self {
+            ContentItem::Image(_) => ContentKind::Image,
+            ContentItem::Video(_) => ContentKind::Video,
+            ContentItem::Text(_) => ContentKind::Text,
+            ContentItem::Clock(_) => ContentKind::Clock,
+            ContentItem::Gif(_) => ContentKind::Gif,
+        }
+    }
+
+    pub fn guid(&self) -> &str {
+        match self {
+            ContentItem::Image(i) => &i.guid,
+            ContentItem::Video(v) => &v.guid,
+            ContentItem::Text(t) => &t.guid,
+            ContentItem::Clock(c) => &c.guid,
+            ContentItem::Gif(g) => &g.guid,
+        }
+    }
+
+    /// Transition effect, for content types that carry one
+    pub fn effect(&self) -> Option<&Effect> {
+        match self {
+            ContentItem::Image(i) => i.effect.as_ref(),
+            ContentItem::Text(t) => t.effect.as_ref(),
+            ContentItem::Gif(g) => g.effect.as_ref(),
+            ContentItem::Video(_) | ContentItem::Clock(_) => None,
+        }
+    }
+
+    /// Delay before the item first appears (`@startDelay`, ms)
+    pub fn start_delay_ms(&self) -> u64 {
+        match self {
+            ContentItem::Image(i) => i.start_delay_ms,
+            ContentItem::Video(v) => v.start_delay_ms,
+            ContentItem::Text(t) => t.start_delay_ms,
+            ContentItem::Clock(c) => c.start_delay_ms,
+            ContentItem::Gif(g) => g.start_delay_ms,
+        }
+    }
+
+    /// Number of content loops to play before advancing, for items that set one
+    pub fn loops(&self) -> Option<u32> {
+        let loops = match self {
+            ContentItem::Gif(g) => g.loops,
+            ContentItem::Video(v) => v.loops,
+            _ => None,
+        };
+        loops.filter(|&n| n > 0)
+    }
+
+    /// Media file referenced by this item, if any
+    pub fn file(&self) -> Option<&FileRef> {
+        match self {
+            ContentItem::Image(i) => Some(&i.file),
+            ContentItem::Video(v) => Some(&v.file),
+            ContentItem::Gif(g) => Some(&g.file),
+            ContentItem::Text(_) | ContentItem::Clock(_) => None,
+        }
+    }
+
+    /// The items this one plays as: a text with `<line>` messages becomes one
+    /// text per message (GUIDs suffixed `#1`, `#2`, ...), sharing its font,
+    /// style and effect. Everything else plays as itself.
+    fn split_lines(self) -> Vec<ContentItem> {
+        match self {
+            ContentItem::Text(mut text) if !text.lines.is_empty() => {
+                let lines = std::mem::take(&mut text.lines);
+                lines
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, line)| {
+                        ContentItem::Text(TextContent {
+                            guid: format!("{}#{}", text.guid, i + 1),
+                            string: Some(line),
+                            ..text.clone()
+                        })
+                    })
+                    .collect()
+            }
+            item => vec![item],
+        }
+    }
+}
+
 /// Transition/animation effect
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Effect {
@@ -222,9 +469,24 @@ pub struct ImageContent {
     pub guid: String,
     #[serde(rename = "@name", default)]
     pub name: String,
+    /// Wait this long (ms) before the item first appears in its area
+    #[serde(rename = "@startDelay", default)]
+    pub start_delay_ms: u64,
     /// fill, center, stretch, tile
     #[serde(rename = "@fit", default = "default_fit")]
     pub fit: String,
+    /// Crop anchor for `fill`: center, top, bottom, left, right, topleft,
+    /// topright, bottomleft, bottomright
+    #[serde(rename = "@align", default = "default_align")]
+    pub align: String,
+    /// Scaling filter: bilinear (smooth, for photos) or nearest (crisp, for
+    /// pixel art and logos)
+    #[serde(rename = "@filter", default = "default_filter")]
+    pub filter: String,
+    /// Color of the margins left by `fit` (#RRGGBB); transparent when absent
+    #[serde(rename = "@padColor", default, skip_serializing_if = "Option::is_none")]
+    pub pad_color: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub effect: Option<Effect>,
     pub file: FileRef,
 }
@@ -233,30 +495,98 @@ fn default_fit() -> String {
     "stretch".to_string()
 }
 
+fn default_filter() -> String {
+    "bilinear".to_string()
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VideoContent {
     #[serde(rename = "@guid")]
     pub guid: String,
     #[serde(rename = "@name", default)]
     pub name: String,
+    /// Wait this long (ms) before the item first appears in its area
+    #[serde(rename = "@startDelay", default)]
+    pub start_delay_ms: u64,
+    /// Legacy switch: true behaves like `fit="fit"`, false like `fit="stretch"`
     #[serde(rename = "@aspectRatio", default)]
     pub aspect_ratio: bool,
+    /// fit, fill, center, stretch; overrides `aspect_ratio` when present
+    #[serde(rename = "@fit", default, skip_serializing_if = "Option::is_none")]
+    pub fit: Option<String>,
+    /// Color of the letterbox bars when the frame doesn't cover the area (#RRGGBB)
+    #[serde(rename = "@letterboxColor", default = "default_letterbox_color")]
+    pub letterbox_color: String,
+    /// Play this many full loops of the video, then advance (overrides the duration)
+    #[serde(rename = "@loops", default, skip_serializing_if = "Option::is_none")]
+    pub loops: Option<u32>,
     pub file: FileRef,
 }
 
+impl VideoContent {
+    /// Effective fit mode, falling back to the `aspectRatio` flag
+    pub fn fit_mode(&self) -> &str {
+        match &self.fit {
+            Some(fit) => fit,
+            None if self.aspect_ratio => "fit",
+            None => "stretch",
+        }
+    }
+}
+
+fn default_letterbox_color() -> String {
+    "#000000".to_string()
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TextContent {
     #[serde(rename = "@guid")]
     pub guid: String,
     #[serde(rename = "@name", default)]
     pub name: String,
+    /// Wait this long (ms) before the item first appears in its area
+    #[serde(rename = "@startDelay", default)]
+    pub start_delay_ms: u64,
     #[serde(rename = "@singleLine", default)]
     pub single_line: bool,
     #[serde(rename = "@background", default)]
     pub background: String,
+    /// Single-line scroll speed level (0=fastest, 8=slowest), as for effects
+    #[serde(rename = "@scrollSpeed", default = "default_scroll_speed")]
+    pub scroll_speed: u8,
+    /// Single-line scroll direction: "left" (right-to-left) or "right"
+    #[serde(rename = "@scrollDir", default = "default_scroll_dir")]
+    pub scroll_dir: String,
+    /// Single-line texts with the same group id scroll as one ticker across
+    /// their areas, sharing one offset and cycle
+    #[serde(rename = "@scrollGroup", default, skip_serializing_if = "Option::is_none")]
+    pub scroll_group: Option<String>,
+    /// Hold single-line scrolling this long (ms) with the start of the text
+    /// flush against the leading edge, and again with its end flush against the
+    /// trailing edge. 0 scrolls continuously through the area.
+    #[serde(rename = "@scrollPauseMs", default)]
+    pub scroll_pause_ms: u64,
+    /// Single-line text wider than the area: "scroll", "ellipsis" (cut with "…")
+    /// or "clip"
+    #[serde(rename = "@overflow", default = "default_overflow")]
+    pub overflow: String,
+    /// Blink the text on and off, for urgent messages
+    #[serde(rename = "@flash", default)]
+    pub flash: bool,
+    /// Blinks per 10 seconds (default 10, one per second)
+    #[serde(rename = "@flashSpeed", default = "default_flash_speed")]
+    pub flash_speed: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub effect: Option<Effect>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub style: Option<TextStyle>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub string: Option<String>,
+    /// Ticker messages, each shown as its own item in the area's rotation
+    /// rather than wrapped together. Split out of the item while parsing.
+    #[serde(rename = "line", default, skip_serializing_if = "Vec::is_empty")]
+    pub lines: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub font: Option<FontSpec>,
 }
 
@@ -270,6 +600,41 @@ pub struct TextStyle {
     pub valign: String,
 }
 
+fn default_scroll_speed() -> u8 {
+    3 // 50 px/sec
+}
+
+fn default_scroll_dir() -> String {
+    "left".to_string()
+}
+
+fn default_overflow() -> String {
+    "scroll".to_string()
+}
+
+fn default_flash_speed() -> u32 {
+    10
+}
+
+impl TextContent {
+    /// Whether the text scrolls (single-line with scroll overflow), so it
+    /// changes over time
+    pub fn scrolls(&self) -> bool {
+        self.single_line && self.overflow == "scroll"
+    }
+
+    /// How long (ms) flashing text stays shown, and then hidden, per blink
+    pub fn flash_half_period_ms(&self) -> u64 {
+        (5_000 / self.flash_speed.max(1) as u64).max(1)
+    }
+
+    /// Whether the text is drawn at `elapsed_ms`: always, unless it flashes
+    /// and is in the hidden half of a blink
+    pub fn flash_visible(&self, elapsed_ms: u64) -> bool {
+        !self.flash || (elapsed_ms / self.flash_half_period_ms()).is_multiple_of(2)
+    }
+}
+
 fn default_align() -> String {
     "center".to_string()
 }
@@ -291,8 +656,20 @@ pub struct FontSpec {
     pub italic: bool,
     #[serde(rename = "@underline", default)]
     pub underline: bool,
+    /// Drop shadow (#RRGGBB) drawn behind the text; None for no shadow
+    #[serde(rename = "@shadowColor", default, skip_serializing_if = "Option::is_none")]
+    pub shadow_color: Option<String>,
+    /// Shadow offset (px) right of the text
+    #[serde(rename = "@shadowOffsetX", default = "default_shadow_offset")]
+    pub shadow_offset_x: i32,
+    /// Shadow offset (px) below the text
+    #[serde(rename = "@shadowOffsetY", default = "default_shadow_offset")]
+    pub shadow_offset_y: i32,
 }
 
+fn default_shadow_offset() -> i32 {
+    1
+}
 fn default_font_name() -> String {
     "Arial".to_string()
 }
@@ -309,6 +686,9 @@ pub struct ClockContent {
     pub guid: String,
     #[serde(rename = "@name", default)]
     pub name: String,
+    /// Wait this long (ms) before the item first appears in its area
+    #[serde(rename = "@startDelay", default)]
+    pub start_delay_ms: u64,
     /// digital or dial
     #[serde(rename = "@type", default = "default_clock_type")]
     pub clock_type: String,
@@ -316,11 +696,15 @@ pub struct ClockContent {
     pub timezone: String,
     #[serde(rename = "@adjust", default)]
     pub adjust: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub title: Option<ClockField>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub date: Option<ClockField>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub week: Option<ClockField>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub time: Option<ClockField>,
-    #[serde(rename = "lunarCalendar")]
+    #[serde(rename = "lunarCalendar", skip_serializing_if = "Option::is_none")]
     pub lunar_calendar: Option<ClockField>,
 }
 
@@ -350,7 +734,14 @@ pub struct GifContent {
     pub guid: String,
     #[serde(rename = "@name", default)]
     pub name: String,
+    /// Wait this long (ms) before the item first appears in its area
+    #[serde(rename = "@startDelay", default)]
+    pub start_delay_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub effect: Option<Effect>,
+    /// Play this many full loops of the animation, then advance (overrides the duration)
+    #[serde(rename = "@loops", default, skip_serializing_if = "Option::is_none")]
+    pub loops: Option<u32>,
     pub file: FileRef,
 }
 
@@ -1,17 +1,155 @@
 /// XML program file parser.
 /// Parses program XML from HDPlayer into our data model.
 use anyhow::{Context, Result};
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8};
 use quick_xml::de::from_str;
+use std::fmt;
 use std::path::Path;
-use tracing::info;
+use tracing::{info, warn};
 
-use super::model::Screen;
+use super::model::{Program, Screen, MAX_MUSIC_VOLUME};
 
-/// Parse a program XML file from disk
+/// Highest effect type defined by the Huidu protocol
+const MAX_EFFECT_TYPE: u8 = 29;
+
+/// A structural problem found by `validate_program_xml`.
+/// Warnings don't stop a program from loading, but it will likely not display as intended.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValidationWarning {
+    /// The screen contains no programs
+    NoPrograms,
+    /// A program has no areas
+    NoAreas { program: String },
+    /// An area has zero width or height
+    ZeroSizeArea { program: String, area: String },
+    /// An area extends past the screen edges
+    AreaOutOfBounds { program: String, area: String },
+    /// An area has no content items
+    EmptyArea { program: String, area: String },
+    /// A referenced media file is not in the program directory
+    MissingFile { program: String, item: String, file: String },
+    /// An effect index outside 0-29
+    UnknownEffect { program: String, item: String, effect: u8 },
+    /// A background music volume above 100 (played at 100)
+    MusicVolumeOutOfRange { program: String, volume: u32 },
+    /// Nothing in the program would be drawn: no areas, or every area is
+    /// zero-size or has neither content nor a background
+    BlankProgram { program: String },
+}
+
+impl fmt::Display for ValidationWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoPrograms => write!(f, "screen contains no programs"),
+            Self::NoAreas { program } => write!(f, "program {program} has no areas"),
+            Self::ZeroSizeArea { program, area } => {
+                write!(f, "area {area} in program {program} has zero size")
+            }
+            Self::AreaOutOfBounds { program, area } => {
+                write!(f, "area {area} in program {program} extends past the screen")
+            }
+            Self::EmptyArea { program, area } => {
+                write!(f, "area {area} in program {program} has no content")
+            }
+            Self::MissingFile { program, item, file } => {
+                write!(f, "item {item} in program {program} references missing file {file}")
+            }
+            Self::UnknownEffect { program, item, effect } => {
+                write!(f, "item {item} in program {program} uses unknown effect {effect}")
+            }
+            Self::MusicVolumeOutOfRange { program, volume } => {
+                write!(f, "program {program} sets music volume {volume}, above 100")
+            }
+            Self::BlankProgram { program } => {
+                write!(f, "program {program} has nothing to display")
+            }
+        }
+    }
+}
+
+/// Parse a program file from disk: JSON for a `.json` extension, XML otherwise
 pub fn parse_program_file(path: &Path) -> Result<Screen> {
-    let xml = std::fs::read_to_string(path)
+    let bytes = std::fs::read(path)
         .with_context(|| format!("Failed to read program file: {}", path.display()))?;
-    parse_program_xml(&xml)
+    if is_json_program(path) {
+        let json = std::str::from_utf8(&bytes)
+            .with_context(|| format!("{} is not UTF-8", path.display()))?;
+        return parse_program_json(json);
+    }
+    parse_program_xml(&decode_xml_bytes(&bytes))
+}
+
+/// Whether `path` names a JSON program file rather than XML
+pub fn is_json_program(path: &Path) -> bool {
+    path.extension().is_some_and(|e| e.eq_ignore_ascii_case("json"))
+}
+
+/// Parse a screen written as JSON. The JSON mirrors the XML model: attributes
+/// are keys starting with "@" (`{"program": [{"@guid": "p1", "area": [...]}]}`)
+/// and an area's content items are listed under `resources.$value`.
+pub fn parse_program_json(json: &str) -> Result<Screen> {
+    let screen: Screen = serde_json::from_str(json).context("Failed to parse program JSON")?;
+    info!("Parsed JSON screen with {} program(s)", screen.programs.len());
+    Ok(screen)
+}
+
+/// Serialize a screen to the JSON form read by `parse_program_json`
+pub fn program_to_json(screen: &Screen) -> Result<String> {
+    serde_json::to_string_pretty(screen).context("Failed to serialize program JSON")
+}
+
+/// Decode raw XML to UTF-8. The encoding comes from a BOM, a UTF-16 byte
+/// pattern, or the `encoding="..."` declaration, in that order; anything else
+/// is taken as UTF-8. When transcoding, the declaration is rewritten to
+/// utf-8 so the result can be stored and re-read as-is.
+pub fn decode_xml_bytes(bytes: &[u8]) -> String {
+    let encoding = if let Some((encoding, _)) = Encoding::for_bom(bytes) {
+        encoding
+    } else if bytes.len() >= 2 && bytes[0] == b'<' && bytes[1] == 0 {
+        UTF_16LE
+    } else if bytes.len() >= 2 && bytes[0] == 0 && bytes[1] == b'<' {
+        UTF_16BE
+    } else {
+        declared_encoding(bytes).unwrap_or(UTF_8)
+    };
+
+    // decode() strips a BOM, whatever `encoding` says
+    let (text, actual, had_errors) = encoding.decode(bytes);
+    if had_errors {
+        warn!("XML is not valid {}; some characters were replaced", actual.name());
+    }
+    if actual == UTF_8 {
+        return text.into_owned();
+    }
+    info!("Transcoded XML from {} to UTF-8", actual.name());
+    with_utf8_declaration(&text).unwrap_or_else(|| text.into_owned())
+}
+
+/// Encoding named in the `<?xml ... encoding="..."?>` declaration. The
+/// declaration is ASCII in every encoding we can read this way.
+fn declared_encoding(bytes: &[u8]) -> Option<&'static Encoding> {
+    let head = String::from_utf8_lossy(&bytes[..bytes.len().min(200)]);
+    let decl = &head[head.find("<?xml")?..];
+    let decl = &decl[..decl.find("?>")?];
+    let start = decl.find("encoding=")? + "encoding=".len();
+    let quote = decl[start..].chars().next().filter(|c| *c == '"' || *c == '\'')?;
+    let label = &decl[start + 1..];
+    let label = &label[..label.find(quote)?];
+    Encoding::for_label(label.as_bytes())
+}
+
+/// Decoded XML with its declared encoding replaced by utf-8, or None if
+/// there is no encoding declaration to rewrite
+fn with_utf8_declaration(xml: &str) -> Option<String> {
+    if !xml.trim_start().starts_with("<?xml") {
+        return None;
+    }
+    let decl_end = xml.find("?>")?;
+    let attr_end = xml[..decl_end].find("encoding=")? + "encoding=".len();
+    let quote = xml[attr_end..].chars().next().filter(|c| *c == '"' || *c == '\'')?;
+    let value_start = attr_end + 1;
+    let value_end = value_start + xml[value_start..decl_end].find(quote)?;
+    Some(format!("{}utf-8{}", &xml[..value_start], &xml[value_end..]))
 }
 
 /// Parse program XML from a string (e.g. from network)
@@ -42,12 +180,138 @@ pub fn parse_program_xml(xml: &str) -> Result<Screen> {
         }
     } else {
         anyhow::bail!(
-            "Unknown XML format, expected <screen> or <sdk>, got: {}...",
-            &xml_trimmed[..xml_trimmed.len().min(50)]
+            "Unknown XML format, expected <screen> or <sdk>, got: {}",
+            excerpt(xml_trimmed, 50)
         );
     }
 }
 
+/// Parse program XML and report structural problems without rendering.
+/// File references are resolved against `program_dir`; areas are checked
+/// against a `screen_width` x `screen_height` display.
+pub fn validate_program_xml(
+    xml: &str,
+    program_dir: &Path,
+    screen_width: u32,
+    screen_height: u32,
+) -> Result<Vec<ValidationWarning>> {
+    let screen = parse_program_xml(xml)?;
+    Ok(validate_screen(&screen, program_dir, screen_width, screen_height))
+}
+
+/// Structural checks behind `validate_program_xml`, for an already-parsed screen
+pub fn validate_screen(
+    screen: &Screen,
+    program_dir: &Path,
+    screen_width: u32,
+    screen_height: u32,
+) -> Vec<ValidationWarning> {
+    let mut warnings = Vec::new();
+    if screen.programs.is_empty() {
+        warnings.push(ValidationWarning::NoPrograms);
+    }
+
+    for program in &screen.programs {
+        let pg = &program.guid;
+        if program.areas.is_empty() {
+            warnings.push(ValidationWarning::NoAreas { program: pg.clone() });
+        } else if is_blank(program) {
+            warnings.push(ValidationWarning::BlankProgram { program: pg.clone() });
+        }
+
+        for area in &program.areas {
+            let rect = &area.rectangle;
+            if rect.width == 0 || rect.height == 0 {
+                warnings.push(ValidationWarning::ZeroSizeArea {
+                    program: pg.clone(),
+                    area: area.guid.clone(),
+                });
+            } else if rect.x < 0
+                || rect.y < 0
+                || rect.x as i64 + rect.width as i64 > screen_width as i64
+                || rect.y as i64 + rect.height as i64 > screen_height as i64
+            {
+                warnings.push(ValidationWarning::AreaOutOfBounds {
+                    program: pg.clone(),
+                    area: area.guid.clone(),
+                });
+            }
+
+            if area.resources.items.is_empty() {
+                warnings.push(ValidationWarning::EmptyArea {
+                    program: pg.clone(),
+                    area: area.guid.clone(),
+                });
+            }
+
+            for item in &area.resources.items {
+                if let Some(file) = item.file()
+                    && !program_dir.join(&file.name).is_file()
+                {
+                    warnings.push(ValidationWarning::MissingFile {
+                        program: pg.clone(),
+                        item: item.guid().to_string(),
+                        file: file.name.clone(),
+                    });
+                }
+                if let Some(effect) = item.effect() {
+                    for index in [effect.effect_in, effect.effect_out] {
+                        if index > MAX_EFFECT_TYPE {
+                            warnings.push(ValidationWarning::UnknownEffect {
+                                program: pg.clone(),
+                                item: item.guid().to_string(),
+                                effect: index,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+
+        if let Some(ref music) = program.background_music {
+            for file in &music.files {
+                if !program_dir.join(&file.name).is_file() {
+                    warnings.push(ValidationWarning::MissingFile {
+                        program: pg.clone(),
+                        item: "backgroundMusic".to_string(),
+                        file: file.name.clone(),
+                    });
+                }
+            }
+            if let Some(volume) = music.volume.filter(|&v| v > MAX_MUSIC_VOLUME) {
+                warnings.push(ValidationWarning::MusicVolumeOutOfRange {
+                    program: pg.clone(),
+                    volume,
+                });
+            }
+        }
+    }
+
+    warnings
+}
+
+/// Whether `program` renders nothing but black: no area has a size and
+/// something to draw (content or a background)
+pub fn is_blank(program: &Program) -> bool {
+    !program.areas.iter().any(|area| {
+        let rect = &area.rectangle;
+        let drawable = !area.resources.items.is_empty()
+            || area.bg_color.is_some()
+            || area.bg_image.is_some();
+        rect.width > 0 && rect.height > 0 && drawable
+    })
+}
+
+/// `BlankProgram` warnings for the programs in `screen` that render nothing
+pub fn blank_program_warnings(screen: &Screen) -> Vec<ValidationWarning> {
+    screen
+        .programs
+        .iter()
+        .filter(|p| is_blank(p))
+        .map(|p| ValidationWarning::BlankProgram { program: p.guid.clone() })
+        .collect()
+}
+
 /// Extract <screen> from SDK-wrapped XML:
 /// <sdk guid="..."><in method="AddProgram"><screen>...</screen></in></sdk>
 fn parse_sdk_wrapped(xml: &str) -> Result<Screen> {
@@ -72,6 +336,17 @@ fn parse_sdk_wrapped(xml: &str) -> Result<Screen> {
     Ok(screen)
 }
 
+/// At most the first `max_bytes` of `text` for an error message, cut at a
+/// character boundary (program XML is often Chinese) and marked with "..."
+/// when shortened
+fn excerpt(text: &str, max_bytes: usize) -> String {
+    if text.len() <= max_bytes {
+        return text.to_string();
+    }
+    let end = (0..=max_bytes).rev().find(|&i| text.is_char_boundary(i)).unwrap_or(0);
+    format!("{}...", &text[..end])
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -108,6 +383,93 @@ mod tests {
         assert_eq!(area.resources.items.len(), 1);
     }
 
+    #[test]
+    fn test_json_round_trip() {
+        let xml = r##"<screen timeStamps="7">
+          <program guid="p1" name="Promo" flag="default">
+            <playControl duration="00:00:20"/>
+            <area guid="a1" zIndex="2"><rectangle x="0" y="0" width="64" height="32"/>
+              <resources>
+                <text guid="t1" singleLine="true" scrollDir="right">
+                  <string>Sale &amp; more</string><font size="14" color="#00ff00"/>
+                </text>
+                <image guid="i1" fit="fill"><file name="logo.png"/></image>
+              </resources>
+            </area>
+          </program>
+        </screen>"##;
+        let from_xml = parse_program_xml(xml).unwrap();
+        let json = program_to_json(&from_xml).unwrap();
+        let from_json = parse_program_json(&json).unwrap();
+
+        // Same structure either way: both serialize identically, to XML and JSON
+        let to_xml = |s: &Screen| quick_xml::se::to_string_with_root("screen", s).unwrap();
+        assert_eq!(to_xml(&from_json), to_xml(&from_xml));
+        assert_eq!(program_to_json(&from_json).unwrap(), json);
+        assert!(from_json.programs[0].flag.is_default());
+        assert_eq!(from_json.programs[0].areas[0].resources.items.len(), 2);
+
+        // The directory scanner picks JSON files by extension
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("promo.json");
+        std::fs::write(&path, &json).unwrap();
+        assert!(is_json_program(&path));
+        assert_eq!(parse_program_file(&path).unwrap().programs[0].guid, "p1");
+    }
+
+    #[test]
+    fn test_parse_program_flags() {
+        use crate::program::model::ProgramFlags;
+
+        let xml = r#"<screen>
+            <program guid="a" flag="default,locked"/>
+            <program guid="b" flag="0x2"/>
+            <program guid="c" flag="1"/>
+            <program guid="d"/>
+        </screen>"#;
+        let screen = parse_program_xml(xml).unwrap();
+        let flags: Vec<&ProgramFlags> = screen.programs.iter().map(|p| &p.flag).collect();
+        assert!(flags[0].is_default() && flags[0].is_locked());
+        assert!(!flags[1].is_default() && flags[1].is_locked());
+        assert!(flags[2].is_default() && !flags[2].is_locked());
+        assert!(!flags[3].is_default() && !flags[3].is_locked());
+
+        // The original attribute text is written back out unchanged
+        let out = quick_xml::se::to_string_with_root("screen", &screen).unwrap();
+        assert!(out.contains(r#"flag="default,locked""#));
+        assert!(out.contains(r#"flag="0x2""#));
+    }
+
+    #[test]
+    fn test_parse_background_music_settings() {
+        let xml = r#"<screen>
+            <program guid="a"><backgroundMusic volume="40" loop="false">
+              <file name="jingle.mp3"/></backgroundMusic></program>
+            <program guid="b"><backgroundMusic><file name="ambient.mp3"/></backgroundMusic></program>
+            <program guid="c"><backgroundMusic volume="250"/></program>
+        </screen>"#;
+        let screen = parse_program_xml(xml).unwrap();
+        let music: Vec<_> =
+            screen.programs.iter().map(|p| p.background_music.as_ref().unwrap()).collect();
+        assert_eq!(music[0].volume, Some(40));
+        assert!(!music[0].loop_playback);
+        assert_eq!(music[0].effective_volume(80), 40);
+
+        // Unset: loops, at the device volume
+        assert_eq!(music[1].volume, None);
+        assert!(music[1].loop_playback);
+        assert_eq!(music[1].effective_volume(80), 80);
+
+        // Out of range: capped, and flagged by validation
+        assert_eq!(music[2].effective_volume(80), 100);
+        let dir = tempfile::tempdir().unwrap();
+        let warnings = validate_screen(&screen, dir.path(), 128, 64);
+        assert!(warnings.contains(&ValidationWarning::MusicVolumeOutOfRange {
+            program: "c".into(),
+            volume: 250,
+        }));
+    }
+
     #[test]
     fn test_parse_sdk_wrapped() {
         let xml = r#"<?xml version="1.0" encoding="utf-8"?>
@@ -157,4 +519,118 @@ mod tests {
         assert_eq!(screen.programs.len(), 1);
         assert_eq!(screen.programs[0].areas[0].resources.items.len(), 1);
     }
+
+    #[test]
+    fn test_validate_valid_program() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("logo.png"), b"").unwrap();
+        let xml = r#"
+        <screen>
+          <program guid="p1">
+            <area guid="a1">
+              <rectangle x="0" y="0" width="128" height="64"/>
+              <resources>
+                <image guid="img-1"><effect in="17" out="17"/><file name="logo.png"/></image>
+              </resources>
+            </area>
+          </program>
+        </screen>
+        "#;
+        assert!(validate_program_xml(xml, dir.path(), 128, 64).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_validate_reports_problems() {
+        let dir = tempfile::tempdir().unwrap();
+        let xml = r#"
+        <screen>
+          <program guid="p1">
+            <area guid="outside">
+              <rectangle x="100" y="0" width="64" height="64"/>
+              <resources>
+                <image guid="img-1"><effect in="42" out="0"/><file name="missing.png"/></image>
+              </resources>
+            </area>
+            <area guid="empty">
+              <rectangle x="0" y="0" width="0" height="16"/>
+              <resources/>
+            </area>
+          </program>
+          <program guid="p2"/>
+        </screen>
+        "#;
+        let warnings = validate_program_xml(xml, dir.path(), 128, 64).unwrap();
+        let p1 = || "p1".to_string();
+        assert_eq!(
+            warnings,
+            vec![
+                ValidationWarning::AreaOutOfBounds { program: p1(), area: "outside".into() },
+                ValidationWarning::MissingFile {
+                    program: p1(),
+                    item: "img-1".into(),
+                    file: "missing.png".into(),
+                },
+                ValidationWarning::UnknownEffect { program: p1(), item: "img-1".into(), effect: 42 },
+                ValidationWarning::ZeroSizeArea { program: p1(), area: "empty".into() },
+                ValidationWarning::EmptyArea { program: p1(), area: "empty".into() },
+                ValidationWarning::NoAreas { program: "p2".into() },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate_empty_screen_and_bad_xml() {
+        let dir = tempfile::tempdir().unwrap();
+        let warnings = validate_program_xml("<screen/>", dir.path(), 128, 64).unwrap();
+        assert_eq!(warnings, vec![ValidationWarning::NoPrograms]);
+        assert!(validate_program_xml("not xml", dir.path(), 128, 64).is_err());
+    }
+
+    #[test]
+    fn test_unknown_format_error_cuts_at_char_boundary() {
+        // "节" takes bytes 48..51, straddling the 50-byte cut
+        let head = format!("<{}", "x".repeat(47));
+        let xml = format!("{head}节目单/>");
+        assert!(!xml.is_char_boundary(50));
+
+        let err = parse_program_xml(&xml).unwrap_err().to_string();
+        assert!(err.ends_with(&format!("got: {head}...")), "{err}");
+
+        // Short input is quoted whole
+        let err = parse_program_xml("<节目/>").unwrap_err().to_string();
+        assert!(err.ends_with("got: <节目/>"), "{err}");
+    }
+
+    #[test]
+    fn test_decode_gbk_program() {
+        use crate::program::model::ContentItem;
+
+        let xml = "<?xml version=\"1.0\" encoding=\"GBK\"?>\
+            <screen><program guid=\"p1\" name=\"节目\"><area guid=\"a1\">\
+            <rectangle width=\"64\" height=\"16\"/><resources>\
+            <text guid=\"t1\"><string>欢迎光临</string></text>\
+            </resources></area></program></screen>";
+        let (gbk, _, _) = encoding_rs::GBK.encode(xml);
+        assert!(std::str::from_utf8(&gbk).is_err());
+
+        let decoded = decode_xml_bytes(&gbk);
+        assert!(decoded.starts_with("<?xml version=\"1.0\" encoding=\"utf-8\"?>"));
+        let screen = parse_program_xml(&decoded).unwrap();
+        assert_eq!(screen.programs[0].name, "节目");
+        let ContentItem::Text(text) = &screen.programs[0].areas[0].resources.items[0] else {
+            panic!("expected text content");
+        };
+        assert_eq!(text.string.as_deref(), Some("欢迎光临"));
+    }
+
+    #[test]
+    fn test_decode_utf16_with_bom() {
+        let xml = "<screen><program guid=\"p1\" name=\"欢迎\"/></screen>";
+        let mut utf16 = vec![0xFF, 0xFE];
+        utf16.extend(xml.encode_utf16().flat_map(|unit| unit.to_le_bytes()));
+        assert_eq!(decode_xml_bytes(&utf16), xml);
+
+        // Plain UTF-8 passes straight through
+        assert_eq!(decode_xml_bytes(xml.as_bytes()), xml);
+    }
 }
@@ -1,51 +1,106 @@
 /// SDK XML command handler — routes incoming commands to appropriate handlers.
 /// Implements the full Huidu SDK command set based on binary analysis.
 use anyhow::Result;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine as _;
 use std::sync::Arc;
-use tokio::sync::{mpsc, RwLock};
+use tokio::sync::{mpsc, oneshot, RwLock};
 use tracing::{info, warn};
 
 use crate::core::player::PlayerCommand;
+use crate::core::playlist::{Playlist, PlaylistEntry};
 use crate::program::parser;
 use crate::protocol::session::Session;
+use crate::protocol::trace;
+use crate::render::plugins::BUILTIN_KINDS;
 use crate::services::manager::ServicesState;
+use crate::services::screen_schedule::SwitchAction;
+
+/// Device-wide state shared by every connection's command handlers
+#[derive(Clone)]
+pub struct HandlerContext {
+    pub player_tx: mpsc::Sender<PlayerCommand>,
+    pub program_dir: String,
+    pub services: Arc<RwLock<ServicesState>>,
+    pub screen_width: u32,
+    pub screen_height: u32,
+    /// Configured device ID (`--device-id`)
+    pub device_id: String,
+    /// FactoryReset is honored only when set (`--allow-factory-reset`)
+    pub allow_factory_reset: bool,
+    /// Log every packet and SDK command (`--protocol-trace`)
+    pub protocol_trace: bool,
+    /// Largest SDK command a session buffers (`--max-xml-len`)
+    pub max_xml_len: usize,
+    /// Largest file a session accepts over a file transfer (`--max-file-size`)
+    pub max_file_size: u64,
+    /// Media the `--fallback-program` uses, never deleted as unused
+    pub fallback_media: Vec<String>,
+}
 
 /// Handle an incoming SDK XML command and return the response XML
 pub async fn handle_sdk_command(
     xml: &str,
-    session: &Session,
-    player_tx: &mpsc::Sender<PlayerCommand>,
-    _program_dir: &str,
-    services: &Arc<RwLock<ServicesState>>,
-    screen_width: u32,
-    screen_height: u32,
+    session: &mut Session,
+    ctx: &HandlerContext,
 ) -> Result<String> {
+    let HandlerContext {
+        player_tx,
+        services,
+        screen_width,
+        screen_height,
+        ..
+    } = ctx;
     let method = extract_method(xml).unwrap_or_default();
     info!("SDK command: {}", method);
-    let guid = &session.guid;
+    if ctx.protocol_trace {
+        info!(target: trace::TARGET, method = %method, "SDK command XML: {}", xml);
+    }
+    // HDPlayer keys on device identity, so echo the stable device GUID rather than
+    // the per-connection one
+    let guid = session.device_guid.clone();
+
+    // A locked device only answers queries until the session gives the password
+    if !session.authenticated
+        && !allowed_while_locked(&method)
+        && services.read().await.lock.is_locked()
+    {
+        warn!("{} refused: device is locked", method);
+        return Ok(format!(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+             <sdk guid=\"{guid}\"><out method=\"{method}\">\
+             <result value=\"{RESULT_LOCKED}\"/></out></sdk>"
+        ));
+    }
 
     match method.as_str() {
         // --- Version Negotiation ---
-        "QueryIFVersion" | "queryIFVersion" | "GetIFVersion" => Ok(format!(
-            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
-             <sdk guid=\"{guid}\"><out method=\"QueryIFVersion\">\
-             <version value=\"0x1000000\"/></out></sdk>"
-        )),
+        "QueryIFVersion" | "queryIFVersion" | "GetIFVersion" => {
+            let capabilities = capabilities_xml();
+            Ok(format!(
+                "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+                 <sdk guid=\"{guid}\"><out method=\"QueryIFVersion\">\
+                 <version value=\"0x1000000\"/>{capabilities}</out></sdk>"
+            ))
+        }
 
         // --- Program Management ---
         "AddProgram" | "addProgram" => {
             match parser::parse_program_xml(xml) {
                 Ok(screen) => {
-                    // Save to disk
-                    {
-                        let state = services.read().await;
-                        let _ = state.storage.save_program(&screen, xml);
-                    }
+                    // Accepted, but tell the sender if a program will show nothing
+                    let warnings: String = parser::blank_program_warnings(&screen)
+                        .iter()
+                        .map(|w| xml_escape(&w.to_string()))
+                        .map(|msg| format!("<warning message=\"{msg}\"/>"))
+                        .collect();
+                    // Save to disk, then play everything saved so far
+                    let screen = services.read().await.storage.save_and_merge(screen, xml);
                     player_tx.send(PlayerCommand::LoadScreen(screen)).await.ok();
                     Ok(format!(
                         "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
                          <sdk guid=\"{guid}\"><out method=\"AddProgram\">\
-                         <result value=\"0\"/></out></sdk>"
+                         <result value=\"0\"/>{warnings}</out></sdk>"
                     ))
                 }
                 Err(e) => {
@@ -63,11 +118,8 @@ pub async fn handle_sdk_command(
         "UpdateProgram" | "updateProgram" => {
             match parser::parse_program_xml(xml) {
                 Ok(screen) => {
-                    {
-                        let state = services.read().await;
-                        let _ = state.storage.save_program(&screen, xml);
-                    }
-                    player_tx.send(PlayerCommand::LoadScreen(screen)).await.ok();
+                    let screen = services.read().await.storage.save_and_merge(screen, xml);
+                    player_tx.send(PlayerCommand::UpdateScreen(screen)).await.ok();
                     Ok(format!(
                         "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
                          <sdk guid=\"{guid}\"><out method=\"UpdateProgram\">\
@@ -86,17 +138,31 @@ pub async fn handle_sdk_command(
         }
 
         "DeleteProgram" | "deleteProgram" => {
-            {
-                let state = services.read().await;
-                let _ = state.storage.clear();
+            let guids = extract_program_guids(xml);
+            if guids.is_empty() {
+                // No target given: clear everything
+                {
+                    let state = services.read().await;
+                    let _ = state.storage.clear();
+                }
+                player_tx
+                    .send(PlayerCommand::LoadScreen(crate::program::model::Screen {
+                        timestamps: String::new(),
+                        programs: Vec::new(),
+                    }))
+                    .await
+                    .ok();
+            } else {
+                {
+                    let state = services.read().await;
+                    if let Err(e) = state.storage.remove_programs(&guids) {
+                        warn!("Failed to update saved programs: {}", e);
+                    }
+                }
+                for program_guid in guids {
+                    player_tx.send(PlayerCommand::RemoveProgram(program_guid)).await.ok();
+                }
             }
-            player_tx
-                .send(PlayerCommand::LoadScreen(crate::program::model::Screen {
-                    timestamps: String::new(),
-                    programs: Vec::new(),
-                }))
-                .await
-                .ok();
             Ok(format!(
                 "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
                  <sdk guid=\"{guid}\"><out method=\"DeleteProgram\">\
@@ -104,6 +170,163 @@ pub async fn handle_sdk_command(
             ))
         }
 
+        "DeleteProgramWithMedia" | "deleteProgramWithMedia" => {
+            let guids = extract_program_guids(xml);
+            // Unlike DeleteProgram, no target is an error rather than "delete all"
+            let result = if guids.is_empty() {
+                1
+            } else {
+                let state = services.read().await;
+                let keep: Vec<String> = state
+                    .boot_logo
+                    .get()
+                    .into_iter()
+                    .chain(ctx.fallback_media.iter().cloned())
+                    .collect();
+                match state.storage.remove_programs_and_media(&guids, &keep) {
+                    Ok(deleted) => {
+                        info!("Deleted {} unused media file(s): {:?}", deleted.len(), deleted);
+                        0
+                    }
+                    Err(e) => {
+                        warn!("Failed to update saved programs: {}", e);
+                        1
+                    }
+                }
+            };
+            if result == 0 {
+                for program_guid in guids {
+                    player_tx.send(PlayerCommand::RemoveProgram(program_guid)).await.ok();
+                }
+            }
+            Ok(format!(
+                "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+                 <sdk guid=\"{guid}\"><out method=\"DeleteProgramWithMedia\">\
+                 <result value=\"{result}\"/></out></sdk>"
+            ))
+        }
+
+        "SetPlayProgram" | "setPlayProgram" => {
+            // 1 = bad index or out of range
+            let mut result = 1;
+            if let Some(index) = extract_attr(xml, "program", "index").and_then(|v| v.parse().ok()) {
+                let (reply_tx, reply_rx) = oneshot::channel();
+                player_tx.send(PlayerCommand::SetCurrentProgram(index, reply_tx)).await.ok();
+                if reply_rx.await.unwrap_or(false) {
+                    result = 0;
+                }
+            }
+            Ok(format!(
+                "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+                 <sdk guid=\"{guid}\"><out method=\"SetPlayProgram\">\
+                 <result value=\"{result}\"/></out></sdk>"
+            ))
+        }
+
+        "GetPlayProgram" | "getPlayProgram" => {
+            let (reply_tx, reply_rx) = oneshot::channel();
+            player_tx.send(PlayerCommand::GetCurrentProgram(reply_tx)).await.ok();
+            let (index, count) = reply_rx.await.unwrap_or((0, 0));
+            Ok(format!(
+                "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+                 <sdk guid=\"{guid}\"><out method=\"GetPlayProgram\">\
+                 <program index=\"{index}\" count=\"{count}\"/>\
+                 <result value=\"0\"/></out></sdk>"
+            ))
+        }
+
+        "GetRawFrame" | "getRawFrame" => {
+            // Unencoded pixels for pixel-exact comparisons, unlike a PNG screenshot
+            let (reply_tx, reply_rx) = oneshot::channel();
+            player_tx.send(PlayerCommand::CaptureFrame(reply_tx)).await.ok();
+            let (result, frame) = match reply_rx.await {
+                Ok((width, height, pixels)) => (
+                    0,
+                    format!(
+                        "<frame width=\"{width}\" height=\"{height}\" format=\"RGBA\">{}</frame>",
+                        BASE64.encode(&pixels)
+                    ),
+                ),
+                Err(_) => (1, String::new()),
+            };
+            Ok(format!(
+                "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+                 <sdk guid=\"{guid}\"><out method=\"GetRawFrame\">\
+                 {frame}<result value=\"{result}\"/></out></sdk>"
+            ))
+        }
+
+        "GetProgramList" | "getProgramList" => {
+            let (reply_tx, reply_rx) = oneshot::channel();
+            player_tx.send(PlayerCommand::GetProgramList(reply_tx)).await.ok();
+            let (current, programs) = reply_rx.await.unwrap_or_default();
+            let mut items = String::new();
+            for (i, program) in programs.iter().enumerate() {
+                items.push_str(&format!(
+                    "<program index=\"{}\" guid=\"{}\" name=\"{}\" areaCount=\"{}\" \
+                     duration=\"{}\" playing=\"{}\"/>",
+                    i,
+                    xml_escape(&program.guid),
+                    xml_escape(&program.name),
+                    program.area_count,
+                    program.duration_secs,
+                    i == current
+                ));
+            }
+            Ok(format!(
+                "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+                 <sdk guid=\"{guid}\"><out method=\"GetProgramList\">\
+                 <programs current=\"{current}\" count=\"{}\">{items}</programs>\
+                 <result value=\"0\"/></out></sdk>",
+                programs.len()
+            ))
+        }
+
+        "GetRenderStats" | "getRenderStats" => {
+            let (reply_tx, reply_rx) = oneshot::channel();
+            player_tx.send(PlayerCommand::GetRenderTimings(reply_tx)).await.ok();
+            let timings = reply_rx.await.unwrap_or_default();
+            let items: String = timings
+                .iter()
+                .map(|(kind, timing)| {
+                    format!(
+                        "<plugin type=\"{}\" calls=\"{}\" totalMs=\"{}\" avgUs=\"{}\" \
+                         maxUs=\"{}\"/>",
+                        kind.name(),
+                        timing.calls,
+                        timing.total.as_millis(),
+                        timing.average().as_micros(),
+                        timing.max.as_micros()
+                    )
+                })
+                .collect();
+            Ok(format!(
+                "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+                 <sdk guid=\"{guid}\"><out method=\"GetRenderStats\">\
+                 <renderStats>{items}</renderStats>\
+                 <result value=\"0\"/></out></sdk>"
+            ))
+        }
+
+        "ClearArea" | "clearArea" => {
+            // <program guid="..."><area guid="..."/></program>; 1 = missing GUID
+            let program_guid = extract_attr(xml, "program", "guid");
+            let area_guid = extract_attr(xml, "area", "guid");
+            let result = match (program_guid, area_guid) {
+                (Some(program_guid), Some(area_guid)) => {
+                    let cmd = PlayerCommand::ClearArea { program_guid, area_guid };
+                    player_tx.send(cmd).await.ok();
+                    0
+                }
+                _ => 1,
+            };
+            Ok(format!(
+                "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+                 <sdk guid=\"{guid}\"><out method=\"ClearArea\">\
+                 <result value=\"{result}\"/></out></sdk>"
+            ))
+        }
+
         // --- Screen Control ---
         "OpenScreen" | "openScreen" => {
             player_tx.send(PlayerCommand::ScreenPower(true)).await.ok();
@@ -123,6 +346,32 @@ pub async fn handle_sdk_command(
             ))
         }
 
+        "SetScreenColor" | "setScreenColor" | "FillScreen" | "fillScreen" => {
+            // <color value="#RRGGBB"/>: flood the panel to check for dead pixels
+            let color = extract_attr(xml, "color", "value").and_then(|c| parse_hex_color(&c));
+            let result = match color {
+                Some((r, g, b)) => {
+                    player_tx.send(PlayerCommand::FillColor(r, g, b)).await.ok();
+                    0
+                }
+                None => 1,
+            };
+            Ok(format!(
+                "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+                 <sdk guid=\"{guid}\"><out method=\"SetScreenColor\">\
+                 <result value=\"{result}\"/></out></sdk>"
+            ))
+        }
+
+        "ClearScreenColor" | "clearScreenColor" => {
+            player_tx.send(PlayerCommand::ClearFill).await.ok();
+            Ok(format!(
+                "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+                 <sdk guid=\"{guid}\"><out method=\"ClearScreenColor\">\
+                 <result value=\"0\"/></out></sdk>"
+            ))
+        }
+
         // --- Brightness ---
         "GetLuminancePloy" | "getLuminancePloy" => {
             let state = services.read().await;
@@ -136,12 +385,12 @@ pub async fn handle_sdk_command(
         }
 
         "SetLuminancePloy" | "setLuminancePloy" => {
-            if let Some(val) = extract_attr(xml, "luminance", "value") {
-                if let Ok(level) = val.parse::<u8>() {
-                    let mut state = services.write().await;
-                    state.brightness.set_level(level);
-                    player_tx.send(PlayerCommand::SetBrightness(level)).await.ok();
-                }
+            if let Some(val) = extract_attr(xml, "luminance", "value")
+                && let Ok(level) = val.parse::<u8>()
+            {
+                let mut state = services.write().await;
+                state.brightness.set_level(level);
+                player_tx.send(PlayerCommand::SetBrightness(level)).await.ok();
             }
             Ok(format!(
                 "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
@@ -157,8 +406,14 @@ pub async fn handle_sdk_command(
             let mut items = String::new();
             for (i, entry) in entries.iter().enumerate() {
                 items.push_str(&format!(
-                    "<item index=\"{}\" onTime=\"{}\" offTime=\"{}\" days=\"{}\"/>",
-                    i, entry.on_time, entry.off_time, entry.days
+                    "<item index=\"{}\" onTime=\"{}\" offTime=\"{}\" days=\"{}\" \
+                     type=\"{}\" priority=\"{}\"/>",
+                    i,
+                    entry.on_time,
+                    entry.off_time,
+                    entry.days,
+                    entry.action.as_str(),
+                    entry.priority
                 ));
             }
             Ok(format!(
@@ -182,9 +437,27 @@ pub async fn handle_sdk_command(
             ))
         }
 
+        "SetPlaylist" | "setPlaylist" => {
+            // <item start="09:00:00" end="09:05:00" program="guid"/>; no program
+            // blanks the screen, no items hands control back to the rotation
+            let result = match extract_playlist_entries(xml) {
+                Some(entries) => {
+                    let playlist = Playlist::new(entries);
+                    player_tx.send(PlayerCommand::SetPlaylist(playlist)).await.ok();
+                    0
+                }
+                None => 1,
+            };
+            Ok(format!(
+                "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+                 <sdk guid=\"{guid}\"><out method=\"SetPlaylist\">\
+                 <result value=\"{result}\"/></out></sdk>"
+            ))
+        }
+
         // --- Time ---
         "GetTimeInfo" | "getTimeInfo" => {
-            let now = chrono::Local::now();
+            let now = services.read().await.clock.now();
             let dt = now.format("%Y-%m-%d %H:%M:%S").to_string();
             Ok(format!(
                 "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
@@ -198,6 +471,13 @@ pub async fn handle_sdk_command(
             if let Some(time_val) = extract_attr(xml, "time", "value") {
                 crate::services::time_sync::TimeSyncService::set_time(&time_val).await;
             }
+            // Optionally follow the manual set with an NTP re-sync
+            if extract_attr(xml, "time", "ntpSync").as_deref() == Some("true") {
+                let time_sync = services.read().await.time_sync.clone();
+                tokio::spawn(async move {
+                    time_sync.sync_now().await;
+                });
+            }
             Ok(format!(
                 "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
                  <sdk guid=\"{guid}\"><out method=\"SetTimeInfo\">\
@@ -205,16 +485,171 @@ pub async fn handle_sdk_command(
             ))
         }
 
+        "SyncNtp" | "syncNtp" => {
+            let time_sync = services.read().await.time_sync.clone();
+            // 1 = a sync was already running
+            let result = if time_sync.sync_now().await { 0 } else { 1 };
+            Ok(format!(
+                "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+                 <sdk guid=\"{guid}\"><out method=\"SyncNtp\">\
+                 <result value=\"{result}\"/></out></sdk>"
+            ))
+        }
+
+        "GetNtpServer" | "getNtpServer" => {
+            let server = xml_escape(&services.read().await.time_sync.server());
+            Ok(format!(
+                "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+                 <sdk guid=\"{guid}\"><out method=\"GetNtpServer\">\
+                 <ntp server=\"{server}\"/>\
+                 <result value=\"0\"/></out></sdk>"
+            ))
+        }
+
+        "SetNtpServer" | "setNtpServer" => {
+            let host = extract_attr(xml, "ntp", "server").unwrap_or_default();
+            let time_sync = services.read().await.time_sync.clone();
+            let result = match time_sync.set_server(&host) {
+                Ok(()) => {
+                    // Resync right away so the new server takes effect
+                    tokio::spawn(async move {
+                        time_sync.sync_now().await;
+                    });
+                    0
+                }
+                Err(e) => {
+                    warn!("SetNtpServer rejected: {}", e);
+                    1
+                }
+            };
+            Ok(format!(
+                "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+                 <sdk guid=\"{guid}\"><out method=\"SetNtpServer\">\
+                 <result value=\"{result}\"/></out></sdk>"
+            ))
+        }
+
         // --- Device Info ---
-        "GetDeviceInfo" | "getDeviceInfo" => Ok(format!(
+        "GetDeviceInfo" | "getDeviceInfo" => {
+            let name = xml_escape(&services.read().await.device_name.get());
+            let device_id = xml_escape(&ctx.device_id);
+            Ok(format!(
+                "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+                 <sdk guid=\"{guid}\"><out method=\"GetDeviceInfo\">\
+                 <deviceInfo cpu=\"RustPlayer\" model=\"huidu-player\" \
+                 fpgaVersion=\"{VERSION}\" screenWidth=\"{screen_width}\" \
+                 screenHeight=\"{screen_height}\" deviceID=\"{device_id}\" \
+                 deviceName=\"{name}\"/>\
+                 <result value=\"0\"/></out></sdk>"
+            ))
+        }
+
+        "GetVersion" | "getVersion" => Ok(format!(
             "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
-             <sdk guid=\"{guid}\"><out method=\"GetDeviceInfo\">\
-             <deviceInfo cpu=\"RustPlayer\" model=\"huidu-player\" \
-             fpgaVersion=\"1.0.0\" screenWidth=\"{screen_width}\" \
-             screenHeight=\"{screen_height}\" deviceID=\"RUST-001\"/>\
+             <sdk guid=\"{guid}\"><out method=\"GetVersion\">\
+             <version app=\"{VERSION}\" buildTime=\"{BUILD_TIME}\" gitHash=\"{GIT_HASH}\"/>\
              <result value=\"0\"/></out></sdk>"
         )),
 
+        "GetDeviceName" | "getDeviceName" => {
+            let name = xml_escape(&services.read().await.device_name.get());
+            Ok(format!(
+                "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+                 <sdk guid=\"{guid}\"><out method=\"GetDeviceName\">\
+                 <device name=\"{name}\"/>\
+                 <result value=\"0\"/></out></sdk>"
+            ))
+        }
+
+        "SetDeviceName" | "setDeviceName" => {
+            let name = extract_attr(xml, "device", "name").unwrap_or_default();
+            let result = match services.read().await.device_name.set(&xml_unescape(&name)) {
+                Ok(()) => 0,
+                Err(e) => {
+                    warn!("SetDeviceName rejected: {}", e);
+                    1
+                }
+            };
+            Ok(format!(
+                "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+                 <sdk guid=\"{guid}\"><out method=\"SetDeviceName\">\
+                 <result value=\"{result}\"/></out></sdk>"
+            ))
+        }
+
+        // --- Device Lock ---
+        "SetDevicePassword" | "setDevicePassword" => {
+            let password = extract_attr(xml, "password", "value").unwrap_or_default();
+            let result = match services.read().await.lock.set(&xml_unescape(&password)) {
+                Ok(()) => {
+                    session.authenticated = true;
+                    0
+                }
+                Err(e) => {
+                    warn!("SetDevicePassword rejected: {}", e);
+                    1
+                }
+            };
+            Ok(format!(
+                "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+                 <sdk guid=\"{guid}\"><out method=\"SetDevicePassword\">\
+                 <result value=\"{result}\"/></out></sdk>"
+            ))
+        }
+
+        "ClearDevicePassword" | "clearDevicePassword" => {
+            services.read().await.lock.clear();
+            Ok(format!(
+                "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+                 <sdk guid=\"{guid}\"><out method=\"ClearDevicePassword\">\
+                 <result value=\"0\"/></out></sdk>"
+            ))
+        }
+
+        "VerifyDevicePassword" | "verifyDevicePassword" => {
+            let password = extract_attr(xml, "password", "value").unwrap_or_default();
+            let verified = services.read().await.lock.verify(&xml_unescape(&password));
+            if verified {
+                session.authenticated = true;
+            } else {
+                warn!("VerifyDevicePassword: wrong password");
+            }
+            let result = if verified { 0 } else { 1 };
+            Ok(format!(
+                "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+                 <sdk guid=\"{guid}\"><out method=\"VerifyDevicePassword\">\
+                 <result value=\"{result}\"/></out></sdk>"
+            ))
+        }
+
+        // --- Relays ---
+        "GetRelay" | "getRelay" => {
+            let state = services.read().await;
+            let items = relay_items(state.relay.states());
+            Ok(format!(
+                "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+                 <sdk guid=\"{guid}\"><out method=\"GetRelay\">\
+                 {items}<result value=\"0\"/></out></sdk>"
+            ))
+        }
+
+        "SetRelay" | "setRelay" => {
+            let mut state = services.write().await;
+            let mut result = 0;
+            for (index, on) in extract_relay_states(xml) {
+                if let Err(e) = state.relay.set(index, on) {
+                    warn!("SetRelay: {}", e);
+                    result = 1;
+                }
+            }
+            let items = relay_items(state.relay.states());
+            Ok(format!(
+                "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+                 <sdk guid=\"{guid}\"><out method=\"SetRelay\">\
+                 {items}<result value=\"{result}\"/></out></sdk>"
+            ))
+        }
+
         // --- Font Management ---
         "GetAllFontInfo" | "getAllFontInfo" => {
             // Return list of available fonts
@@ -278,18 +713,75 @@ pub async fn handle_sdk_command(
         }
 
         // --- Boot Logo ---
-        "GetBootLogo" | "getBootLogo" => Ok(format!(
-            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
-             <sdk guid=\"{guid}\"><out method=\"GetBootLogo\">\
-             <bootLogo name=\"\"/>\
-             <result value=\"0\"/></out></sdk>"
-        )),
+        "GetBootLogo" | "getBootLogo" => {
+            let name = services.read().await.boot_logo.get().unwrap_or_default();
+            let name = xml_escape(&name);
+            Ok(format!(
+                "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+                 <sdk guid=\"{guid}\"><out method=\"GetBootLogo\">\
+                 <bootLogo name=\"{name}\"/>\
+                 <result value=\"0\"/></out></sdk>"
+            ))
+        }
 
-        "SetBootLogoName" | "setBootLogoName" | "ClearBootLogo" | "clearBootLogo" => Ok(format!(
-            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
-             <sdk guid=\"{guid}\"><out method=\"{method}\">\
-             <result value=\"0\"/></out></sdk>"
-        )),
+        // The logo file itself arrives through a normal file transfer
+        "SetBootLogoName" | "setBootLogoName" => {
+            let name = extract_attr(xml, "bootLogo", "name").unwrap_or_default();
+            let result = match services.read().await.boot_logo.set(&xml_unescape(&name)) {
+                Ok(()) => 0,
+                Err(e) => {
+                    warn!("SetBootLogoName rejected: {}", e);
+                    1
+                }
+            };
+            Ok(format!(
+                "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+                 <sdk guid=\"{guid}\"><out method=\"SetBootLogoName\">\
+                 <result value=\"{result}\"/></out></sdk>"
+            ))
+        }
+
+        "ClearBootLogo" | "clearBootLogo" => {
+            services.read().await.boot_logo.clear();
+            Ok(format!(
+                "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+                 <sdk guid=\"{guid}\"><out method=\"ClearBootLogo\">\
+                 <result value=\"0\"/></out></sdk>"
+            ))
+        }
+
+        // --- Maintenance ---
+        "FactoryReset" | "RestoreFactory" => {
+            let result = if !ctx.allow_factory_reset {
+                warn!("FactoryReset refused: start the player with --allow-factory-reset");
+                1
+            } else {
+                let reset = services.write().await.factory_reset();
+                let level = services.read().await.brightness.get_level();
+                player_tx
+                    .send(PlayerCommand::LoadScreen(crate::program::model::Screen {
+                        timestamps: String::new(),
+                        programs: Vec::new(),
+                    }))
+                    .await
+                    .ok();
+                player_tx.send(PlayerCommand::SetPlaylist(Playlist::default())).await.ok();
+                player_tx.send(PlayerCommand::SetBrightness(level)).await.ok();
+                player_tx.send(PlayerCommand::ScreenPower(true)).await.ok();
+                match reset {
+                    Ok(()) => 0,
+                    Err(e) => {
+                        warn!("FactoryReset incomplete: {}", e);
+                        1
+                    }
+                }
+            };
+            Ok(format!(
+                "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+                 <sdk guid=\"{guid}\"><out method=\"{method}\">\
+                 <result value=\"{result}\"/></out></sdk>"
+            ))
+        }
 
         // --- TCP Server Config ---
         "GetSDKTcpServer" | "getSDKTcpServer" => Ok(format!(
@@ -331,6 +823,65 @@ pub async fn handle_sdk_command(
     }
 }
 
+/// Result code for commands refused because the device is locked
+const RESULT_LOCKED: u32 = 2;
+
+/// Result code for commands sent before the transport was negotiated
+const RESULT_NOT_NEGOTIATED: u32 = 3;
+
+/// Answer to an SDK command sent before the transport was negotiated: it is
+/// refused without being handled
+pub fn not_negotiated_response(xml: &str, session: &Session) -> String {
+    let method = xml_escape(&extract_method(xml).unwrap_or_default());
+    let guid = &session.device_guid;
+    format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+         <sdk guid=\"{guid}\"><out method=\"{method}\">\
+         <result value=\"{RESULT_NOT_NEGOTIATED}\"/></out></sdk>"
+    )
+}
+
+/// Commands a locked device still answers without the password: queries,
+/// and the password check itself
+fn allowed_while_locked(method: &str) -> bool {
+    method.starts_with("Get")
+        || method.starts_with("get")
+        || matches!(
+            method,
+            "QueryIFVersion" | "queryIFVersion" | "VerifyDevicePassword" | "verifyDevicePassword"
+        )
+}
+
+/// Player version, reported by GetVersion and GetDeviceInfo
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+/// Build stamps from build.rs
+const BUILD_TIME: &str = env!("HUIDU_BUILD_TIME");
+const GIT_HASH: &str = env!("HUIDU_GIT_HASH");
+
+/// Device features beyond content playback that the SDK commands implement.
+/// Sensors, intercut (insert) programs and Wi-Fi setup are not supported.
+const FEATURES: [&str; 8] = [
+    "brightness",
+    "screenSwitch",
+    "ntp",
+    "relay",
+    "bootLogo",
+    "deviceName",
+    "clearArea",
+    "fileManagement",
+];
+
+/// `<capabilities>` for QueryIFVersion: the content kinds this build can
+/// render, then the supported device features
+fn capabilities_xml() -> String {
+    let content = BUILTIN_KINDS.iter().map(|kind| kind.name());
+    let items: String = content
+        .chain(FEATURES)
+        .map(|name| format!("<capability name=\"{name}\"/>"))
+        .collect();
+    format!("<capabilities>{items}</capabilities>")
+}
+
 /// Extract the method name from <sdk...><in method="MethodName">
 fn extract_method(xml: &str) -> Option<String> {
     let in_start = xml.find("<in ")?;
@@ -351,25 +902,91 @@ fn extract_attr(xml: &str, element: &str, attr: &str) -> Option<String> {
     Some(xml[start..end].to_string())
 }
 
+/// Escape text for use in an XML attribute value
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Decode entities in an attribute value taken from `extract_attr`
+fn xml_unescape(s: &str) -> String {
+    quick_xml::escape::unescape(s).map_or_else(|_| s.to_string(), |u| u.into_owned())
+}
+
+/// "#RRGGBB" (the '#' is optional) as (r, g, b)
+fn parse_hex_color(s: &str) -> Option<(u8, u8, u8)> {
+    let hex = s.trim().trim_start_matches('#');
+    if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    Some((channel(0)?, channel(2)?, channel(4)?))
+}
+
 /// Extract screen schedule entries from XML
 fn extract_schedule_entries(xml: &str) -> Vec<crate::services::screen_schedule::ScreenScheduleEntry> {
     let mut entries = Vec::new();
     let mut search_from = 0;
     while let Some(pos) = xml[search_from..].find("<item ") {
         let abs_pos = search_from + pos;
-        let on_time = extract_attr(&xml[abs_pos..], "item", "onTime").unwrap_or_default();
-        let off_time = extract_attr(&xml[abs_pos..], "item", "offTime").unwrap_or_default();
-        let days = extract_attr(&xml[abs_pos..], "item", "days").unwrap_or_default();
+        // Only look inside this <item ...> tag: type and priority are optional
+        // and must not be picked up from the next item
+        let tag = &xml[abs_pos..];
+        let tag = &tag[..tag.find('>').map_or(tag.len(), |end| end + 1)];
+        let on_time = extract_attr(tag, "item", "onTime").unwrap_or_default();
+        let off_time = extract_attr(tag, "item", "offTime").unwrap_or_default();
+        let days = extract_attr(tag, "item", "days").unwrap_or_default();
+        let action = extract_attr(tag, "item", "type")
+            .map(|t| SwitchAction::parse(&t))
+            .unwrap_or_default();
+        let priority = extract_attr(tag, "item", "priority")
+            .and_then(|p| p.parse().ok())
+            .unwrap_or(0);
         entries.push(crate::services::screen_schedule::ScreenScheduleEntry {
             on_time,
             off_time,
             days,
+            action,
+            priority,
         });
         search_from = abs_pos + 5;
     }
     entries
 }
 
+/// Extract playlist slots from SetPlaylist XML; None if any time is invalid
+fn extract_playlist_entries(xml: &str) -> Option<Vec<PlaylistEntry>> {
+    let mut entries = Vec::new();
+    let mut search_from = 0;
+    while let Some(pos) = xml[search_from..].find("<item ") {
+        let abs_pos = search_from + pos;
+        let tag = &xml[abs_pos..];
+        let tag = &tag[..tag.find('>').map_or(tag.len(), |end| end + 1)];
+        let start = extract_attr(tag, "item", "start").unwrap_or_default();
+        let end = extract_attr(tag, "item", "end").unwrap_or_default();
+        let program = extract_attr(tag, "item", "program").map(|g| xml_unescape(&g));
+        entries.push(PlaylistEntry::new(&start, &end, program)?);
+        search_from = abs_pos + 5;
+    }
+    Some(entries)
+}
+
+/// Extract target program GUIDs (`<program guid="..."/>`) from DeleteProgram XML
+fn extract_program_guids(xml: &str) -> Vec<String> {
+    let mut guids = Vec::new();
+    let mut search_from = 0;
+    while let Some(pos) = xml[search_from..].find("<program ") {
+        let abs_pos = search_from + pos;
+        if let Some(guid) = extract_attr(&xml[abs_pos..], "program", "guid") {
+            guids.push(guid);
+        }
+        search_from = abs_pos + 8;
+    }
+    guids
+}
+
 /// Extract file list from DeleteFiles XML
 fn extract_file_list(xml: &str) -> Vec<String> {
     let mut files = Vec::new();
@@ -384,10 +1001,57 @@ fn extract_file_list(xml: &str) -> Vec<String> {
     files
 }
 
+/// Extract `<relay index="N" state="on|off"/>` targets from SetRelay XML
+fn extract_relay_states(xml: &str) -> Vec<(usize, bool)> {
+    let mut relays = Vec::new();
+    let mut search_from = 0;
+    while let Some(pos) = xml[search_from..].find("<relay ") {
+        let abs_pos = search_from + pos;
+        let index = extract_attr(&xml[abs_pos..], "relay", "index").and_then(|v| v.parse().ok());
+        let state = extract_attr(&xml[abs_pos..], "relay", "state");
+        if let (Some(index), Some(state)) = (index, state) {
+            relays.push((index, matches!(state.as_str(), "on" | "1" | "true")));
+        }
+        search_from = abs_pos + 6;
+    }
+    relays
+}
+
+/// Render relay states as `<relay index="N" state="on|off"/>` elements
+fn relay_items(states: &[bool]) -> String {
+    states
+        .iter()
+        .enumerate()
+        .map(|(i, &on)| {
+            let state = if on { "on" } else { "off" };
+            format!("<relay index=\"{i}\" state=\"{state}\"/>")
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn context(
+        services: &Arc<RwLock<ServicesState>>,
+        tx: &mpsc::Sender<PlayerCommand>,
+    ) -> HandlerContext {
+        HandlerContext {
+            player_tx: tx.clone(),
+            program_dir: String::new(),
+            services: services.clone(),
+            screen_width: 32,
+            screen_height: 16,
+            device_id: "RUST-001".to_string(),
+            allow_factory_reset: false,
+            protocol_trace: false,
+            max_xml_len: crate::protocol::session::DEFAULT_MAX_XML_LEN,
+            max_file_size: crate::protocol::session::DEFAULT_MAX_FILE_SIZE,
+            fallback_media: Vec::new(),
+        }
+    }
+
     #[test]
     fn test_extract_method() {
         let xml = r#"<sdk guid="abc"><in method="AddProgram"><screen></screen></in></sdk>"#;
@@ -400,4 +1064,533 @@ mod tests {
         assert_eq!(extract_attr(xml, "luminance", "value"), Some("75".to_string()));
         assert_eq!(extract_attr(xml, "luminance", "mode"), Some("manual".to_string()));
     }
+
+    #[test]
+    fn test_extract_program_guids() {
+        let xml = r#"<sdk guid="abc"><in method="DeleteProgram"><program guid="p1"/><program guid="p2"/></in></sdk>"#;
+        assert_eq!(extract_program_guids(xml), vec!["p1", "p2"]);
+
+        let clear_all = r#"<sdk guid="abc"><in method="DeleteProgram"/></sdk>"#;
+        assert!(extract_program_guids(clear_all).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_added_programs_survive_restart() {
+        use crate::config::PlayerConfig;
+
+        let dir = tempfile::tempdir().unwrap();
+        let config = PlayerConfig::for_test(dir.path());
+        let services = Arc::new(RwLock::new(ServicesState::new(&config)));
+        let mut session = Session::new("device".to_string());
+        let (tx, mut rx) = mpsc::channel(8);
+        let ctx = context(&services, &tx);
+
+        let add = |guid: &str| {
+            format!(
+                r#"<sdk guid="x"><in method="AddProgram"><screen><program guid="{guid}"><area guid="a"><rectangle width="8" height="8"/><resources/></area></program></screen></in></sdk>"#
+            )
+        };
+        for guid in ["p1", "p2"] {
+            let resp = handle_sdk_command(&add(guid), &mut session, &ctx).await.unwrap();
+            assert!(resp.contains(r#"<result value="0"/>"#));
+        }
+        // The player is given everything received so far, not just the latest
+        rx.try_recv().unwrap();
+        let Ok(PlayerCommand::LoadScreen(screen)) = rx.try_recv() else {
+            panic!("expected LoadScreen");
+        };
+        let guids: Vec<_> = screen.programs.iter().map(|p| p.guid.as_str()).collect();
+        assert_eq!(guids, ["p1", "p2"]);
+
+        // Simulated restart: fresh services over the same directory
+        let restored = |config: &PlayerConfig| {
+            ServicesState::new(config)
+                .storage
+                .load_current_program()
+                .map(|screen| screen.programs.into_iter().map(|p| p.guid).collect::<Vec<_>>())
+        };
+        assert_eq!(restored(&config).unwrap(), ["p1", "p2"]);
+
+        let delete = r#"<sdk guid="x"><in method="DeleteProgram"><program guid="p1"/></in></sdk>"#;
+        handle_sdk_command(delete, &mut session, &ctx).await.unwrap();
+        assert_eq!(restored(&config).unwrap(), ["p2"]);
+
+        let delete_all = r#"<sdk guid="x"><in method="DeleteProgram"/></sdk>"#;
+        handle_sdk_command(delete_all, &mut session, &ctx).await.unwrap();
+        assert!(restored(&config).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_set_relay_toggles_output() {
+        use crate::config::PlayerConfig;
+        use crate::services::relay::{MockRelays, RelayService};
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut state = ServicesState::new(&PlayerConfig::for_test(dir.path()));
+        let mock = MockRelays::new(2);
+        let outputs = mock.outputs.clone();
+        state.relay = RelayService::new(Box::new(mock));
+        let services = Arc::new(RwLock::new(state));
+        let mut session = Session::new("device".to_string());
+        let (tx, _rx) = mpsc::channel(4);
+        let ctx = context(&services, &tx);
+
+        let set = r#"<sdk guid="x"><in method="SetRelay"><relay index="1" state="on"/></in></sdk>"#;
+        let resp = handle_sdk_command(set, &mut session, &ctx).await.unwrap();
+        assert!(resp.contains(r#"<relay index="1" state="on"/>"#));
+        assert!(resp.contains(r#"<result value="0"/>"#));
+        assert_eq!(*outputs.lock().unwrap(), vec![false, true]);
+
+        // State is held by the shared services, so a later connection sees it
+        let get = r#"<sdk guid="x"><in method="GetRelay"/></sdk>"#;
+        let mut other = Session::new("device".to_string());
+        let resp = handle_sdk_command(get, &mut other, &ctx).await.unwrap();
+        assert!(resp.contains(r#"<relay index="0" state="off"/><relay index="1" state="on"/>"#));
+
+        let bad = r#"<sdk guid="x"><in method="SetRelay"><relay index="5" state="on"/></in></sdk>"#;
+        let resp = handle_sdk_command(bad, &mut session, &ctx).await.unwrap();
+        assert!(resp.contains(r#"<result value="1"/>"#));
+    }
+
+    #[tokio::test]
+    async fn test_get_program_list() {
+        use crate::config::PlayerConfig;
+        use crate::core::player::ProgramSummary;
+
+        let dir = tempfile::tempdir().unwrap();
+        let state = ServicesState::new(&PlayerConfig::for_test(dir.path()));
+        let services = Arc::new(RwLock::new(state));
+        let mut session = Session::new("device".to_string());
+        let (tx, mut rx) = mpsc::channel(4);
+        let ctx = context(&services, &tx);
+
+        // Stand in for the player's command loop
+        tokio::spawn(async move {
+            while let Some(cmd) = rx.recv().await {
+                if let PlayerCommand::GetProgramList(reply) = cmd {
+                    let summary = |guid: &str, name: &str, duration_secs| ProgramSummary {
+                        guid: guid.to_string(),
+                        name: name.to_string(),
+                        area_count: 1,
+                        duration_secs,
+                    };
+                    let programs =
+                        vec![summary("p1", "One", 10), summary("p2", "Sale & <Deals>", 30)];
+                    reply.send((1, programs)).ok();
+                }
+            }
+        });
+
+        let xml = r#"<sdk guid="x"><in method="GetProgramList"/></sdk>"#;
+        let resp = handle_sdk_command(xml, &mut session, &ctx).await.unwrap();
+        assert!(resp.contains(r#"<programs current="1" count="2">"#));
+        assert!(resp.contains(
+            r#"<program index="0" guid="p1" name="One" areaCount="1" duration="10" playing="false"/>"#
+        ));
+        assert!(resp.contains(
+            r#"<program index="1" guid="p2" name="Sale &amp; &lt;Deals&gt;" areaCount="1" duration="30" playing="true"/>"#
+        ));
+        assert!(resp.contains(r#"<result value="0"/>"#));
+    }
+
+    #[tokio::test]
+    async fn test_get_raw_frame() {
+        use crate::config::PlayerConfig;
+
+        let dir = tempfile::tempdir().unwrap();
+        let state = ServicesState::new(&PlayerConfig::for_test(dir.path()));
+        let services = Arc::new(RwLock::new(state));
+        let mut session = Session::new("device".to_string());
+        let (tx, mut rx) = mpsc::channel(4);
+        let ctx = context(&services, &tx);
+
+        tokio::spawn(async move {
+            while let Some(cmd) = rx.recv().await {
+                if let PlayerCommand::CaptureFrame(reply) = cmd {
+                    let pixels = [255, 0, 0, 255].repeat(8 * 4);
+                    reply.send((8, 4, pixels)).ok();
+                }
+            }
+        });
+
+        let xml = r#"<sdk guid="x"><in method="GetRawFrame"/></sdk>"#;
+        let resp = handle_sdk_command(xml, &mut session, &ctx).await.unwrap();
+        assert!(resp.contains(r#"<frame width="8" height="4" format="RGBA">"#));
+        assert!(resp.contains(r#"<result value="0"/>"#));
+        let start = resp.find("RGBA\">").unwrap() + "RGBA\">".len();
+        let end = resp.find("</frame>").unwrap();
+        let pixels = BASE64.decode(&resp[start..end]).unwrap();
+        assert_eq!(pixels.len(), 8 * 4 * 4);
+        assert_eq!(&pixels[..4], &[255, 0, 0, 255]);
+    }
+
+    #[tokio::test]
+    async fn test_set_screen_color() {
+        use crate::config::PlayerConfig;
+
+        let dir = tempfile::tempdir().unwrap();
+        let state = ServicesState::new(&PlayerConfig::for_test(dir.path()));
+        let services = Arc::new(RwLock::new(state));
+        let mut session = Session::new("device".to_string());
+        let (tx, mut rx) = mpsc::channel(4);
+        let ctx = context(&services, &tx);
+
+        let xml = r##"<sdk guid="x"><in method="SetScreenColor"><color value="#FF8000"/></in></sdk>"##;
+        let resp = handle_sdk_command(xml, &mut session, &ctx).await.unwrap();
+        assert!(resp.contains(r#"<result value="0"/>"#));
+        assert!(matches!(rx.try_recv(), Ok(PlayerCommand::FillColor(255, 128, 0))));
+
+        // Malformed colors are rejected rather than guessed
+        let xml = r#"<sdk guid="x"><in method="FillScreen"><color value="red"/></in></sdk>"#;
+        let resp = handle_sdk_command(xml, &mut session, &ctx).await.unwrap();
+        assert!(resp.contains(r#"<result value="1"/>"#));
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_factory_reset_clears_programs_and_schedules() {
+        use crate::config::PlayerConfig;
+        use crate::services::relay::{MockRelays, RelayService};
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut state = ServicesState::new(&PlayerConfig::for_test(dir.path()));
+        let relays = MockRelays::new(2);
+        let outputs = relays.outputs.clone();
+        state.relay = RelayService::new(Box::new(relays));
+        state.relay.set(1, true).unwrap();
+        state.brightness.set_level(40);
+        let services = Arc::new(RwLock::new(state));
+        let mut session = Session::new("device".to_string());
+        let (tx, mut rx) = mpsc::channel(8);
+        let ctx = context(&services, &tx);
+
+        std::fs::write(dir.path().join("current_program.xml"), "<screen/>").unwrap();
+        std::fs::write(dir.path().join("logo.png"), b"png").unwrap();
+        let schedule = r#"<sdk guid="x"><in method="SetSwitchTime"><item onTime="08:00" offTime="22:00" days="1111111"/></in></sdk>"#;
+        handle_sdk_command(schedule, &mut session, &ctx).await.unwrap();
+        services.read().await.device_name.set("Lobby").unwrap();
+
+        // Refused unless the safety flag is set
+        let reset = r#"<sdk guid="x"><in method="FactoryReset"/></sdk>"#;
+        let resp = handle_sdk_command(reset, &mut session, &ctx).await.unwrap();
+        assert!(resp.contains(r#"<result value="1"/>"#));
+        assert_eq!(services.read().await.screen_schedule.get_schedule().len(), 1);
+        assert!(rx.try_recv().is_err());
+
+        let ctx = HandlerContext {
+            allow_factory_reset: true,
+            ..ctx
+        };
+        let resp = handle_sdk_command(reset, &mut session, &ctx).await.unwrap();
+        assert!(resp.contains(r#"<out method="FactoryReset"><result value="0"/>"#));
+        {
+            let state = services.read().await;
+            assert!(state.screen_schedule.get_schedule().is_empty());
+            assert!(state.storage.list_files().is_empty());
+            assert_eq!(state.device_name.get(), crate::services::device_name::DEFAULT_DEVICE_NAME);
+            assert_eq!(state.brightness.get_level(), 100);
+        }
+        assert_eq!(*outputs.lock().unwrap(), [false, false]);
+        match rx.recv().await {
+            Some(PlayerCommand::LoadScreen(screen)) => assert!(screen.programs.is_empty()),
+            other => panic!("expected LoadScreen, got {other:?}"),
+        }
+        match rx.recv().await {
+            Some(PlayerCommand::SetPlaylist(playlist)) => assert!(playlist.is_empty()),
+            other => panic!("expected SetPlaylist, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_protocol_trace_logs_sdk_command() {
+        use crate::config::PlayerConfig;
+
+        #[derive(Clone, Default)]
+        struct Captured(Arc<std::sync::Mutex<Vec<u8>>>);
+        impl std::io::Write for Captured {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+        let captured = Captured::default();
+        let writer = captured.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer(move || writer.clone())
+            .with_ansi(false)
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let dir = tempfile::tempdir().unwrap();
+        let state = ServicesState::new(&PlayerConfig::for_test(dir.path()));
+        let services = Arc::new(RwLock::new(state));
+        let mut session = Session::new("device".to_string());
+        let (tx, _rx) = mpsc::channel(4);
+        let xml = r#"<sdk guid="x"><in method="GetDeviceName"/></sdk>"#;
+        let traced = || String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+
+        handle_sdk_command(xml, &mut session, &context(&services, &tx)).await.unwrap();
+        assert!(!traced().contains(trace::TARGET));
+
+        let ctx = HandlerContext {
+            protocol_trace: true,
+            ..context(&services, &tx)
+        };
+        handle_sdk_command(xml, &mut session, &ctx).await.unwrap();
+        let line = traced().lines().find(|l| l.contains(trace::TARGET)).unwrap().to_string();
+        assert!(line.contains("method=GetDeviceName"), "{line}");
+        assert!(line.contains(xml), "{line}");
+    }
+
+    #[tokio::test]
+    async fn test_ntp_server_round_trip() {
+        use crate::config::PlayerConfig;
+
+        let dir = tempfile::tempdir().unwrap();
+        let config = PlayerConfig::for_test(dir.path());
+        let mut state = ServicesState::new(&config);
+        // Record syncs rather than running ntpdate against the host clock
+        let (synced_tx, mut synced_rx) = mpsc::unbounded_channel();
+        state.time_sync = state
+            .time_sync
+            .with_sync(move |server: &str| synced_tx.send(server.to_string()).unwrap());
+        let services = Arc::new(RwLock::new(state));
+        let mut session = Session::new("device".to_string());
+        let (tx, _rx) = mpsc::channel(4);
+        let ctx = context(&services, &tx);
+
+        let get = r#"<sdk guid="x"><in method="GetNtpServer"/></sdk>"#;
+        let resp = handle_sdk_command(get, &mut session, &ctx).await.unwrap();
+        assert!(resp.contains(r#"<ntp server="pool.ntp.org"/>"#));
+
+        let set = r#"<sdk guid="x"><in method="SetNtpServer"><ntp server="time.example.com"/></in></sdk>"#;
+        let resp = handle_sdk_command(set, &mut session, &ctx).await.unwrap();
+        assert!(resp.contains(r#"<result value="0"/>"#));
+        let resp = handle_sdk_command(get, &mut session, &ctx).await.unwrap();
+        assert!(resp.contains(r#"<ntp server="time.example.com"/>"#));
+        // The new server is synced against right away
+        assert_eq!(synced_rx.recv().await.unwrap(), "time.example.com");
+
+        for bad in [r#"<ntp server=""/>"#, r#"<ntp server="-q"/>"#, "<ntp/>"] {
+            let set = format!(r#"<sdk guid="x"><in method="SetNtpServer">{bad}</in></sdk>"#);
+            let resp =
+                handle_sdk_command(&set, &mut session, &ctx).await.unwrap();
+            assert!(resp.contains(r#"<result value="1"/>"#), "{bad}");
+        }
+
+        // Persisted, so it survives a restart
+        let restarted = ServicesState::new(&config);
+        assert_eq!(restarted.time_sync.server(), "time.example.com");
+    }
+
+    #[tokio::test]
+    async fn test_device_name_round_trip() {
+        use crate::config::PlayerConfig;
+
+        let dir = tempfile::tempdir().unwrap();
+        let config = PlayerConfig::for_test(dir.path());
+        let services = Arc::new(RwLock::new(ServicesState::new(&config)));
+        let mut session = Session::new("device".to_string());
+        let (tx, _rx) = mpsc::channel(4);
+        let ctx = context(&services, &tx);
+
+        let get = r#"<sdk guid="x"><in method="GetDeviceName"/></sdk>"#;
+        let resp = handle_sdk_command(get, &mut session, &ctx).await.unwrap();
+        assert!(resp.contains(r#"<device name="BoxPlayer"/>"#));
+
+        let set = r#"<sdk guid="x"><in method="SetDeviceName"><device name="Lobby &amp; Bar"/></in></sdk>"#;
+        let resp = handle_sdk_command(set, &mut session, &ctx).await.unwrap();
+        assert!(resp.contains(r#"<result value="0"/>"#));
+        let resp = handle_sdk_command(get, &mut session, &ctx).await.unwrap();
+        assert!(resp.contains(r#"<device name="Lobby &amp; Bar"/>"#));
+
+        let info = r#"<sdk guid="x"><in method="GetDeviceInfo"/></sdk>"#;
+        let resp = handle_sdk_command(info, &mut session, &ctx).await.unwrap();
+        assert!(resp.contains(r#"deviceName="Lobby &amp; Bar""#));
+
+        let empty = r#"<sdk guid="x"><in method="SetDeviceName"><device name=" "/></in></sdk>"#;
+        let resp = handle_sdk_command(empty, &mut session, &ctx).await.unwrap();
+        assert!(resp.contains(r#"<result value="1"/>"#));
+
+        // Persisted, and shared with clones such as the discovery broadcaster's
+        let restarted = ServicesState::new(&config);
+        assert_eq!(restarted.device_name.get(), "Lobby & Bar");
+    }
+
+    #[tokio::test]
+    async fn test_add_blank_program_warns() {
+        use crate::config::PlayerConfig;
+
+        let dir = tempfile::tempdir().unwrap();
+        let config = PlayerConfig::for_test(dir.path());
+        let services = Arc::new(RwLock::new(ServicesState::new(&config)));
+        let mut session = Session::new("device".to_string());
+        let (tx, mut rx) = mpsc::channel(4);
+        let ctx = context(&services, &tx);
+
+        let add = r##"<sdk guid="x"><in method="AddProgram"><screen>
+            <program guid="empty"/>
+            <program guid="unsized"><area guid="a"><rectangle width="0" height="8"/>
+              <resources><text guid="t"><string>hi</string></text></resources></area></program>
+            <program guid="ok"><area guid="b" bgColor="#FF0000"><rectangle width="8" height="8"/>
+              <resources/></area></program>
+            </screen></in></sdk>"##;
+        let resp = handle_sdk_command(add, &mut session, &ctx).await.unwrap();
+        assert!(resp.contains(r#"<result value="0"/>"#));
+        assert!(resp.contains(r#"<warning message="program empty has nothing to display"/>"#));
+        assert!(resp.contains(r#"<warning message="program unsized has nothing to display"/>"#));
+        assert!(!resp.contains("program ok"));
+
+        // Still accepted and loaded
+        let Some(PlayerCommand::LoadScreen(screen)) = rx.recv().await else {
+            panic!("expected LoadScreen");
+        };
+        assert_eq!(screen.programs.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_query_if_version_lists_capabilities() {
+        use crate::config::PlayerConfig;
+
+        let dir = tempfile::tempdir().unwrap();
+        let config = PlayerConfig::for_test(dir.path());
+        let services = Arc::new(RwLock::new(ServicesState::new(&config)));
+        let mut session = Session::new("device".to_string());
+        let (tx, _rx) = mpsc::channel(4);
+        let ctx = context(&services, &tx);
+
+        let query = r#"<sdk guid="x"><in method="QueryIFVersion"/></sdk>"#;
+        let resp = handle_sdk_command(query, &mut session, &ctx).await.unwrap();
+        assert!(resp.contains(r#"<version value="0x1000000"/><capabilities>"#));
+        for kind in BUILTIN_KINDS {
+            assert!(resp.contains(&format!(r#"<capability name="{}"/>"#, kind.name())));
+        }
+        assert!(resp.contains(r#"<capability name="video"/>"#));
+        assert!(!resp.contains("sensor"));
+        assert!(!resp.contains("intercut"));
+    }
+
+    #[tokio::test]
+    async fn test_boot_logo_round_trip() {
+        use crate::config::PlayerConfig;
+
+        let dir = tempfile::tempdir().unwrap();
+        let config = PlayerConfig::for_test(dir.path());
+        let services = Arc::new(RwLock::new(ServicesState::new(&config)));
+        let mut session = Session::new("device".to_string());
+        let (tx, _rx) = mpsc::channel(4);
+        let ctx = context(&services, &tx);
+
+        let get = r#"<sdk guid="x"><in method="GetBootLogo"/></sdk>"#;
+        let resp = handle_sdk_command(get, &mut session, &ctx).await.unwrap();
+        assert!(resp.contains(r#"<bootLogo name=""/>"#));
+
+        let set = r#"<sdk guid="x"><in method="SetBootLogoName"><bootLogo name="logo.png"/></in></sdk>"#;
+        let resp = handle_sdk_command(set, &mut session, &ctx).await.unwrap();
+        assert!(resp.contains(r#"<result value="0"/>"#));
+        let resp = handle_sdk_command(get, &mut session, &ctx).await.unwrap();
+        assert!(resp.contains(r#"<bootLogo name="logo.png"/>"#));
+        assert_eq!(ServicesState::new(&config).boot_logo.get().as_deref(), Some("logo.png"));
+
+        let escape = r#"<sdk guid="x"><in method="SetBootLogoName"><bootLogo name="../x.png"/></in></sdk>"#;
+        let resp = handle_sdk_command(escape, &mut session, &ctx).await.unwrap();
+        assert!(resp.contains(r#"<result value="1"/>"#));
+
+        let clear = r#"<sdk guid="x"><in method="ClearBootLogo"/></sdk>"#;
+        handle_sdk_command(clear, &mut session, &ctx).await.unwrap();
+        let resp = handle_sdk_command(get, &mut session, &ctx).await.unwrap();
+        assert!(resp.contains(r#"<bootLogo name=""/>"#));
+        assert_eq!(ServicesState::new(&config).boot_logo.get(), None);
+    }
+
+    #[tokio::test]
+    async fn test_device_info_reports_configured_id() {
+        use crate::config::PlayerConfig;
+
+        let dir = tempfile::tempdir().unwrap();
+        let state = ServicesState::new(&PlayerConfig::for_test(dir.path()));
+        let services = Arc::new(RwLock::new(state));
+        let (tx, _rx) = mpsc::channel(4);
+        let ctx = HandlerContext {
+            device_id: "LOBBY-42".to_string(),
+            screen_width: 192,
+            screen_height: 96,
+            ..context(&services, &tx)
+        };
+        let mut session = Session::new("device".to_string());
+
+        let xml = r#"<sdk guid="x"><in method="GetDeviceInfo"/></sdk>"#;
+        let resp = handle_sdk_command(xml, &mut session, &ctx).await.unwrap();
+        assert!(resp.contains(r#"deviceID="LOBBY-42""#));
+        assert!(resp.contains(r#"screenWidth="192" screenHeight="96""#));
+        assert!(resp.contains(&format!(r#"fpgaVersion="{}""#, env!("CARGO_PKG_VERSION"))));
+    }
+
+    #[tokio::test]
+    async fn test_locked_device_rejects_add_program() {
+        use crate::config::PlayerConfig;
+
+        let dir = tempfile::tempdir().unwrap();
+        let config = PlayerConfig::for_test(dir.path());
+        let services = Arc::new(RwLock::new(ServicesState::new(&config)));
+        let (tx, mut rx) = mpsc::channel(4);
+        let ctx = context(&services, &tx);
+
+        let mut owner = Session::new("device".to_string());
+        let lock = r#"<sdk guid="x"><in method="SetDevicePassword"><password value="s3cret"/></in></sdk>"#;
+        let resp = handle_sdk_command(lock, &mut owner, &ctx).await.unwrap();
+        assert!(resp.contains(r#"<result value="0"/>"#));
+        // Persisted
+        assert!(ServicesState::new(&config).lock.is_locked());
+
+        // Another client without the password can look but not touch
+        let mut session = Session::new("device".to_string());
+        let add = r#"<sdk guid="x"><in method="AddProgram"><screen>
+            <program guid="p"><area guid="a"><rectangle width="8" height="8"/>
+              <resources><text guid="t"><string>hi</string></text></resources></area></program>
+            </screen></in></sdk>"#;
+        let resp = handle_sdk_command(add, &mut session, &ctx).await.unwrap();
+        assert!(resp.contains(r#"<result value="2"/>"#));
+        assert!(rx.try_recv().is_err());
+        let info = r#"<sdk guid="x"><in method="GetDeviceInfo"/></sdk>"#;
+        let resp = handle_sdk_command(info, &mut session, &ctx).await.unwrap();
+        assert!(resp.contains(r#"<result value="0"/>"#));
+
+        // A wrong password doesn't help; the right one does
+        let verify = |password: &str| {
+            format!(
+                r#"<sdk guid="x"><in method="VerifyDevicePassword"><password value="{password}"/></in></sdk>"#
+            )
+        };
+        let resp = handle_sdk_command(&verify("guess"), &mut session, &ctx).await.unwrap();
+        assert!(resp.contains(r#"<result value="1"/>"#));
+        let resp = handle_sdk_command(add, &mut session, &ctx).await.unwrap();
+        assert!(resp.contains(r#"<result value="2"/>"#));
+        let resp = handle_sdk_command(&verify("s3cret"), &mut session, &ctx).await.unwrap();
+        assert!(resp.contains(r#"<result value="0"/>"#));
+        let resp = handle_sdk_command(add, &mut session, &ctx).await.unwrap();
+        assert!(resp.contains(r#"<result value="0"/>"#));
+        assert!(matches!(rx.try_recv(), Ok(PlayerCommand::LoadScreen(_))));
+    }
+
+    #[tokio::test]
+    async fn test_get_version_reports_crate_version() {
+        use crate::config::PlayerConfig;
+
+        let dir = tempfile::tempdir().unwrap();
+        let state = ServicesState::new(&PlayerConfig::for_test(dir.path()));
+        let services = Arc::new(RwLock::new(state));
+        let (tx, _rx) = mpsc::channel(4);
+        let ctx = context(&services, &tx);
+        let mut session = Session::new("device".to_string());
+
+        let xml = r#"<sdk guid="x"><in method="GetVersion"/></sdk>"#;
+        let resp = handle_sdk_command(xml, &mut session, &ctx).await.unwrap();
+        assert!(resp.contains(&format!(r#"app="{}""#, env!("CARGO_PKG_VERSION"))));
+        assert!(resp.contains(r#"gitHash=""#));
+        assert!(resp.contains(r#"<result value="0"/>"#));
+    }
 }
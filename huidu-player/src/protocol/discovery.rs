@@ -2,14 +2,29 @@
 /// The real BoxPlayer protocol uses binary packet headers followed by XML payloads.
 /// The device both responds to HDPlayer search requests AND periodically broadcasts
 /// its device info to 255.255.255.255:9527.
+/// On IPv6 networks the same packets are exchanged over the link-local
+/// multicast group `DISCOVERY_GROUP_V6`.
 use anyhow::Result;
+use socket2::{Domain, Protocol, Socket, Type};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
 use tokio::net::UdpSocket;
 use tokio::time::{self, Duration};
 use tracing::{debug, info, warn};
 
+use crate::protocol::trace;
+use crate::services::device_name::DeviceNameService;
+use crate::services::lock::LockService;
+
 /// Discovery port used by Huidu protocol (confirmed in both HDPlayer.exe and BoxPlayer binaries)
 pub const DISCOVERY_PORT: u16 = 9527;
 
+/// Link-local multicast group used for IPv6 discovery (IPv6 has no broadcast)
+pub const DISCOVERY_GROUP_V6: Ipv6Addr = Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 0x9527);
+
+/// Well-known public addresses used only to ask the OS which local address routes out
+const ROUTE_PROBE_V4: &str = "8.8.8.8:80";
+const ROUTE_PROBE_V6: &str = "[2001:4860:4860::8888]:80";
+
 /// Device info for discovery responses
 #[derive(Clone)]
 pub struct DeviceInfo {
@@ -17,30 +32,84 @@ pub struct DeviceInfo {
     pub ip_address: String,
     pub screen_width: u16,
     pub screen_height: u16,
-    pub player_name: String,
 }
 
-/// Run the UDP discovery service — listens for search requests AND broadcasts periodically
-pub async fn run(device_info: DeviceInfo) -> Result<()> {
+/// Run the UDP discovery service — listens for search requests AND broadcasts periodically.
+/// IPv6 discovery runs alongside IPv4 when the host has IPv6. Packets carry
+/// the current `name` and `lock` state, so a change shows up in the next
+/// broadcast. With `protocol_trace` every packet received and sent is logged.
+pub async fn run(
+    device_info: DeviceInfo,
+    name: DeviceNameService,
+    lock: LockService,
+    protocol_trace: bool,
+) -> Result<()> {
     let addr = format!("0.0.0.0:{}", DISCOVERY_PORT);
     let socket = UdpSocket::bind(&addr).await?;
     socket.set_broadcast(true)?;
     info!("UDP discovery listening on {}", addr);
+    let broadcast_addr = SocketAddr::from((Ipv4Addr::BROADCAST, DISCOVERY_PORT));
+    let v4 = serve(socket, broadcast_addr, &device_info, &name, &lock, protocol_trace);
+
+    match bind_v6() {
+        Ok(socket) => {
+            info!("UDP discovery listening on [::]:{} ({})", DISCOVERY_PORT, DISCOVERY_GROUP_V6);
+            let group_addr = SocketAddr::from((DISCOVERY_GROUP_V6, DISCOVERY_PORT));
+            let v6 = serve(socket, group_addr, &device_info, &name, &lock, protocol_trace);
+            tokio::try_join!(v4, v6)?;
+            Ok(())
+        }
+        Err(e) => {
+            debug!("IPv6 discovery unavailable: {}", e);
+            v4.await
+        }
+    }
+}
+
+/// IPv6-only socket on the discovery port, joined to the discovery group.
+/// IPv6-only so it doesn't collide with the IPv4 socket on dual-stack hosts.
+fn bind_v6() -> Result<UdpSocket> {
+    let socket = Socket::new(Domain::IPV6, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_only_v6(true)?;
+    socket.set_reuse_address(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&SocketAddr::from((Ipv6Addr::UNSPECIFIED, DISCOVERY_PORT)).into())?;
+    // Interface 0: let the OS pick the default multicast interface
+    socket.join_multicast_v6(&DISCOVERY_GROUP_V6, 0)?;
+    Ok(UdpSocket::from_std(socket.into())?)
+}
 
+/// Answer search requests on `socket` and announce the device to `announce_addr`
+async fn serve(
+    socket: UdpSocket,
+    announce_addr: SocketAddr,
+    device_info: &DeviceInfo,
+    name: &DeviceNameService,
+    lock: &LockService,
+    protocol_trace: bool,
+) -> Result<()> {
     let mut buf = [0u8; 2048];
     let mut broadcast_interval = time::interval(Duration::from_secs(3));
 
-    // Build the broadcast packets
-    let dev_info_packet = build_device_info_packet(&device_info);
-    let ext1_packet = build_ext1_packet(&device_info);
-    let broadcast_addr = "255.255.255.255:9527";
-
     loop {
+        // Build the packets fresh each time so they carry the current name and lock
+        let dev_info_packet = build_device_info_packet(device_info, &name.get());
+        let ext1_packet = build_ext1_packet(device_info, lock.is_locked());
+
         tokio::select! {
             result = socket.recv_from(&mut buf) => {
                 match result {
                     Ok((len, peer)) => {
                         debug!("UDP recv {} bytes from {}", len, peer);
+                        if protocol_trace {
+                            info!(
+                                target: trace::TARGET,
+                                "UDP in from {} ({} bytes): {}",
+                                peer,
+                                len,
+                                trace::hex_preview(&buf[..len])
+                            );
+                        }
 
                         // Try to detect if this is a search request
                         // HDPlayer may send various packet formats; respond to anything
@@ -62,6 +131,9 @@ pub async fn run(device_info: DeviceInfo) -> Result<()> {
                                 warn!("Failed to send Ext1 response: {}", e);
                             }
                             info!("Responded to search from {}", peer);
+                            if protocol_trace {
+                                trace_replies(peer, &dev_info_packet, &ext1_packet);
+                            }
                         }
                     }
                     Err(e) => {
@@ -71,22 +143,38 @@ pub async fn run(device_info: DeviceInfo) -> Result<()> {
             }
             _ = broadcast_interval.tick() => {
                 // Periodically broadcast device info (like RespondDevInfoTimer in BoxPlayer)
-                if let Err(e) = socket.send_to(&dev_info_packet, broadcast_addr).await {
+                if let Err(e) = socket.send_to(&dev_info_packet, announce_addr).await {
                     debug!("Broadcast DeviceInfo failed: {}", e);
                 }
                 tokio::time::sleep(Duration::from_millis(50)).await;
-                if let Err(e) = socket.send_to(&ext1_packet, broadcast_addr).await {
+                if let Err(e) = socket.send_to(&ext1_packet, announce_addr).await {
                     debug!("Broadcast Ext1 failed: {}", e);
                 }
                 debug!("Broadcast device info");
+                if protocol_trace {
+                    trace_replies(announce_addr, &dev_info_packet, &ext1_packet);
+                }
             }
         }
     }
 }
 
+/// Trace the DeviceInfo and Ext1 packets sent to `to`
+fn trace_replies(to: SocketAddr, dev_info_packet: &[u8], ext1_packet: &[u8]) {
+    for packet in [dev_info_packet, ext1_packet] {
+        info!(
+            target: trace::TARGET,
+            "UDP out to {} ({} bytes): {}",
+            to,
+            packet.len(),
+            trace::hex_preview(packet)
+        );
+    }
+}
+
 /// Build the DeviceInfo packet matching the BoxPlayer format.
 /// Format observed in Wireshark: [device_id bytes][binary header][BoxPlayer name][DeviceInfo XML]
-fn build_device_info_packet(info: &DeviceInfo) -> Vec<u8> {
+fn build_device_info_packet(info: &DeviceInfo, player_name: &str) -> Vec<u8> {
     let mut packet = Vec::new();
 
     // Device ID (padded to 15 bytes with nulls, matching BoxPlayer)
@@ -96,23 +184,23 @@ fn build_device_info_packet(info: &DeviceInfo) -> Vec<u8> {
     id_buf[..copy_len].copy_from_slice(&id_bytes[..copy_len]);
     packet.extend_from_slice(&id_buf);
 
-    // IP address as 4 bytes
-    let ip_parts: Vec<u8> = info
-        .ip_address
-        .split('.')
-        .filter_map(|s| s.parse().ok())
-        .collect();
-    if ip_parts.len() == 4 {
-        packet.extend_from_slice(&ip_parts);
-    } else {
-        packet.extend_from_slice(&[0, 0, 0, 0]);
+    // IP address as 4 bytes. An IPv6 address doesn't fit, so it leaves these
+    // zero and is carried in the XML below for clients that know the IPv6 capability.
+    let ip = info.ip_address.parse::<IpAddr>().ok();
+    match ip {
+        Some(IpAddr::V4(v4)) => packet.extend_from_slice(&v4.octets()),
+        _ => packet.extend_from_slice(&[0, 0, 0, 0]),
     }
 
     // Player name (null-terminated)
-    packet.extend_from_slice(info.player_name.as_bytes());
+    packet.extend_from_slice(player_name.as_bytes());
     packet.push(0);
 
     // DeviceInfo XML
+    let ipv6 = match ip {
+        Some(IpAddr::V6(v6)) => format!("<Capability IPv6=\"1\"/><IPv6Address Value=\"{v6}\"/>"),
+        _ => String::new(),
+    };
     let xml = format!(
         "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
          <DeviceInfo>\
@@ -120,6 +208,7 @@ fn build_device_info_packet(info: &DeviceInfo) -> Vec<u8> {
          <ScreenOnOff Value=\"1\"/>\
          <ScreenR Value=\"0\"/>\
          <HardwareVersion Value=\"1.0\"/>\
+         {ipv6}\
          </DeviceInfo>"
     );
     packet.extend_from_slice(xml.as_bytes());
@@ -128,8 +217,9 @@ fn build_device_info_packet(info: &DeviceInfo) -> Vec<u8> {
 }
 
 /// Build the ext1 status packet.
-/// Format: [device_id bytes][ext1 XML with play status]
-fn build_ext1_packet(info: &DeviceInfo) -> Vec<u8> {
+/// Format: [device_id bytes][ext1 XML with play status]; `locked` is whether
+/// a device password is set
+fn build_ext1_packet(info: &DeviceInfo, locked: bool) -> Vec<u8> {
     let mut packet = Vec::new();
 
     // Device ID (padded to 15 bytes)
@@ -140,13 +230,14 @@ fn build_ext1_packet(info: &DeviceInfo) -> Vec<u8> {
     packet.extend_from_slice(&id_buf);
 
     // ext1 XML with status info
+    let locked = u8::from(locked);
     let xml = format!(
         "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
          <ext1>\
          <PlayStatus value=\"1\"/>\
          <ProgramIndex index=\"0\"/>\
          <ProgramCount count=\"1\" normalCount=\"1\" intercutCount=\"0\"/>\
-         <DeviceLocker enable=\"0\"/>\
+         <DeviceLocker enable=\"{locked}\"/>\
          <WifiApPasswd simple=\"1\"/>\
          </ext1>"
     );
@@ -155,15 +246,76 @@ fn build_ext1_packet(info: &DeviceInfo) -> Vec<u8> {
     packet
 }
 
-/// Get the local IP address for the discovery response
+/// Get the local IP address for the discovery response: the IPv4 address
+/// when there is one, otherwise the IPv6 one (IPv6-only networks)
 pub fn get_local_ip() -> String {
-    let socket = std::net::UdpSocket::bind("0.0.0.0:0").ok();
-    if let Some(sock) = socket {
-        if sock.connect("8.8.8.8:80").is_ok() {
-            if let Ok(addr) = sock.local_addr() {
-                return addr.ip().to_string();
-            }
+    pick_local_ip(route_source).to_string()
+}
+
+/// Choose the address family given `probe`, which returns the local address
+/// routing to a remote address (None when that family has no route)
+fn pick_local_ip(probe: impl Fn(&str) -> Option<IpAddr>) -> IpAddr {
+    probe(ROUTE_PROBE_V4)
+        .filter(|ip| !ip.is_unspecified())
+        .or_else(|| probe(ROUTE_PROBE_V6).filter(|ip| !ip.is_unspecified()))
+        .unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED))
+}
+
+/// Local address the OS would send from to reach `target`. Connecting a UDP
+/// socket only looks up the route; no packets are sent.
+fn route_source(target: &str) -> Option<IpAddr> {
+    let target: SocketAddr = target.parse().ok()?;
+    let bind = if target.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+    let sock = std::net::UdpSocket::bind(bind).ok()?;
+    sock.connect(target).ok()?;
+    Some(sock.local_addr().ok()?.ip())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn device(ip: &str) -> DeviceInfo {
+        DeviceInfo {
+            device_id: "RUST-001".to_string(),
+            ip_address: ip.to_string(),
+            screen_width: 128,
+            screen_height: 64,
         }
     }
-    "0.0.0.0".to_string()
+
+    #[test]
+    fn test_local_ip_falls_back_to_ipv6() {
+        let v6: IpAddr = "2001:db8::42".parse().unwrap();
+        let ip = pick_local_ip(|target| (target == ROUTE_PROBE_V6).then_some(v6));
+        assert_eq!(ip, v6);
+
+        // IPv4 wins on dual-stack hosts
+        let v4: IpAddr = "192.168.1.20".parse().unwrap();
+        let ip = pick_local_ip(|target| Some(if target == ROUTE_PROBE_V4 { v4 } else { v6 }));
+        assert_eq!(ip, v4);
+
+        assert_eq!(pick_local_ip(|_| None), IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+    }
+
+    #[test]
+    fn test_device_info_packet_carries_ip() {
+        let packet = build_device_info_packet(&device("192.168.1.20"), "BoxPlayer");
+        assert_eq!(&packet[15..19], &[192, 168, 1, 20]);
+        assert!(!String::from_utf8_lossy(&packet).contains("IPv6"));
+
+        let packet = build_device_info_packet(&device("2001:db8::42"), "BoxPlayer");
+        assert_eq!(&packet[15..19], &[0, 0, 0, 0]);
+        let text = String::from_utf8_lossy(&packet);
+        assert!(text.contains(r#"<Capability IPv6="1"/><IPv6Address Value="2001:db8::42"/>"#));
+    }
+
+    #[test]
+    fn test_ext1_reports_device_lock() {
+        let packet = build_ext1_packet(&device("192.168.1.20"), false);
+        assert!(String::from_utf8_lossy(&packet).contains(r#"<DeviceLocker enable="0"/>"#));
+
+        let packet = build_ext1_packet(&device("192.168.1.20"), true);
+        assert!(String::from_utf8_lossy(&packet).contains(r#"<DeviceLocker enable="1"/>"#));
+    }
 }
@@ -0,0 +1,242 @@
+/// Minimal HTTP/1.1 server for the player's small web endpoints.
+/// One request per connection, no keep-alive or chunked bodies — just enough
+/// for probes and a browser, without pulling in a web framework. A response
+/// can instead stream its body until the sender hangs up (MJPEG preview).
+use anyhow::{bail, Context, Result};
+use std::future::Future;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tracing::{debug, warn};
+
+/// Largest request head (request line + headers) we read
+const MAX_HEAD_LEN: usize = 8 * 1024;
+
+/// Largest request body we accept
+const MAX_BODY_LEN: usize = 16 * 1024 * 1024;
+
+#[derive(Debug)]
+pub struct Request {
+    pub method: String,
+    /// Path without the query string
+    pub path: String,
+    /// Header names and values in the order received
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    /// Value of the first header called `name` (case-insensitive)
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+#[derive(Debug)]
+pub struct Response {
+    pub status: u16,
+    pub content_type: &'static str,
+    /// Headers beyond the ones every response gets
+    pub headers: Vec<(&'static str, String)>,
+    pub body: Vec<u8>,
+    /// Body chunks written as they arrive, instead of `body`, until the
+    /// channel closes or the client goes away
+    pub stream: Option<mpsc::Receiver<Vec<u8>>>,
+}
+
+impl Response {
+    pub fn new(status: u16, content_type: &'static str, body: impl Into<Vec<u8>>) -> Self {
+        Self {
+            status,
+            content_type,
+            headers: Vec::new(),
+            body: body.into(),
+            stream: None,
+        }
+    }
+
+    /// A 200 response whose body is whatever arrives on `chunks`
+    pub fn stream(content_type: &'static str, chunks: mpsc::Receiver<Vec<u8>>) -> Self {
+        Self {
+            stream: Some(chunks),
+            ..Self::new(200, content_type, Vec::new())
+        }
+    }
+
+    pub fn with_header(mut self, name: &'static str, value: impl Into<String>) -> Self {
+        self.headers.push((name, value.into()));
+        self
+    }
+
+    pub fn json(status: u16, body: impl Into<Vec<u8>>) -> Self {
+        Self::new(status, "application/json", body)
+    }
+
+    pub fn text(status: u16, body: impl Into<Vec<u8>>) -> Self {
+        Self::new(status, "text/plain; charset=utf-8", body)
+    }
+
+    pub fn not_found() -> Self {
+        Self::text(404, "not found\n")
+    }
+}
+
+/// Accept connections on `listener` and answer each request with `handler`
+pub async fn serve<H, F>(listener: TcpListener, handler: H) -> Result<()>
+where
+    H: Fn(Request) -> F + Clone + Send + Sync + 'static,
+    F: Future<Output = Response> + Send,
+{
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let handler = handler.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(stream, handler).await {
+                debug!("HTTP connection from {} failed: {}", peer, e);
+            }
+        });
+    }
+}
+
+async fn handle_connection<H, F>(mut stream: TcpStream, handler: H) -> Result<()>
+where
+    H: Fn(Request) -> F,
+    F: Future<Output = Response>,
+{
+    let response = match read_request(&mut stream).await {
+        Ok(request) => handler(request).await,
+        Err(e) => {
+            warn!("Bad HTTP request: {}", e);
+            Response::text(400, "bad request\n")
+        }
+    };
+    write_response(&mut stream, response).await
+}
+
+async fn read_request(stream: &mut TcpStream) -> Result<Request> {
+    // Read until the end of the headers; anything after is the start of the body
+    let mut buf = Vec::new();
+    let head_end = loop {
+        if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos;
+        }
+        if buf.len() > MAX_HEAD_LEN {
+            bail!("request head longer than {MAX_HEAD_LEN} bytes");
+        }
+        let mut chunk = [0u8; 1024];
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            bail!("connection closed before end of headers");
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    };
+
+    let head = std::str::from_utf8(&buf[..head_end]).context("request head is not UTF-8")?;
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next().unwrap_or_default().split_whitespace();
+    let method = request_line.next().context("missing method")?.to_string();
+    let target = request_line.next().context("missing path")?;
+    let path = target.split('?').next().unwrap_or(target).to_string();
+
+    let headers: Vec<(String, String)> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+        .collect();
+
+    let content_length = headers
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+        .map(|(_, value)| value.parse::<usize>())
+        .transpose()
+        .context("invalid Content-Length")?
+        .unwrap_or(0);
+    if content_length > MAX_BODY_LEN {
+        bail!("body of {content_length} bytes exceeds {MAX_BODY_LEN}");
+    }
+
+    let mut body = buf[head_end + 4..].to_vec();
+    body.truncate(content_length);
+    if body.len() < content_length {
+        let already = body.len();
+        body.resize(content_length, 0);
+        stream.read_exact(&mut body[already..]).await?;
+    }
+
+    Ok(Request {
+        method,
+        path,
+        headers,
+        body,
+    })
+}
+
+async fn write_response(stream: &mut TcpStream, mut response: Response) -> Result<()> {
+    let mut head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\n\
+         Cache-Control: no-store\r\nConnection: close\r\n",
+        response.status,
+        reason(response.status),
+        response.content_type,
+    );
+    // A streamed body ends when the connection closes
+    if response.stream.is_none() {
+        head.push_str(&format!("Content-Length: {}\r\n", response.body.len()));
+    }
+    for (name, value) in &response.headers {
+        head.push_str(&format!("{name}: {value}\r\n"));
+    }
+    head.push_str("\r\n");
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(&response.body).await?;
+    if let Some(chunks) = &mut response.stream {
+        while let Some(chunk) = chunks.recv().await {
+            stream.write_all(&chunk).await?;
+        }
+    }
+    stream.shutdown().await?;
+    Ok(())
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Payload Too Large",
+        503 => "Service Unavailable",
+        _ => "Internal Server Error",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_request_body_and_query_are_parsed() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(serve(listener, |req: Request| async move {
+            let body = String::from_utf8_lossy(&req.body).to_string();
+            Response::text(200, format!("{} {} {body}", req.method, req.path))
+        }));
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        client
+            .write_all(b"POST /upload?x=1 HTTP/1.1\r\nContent-Length: 5\r\n\r\nhello")
+            .await
+            .unwrap();
+        let mut reply = String::new();
+        client.read_to_string(&mut reply).await.unwrap();
+        assert!(reply.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(reply.ends_with("\r\n\r\nPOST /upload hello"));
+
+        server.abort();
+    }
+}
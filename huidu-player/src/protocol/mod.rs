@@ -1,4 +1,7 @@
 pub mod command;
 pub mod discovery;
+pub mod http;
 pub mod server;
 pub mod session;
+pub mod trace;
+pub mod web;
@@ -2,16 +2,17 @@
 use anyhow::Result;
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 use std::io::Cursor;
+use std::net::SocketAddr;
 use std::sync::Arc;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::{TcpListener, TcpStream};
-use tokio::sync::{mpsc, RwLock};
 use tracing::{error, info, warn};
 
-use crate::core::player::PlayerCommand;
-use crate::protocol::command;
-use crate::protocol::session::Session;
-use crate::services::manager::ServicesState;
+use crate::core::player::{PlayerCommand, ReceivedFile};
+use crate::program::{bundle, parser};
+use crate::protocol::command::{self, HandlerContext};
+use crate::protocol::session::{self, Session, SessionCache};
+use crate::protocol::trace;
 
 const CMD_TCP_HEARTBEAT_ASK: u16 = 0x005F;
 const CMD_TCP_HEARTBEAT_ANSWER: u16 = 0x0060;
@@ -28,31 +29,43 @@ const CMD_FILE_END_ANSWER: u16 = 0x8006;
 const TRANSPORT_VERSION: u32 = 0x0100_0005;
 const MAX_PACKET_SIZE: usize = 9 * 1024;
 
-pub async fn run(
-    port: u16,
-    player_tx: mpsc::Sender<PlayerCommand>,
-    program_dir: String,
-    services: Arc<RwLock<ServicesState>>,
-    screen_width: u32,
-    screen_height: u32,
-) -> Result<()> {
+pub async fn run(port: u16, ctx: HandlerContext) -> Result<()> {
     let addr = format!("0.0.0.0:{}", port);
     let listener = TcpListener::bind(&addr).await?;
     info!("Protocol server listening on {}", addr);
 
+    let device_guid = {
+        let state = ctx.services.read().await;
+        session::load_device_guid(&ctx.device_id, &state.storage.state_dir())
+    };
+    info!("Device GUID: {}", device_guid);
+
+    serve(listener, Arc::new(ctx), device_guid).await
+}
+
+/// Accept connections on an already-bound listener, one task per client
+pub async fn serve(
+    listener: TcpListener,
+    ctx: Arc<HandlerContext>,
+    device_guid: String,
+) -> Result<()> {
+    let sessions = Arc::new(SessionCache::new(session::DEFAULT_RESUME_WINDOW));
     loop {
         match listener.accept().await {
             Ok((stream, peer)) => {
                 info!("New connection from {}", peer);
-                let tx = player_tx.clone();
-                let dir = program_dir.clone();
-                let svc = services.clone();
+                let ctx = ctx.clone();
+                let sessions = sessions.clone();
+                let mut session = Session::new(device_guid.clone());
+                session.set_max_xml_len(ctx.max_xml_len);
+                session.set_max_file_size(ctx.max_file_size);
                 tokio::spawn(async move {
-                    if let Err(e) =
-                        handle_connection(stream, tx, dir, svc, screen_width, screen_height).await
-                    {
+                    // A client reconnecting after a drop picks up where it left off
+                    sessions.resume(peer.ip(), &mut session);
+                    if let Err(e) = handle_connection(stream, peer, &ctx, &mut session).await {
                         warn!("Connection error from {}: {}", peer, e);
                     }
+                    sessions.suspend(peer.ip(), session);
                     info!("Connection closed: {}", peer);
                 });
             }
@@ -65,22 +78,17 @@ pub async fn run(
 
 async fn handle_connection(
     mut stream: TcpStream,
-    player_tx: mpsc::Sender<PlayerCommand>,
-    program_dir: String,
-    services: Arc<RwLock<ServicesState>>,
-    screen_width: u32,
-    screen_height: u32,
+    peer: SocketAddr,
+    ctx: &HandlerContext,
+    session: &mut Session,
 ) -> Result<()> {
-    let mut session = Session::new();
+    let program_dir = &ctx.program_dir;
     let mut buf = vec![0u8; MAX_PACKET_SIZE];
 
-    loop {
-        let length = match stream.read_u16_le().await {
-            Ok(l) => l as usize,
-            Err(_) => break,
-        };
+    while let Ok(length) = stream.read_u16_le().await {
+        let length = length as usize;
 
-        if length < 2 || length > MAX_PACKET_SIZE {
+        if !(2..=MAX_PACKET_SIZE).contains(&length) {
             warn!("Invalid packet length: {}", length);
             break;
         }
@@ -94,11 +102,15 @@ async fn handle_connection(
             }
             stream.read_exact(&mut buf[..data_len]).await?;
         }
+        if ctx.protocol_trace {
+            trace_packet("in", cmd, &buf[..data_len]);
+        }
 
         let response = match cmd {
             CMD_TCP_HEARTBEAT_ASK => Some(make_packet(CMD_TCP_HEARTBEAT_ANSWER, &[])),
 
             CMD_SDK_SERVICE_ASK => {
+                session.transport_version = Some(TRANSPORT_VERSION);
                 let mut resp_data = Vec::new();
                 WriteBytesExt::write_u32::<LittleEndian>(&mut resp_data, TRANSPORT_VERSION)
                     .unwrap();
@@ -113,24 +125,21 @@ async fn handle_connection(
                     let index = ReadBytesExt::read_u32::<LittleEndian>(&mut cursor)? as usize;
                     let xml_chunk = &buf[8..data_len];
 
-                    session.accumulate_xml(xml_chunk, total_len, index);
-
-                    if session.xml_complete() {
+                    if session.accumulate_xml(xml_chunk, total_len, index)
+                        && session.xml_complete()
+                    {
                         let xml = session.take_xml();
-                        let xml_str = String::from_utf8_lossy(&xml);
+                        let xml_str = parser::decode_xml_bytes(&xml);
                         info!("SDK command ({} bytes)", xml_str.len());
 
-                        match command::handle_sdk_command(
-                            &xml_str,
-                            &session,
-                            &player_tx,
-                            &program_dir,
-                            &services,
-                            screen_width,
-                            screen_height,
-                        )
-                        .await
-                        {
+                        // Negotiated on this connection, or on one dropped moments ago
+                        let handled = if session.transport_version.is_none() {
+                            warn!("SDK command from {} before transport negotiation", peer);
+                            Ok(command::not_negotiated_response(&xml_str, session))
+                        } else {
+                            command::handle_sdk_command(&xml_str, session, ctx).await
+                        };
+                        match handled {
                             Ok(response_xml) => {
                                 let xml_bytes = response_xml.as_bytes();
                                 let mut resp = Vec::new();
@@ -167,10 +176,19 @@ async fn handle_connection(
                         .to_string();
 
                     info!("File start: {} ({} bytes, type {})", filename, file_size, file_type);
-                    session.start_file_transfer(filename, file_size, file_type, md5_str);
+                    // Non-zero status tells the client the file was refused
+                    let status = if locked_out(ctx, session).await {
+                        warn!("File {} refused: device is locked", filename);
+                        1
+                    } else if session.start_file_transfer(filename, file_size, file_type, md5_str)
+                    {
+                        0
+                    } else {
+                        1
+                    };
 
                     let mut resp = Vec::new();
-                    WriteBytesExt::write_u32::<LittleEndian>(&mut resp, 0).unwrap();
+                    WriteBytesExt::write_u32::<LittleEndian>(&mut resp, status).unwrap();
                     WriteBytesExt::write_u64::<LittleEndian>(&mut resp, 0).unwrap();
                     Some(make_packet(CMD_FILE_START_ANSWER, &resp))
                 } else {
@@ -179,6 +197,7 @@ async fn handle_connection(
             }
 
             CMD_FILE_CONTENT_ASK => {
+                // An oversized transfer is dropped; its end packet then reports failure
                 if data_len > 0 {
                     session.append_file_data(&buf[..data_len]);
                 }
@@ -186,14 +205,29 @@ async fn handle_connection(
             }
 
             CMD_FILE_END_ASK => {
-                if let Some(transfer) = session.complete_file_transfer() {
+                // Non-zero status tells the client the file was not stored
+                let mut status = 0;
+                if session.take_file_aborted() {
+                    status = 1;
+                } else if let Some(transfer) = session.complete_file_transfer() {
                     let dest = std::path::Path::new(&program_dir).join(&transfer.filename);
-                    info!("File saved: {} ({} bytes)", transfer.filename, transfer.data.len());
-                    let _ = std::fs::create_dir_all(&program_dir);
-                    let _ = std::fs::write(&dest, &transfer.data);
+                    let saved = std::fs::create_dir_all(program_dir)
+                        .and_then(|_| std::fs::write(&dest, &transfer.data));
+                    match saved {
+                        Ok(()) => {
+                            file_received(ctx, transfer, peer).await;
+                            if bundle::is_bundle(&dest) {
+                                load_bundle(ctx, session, &dest).await;
+                            }
+                        }
+                        Err(e) => {
+                            warn!("Failed to save {}: {}", dest.display(), e);
+                            status = 1;
+                        }
+                    }
                 }
                 let mut resp = Vec::new();
-                WriteBytesExt::write_u32::<LittleEndian>(&mut resp, 0).unwrap();
+                WriteBytesExt::write_u32::<LittleEndian>(&mut resp, status).unwrap();
                 Some(make_packet(CMD_FILE_END_ANSWER, &resp))
             }
 
@@ -204,6 +238,10 @@ async fn handle_connection(
         };
 
         if let Some(resp) = response {
+            if ctx.protocol_trace {
+                let resp_cmd = u16::from_le_bytes([resp[2], resp[3]]);
+                trace_packet("out", resp_cmd, &resp[4..]);
+            }
             stream.write_all(&resp).await?;
         }
     }
@@ -211,6 +249,74 @@ async fn handle_connection(
     Ok(())
 }
 
+/// Log a completed upload with structured fields and tell the player, which
+/// publishes it to event subscribers
+async fn file_received(ctx: &HandlerContext, transfer: session::FileTransfer, peer: SocketAddr) {
+    let file = ReceivedFile {
+        size: transfer.data.len() as u64,
+        md5: format!("{:x}", md5::compute(&transfer.data)),
+        filename: transfer.filename,
+        peer: peer.to_string(),
+    };
+    info!(
+        filename = %file.filename,
+        size = file.size,
+        md5 = %file.md5,
+        peer = %file.peer,
+        "File received"
+    );
+    ctx.player_tx.send(PlayerCommand::FileReceived(file)).await.ok();
+}
+
+/// Trace one packet. File contents are never dumped, only their size.
+fn trace_packet(direction: &str, cmd: u16, data: &[u8]) {
+    if cmd == CMD_FILE_CONTENT_ASK {
+        info!(
+            target: trace::TARGET,
+            "TCP {} 0x{:04X} ({} bytes of file data)",
+            direction,
+            cmd,
+            data.len()
+        );
+    } else {
+        info!(
+            target: trace::TARGET,
+            "TCP {} 0x{:04X} ({} bytes): {}",
+            direction,
+            cmd,
+            data.len(),
+            trace::hex_preview(data)
+        );
+    }
+}
+
+/// Unpack a transferred program bundle into the program directory and add
+/// its programs to the saved ones, like AddProgram. The bundle itself is
+/// deleted once unpacked.
+async fn load_bundle(ctx: &HandlerContext, session: &Session, path: &std::path::Path) {
+    // The device may have been locked while the bundle was uploading
+    if locked_out(ctx, session).await {
+        warn!("Bundle {} not loaded: device is locked", path.display());
+        return;
+    }
+    match bundle::load(path, std::path::Path::new(&ctx.program_dir)) {
+        Ok((screen, xml)) => {
+            info!("Loaded {} program(s) from bundle {}", screen.programs.len(), path.display());
+            if let Err(e) = std::fs::remove_file(path) {
+                warn!("Failed to remove {}: {}", path.display(), e);
+            }
+            let screen = ctx.services.read().await.storage.save_and_merge(screen, &xml);
+            ctx.player_tx.send(PlayerCommand::LoadScreen(screen)).await.ok();
+        }
+        Err(e) => warn!("Failed to load bundle {}: {}", path.display(), e),
+    }
+}
+
+/// Whether the device is locked and `session` hasn't given the password
+async fn locked_out(ctx: &HandlerContext, session: &Session) -> bool {
+    !session.authenticated && ctx.services.read().await.lock.is_locked()
+}
+
 fn make_packet(cmd: u16, data: &[u8]) -> Vec<u8> {
     let length = (data.len() + 2) as u16;
     let mut packet = Vec::with_capacity(4 + data.len());
@@ -219,3 +325,242 @@ fn make_packet(cmd: u16, data: &[u8]) -> Vec<u8> {
     packet.extend_from_slice(data);
     packet
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::PlayerConfig;
+    use crate::services::manager::ServicesState;
+    use tokio::sync::{mpsc, RwLock};
+
+    async fn read_packet(stream: &mut TcpStream) -> (u16, Vec<u8>) {
+        let length = stream.read_u16_le().await.unwrap() as usize;
+        let cmd = stream.read_u16_le().await.unwrap();
+        let mut data = vec![0u8; length - 2];
+        stream.read_exact(&mut data).await.unwrap();
+        (cmd, data)
+    }
+
+    fn sdk_chunk(total_len: usize, index: u32, chunk: &[u8]) -> Vec<u8> {
+        let mut data = Vec::new();
+        WriteBytesExt::write_u32::<LittleEndian>(&mut data, total_len as u32).unwrap();
+        WriteBytesExt::write_u32::<LittleEndian>(&mut data, index).unwrap();
+        data.extend_from_slice(chunk);
+        make_packet(CMD_SDK_CMD_ASK, &data)
+    }
+
+    /// Serve on an ephemeral port; returns the address, the player command
+    /// receiver and the server task
+    async fn start_server(
+        dir: &std::path::Path,
+    ) -> (SocketAddr, mpsc::Receiver<PlayerCommand>, tokio::task::JoinHandle<Result<()>>) {
+        let config = PlayerConfig::for_test(dir);
+        let services = Arc::new(RwLock::new(ServicesState::new(&config)));
+        let (tx, rx) = mpsc::channel(8);
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let ctx = HandlerContext {
+            player_tx: tx,
+            program_dir: dir.to_string_lossy().to_string(),
+            services,
+            screen_width: 32,
+            screen_height: 16,
+            device_id: "device".to_string(),
+            allow_factory_reset: false,
+            protocol_trace: false,
+            max_xml_len: session::DEFAULT_MAX_XML_LEN,
+            max_file_size: session::DEFAULT_MAX_FILE_SIZE,
+            fallback_media: Vec::new(),
+        };
+        let server = tokio::spawn(serve(listener, Arc::new(ctx), "dev-guid".to_string()));
+        (addr, rx, server)
+    }
+
+    #[tokio::test]
+    async fn test_add_program_over_tcp() {
+        let dir = tempfile::tempdir().unwrap();
+        let (addr, mut rx, server) = start_server(dir.path()).await;
+        let mut client = TcpStream::connect(addr).await.unwrap();
+
+        // Transport negotiation
+        let ask = make_packet(CMD_SDK_SERVICE_ASK, &TRANSPORT_VERSION.to_le_bytes());
+        client.write_all(&ask).await.unwrap();
+        let (cmd, data) = read_packet(&mut client).await;
+        assert_eq!(cmd, CMD_SDK_SERVICE_ANSWER);
+        assert_eq!(data, TRANSPORT_VERSION.to_le_bytes());
+
+        // AddProgram split across two packets
+        let xml = r#"<?xml version="1.0" encoding="utf-8"?><sdk guid="x"><in method="AddProgram"><screen><program guid="p1" name="Net"><area guid="a1"><rectangle width="32" height="16"/><resources/></area></program></screen></in></sdk>"#;
+        let (first, second) = xml.as_bytes().split_at(xml.len() / 2);
+        client.write_all(&sdk_chunk(xml.len(), 0, first)).await.unwrap();
+        client.write_all(&sdk_chunk(xml.len(), 1, second)).await.unwrap();
+
+        let (cmd, data) = read_packet(&mut client).await;
+        assert_eq!(cmd, CMD_SDK_CMD_ANSWER);
+        let reply_len = u32::from_le_bytes(data[..4].try_into().unwrap()) as usize;
+        let reply = std::str::from_utf8(&data[8..]).unwrap();
+        assert_eq!(reply.len(), reply_len);
+        assert!(reply.contains(r#"<sdk guid="dev-guid"><out method="AddProgram"><result value="0"/>"#));
+
+        match rx.recv().await {
+            Some(PlayerCommand::LoadScreen(screen)) => assert_eq!(screen.programs[0].guid, "p1"),
+            other => panic!("expected LoadScreen, got {other:?}"),
+        }
+
+        // Heartbeats are answered on the same connection
+        client.write_all(&make_packet(CMD_TCP_HEARTBEAT_ASK, &[])).await.unwrap();
+        assert_eq!(read_packet(&mut client).await, (CMD_TCP_HEARTBEAT_ANSWER, Vec::new()));
+
+        server.abort();
+    }
+
+    #[tokio::test]
+    async fn test_file_transfer_reports_received_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let (addr, mut rx, server) = start_server(dir.path()).await;
+        let mut client = TcpStream::connect(addr).await.unwrap();
+
+        let content = b"hello sign";
+        let md5 = format!("{:x}", md5::compute(content));
+        let mut start = md5.as_bytes().to_vec();
+        WriteBytesExt::write_u64::<LittleEndian>(&mut start, content.len() as u64).unwrap();
+        WriteBytesExt::write_u16::<LittleEndian>(&mut start, 0).unwrap();
+        start.extend_from_slice(b"logo.png\0");
+        client.write_all(&make_packet(CMD_FILE_START_ASK, &start)).await.unwrap();
+        assert_eq!(read_packet(&mut client).await.0, CMD_FILE_START_ANSWER);
+        client.write_all(&make_packet(CMD_FILE_CONTENT_ASK, content)).await.unwrap();
+        client.write_all(&make_packet(CMD_FILE_END_ASK, &[])).await.unwrap();
+        let (cmd, data) = read_packet(&mut client).await;
+        assert_eq!(cmd, CMD_FILE_END_ANSWER);
+        assert_eq!(data, 0u32.to_le_bytes());
+
+        assert_eq!(std::fs::read(dir.path().join("logo.png")).unwrap(), content);
+        match rx.recv().await {
+            Some(PlayerCommand::FileReceived(file)) => assert_eq!(
+                file,
+                ReceivedFile {
+                    filename: "logo.png".to_string(),
+                    size: content.len() as u64,
+                    md5,
+                    peer: client.local_addr().unwrap().to_string(),
+                }
+            ),
+            other => panic!("expected FileReceived, got {other:?}"),
+        }
+
+        server.abort();
+    }
+
+    #[tokio::test]
+    async fn test_reconnect_skips_negotiation() {
+        let dir = tempfile::tempdir().unwrap();
+        let (addr, _rx, server) = start_server(dir.path()).await;
+        let xml = r#"<sdk guid="x"><in method="GetDeviceName"/></sdk>"#;
+
+        // Commands before negotiation are refused
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        client.write_all(&sdk_chunk(xml.len(), 0, xml.as_bytes())).await.unwrap();
+        let (cmd, data) = read_packet(&mut client).await;
+        assert_eq!(cmd, CMD_SDK_CMD_ANSWER);
+        let reply = String::from_utf8_lossy(&data[8..]).into_owned();
+        assert!(reply.contains(r#"<out method="GetDeviceName"><result value="3"/>"#), "{reply}");
+
+        // Negotiate, then drop the connection
+        let ask = make_packet(CMD_SDK_SERVICE_ASK, &TRANSPORT_VERSION.to_le_bytes());
+        client.write_all(&ask).await.unwrap();
+        assert_eq!(read_packet(&mut client).await.0, CMD_SDK_SERVICE_ANSWER);
+        client.shutdown().await.unwrap();
+        let mut rest = Vec::new();
+        client.read_to_end(&mut rest).await.unwrap();
+
+        // Reconnecting within the window, commands work straight away
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        client.write_all(&sdk_chunk(xml.len(), 0, xml.as_bytes())).await.unwrap();
+        assert_eq!(read_packet(&mut client).await.0, CMD_SDK_CMD_ANSWER);
+
+        server.abort();
+    }
+
+    #[tokio::test]
+    async fn test_locked_device_refuses_files() {
+        let dir = tempfile::tempdir().unwrap();
+        // Locked before the server starts, as after a restart
+        let state = ServicesState::new(&PlayerConfig::for_test(dir.path()));
+        state.lock.set("s3cret").unwrap();
+        let (addr, _rx, server) = start_server(dir.path()).await;
+        let mut client = TcpStream::connect(addr).await.unwrap();
+
+        let content = b"hello sign";
+        let mut start = format!("{:x}", md5::compute(content)).into_bytes();
+        WriteBytesExt::write_u64::<LittleEndian>(&mut start, content.len() as u64).unwrap();
+        WriteBytesExt::write_u16::<LittleEndian>(&mut start, 0).unwrap();
+        start.extend_from_slice(b"logo.png\0");
+        client.write_all(&make_packet(CMD_FILE_START_ASK, &start)).await.unwrap();
+        let (cmd, data) = read_packet(&mut client).await;
+        assert_eq!(cmd, CMD_FILE_START_ANSWER);
+        assert_eq!(u32::from_le_bytes(data[..4].try_into().unwrap()), 1);
+        client.write_all(&make_packet(CMD_FILE_CONTENT_ASK, content)).await.unwrap();
+        client.write_all(&make_packet(CMD_FILE_END_ASK, &[])).await.unwrap();
+        assert_eq!(read_packet(&mut client).await.0, CMD_FILE_END_ANSWER);
+        assert!(!dir.path().join("logo.png").exists());
+
+        server.abort();
+    }
+
+    #[tokio::test]
+    async fn test_oversized_file_end_reports_failure() {
+        let dir = tempfile::tempdir().unwrap();
+        let (addr, _rx, server) = start_server(dir.path()).await;
+        let mut client = TcpStream::connect(addr).await.unwrap();
+
+        // Declares 4 bytes, then sends 10
+        let mut start = format!("{:x}", md5::compute(b"logo")).into_bytes();
+        WriteBytesExt::write_u64::<LittleEndian>(&mut start, 4).unwrap();
+        WriteBytesExt::write_u16::<LittleEndian>(&mut start, 0).unwrap();
+        start.extend_from_slice(b"logo.png\0");
+        client.write_all(&make_packet(CMD_FILE_START_ASK, &start)).await.unwrap();
+        assert_eq!(read_packet(&mut client).await.0, CMD_FILE_START_ANSWER);
+        client.write_all(&make_packet(CMD_FILE_CONTENT_ASK, b"hello sign")).await.unwrap();
+        client.write_all(&make_packet(CMD_FILE_END_ASK, &[])).await.unwrap();
+        let (cmd, data) = read_packet(&mut client).await;
+        assert_eq!(cmd, CMD_FILE_END_ANSWER);
+        assert_eq!(data, 1u32.to_le_bytes());
+        assert!(!dir.path().join("logo.png").exists());
+
+        server.abort();
+    }
+
+    #[tokio::test]
+    async fn test_bundle_adds_to_saved_programs() {
+        let dir = tempfile::tempdir().unwrap();
+        let saved = r#"<screen><program guid="p1"><area guid="a1">
+            <rectangle width="8" height="8"/><resources/></area></program></screen>"#;
+        let storage = crate::services::storage::StorageService::new(dir.path().to_path_buf());
+        storage.save_program(&parser::parse_program_xml(saved).unwrap(), saved).unwrap();
+        let (addr, mut rx, server) = start_server(dir.path()).await;
+        let mut client = TcpStream::connect(addr).await.unwrap();
+
+        let xml = br#"<screen><program guid="b1"><area guid="a2">
+            <rectangle width="8" height="8"/><resources/></area></program></screen>"#;
+        let content = bundle::zip(&[("program.xml", xml, true)]);
+        let mut start = format!("{:x}", md5::compute(&content)).into_bytes();
+        WriteBytesExt::write_u64::<LittleEndian>(&mut start, content.len() as u64).unwrap();
+        WriteBytesExt::write_u16::<LittleEndian>(&mut start, 0).unwrap();
+        start.extend_from_slice(b"sale.hdp\0");
+        client.write_all(&make_packet(CMD_FILE_START_ASK, &start)).await.unwrap();
+        assert_eq!(read_packet(&mut client).await.0, CMD_FILE_START_ANSWER);
+        client.write_all(&make_packet(CMD_FILE_CONTENT_ASK, &content)).await.unwrap();
+        client.write_all(&make_packet(CMD_FILE_END_ASK, &[])).await.unwrap();
+        assert_eq!(read_packet(&mut client).await.0, CMD_FILE_END_ANSWER);
+
+        assert!(matches!(rx.recv().await, Some(PlayerCommand::FileReceived(_))));
+        let Some(PlayerCommand::LoadScreen(screen)) = rx.recv().await else {
+            panic!("expected LoadScreen");
+        };
+        let guids: Vec<_> = screen.programs.iter().map(|p| p.guid.as_str()).collect();
+        assert_eq!(guids, ["p1", "b1"]);
+
+        server.abort();
+    }
+}
@@ -1,14 +1,104 @@
 /// TCP session state for a connected HDPlayer client.
-use uuid::Uuid;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+use uuid::{Builder, Uuid};
+
+/// File (inside the storage state dir) holding the persisted device GUID
+const DEVICE_GUID_FILE: &str = "device_guid";
+
+/// Default cap on a single accumulated SDK command
+pub const DEFAULT_MAX_XML_LEN: usize = 4 * 1024 * 1024;
+
+/// Default cap on a single transferred file
+pub const DEFAULT_MAX_FILE_SIZE: u64 = 512 * 1024 * 1024;
+
+/// Most we reserve up front for a file transfer; the buffer grows as data arrives
+const FILE_PREALLOC_LIMIT: usize = 1024 * 1024;
+
+/// How long a dropped client's session state is kept for it to reconnect
+pub const DEFAULT_RESUME_WINDOW: Duration = Duration::from_secs(60);
 
 pub struct Session {
-    /// Unique session GUID
+    /// Unique per-connection GUID (changes on every reconnect)
     pub guid: String,
+    /// Stable device GUID, identical across sessions and reconnects
+    pub device_guid: String,
     /// XML accumulation buffer (commands may span multiple packets)
     xml_buffer: Vec<u8>,
     xml_total_len: usize,
+    /// Largest declared XML length we are willing to buffer
+    max_xml_len: usize,
     /// Active file transfer state
     file_transfer: Option<FileTransfer>,
+    /// Largest declared file size we accept
+    max_file_size: u64,
+    /// The current transfer was dropped for sending more than it declared,
+    /// so its end must be answered with a failure
+    file_aborted: bool,
+    /// Transport version agreed with the client (None: not negotiated yet)
+    pub transport_version: Option<u32>,
+    /// The client presented the device password. Not carried over a
+    /// reconnect: a new connection must authenticate again.
+    pub authenticated: bool,
+}
+
+/// What survives a dropped connection: the negotiated transport and any
+/// half-received command or file
+pub struct SuspendedSession {
+    transport_version: Option<u32>,
+    xml_buffer: Vec<u8>,
+    xml_total_len: usize,
+    file_transfer: Option<FileTransfer>,
+    file_aborted: bool,
+    suspended_at: Instant,
+}
+
+/// State of recently dropped sessions, keyed by client address, so a client
+/// that reconnects within the window carries on where it left off
+pub struct SessionCache {
+    window: Duration,
+    suspended: Mutex<HashMap<IpAddr, SuspendedSession>>,
+}
+
+impl SessionCache {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            suspended: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Keep `session`'s state for a reconnect from `peer`. Sessions that never
+    /// negotiated and hold nothing partial are not worth keeping.
+    pub fn suspend(&self, peer: IpAddr, session: Session) {
+        let worth_keeping = session.transport_version.is_some()
+            || !session.xml_buffer.is_empty()
+            || session.file_transfer.is_some()
+            || session.file_aborted;
+        let mut suspended = self.suspended.lock().unwrap();
+        suspended.retain(|_, s| s.suspended_at.elapsed() < self.window);
+        if worth_keeping {
+            suspended.insert(peer, session.suspend());
+        }
+    }
+
+    /// Restore the state `peer` left behind, if it dropped within the window.
+    /// Returns whether anything was restored.
+    pub fn resume(&self, peer: IpAddr, session: &mut Session) -> bool {
+        let saved = self.suspended.lock().unwrap().remove(&peer);
+        match saved {
+            Some(saved) if saved.suspended_at.elapsed() < self.window => {
+                info!("Resuming session state for {}", peer);
+                session.resume(saved);
+                true
+            }
+            _ => false,
+        }
+    }
 }
 
 pub struct FileTransfer {
@@ -20,22 +110,81 @@ pub struct FileTransfer {
 }
 
 impl Session {
-    pub fn new() -> Self {
+    pub fn new(device_guid: String) -> Self {
         Self {
             guid: Uuid::new_v4().to_string(),
+            device_guid,
             xml_buffer: Vec::new(),
             xml_total_len: 0,
+            max_xml_len: DEFAULT_MAX_XML_LEN,
             file_transfer: None,
+            max_file_size: DEFAULT_MAX_FILE_SIZE,
+            file_aborted: false,
+            transport_version: None,
+            authenticated: false,
         }
     }
 
-    /// Accumulate XML data from an SDK command packet
-    pub fn accumulate_xml(&mut self, chunk: &[u8], total_len: usize, index: usize) {
+    fn suspend(self) -> SuspendedSession {
+        SuspendedSession {
+            transport_version: self.transport_version,
+            xml_buffer: self.xml_buffer,
+            xml_total_len: self.xml_total_len,
+            file_transfer: self.file_transfer,
+            file_aborted: self.file_aborted,
+            suspended_at: Instant::now(),
+        }
+    }
+
+    fn resume(&mut self, saved: SuspendedSession) {
+        self.transport_version = saved.transport_version;
+        self.xml_buffer = saved.xml_buffer;
+        self.xml_total_len = saved.xml_total_len;
+        self.file_transfer = saved.file_transfer;
+        self.file_aborted = saved.file_aborted;
+    }
+
+    pub fn set_max_xml_len(&mut self, max: usize) {
+        self.max_xml_len = max;
+    }
+
+    pub fn set_max_file_size(&mut self, max: u64) {
+        self.max_file_size = max;
+    }
+
+    /// Accumulate XML data from an SDK command packet.
+    ///
+    /// Returns false (and drops the partial command) if the declared length is
+    /// over the limit or the client sends more than it declared.
+    pub fn accumulate_xml(&mut self, chunk: &[u8], total_len: usize, index: usize) -> bool {
         if index == 0 {
             self.xml_buffer.clear();
+            if total_len > self.max_xml_len {
+                warn!(
+                    "Rejecting SDK command: declared {} bytes exceeds limit of {}",
+                    total_len, self.max_xml_len
+                );
+                self.reset_xml();
+                return false;
+            }
             self.xml_total_len = total_len;
         }
+        if self.xml_buffer.len() + chunk.len() > self.xml_total_len {
+            warn!(
+                "Rejecting SDK command: received {} bytes, declared {}",
+                self.xml_buffer.len() + chunk.len(),
+                self.xml_total_len
+            );
+            self.reset_xml();
+            return false;
+        }
         self.xml_buffer.extend_from_slice(chunk);
+        true
+    }
+
+    fn reset_xml(&mut self) {
+        self.xml_total_len = 0;
+        self.xml_buffer = Vec::new();
     }
 
     /// Check if we've received all XML data
@@ -49,32 +198,204 @@ impl Session {
         std::mem::take(&mut self.xml_buffer)
     }
 
-    /// Start a new file transfer
+    /// Start a new file transfer.
+    ///
+    /// Returns false (and starts nothing) if the declared size is over the limit.
     pub fn start_file_transfer(
         &mut self,
         filename: String,
         size: u64,
         file_type: u16,
         md5: String,
-    ) {
+    ) -> bool {
+        self.file_transfer = None;
+        self.file_aborted = false;
+        if size > self.max_file_size {
+            warn!(
+                "Rejecting file {}: declared {} bytes exceeds limit of {}",
+                filename, size, self.max_file_size
+            );
+            return false;
+        }
+        // The size is only the client's claim, so don't reserve all of it
+        let capacity = size.min(FILE_PREALLOC_LIMIT as u64) as usize;
         self.file_transfer = Some(FileTransfer {
             filename,
             expected_size: size,
             file_type,
             md5,
-            data: Vec::with_capacity(size as usize),
+            data: Vec::with_capacity(capacity),
         });
+        true
     }
 
-    /// Append data to the active file transfer
-    pub fn append_file_data(&mut self, data: &[u8]) {
-        if let Some(ref mut transfer) = self.file_transfer {
-            transfer.data.extend_from_slice(data);
+    /// Append data to the active file transfer.
+    ///
+    /// Returns false and drops the transfer if the client sends more than it
+    /// declared.
+    pub fn append_file_data(&mut self, data: &[u8]) -> bool {
+        let Some(ref mut transfer) = self.file_transfer else {
+            return true;
+        };
+        let received = transfer.data.len() as u64 + data.len() as u64;
+        if received > transfer.expected_size {
+            warn!(
+                "Aborting transfer of {}: received {} bytes, declared {}",
+                transfer.filename, received, transfer.expected_size
+            );
+            self.file_transfer = None;
+            self.file_aborted = true;
+            return false;
         }
+        transfer.data.extend_from_slice(data);
+        true
     }
 
     /// Complete the file transfer and return the data
     pub fn complete_file_transfer(&mut self) -> Option<FileTransfer> {
         self.file_transfer.take()
     }
+
+    /// Whether the transfer now ending was aborted by `append_file_data`.
+    /// Clears the flag.
+    pub fn take_file_aborted(&mut self) -> bool {
+        std::mem::take(&mut self.file_aborted)
+    }
+}
+
+/// Load the persisted device GUID, deriving and saving one from the device ID on first use
+pub fn load_device_guid(device_id: &str, state_dir: &Path) -> String {
+    let path = state_dir.join(DEVICE_GUID_FILE);
+    if let Ok(saved) = std::fs::read_to_string(&path) {
+        let saved = saved.trim();
+        if !saved.is_empty() {
+            return saved.to_string();
+        }
+    }
+
+    let guid = derive_device_guid(device_id);
+    if let Err(e) = std::fs::create_dir_all(state_dir).and_then(|_| std::fs::write(&path, &guid)) {
+        warn!("Failed to persist device GUID to {}: {}", path.display(), e);
+    }
+    guid
+}
+
+/// Derive a name-based (MD5) UUID from the device ID so it is reproducible even without storage
+fn derive_device_guid(device_id: &str) -> String {
+    Builder::from_md5_bytes(md5::compute(device_id.as_bytes()).0)
+        .into_uuid()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sessions_share_device_guid() {
+        let dir = tempfile::tempdir().unwrap();
+        let first = Session::new(load_device_guid("RUST-001", dir.path()));
+        let second = Session::new(load_device_guid("RUST-001", dir.path()));
+
+        assert_eq!(first.device_guid, second.device_guid);
+        assert_ne!(first.guid, second.guid);
+        assert!(dir.path().join(DEVICE_GUID_FILE).exists());
+    }
+
+    #[test]
+    fn test_device_guid_prefers_persisted_value() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join(DEVICE_GUID_FILE), "saved-guid\n").unwrap();
+        assert_eq!(load_device_guid("RUST-001", dir.path()), "saved-guid");
+    }
+
+    #[test]
+    fn test_reconnect_resumes_negotiated_session() {
+        let peer: IpAddr = "192.168.1.20".parse().unwrap();
+        let cache = SessionCache::new(DEFAULT_RESUME_WINDOW);
+
+        // Negotiate, get halfway through a command and a file, then drop
+        let mut first = Session::new("guid".to_string());
+        first.transport_version = Some(0x0100_0005);
+        assert!(first.accumulate_xml(b"<sdk>", 11, 0));
+        assert!(first.start_file_transfer("a.png".to_string(), 4, 0, String::new()));
+        cache.suspend(peer, first);
+
+        // The reconnect skips negotiation and finishes the command and file
+        let mut second = Session::new("guid".to_string());
+        assert!(cache.resume(peer, &mut second));
+        assert_eq!(second.transport_version, Some(0x0100_0005));
+        assert!(second.accumulate_xml(b"</sdk>", 11, 1));
+        assert_eq!(second.take_xml(), b"<sdk></sdk>");
+        assert!(second.append_file_data(b"logo"));
+        assert_eq!(second.complete_file_transfer().unwrap().data, b"logo");
+
+        // State is handed over once, and only to the same address
+        cache.suspend(peer, second);
+        let mut other = Session::new("guid".to_string());
+        assert!(!cache.resume("192.168.1.21".parse().unwrap(), &mut other));
+        assert!(other.transport_version.is_none());
+        let mut third = Session::new("guid".to_string());
+        assert!(cache.resume(peer, &mut third));
+        assert!(!cache.resume(peer, &mut Session::new("guid".to_string())));
+
+        // Outside the window the client negotiates from scratch
+        let expired = SessionCache::new(Duration::ZERO);
+        expired.suspend(peer, third);
+        let mut late = Session::new("guid".to_string());
+        assert!(!expired.resume(peer, &mut late));
+        assert!(late.transport_version.is_none());
+    }
+
+    #[test]
+    fn test_xml_accumulation_is_bounded() {
+        let mut session = Session::new("guid".to_string());
+        session.set_max_xml_len(1024);
+
+        // A huge declared length is refused before anything is buffered
+        assert!(!session.accumulate_xml(b"<sdk", usize::MAX, 0));
+        assert!(session.xml_buffer.is_empty());
+        assert_eq!(session.xml_total_len, 0);
+
+        // Continuation chunks of the rejected command can't grow the buffer either
+        assert!(!session.accumulate_xml(&[b'x'; 512], usize::MAX, 1));
+        assert!(session.xml_buffer.is_empty());
+
+        // Sending more than declared is also rejected
+        assert!(session.accumulate_xml(b"<sdk>", 10, 0));
+        assert!(!session.accumulate_xml(b"</sdk></sdk>", 10, 1));
+        assert!(session.xml_buffer.is_empty());
+
+        // A well-behaved command still goes through
+        assert!(session.accumulate_xml(b"<sdk>", 11, 0));
+        assert!(!session.xml_complete());
+        assert!(session.accumulate_xml(b"</sdk>", 11, 1));
+        assert!(session.xml_complete());
+        assert_eq!(session.take_xml(), b"<sdk></sdk>");
+    }
+
+    #[test]
+    fn test_file_transfer_size_is_bounded() {
+        let mut session = Session::new("guid".to_string());
+        session.set_max_file_size(1024);
+
+        // An absurd declared size is refused before anything is allocated
+        assert!(!session.start_file_transfer("huge.bin".into(), u64::MAX, 0, String::new()));
+        assert!(session.file_transfer.is_none());
+
+        // Under the default limit, preallocation is still capped
+        session.set_max_file_size(DEFAULT_MAX_FILE_SIZE);
+        let big = DEFAULT_MAX_FILE_SIZE;
+        assert!(session.start_file_transfer("big.bin".into(), big, 0, String::new()));
+        let capacity = session.file_transfer.as_ref().unwrap().data.capacity();
+        assert!(capacity <= FILE_PREALLOC_LIMIT);
+
+        // Sending past the declared size aborts the transfer
+        assert!(session.start_file_transfer("small.bin".into(), 4, 0, String::new()));
+        assert!(session.append_file_data(b"abc"));
+        assert!(!session.append_file_data(b"de"));
+        assert!(session.complete_file_transfer().is_none());
+        assert!(session.take_file_aborted());
+        assert!(!session.take_file_aborted());
+    }
 }
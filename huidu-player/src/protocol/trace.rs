@@ -0,0 +1,22 @@
+//! Protocol trace mode (`--protocol-trace`) for debugging HDPlayer interop.
+//! Every packet and SDK command is logged at info level under `TARGET`, so
+//! the trace can be filtered on its own (e.g. `RUST_LOG=protocol_trace=info`).
+
+/// Tracing target of all protocol trace lines
+pub const TARGET: &str = "protocol_trace";
+
+/// Bytes of a packet shown in its trace line
+const PREVIEW_LEN: usize = 32;
+
+/// Space-separated hex of the start of `data`, with "..." if it was cut
+pub fn hex_preview(data: &[u8]) -> String {
+    let mut hex: String = data[..data.len().min(PREVIEW_LEN)]
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<Vec<_>>()
+        .join(" ");
+    if data.len() > PREVIEW_LEN {
+        hex.push_str(" ...");
+    }
+    hex
+}
@@ -0,0 +1,466 @@
+/// Browser UI for operators without HDPlayer (`--web-port`).
+/// Serves a status page showing the current frame (an MJPEG stream, or a PNG
+/// snapshot at /frame.png) and the loaded programs,
+/// with a form to upload a program XML that is added to them. It listens on
+/// loopback unless `--web-bind` says otherwise, and asks for the device
+/// password (HTTP Basic, any user name) while the device is locked.
+use anyhow::Result;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine as _;
+use serde_json::json;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::{mpsc, oneshot, RwLock};
+use tracing::{info, warn};
+
+use crate::core::player::{PlayerCommand, ProgramSummary};
+use crate::program::parser;
+use crate::protocol::http::{self, Request, Response};
+use crate::render::export;
+use crate::services::manager::ServicesState;
+
+/// Time between frames of the MJPEG preview
+const STREAM_FRAME_INTERVAL: Duration = Duration::from_millis(200);
+
+/// MJPEG preview: JPEG parts, each starting with a "--frame" line
+const STREAM_CONTENT_TYPE: &str = "multipart/x-mixed-replace; boundary=frame";
+
+/// The status page; everything else it shows is fetched from the JSON routes
+const INDEX_HTML: &str = r#"<!DOCTYPE html>
+<html><head><meta charset="utf-8"><title>Huidu Player</title>
+<style>
+body { font-family: sans-serif; margin: 2em; background: #222; color: #eee; }
+img { image-rendering: pixelated; border: 1px solid #555; min-width: 256px; }
+td, th { padding: 0 1em 0 0; text-align: left; }
+</style></head>
+<body>
+<h1>Huidu Player</h1>
+<p><img id="frame" src="/stream.mjpg" alt="current frame"></p>
+<p id="status">Loading...</p>
+<table><thead><tr><th></th><th>Name</th><th>GUID</th><th>Areas</th><th>Duration</th></tr></thead>
+<tbody id="programs"></tbody></table>
+<h2>Upload program</h2>
+<form id="upload">
+<input type="file" id="file" accept=".xml"> <button>Upload and play</button>
+</form>
+<p id="result"></p>
+<script>
+async function refresh() {
+  const status = await (await fetch('/status.json')).json();
+  document.getElementById('status').textContent =
+    status.programs + ' program(s) loaded' + (status.playing ? ', playing ' + status.playing : '') +
+    ', brightness ' + status.brightness + '%';
+  const list = await (await fetch('/programs.json')).json();
+  const rows = document.getElementById('programs');
+  rows.replaceChildren(...list.programs.map((p, i) => {
+    const row = document.createElement('tr');
+    const playing = i === list.current ? '▶' : '';
+    for (const cell of [playing, p.name, p.guid, p.areas, p.duration + 's']) {
+      row.appendChild(document.createElement('td')).textContent = cell;
+    }
+    return row;
+  }));
+}
+document.getElementById('upload').onsubmit = async (e) => {
+  e.preventDefault();
+  const file = document.getElementById('file').files[0];
+  if (!file) return;
+  const reply = await (await fetch('/program', { method: 'POST', body: await file.text() })).json();
+  document.getElementById('result').textContent =
+    reply.error ? 'Rejected: ' + reply.error : 'Loaded ' + reply.programs + ' program(s)';
+  refresh();
+};
+refresh();
+setInterval(refresh, 2000);
+</script>
+</body></html>
+"#;
+
+/// What the routes need from the rest of the player
+#[derive(Clone)]
+struct Web {
+    player_tx: mpsc::Sender<PlayerCommand>,
+    services: Arc<RwLock<ServicesState>>,
+}
+
+/// Serve the web UI on `addr`
+pub async fn run(
+    addr: SocketAddr,
+    player_tx: mpsc::Sender<PlayerCommand>,
+    services: Arc<RwLock<ServicesState>>,
+) -> Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    info!("Web UI listening on http://{}", addr);
+    serve(listener, Web { player_tx, services }).await
+}
+
+async fn serve(listener: TcpListener, web: Web) -> Result<()> {
+    http::serve(listener, move |request| {
+        let web = web.clone();
+        async move { web.respond(request).await }
+    })
+    .await
+}
+
+impl Web {
+    async fn respond(&self, request: Request) -> Response {
+        if !same_origin(&request) {
+            warn!("Web UI refused cross-origin {} {}", request.method, request.path);
+            return Response::text(403, "cross-origin request refused\n");
+        }
+        if !self.authorized(&request).await {
+            return Response::text(401, "device password required\n")
+                .with_header("WWW-Authenticate", "Basic realm=\"Huidu Player\"");
+        }
+        match (request.method.as_str(), request.path.as_str()) {
+            ("GET", "/") => Response::new(200, "text/html; charset=utf-8", INDEX_HTML),
+            ("GET", "/status.json") => self.status().await,
+            ("GET", "/programs.json") => self.programs().await,
+            ("GET", "/frame.png") => self.frame().await,
+            ("GET", "/stream.mjpg") => self.stream(),
+            ("POST", "/program") => self.upload(&request.body).await,
+            (
+                _,
+                "/" | "/status.json" | "/programs.json" | "/frame.png" | "/stream.mjpg"
+                | "/program",
+            ) => {
+                Response::text(405, "method not allowed\n")
+            }
+            _ => Response::not_found(),
+        }
+    }
+
+    /// Whether the request may be served: always while the device is unlocked,
+    /// otherwise only with the device password as Basic credentials
+    async fn authorized(&self, request: &Request) -> bool {
+        let lock = self.services.read().await.lock.clone();
+        if !lock.is_locked() {
+            return true;
+        }
+        let password = request
+            .header("Authorization")
+            .and_then(|value| value.strip_prefix("Basic "))
+            .and_then(|encoded| BASE64.decode(encoded.trim()).ok())
+            .and_then(|decoded| String::from_utf8(decoded).ok())
+            .and_then(|credentials| Some(credentials.split_once(':')?.1.to_string()));
+        password.is_some_and(|password| lock.verify(&password))
+    }
+
+    /// (current index, loaded programs), as for the GetProgramList SDK command
+    async fn program_list(&self) -> (usize, Vec<ProgramSummary>) {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.player_tx.send(PlayerCommand::GetProgramList(reply_tx)).await.ok();
+        reply_rx.await.unwrap_or_default()
+    }
+
+    async fn status(&self) -> Response {
+        let (current, programs) = self.program_list().await;
+        let brightness = self.services.read().await.brightness.get_level();
+        let body = json!({
+            "programs": programs.len(),
+            "current": current,
+            "playing": programs.get(current).map(|p| &p.name),
+            "brightness": brightness,
+        });
+        Response::json(200, body.to_string())
+    }
+
+    async fn programs(&self) -> Response {
+        let (current, programs) = self.program_list().await;
+        let programs: Vec<_> = programs
+            .iter()
+            .map(|p| {
+                json!({
+                    "guid": p.guid,
+                    "name": p.name,
+                    "areas": p.area_count,
+                    "duration": p.duration_secs,
+                })
+            })
+            .collect();
+        Response::json(200, json!({ "current": current, "programs": programs }).to_string())
+    }
+
+    /// (width, height, RGBA pixels) of the last rendered frame; None if the
+    /// player isn't running
+    async fn capture(&self) -> Option<(u32, u32, Vec<u8>)> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.player_tx.send(PlayerCommand::CaptureFrame(reply_tx)).await.ok();
+        reply_rx.await.ok()
+    }
+
+    async fn frame(&self) -> Response {
+        let Some((width, height, pixels)) = self.capture().await else {
+            return Response::text(503, "player not running\n");
+        };
+        match export::encode_png(&pixels, width, height) {
+            Ok(png) => Response::new(200, "image/png", png),
+            Err(e) => {
+                warn!("Failed to encode frame for the web UI: {}", e);
+                Response::text(500, "failed to encode frame\n")
+            }
+        }
+    }
+
+    /// Stream the current frame as MJPEG until the client disconnects
+    fn stream(&self) -> Response {
+        let (tx, rx) = mpsc::channel(1);
+        let web = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(STREAM_FRAME_INTERVAL);
+            loop {
+                interval.tick().await;
+                let Some((width, height, pixels)) = web.capture().await else {
+                    break;
+                };
+                let jpeg = match export::encode_jpeg(&pixels, width, height) {
+                    Ok(jpeg) => jpeg,
+                    Err(e) => {
+                        warn!("Failed to encode frame for the web UI stream: {}", e);
+                        break;
+                    }
+                };
+                let mut part = format!(
+                    "--frame\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\n\r\n",
+                    jpeg.len()
+                )
+                .into_bytes();
+                part.extend_from_slice(&jpeg);
+                part.extend_from_slice(b"\r\n");
+                // The receiver is dropped once the client goes away
+                if tx.send(part).await.is_err() {
+                    break;
+                }
+            }
+        });
+        Response::stream(STREAM_CONTENT_TYPE, rx)
+    }
+
+    /// Add the uploaded program XML to the saved programs and play them, like AddProgram
+    async fn upload(&self, body: &[u8]) -> Response {
+        let xml = parser::decode_xml_bytes(body);
+        let screen = match parser::parse_program_xml(&xml) {
+            Ok(screen) => screen,
+            Err(e) => {
+                warn!("Rejected program uploaded from the web UI: {}", e);
+                return Response::json(400, json!({ "error": e.to_string() }).to_string());
+            }
+        };
+        let warnings: Vec<String> =
+            parser::blank_program_warnings(&screen).iter().map(|w| w.to_string()).collect();
+        let count = screen.programs.len();
+        info!("Program uploaded from the web UI ({} program(s))", count);
+        let screen = self.services.read().await.storage.save_and_merge(screen, &xml);
+        self.player_tx.send(PlayerCommand::LoadScreen(screen)).await.ok();
+        Response::json(200, json!({ "programs": count, "warnings": warnings }).to_string())
+    }
+}
+
+/// False for browser requests sent from another site's page (CSRF): their
+/// Origin doesn't name the host they are addressed to. Requests without an
+/// Origin (same-origin GETs, curl) are let through.
+fn same_origin(request: &Request) -> bool {
+    let Some(origin) = request.header("Origin") else {
+        return true;
+    };
+    let origin_host = origin.split_once("://").map(|(_, host)| host.trim_end_matches('/'));
+    match (origin_host, request.header("Host")) {
+        (Some(origin_host), Some(host)) => origin_host.eq_ignore_ascii_case(host),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::PlayerConfig;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+
+    async fn request(addr: SocketAddr, method: &str, path: &str, body: &str) -> String {
+        request_with(addr, method, path, "", body).await
+    }
+
+    /// Send a request with extra header lines (each ending in CRLF)
+    async fn request_with(
+        addr: SocketAddr,
+        method: &str,
+        path: &str,
+        headers: &str,
+        body: &str,
+    ) -> String {
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        let request = format!(
+            "{method} {path} HTTP/1.1\r\nHost: localhost\r\n{headers}\
+             Content-Length: {}\r\n\r\n{body}",
+            body.len()
+        );
+        client.write_all(request.as_bytes()).await.unwrap();
+        let mut reply = String::new();
+        client.read_to_string(&mut reply).await.unwrap();
+        reply
+    }
+
+    #[tokio::test]
+    async fn test_status_reports_program_count() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = ServicesState::new(&PlayerConfig::for_test(dir.path()));
+        let services = Arc::new(RwLock::new(state));
+        let (tx, mut rx) = mpsc::channel(4);
+        tokio::spawn(async move {
+            while let Some(cmd) = rx.recv().await {
+                if let PlayerCommand::GetProgramList(reply) = cmd {
+                    let program = |guid: &str| ProgramSummary {
+                        guid: guid.to_string(),
+                        name: format!("Program {guid}"),
+                        area_count: 1,
+                        duration_secs: 10,
+                    };
+                    reply.send((1, vec![program("p1"), program("p2")])).ok();
+                }
+            }
+        });
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(serve(listener, Web { player_tx: tx, services }));
+
+        let reply = request(addr, "GET", "/status.json", "").await;
+        assert!(reply.starts_with("HTTP/1.1 200 OK"));
+        let body = reply.split("\r\n\r\n").nth(1).unwrap();
+        let status: serde_json::Value = serde_json::from_str(body).unwrap();
+        assert_eq!(status["programs"], 2);
+        assert_eq!(status["current"], 1);
+        assert_eq!(status["playing"], "Program p2");
+
+        // Bad uploads are refused with the parse error
+        let reply = request(addr, "POST", "/program", "<screen><program").await;
+        assert!(reply.starts_with("HTTP/1.1 400"));
+        assert!(request(addr, "DELETE", "/program", "").await.starts_with("HTTP/1.1 405"));
+
+        server.abort();
+    }
+
+    #[tokio::test]
+    async fn test_upload_decodes_utf16() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = ServicesState::new(&PlayerConfig::for_test(dir.path()));
+        let (tx, mut rx) = mpsc::channel(4);
+        let web = Web {
+            player_tx: tx,
+            services: Arc::new(RwLock::new(state)),
+        };
+
+        // As HDPlayer saves it on Windows: UTF-16LE with a BOM
+        let xml = r#"<screen><program guid="p" name="促销"><area guid="a">
+            <rectangle width="8" height="8"/><resources/></area></program></screen>"#;
+        let body: Vec<u8> = [0xff, 0xfe]
+            .into_iter()
+            .chain(xml.encode_utf16().flat_map(u16::to_le_bytes))
+            .collect();
+        let reply = web.upload(&body).await;
+        assert_eq!(reply.status, 200);
+        let Some(PlayerCommand::LoadScreen(screen)) = rx.recv().await else {
+            panic!("expected LoadScreen");
+        };
+        assert_eq!(screen.programs[0].name, "促销");
+    }
+
+    #[tokio::test]
+    async fn test_locked_device_needs_password() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = ServicesState::new(&PlayerConfig::for_test(dir.path()));
+        state.lock.set("s3cret").unwrap();
+        let services = Arc::new(RwLock::new(state));
+        let (tx, mut rx) = mpsc::channel(4);
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let web = Web {
+            player_tx: tx,
+            services: services.clone(),
+        };
+        let server = tokio::spawn(serve(listener, web));
+
+        let reply = request(addr, "GET", "/", "").await;
+        assert!(reply.starts_with("HTTP/1.1 401"));
+        assert!(reply.contains("WWW-Authenticate: Basic"));
+        let wrong = format!("Authorization: Basic {}\r\n", BASE64.encode("admin:guess"));
+        assert!(request_with(addr, "GET", "/", &wrong, "").await.starts_with("HTTP/1.1 401"));
+        let right = format!("Authorization: Basic {}\r\n", BASE64.encode("admin:s3cret"));
+        assert!(request_with(addr, "GET", "/", &right, "").await.starts_with("HTTP/1.1 200"));
+
+        // Uploads need the password too
+        let xml = r#"<screen><program guid="p"><area guid="a">
+            <rectangle width="8" height="8"/>
+            <resources><text guid="t"><string>hi</string></text></resources>
+            </area></program></screen>"#;
+        assert!(request(addr, "POST", "/program", xml).await.starts_with("HTTP/1.1 401"));
+        assert!(rx.try_recv().is_err());
+        assert!(services.read().await.storage.load_current_program().is_none());
+
+        server.abort();
+    }
+
+    #[tokio::test]
+    async fn test_cross_origin_requests_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = ServicesState::new(&PlayerConfig::for_test(dir.path()));
+        let services = Arc::new(RwLock::new(state));
+        let (tx, _rx) = mpsc::channel(4);
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(serve(listener, Web { player_tx: tx, services }));
+
+        let evil = "Origin: http://evil.example\r\n";
+        let reply = request_with(addr, "POST", "/program", evil, "<screen/>").await;
+        assert!(reply.starts_with("HTTP/1.1 403"));
+        let null = "Origin: null\r\n";
+        assert!(request_with(addr, "GET", "/", null, "").await.starts_with("HTTP/1.1 403"));
+        let own = "Origin: http://localhost\r\n";
+        assert!(request_with(addr, "GET", "/", own, "").await.starts_with("HTTP/1.1 200"));
+
+        server.abort();
+    }
+
+    #[tokio::test]
+    async fn test_stream_sends_jpeg_frames() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = ServicesState::new(&PlayerConfig::for_test(dir.path()));
+        let services = Arc::new(RwLock::new(state));
+        let (tx, mut rx) = mpsc::channel(4);
+        tokio::spawn(async move {
+            while let Some(cmd) = rx.recv().await {
+                if let PlayerCommand::CaptureFrame(reply) = cmd {
+                    reply.send((2, 2, [255, 0, 0, 255].repeat(4))).ok();
+                }
+            }
+        });
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(serve(listener, Web { player_tx: tx, services }));
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        client.write_all(b"GET /stream.mjpg HTTP/1.1\r\nHost: localhost\r\n\r\n").await.unwrap();
+        let mut received = Vec::new();
+        let mut chunk = [0u8; 4096];
+        let parts = |received: &[u8]| received.windows(7).filter(|w| w == b"--frame").count();
+        while parts(&received) < 2 {
+            let n = client.read(&mut chunk).await.unwrap();
+            assert!(n > 0, "stream ended early");
+            received.extend_from_slice(&chunk[..n]);
+        }
+        let head = String::from_utf8_lossy(&received);
+        assert!(head.starts_with("HTTP/1.1 200 OK"));
+        assert!(head.contains(STREAM_CONTENT_TYPE));
+        assert!(!head.split("\r\n\r\n").next().unwrap().contains("Content-Length"));
+        // Each part is a JPEG
+        assert!(received.windows(3).any(|w| w == [0xFF, 0xD8, 0xFF]));
+
+        server.abort();
+    }
+}
@@ -0,0 +1,133 @@
+/// Color correction for panels with a color cast, applied to each finished
+/// frame. Configured either as per-channel gains or a full 3x3 matrix.
+use tiny_skia::Pixmap;
+
+/// Row-major 3x3 matrix mapping (r, g, b) in to (r, g, b) out
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColorCorrection {
+    pub matrix: [[f32; 3]; 3],
+}
+
+impl Default for ColorCorrection {
+    fn default() -> Self {
+        Self::gains(1.0, 1.0, 1.0)
+    }
+}
+
+impl ColorCorrection {
+    /// Scale each channel independently
+    pub fn gains(r: f32, g: f32, b: f32) -> Self {
+        Self {
+            matrix: [[r, 0.0, 0.0], [0.0, g, 0.0], [0.0, 0.0, b]],
+        }
+    }
+
+    pub fn is_identity(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Precompute the per-pixel work for this correction
+    pub fn compile(&self) -> CompiledCorrection {
+        let m = &self.matrix;
+        let diagonal = (0..3).all(|i| (0..3).all(|j| i == j || m[i][j] == 0.0));
+        if diagonal {
+            // Per-channel gains: one lookup table per channel
+            let lut = |gain: f32| {
+                let mut table = [0u8; 256];
+                for (v, out) in table.iter_mut().enumerate() {
+                    *out = (v as f32 * gain).round().clamp(0.0, 255.0) as u8;
+                }
+                table
+            };
+            CompiledCorrection::Gains(Box::new([lut(m[0][0]), lut(m[1][1]), lut(m[2][2])]))
+        } else {
+            // Fixed point, 1.0 = 1024
+            let fixed = m.map(|row| row.map(|c| (c * 1024.0).round() as i32));
+            CompiledCorrection::Matrix(fixed)
+        }
+    }
+}
+
+impl std::str::FromStr for ColorCorrection {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let usage = || {
+            format!("Invalid color correction: {s} (expected gain:R,G,B or matrix:9 values)")
+        };
+        let (kind, values) = s.split_once(':').ok_or_else(usage)?;
+        let values: Vec<f32> = values
+            .split(',')
+            .map(|v| v.trim().parse::<f32>())
+            .collect::<Result<_, _>>()
+            .map_err(|_| usage())?;
+        if values.iter().any(|v| !v.is_finite()) {
+            return Err(usage());
+        }
+        match (kind.trim().to_lowercase().as_str(), values.as_slice()) {
+            ("gain", &[r, g, b]) => Ok(Self::gains(r, g, b)),
+            ("matrix", m) if m.len() == 9 => Ok(Self {
+                matrix: [[m[0], m[1], m[2]], [m[3], m[4], m[5]], [m[6], m[7], m[8]]],
+            }),
+            _ => Err(usage()),
+        }
+    }
+}
+
+/// A color correction ready to apply to frames
+#[derive(Debug, Clone)]
+pub enum CompiledCorrection {
+    /// Lookup tables for the red, green and blue channels
+    Gains(Box<[[u8; 256]; 3]>),
+    /// Matrix coefficients in 1/1024ths
+    Matrix([[i32; 3]; 3]),
+}
+
+impl CompiledCorrection {
+    /// Correct every pixel of `frame` in place. Frames are opaque, so the
+    /// premultiplied channels are the plain color.
+    pub fn apply(&self, frame: &mut Pixmap) {
+        let data = frame.data_mut();
+        match self {
+            CompiledCorrection::Gains(luts) => {
+                for px in data.chunks_exact_mut(4) {
+                    px[0] = luts[0][px[0] as usize];
+                    px[1] = luts[1][px[1] as usize];
+                    px[2] = luts[2][px[2] as usize];
+                }
+            }
+            CompiledCorrection::Matrix(m) => {
+                for px in data.chunks_exact_mut(4) {
+                    let rgb = [px[0] as i32, px[1] as i32, px[2] as i32];
+                    for (out, row) in px.iter_mut().zip(m) {
+                        let v = row[0] * rgb[0] + row[1] * rgb[1] + row[2] * rgb[2];
+                        *out = ((v + 512) >> 10).clamp(0, 255) as u8;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tiny_skia::Color;
+
+    fn corrected(correction: &str, rgb: [u8; 3]) -> [u8; 3] {
+        let correction: ColorCorrection = correction.parse().unwrap();
+        let mut frame = Pixmap::new(1, 1).unwrap();
+        frame.fill(Color::from_rgba8(rgb[0], rgb[1], rgb[2], 255));
+        correction.compile().apply(&mut frame);
+        frame.data()[..3].try_into().unwrap()
+    }
+
+    #[test]
+    fn test_red_gain_halves_red() {
+        assert_eq!(corrected("gain:0.5,1,1", [200, 100, 50]), [100, 100, 50]);
+        // Same correction written as a matrix, plus channel mixing
+        assert_eq!(corrected("matrix:0.5,0,0,0,1,0,0,0,1", [200, 100, 50]), [100, 100, 50]);
+        assert_eq!(corrected("matrix:0,0,1,0,1,0,1,0,0", [200, 100, 50]), [50, 100, 200]);
+        assert!("gain:1,2".parse::<ColorCorrection>().is_err());
+        assert!(ColorCorrection::default().is_identity());
+    }
+}
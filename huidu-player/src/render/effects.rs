@@ -22,10 +22,17 @@ pub struct EffectState {
     pub in_speed: u8,
     /// Exit speed (0-8, lower = faster)
     pub out_speed: u8,
+    /// Distance (px) of one head-to-tail loop; the engine sets this to the area
+    /// width or height
+    pub scroll_extent: u32,
+    /// How long (ms) the item waits from `phase_start_ms` before entering
+    pub start_delay_ms: u64,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum EffectPhase {
+    /// Not shown yet: waiting out the item's start delay
+    Waiting,
     Entering,
     Displaying,
     Exiting,
@@ -44,9 +51,35 @@ impl EffectState {
             effect_out,
             in_speed,
             out_speed,
+            scroll_extent: 0,
+            start_delay_ms: 0,
         }
     }
 
+    /// Hold the item back for `delay_ms` from the current phase start before
+    /// it enters. Nothing of it is drawn meanwhile.
+    pub fn delay_start(&mut self, delay_ms: u64) {
+        if delay_ms > 0 {
+            self.phase = EffectPhase::Waiting;
+            self.progress = 0.0;
+            self.start_delay_ms = delay_ms;
+        }
+    }
+
+    /// Head-to-tail series moves (26-29) scroll the same item forever, unless
+    /// the duration is 0 (see `update`)
+    pub fn is_looping(&self) -> bool {
+        is_head_to_tail(self.effect_in)
+    }
+
+    /// Time for a series move to scroll the content through once: in and back out
+    /// for 21-24, a single full loop for head-to-tail
+    fn scroll_once_ms(&self) -> u64 {
+        let extent = self.scroll_extent.max(1) as u64;
+        let distance = if self.is_looping() { extent } else { extent * 2 };
+        (distance * 1000 / scroll_speed_px_per_sec(self.in_speed)).max(1)
+    }
+
     /// Get the transition duration in ms for a given speed (0=fastest, 8=slowest)
     fn transition_duration_ms(speed: u8) -> u64 {
         match speed {
@@ -66,6 +99,41 @@ impl EffectState {
     /// Update the effect state based on elapsed time.
     /// Returns true if the content item should advance to the next one.
     pub fn update(&mut self, elapsed_ms: u64) -> bool {
+        if self.phase == EffectPhase::Waiting {
+            let entering_at = self.phase_start_ms + self.start_delay_ms;
+            if elapsed_ms < entering_at {
+                return false;
+            }
+            // Effects run as though the item started when the delay ended
+            self.phase = EffectPhase::Entering;
+            self.phase_start_ms = entering_at;
+        }
+
+        if self.display_duration_ms == 0 && is_series_move(self.effect_in) {
+            // Duration 0 on a scrolling item means "scroll through once, then
+            // advance", not "forever"
+            let elapsed_in_phase = elapsed_ms.saturating_sub(self.phase_start_ms);
+            let total = self.scroll_once_ms();
+            if elapsed_in_phase >= total {
+                self.progress = 1.0;
+                self.phase = EffectPhase::Done;
+                return true;
+            }
+            self.phase = EffectPhase::Entering;
+            self.progress = elapsed_in_phase as f32 / total as f32;
+            return false;
+        }
+
+        if self.is_looping() {
+            // Progress is the position within the current loop; never advance
+            let elapsed_in_phase = elapsed_ms.saturating_sub(self.phase_start_ms);
+            let extent = self.scroll_extent.max(1) as u64;
+            let travelled = elapsed_in_phase * scroll_speed_px_per_sec(self.in_speed) / 1000;
+            self.phase = EffectPhase::Entering;
+            self.progress = (travelled % extent) as f32 / extent as f32;
+            return false;
+        }
+
         match self.phase {
             EffectPhase::Entering => {
                 let dur = Self::transition_duration_ms(self.in_speed);
@@ -85,7 +153,7 @@ impl EffectState {
             }
             EffectPhase::Displaying => {
                 if self.display_duration_ms == 0 {
-                    // Duration 0 means display forever (single item or continuous scroll)
+                    // Duration 0 means display forever for static effects
                     return false;
                 }
                 let elapsed_in_phase = elapsed_ms.saturating_sub(self.phase_start_ms);
@@ -112,6 +180,8 @@ impl EffectState {
                 false
             }
             EffectPhase::Done => true,
+            // Handled above
+            EffectPhase::Waiting => false,
         }
     }
 
@@ -120,6 +190,7 @@ impl EffectState {
         self.phase = EffectPhase::Entering;
         self.progress = 0.0;
         self.phase_start_ms = start_ms;
+        self.start_delay_ms = 0;
         self.display_duration_ms = duration_tenths as u64 * 100;
         self.effect_in = effect_in;
         self.effect_out = effect_out;
@@ -128,8 +199,39 @@ impl EffectState {
     }
 }
 
+/// Whether an effect type is one of the head-to-tail series moves (26-29)
+pub fn is_head_to_tail(effect_type: u8) -> bool {
+    (26..=29).contains(&effect_type)
+}
+
+/// Whether an effect type is a series move (21-29), i.e. a continuous scroll
+pub fn is_series_move(effect_type: u8) -> bool {
+    (21..=29).contains(&effect_type)
+}
+
+/// Scroll speed in px/sec for a speed level (0=fastest, 8=slowest)
+pub fn scroll_speed_px_per_sec(speed: u8) -> u64 {
+    match speed {
+        0 => 100,
+        1 => 80,
+        2 => 64,
+        3 => 50,
+        4 => 40,
+        5 => 32,
+        6 => 24,
+        7 => 16,
+        8 => 8,
+        _ => 50,
+    }
+}
+
 /// Apply a transition effect to a rendered content pixmap,
 /// compositing it onto the target area surface.
+///
+/// Effects only decide how much of the content reaches the area surface; the
+/// area's own opacity is applied once, when the engine composites the surface
+/// onto the frame. A fade at opacity `p` into an area of opacity `a` therefore
+/// shows the content at `p * a`, with the area background at `a` throughout.
 pub fn apply_effect(
     effect_type: u8,
     progress: f32,
@@ -143,8 +245,9 @@ pub fn apply_effect(
         EffectPhase::Entering => progress,
         EffectPhase::Exiting => 1.0 - progress,
         EffectPhase::Displaying => 1.0,
-        EffectPhase::Done => return,
-    };
+        EffectPhase::Waiting | EffectPhase::Done => return,
+    }
+    .clamp(0.0, 1.0);
 
     match effect_type {
         0 => {
@@ -247,14 +350,17 @@ pub fn apply_effect(
             draw_region(content, target, 0, center, 0, center, width, half as u32);
         }
         15 => {
-            // Horizontal close (close to center)
-            let edge = ((1.0 - p) * width as f32 / 2.0) as i32;
-            draw_region(content, target, edge, 0, edge, 0, width - 2 * edge as u32, height);
+            // Horizontal close (close to center). Clamp the edge so the visible
+            // span can't underflow on the last frames.
+            let edge = (((1.0 - p) * width as f32 / 2.0) as u32).min(width / 2);
+            let visible = width.saturating_sub(2 * edge);
+            draw_region(content, target, edge as i32, 0, edge as i32, 0, visible, height);
         }
         16 => {
             // Vertical close
-            let edge = ((1.0 - p) * height as f32 / 2.0) as i32;
-            draw_region(content, target, 0, edge, 0, edge, width, height - 2 * edge as u32);
+            let edge = (((1.0 - p) * height as f32 / 2.0) as u32).min(height / 2);
+            let visible = height.saturating_sub(2 * edge);
+            draw_region(content, target, 0, edge as i32, 0, edge as i32, width, visible);
         }
         17 => {
             // Fade
@@ -299,8 +405,18 @@ pub fn apply_effect(
             apply_effect(pseudo_type, progress, phase, content, target, width, height);
         }
         26..=29 => {
-            // Head-to-tail series move — same as series move for now
-            draw_full(content, target);
+            // Head-to-tail series move: progress is the loop position. The
+            // content is drawn twice, one copy trailing the other by its own
+            // size, so the tail runs straight into the head with no gap.
+            let (dx, dy, step_x, step_y) = match effect_type {
+                26 => (-(progress * width as f32) as i32, 0, width as i32, 0),   // left
+                27 => ((progress * width as f32) as i32, 0, -(width as i32), 0), // right
+                28 => (0, -(progress * height as f32) as i32, 0, height as i32), // up
+                _ => (0, (progress * height as f32) as i32, 0, -(height as i32)), // down
+            };
+            for (x, y) in [(dx, dy), (dx + step_x, dy + step_y)] {
+                draw_region(content, target, x, y, 0, 0, width, height);
+            }
         }
         _ => {
             draw_full(content, target);
@@ -319,6 +435,7 @@ fn draw_full(content: &Pixmap, target: &mut Pixmap) {
 }
 
 /// Draw a rectangular region from content onto target
+#[allow(clippy::too_many_arguments)]
 fn draw_region(
     content: &Pixmap,
     target: &mut Pixmap,
@@ -329,35 +446,187 @@ fn draw_region(
     w: u32,
     h: u32,
 ) {
-    let cw = content.width() as i32;
-    let tw = target.width() as i32;
-    let th = target.height() as i32;
+    // Clip the copy rectangle against both surfaces up front; i64 keeps
+    // extreme offsets from overflowing
+    let (cw, ch) = (content.width() as i64, content.height() as i64);
+    let (tw, th) = (target.width() as i64, target.height() as i64);
+    let (dst_x, dst_y, src_x, src_y) = (dst_x as i64, dst_y as i64, src_x as i64, src_y as i64);
+    let col_start = 0.max(-src_x).max(-dst_x);
+    let col_end = (w as i64).min(cw - src_x).min(tw - dst_x);
+    let row_start = 0.max(-src_y).max(-dst_y);
+    let row_end = (h as i64).min(ch - src_y).min(th - dst_y);
+    if col_start >= col_end || row_start >= row_end {
+        return;
+    }
+
     let src_data = content.data();
     let dst_data = target.data_mut();
 
-    for row in 0..h as i32 {
+    for row in row_start..row_end {
         let sy = src_y + row;
         let dy = dst_y + row;
-        if sy < 0 || sy >= content.height() as i32 || dy < 0 || dy >= th {
-            continue;
-        }
-        for col in 0..w as i32 {
-            let sx = src_x + col;
-            let dx = dst_x + col;
-            if sx < 0 || sx >= cw || dx < 0 || dx >= tw {
+        for col in col_start..col_end {
+            let si = ((sy * cw + src_x + col) * 4) as usize;
+            let di = ((dy * tw + dst_x + col) * 4) as usize;
+            // Never trust the arithmetic alone: both slices are checked
+            let (Some(src), Some(dst)) = (src_data.get(si..si + 4), dst_data.get_mut(di..di + 4))
+            else {
                 continue;
-            }
-            let si = ((sy * cw + sx) * 4) as usize;
-            let di = ((dy * tw + dx) * 4) as usize;
+            };
             // Simple alpha-over compositing
-            let sa = src_data[si + 3] as f32 / 255.0;
+            let sa = src[3] as f32 / 255.0;
             if sa > 0.0 {
                 let inv_sa = 1.0 - sa;
-                dst_data[di] = (src_data[si] as f32 + dst_data[di] as f32 * inv_sa) as u8;
-                dst_data[di + 1] = (src_data[si + 1] as f32 + dst_data[di + 1] as f32 * inv_sa) as u8;
-                dst_data[di + 2] = (src_data[si + 2] as f32 + dst_data[di + 2] as f32 * inv_sa) as u8;
-                dst_data[di + 3] = ((sa + dst_data[di + 3] as f32 / 255.0 * inv_sa) * 255.0) as u8;
+                dst[0] = (src[0] as f32 + dst[0] as f32 * inv_sa) as u8;
+                dst[1] = (src[1] as f32 + dst[1] as f32 * inv_sa) as u8;
+                dst[2] = (src[2] as f32 + dst[2] as f32 * inv_sa) as u8;
+                dst[3] = ((sa + dst[3] as f32 / 255.0 * inv_sa) * 255.0) as u8;
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tiny_skia::Color;
+
+    fn solid(w: u32, h: u32) -> Pixmap {
+        let mut p = Pixmap::new(w, h).unwrap();
+        p.fill(Color::WHITE);
+        p
+    }
+
+    fn opaque_count(p: &Pixmap) -> usize {
+        p.data().chunks_exact(4).filter(|px| px[3] > 0).count()
+    }
+
+    #[test]
+    fn test_close_effects_cover_full_progress_range() {
+        // Odd sizes exercise the rounding at the center
+        for (w, h) in [(7u32, 5u32), (8, 8), (1, 1), (128, 17)] {
+            let content = solid(w, h);
+            for effect in [15u8, 16] {
+                for step in 0..=100 {
+                    let progress = step as f32 / 100.0;
+                    for phase in [EffectPhase::Entering, EffectPhase::Exiting] {
+                        let mut target = Pixmap::new(w, h).unwrap();
+                        apply_effect(effect, progress, phase, &content, &mut target, w, h);
+                    }
+                }
+
+                // Collapsed to the center at the start of the entrance; odd
+                // sizes keep the single middle column/row
+                let mut target = Pixmap::new(w, h).unwrap();
+                apply_effect(effect, 0.0, EffectPhase::Entering, &content, &mut target, w, h);
+                let collapsed = if effect == 15 { (w % 2) * h } else { (h % 2) * w };
+                assert_eq!(opaque_count(&target), collapsed as usize);
+
+                // Fully open at the end
+                let mut target = Pixmap::new(w, h).unwrap();
+                apply_effect(effect, 1.0, EffectPhase::Entering, &content, &mut target, w, h);
+                assert_eq!(opaque_count(&target), (w * h) as usize);
+            }
+        }
+    }
+
+    #[test]
+    fn test_head_to_tail_draws_two_copies_at_seam() {
+        // Left half red, right half blue
+        let mut content = Pixmap::new(10, 1).unwrap();
+        for (i, px) in content.data_mut().chunks_exact_mut(4).enumerate() {
+            px.copy_from_slice(if i < 5 { &[255, 0, 0, 255] } else { &[0, 0, 255, 255] });
+        }
+        let px = |p: &Pixmap, x: usize| p.data()[x * 4..x * 4 + 4].to_vec();
+
+        // Halfway through a leftward loop the blue tail is followed by the red head
+        let mut target = Pixmap::new(10, 1).unwrap();
+        apply_effect(26, 0.5, EffectPhase::Entering, &content, &mut target, 10, 1);
+        assert_eq!(px(&target, 4), vec![0, 0, 255, 255]);
+        assert_eq!(px(&target, 5), vec![255, 0, 0, 255]);
+        assert_eq!(px(&target, 9), vec![255, 0, 0, 255]);
+
+        // Rightward: the second copy fills in from the left edge
+        let mut target = Pixmap::new(10, 1).unwrap();
+        apply_effect(27, 0.3, EffectPhase::Entering, &content, &mut target, 10, 1);
+        assert_eq!(px(&target, 2), vec![0, 0, 255, 255]);
+        assert_eq!(px(&target, 3), vec![255, 0, 0, 255]);
+        assert!(target.data().chunks_exact(4).all(|p| p[3] == 255));
+    }
+
+    #[test]
+    fn test_head_to_tail_never_advances() {
+        let mut state = EffectState::new(26, 1, 3, 3, 10);
+        state.scroll_extent = 100;
+        for ms in (0..20_000).step_by(100) {
+            assert!(!state.update(ms));
+            assert!((0.0..1.0).contains(&state.progress));
+        }
+        // 50 px/sec over a 100px extent wraps every 2 seconds
+        state.update(21_000);
+        assert!((state.progress - 0.5).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_duration_zero_static_item_displays_forever() {
+        let mut state = EffectState::new(1, 1, 1, 1, 0);
+        state.scroll_extent = 100;
+        for ms in (0..600_000).step_by(1000) {
+            assert!(!state.update(ms));
+        }
+        assert_eq!(state.phase, EffectPhase::Displaying);
+    }
+
+    #[test]
+    fn test_duration_zero_scroll_advances_when_done() {
+        // Series move at 50 px/sec through a 100px area: in and out is 4 seconds
+        let mut state = EffectState::new(21, 0, 3, 0, 0);
+        state.scroll_extent = 100;
+        assert!(!state.update(0));
+        assert!(!state.update(2000));
+        assert!((state.progress - 0.5).abs() < 0.01);
+        assert!(!state.update(3999));
+        assert!(state.update(4000));
+
+        // Head-to-tail goes round once (2 seconds) before advancing
+        let mut state = EffectState::new(26, 0, 3, 0, 0);
+        state.scroll_extent = 100;
+        assert!(!state.update(1999));
+        assert!(state.update(2000));
+    }
+
+    #[test]
+    fn test_close_effects_tolerate_out_of_range_progress() {
+        let content = solid(9, 9);
+        for progress in [-0.5f32, 1.5, f32::NAN] {
+            let mut target = Pixmap::new(9, 9).unwrap();
+            apply_effect(15, progress, EffectPhase::Entering, &content, &mut target, 9, 9);
+            apply_effect(16, progress, EffectPhase::Exiting, &content, &mut target, 9, 9);
+        }
+    }
+
+    #[test]
+    fn test_draw_region_mismatched_sizes_never_panic() {
+        let offsets = [i32::MIN, -1000, -17, -1, 0, 1, 3, 17, 1000, i32::MAX];
+        let extents = [0u32, 1, 5, 64, u32::MAX];
+        for (cw, ch, tw, th) in [(1, 1, 64, 32), (64, 32, 1, 1), (7, 3, 5, 9), (128, 1, 1, 128)] {
+            let content = solid(cw, ch);
+            let mut target = Pixmap::new(tw, th).unwrap();
+            for &dx in &offsets {
+                for &sx in &offsets {
+                    for &w in &extents {
+                        draw_region(&content, &mut target, dx, 0, sx, 0, w, ch);
+                        draw_region(&content, &mut target, 0, dx, 0, sx, cw, w);
+                    }
+                }
+            }
+        }
+
+        // An in-range copy still lands where asked
+        let content = solid(4, 4);
+        let mut target = Pixmap::new(8, 8).unwrap();
+        draw_region(&content, &mut target, -2, 6, 0, 0, 4, 4);
+        assert_eq!(opaque_count(&target), 2 * 2);
+        assert_eq!(target.pixel(1, 7).unwrap().alpha(), 255);
+    }
+}
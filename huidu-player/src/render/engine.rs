@@ -1,23 +1,108 @@
 /// Rendering engine — composites areas onto a framebuffer using tiny-skia.
 /// Handles content cycling with transition effects.
-use anyhow::Result;
-use std::path::Path;
+use std::collections::{HashMap, HashSet};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 use tiny_skia::{Color, Pixmap, PixmapPaint, Transform};
+use tracing::{error, warn};
 
-use crate::program::model::{ContentItem, Program};
+use crate::program::model::{parse_color, Area, ContentItem, ContentKind, Program};
+use crate::render::color::{ColorCorrection, CompiledCorrection};
 use crate::render::effects::{self, EffectPhase, EffectState};
-use crate::render::plugins::clock::ClockRenderer;
-use crate::render::plugins::gif::GifRenderer;
 use crate::render::plugins::image::ImageRenderer;
-use crate::render::plugins::text::TextRenderer;
-use crate::render::plugins::video::VideoRenderer;
-use crate::render::plugins::ContentRenderer;
+use crate::render::plugins::{self, ContentRenderer, Decoded, PreloadRequest};
+use crate::render::testpattern::{self, TestPattern};
+
+/// Default time for a brightness change to fade in, so the sign doesn't flash
+pub const DEFAULT_BRIGHTNESS_FADE_MS: u64 = 500;
+
+/// How often content that failed to render is retried (e.g. a file uploaded late)
+const FAILED_RENDER_RETRY_MS: u64 = 1000;
+
+/// Edge length of the marker drawn where content failed to render
+const ERROR_GLYPH_SIZE: u32 = 8;
+
+/// Media for the renderers to decode ahead of its first render, from
+/// [`RenderEngine::media_preload`]. Requests with no kind are area backgrounds.
+pub struct MediaPreload {
+    program_dir: PathBuf,
+    requests: Vec<(Option<ContentKind>, PreloadRequest)>,
+}
+
+impl MediaPreload {
+    pub fn is_empty(&self) -> bool {
+        self.requests.is_empty()
+    }
+
+    /// Decode everything requested. Blocks until done, so run it off the render loop.
+    pub fn decode(self) -> PreloadedMedia {
+        let decoded = self
+            .requests
+            .into_iter()
+            .map(|(kind, request)| (kind, request.decode(&self.program_dir)))
+            .collect();
+        PreloadedMedia(decoded)
+    }
+}
+
+/// Decoded media from [`MediaPreload::decode`], for [`RenderEngine::insert_preloaded`]
+pub struct PreloadedMedia(Vec<(Option<ContentKind>, Decoded)>);
+
+impl std::fmt::Debug for PreloadedMedia {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let files: usize = self.0.iter().map(|(_, decoded)| decoded.len()).sum();
+        write!(f, "PreloadedMedia({files} file(s))")
+    }
+}
+
+/// Time spent in one content type's renderer since startup, as reported by
+/// GetRenderStats
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RenderTiming {
+    /// Renders performed (cached frames don't count)
+    pub calls: u64,
+    pub total: Duration,
+    /// Slowest single render
+    pub max: Duration,
+}
+
+impl RenderTiming {
+    fn record(&mut self, took: Duration) {
+        self.calls += 1;
+        self.total += took;
+        self.max = self.max.max(took);
+    }
+
+    pub fn average(&self) -> Duration {
+        Duration::from_nanos((self.total.as_nanos() / self.calls.max(1) as u128) as u64)
+    }
+}
+
+/// Cross-fade from the outgoing program's last frame
+struct ProgramTransition {
+    from: Pixmap,
+    start_ms: u64,
+    duration_ms: u64,
+}
 
 /// Per-area state for content cycling
 struct AreaState {
     /// Which content item is currently displayed (index into resources)
     current_item: usize,
     effect: EffectState,
+    /// Item whose render is currently held in the area's content surface
+    rendered_item: Option<usize>,
+    /// When the content next needs redrawing (None: never, until the item changes)
+    next_redraw_ms: Option<u64>,
+    /// Whether the area surface holds a finished composite that can be reused
+    surface_ready: bool,
+    /// The last render of `rendered_item` failed and shows the error glyph
+    render_failed: bool,
+    /// Whether the current item's `@loops` has been turned into a display duration
+    loops_resolved: bool,
+    /// Still on the first pass through the items, where `@startDelay` applies
+    first_cycle: bool,
 }
 
 pub struct RenderEngine {
@@ -25,59 +110,249 @@ pub struct RenderEngine {
     area_surfaces: Vec<Pixmap>,
     content_surfaces: Vec<Pixmap>,
     area_states: Vec<AreaState>,
-    image_renderer: ImageRenderer,
-    text_renderer: TextRenderer,
-    clock_renderer: ClockRenderer,
-    gif_renderer: GifRenderer,
-    video_renderer: VideoRenderer,
+    renderers: HashMap<ContentKind, Box<dyn ContentRenderer>>,
+    /// Image cache for area backgrounds
+    background_images: ImageRenderer,
+    /// Frames rendered so far (diagnostics only; timing comes from the caller)
     frame: u64,
-    ms_per_frame: u64,
-    /// Software brightness level (0-100)
-    brightness: u8,
+    /// Elapsed time of the last rendered frame
+    last_elapsed_ms: u64,
+    /// Software brightness level (0-100) being faded towards
+    target_brightness: u8,
+    /// Brightness the current fade started from, and when (ms)
+    fade_from: f32,
+    fade_start_ms: u64,
+    /// Brightness applied to the last frame
+    current_brightness: f32,
+    /// How long a brightness change takes to fade in
+    brightness_fade_ms: u64,
+    /// (program GUID, area GUID) of areas blanked with `clear_area`
+    cleared_areas: HashSet<(String, String)>,
+    /// Resources already reported as unloadable, so each is logged once
+    reported_failures: HashSet<String>,
+    /// How long a program change cross-fades (0 = cut)
+    program_transition_ms: u64,
+    transition: Option<ProgramTransition>,
+    /// Color correction applied to finished frames (None = identity)
+    color_correction: Option<CompiledCorrection>,
+    /// Time spent in each content type's renderer
+    render_timings: HashMap<ContentKind, RenderTiming>,
 }
 
 impl RenderEngine {
-    pub fn new(width: u32, height: u32, fps: u32) -> Self {
+    pub fn new(width: u32, height: u32) -> Self {
         Self {
             framebuffer: Pixmap::new(width, height).expect("Failed to create framebuffer"),
             area_surfaces: Vec::new(),
             content_surfaces: Vec::new(),
             area_states: Vec::new(),
-            image_renderer: ImageRenderer::new(),
-            text_renderer: TextRenderer::new(),
-            clock_renderer: ClockRenderer::new(),
-            gif_renderer: GifRenderer::new(),
-            video_renderer: VideoRenderer::new(),
+            renderers: plugins::BUILTIN_KINDS
+                .iter()
+                .map(|&kind| (kind, plugins::builtin_renderer(kind)))
+                .collect(),
+            background_images: ImageRenderer::new(),
             frame: 0,
-            ms_per_frame: 1000 / fps as u64,
-            brightness: 100,
+            last_elapsed_ms: 0,
+            target_brightness: 100,
+            fade_from: 100.0,
+            fade_start_ms: 0,
+            current_brightness: 100.0,
+            brightness_fade_ms: DEFAULT_BRIGHTNESS_FADE_MS,
+            cleared_areas: HashSet::new(),
+            reported_failures: HashSet::new(),
+            program_transition_ms: 0,
+            transition: None,
+            color_correction: None,
+            render_timings: HashMap::new(),
         }
     }
 
+    /// Render time per content type since startup, for spotting slow plugins
+    pub fn render_timings(&self) -> &HashMap<ContentKind, RenderTiming> {
+        &self.render_timings
+    }
+
+    /// Replace the renderer used for one content type
+    pub fn set_renderer(&mut self, kind: ContentKind, renderer: Box<dyn ContentRenderer>) {
+        self.renderers.insert(kind, renderer);
+    }
+
+    /// Fade to a new brightness level (0-100) over the configured fade time,
+    /// starting from whatever is showing now
     pub fn set_brightness(&mut self, level: u8) {
-        self.brightness = level.min(100);
+        self.fade_from = self.current_brightness;
+        self.fade_start_ms = self.last_elapsed_ms;
+        self.target_brightness = level.min(100);
+    }
+
+    /// Brightness changes take this long; 0 switches instantly
+    pub fn set_brightness_fade(&mut self, fade_ms: u64) {
+        self.brightness_fade_ms = fade_ms;
+    }
+
+    /// Brightness applied to the most recent frame
+    pub fn current_brightness(&self) -> f32 {
+        self.current_brightness
+    }
+
+    /// Program changes cross-fade over this long; 0 cuts instantly
+    pub fn set_program_transition(&mut self, transition_ms: u64) {
+        self.program_transition_ms = transition_ms;
+    }
+
+    /// Correct every frame with `correction` after brightness is applied
+    pub fn set_color_correction(&mut self, correction: &ColorCorrection) {
+        self.color_correction = (!correction.is_identity()).then(|| correction.compile());
+    }
+
+    /// Called before switching programs: keep the outgoing program's last
+    /// frame and fade from it to the new program starting at `now_ms`
+    pub fn begin_program_transition(&mut self, now_ms: u64) {
+        self.begin_transition(now_ms, self.program_transition_ms);
+    }
+
+    /// Fade from the last frame to whatever renders next over `duration_ms`,
+    /// replacing any transition in progress (0 cuts instantly)
+    pub fn begin_transition(&mut self, now_ms: u64, duration_ms: u64) {
+        if duration_ms == 0 || self.frame == 0 {
+            self.transition = None;
+            return;
+        }
+        self.transition = Some(ProgramTransition {
+            from: self.framebuffer.clone(),
+            start_ms: now_ms,
+            duration_ms,
+        });
+    }
+
+    /// Blank an area: it renders transparent, background included, until
+    /// `restore_areas` (the next program update)
+    pub fn clear_area(&mut self, program_guid: &str, area_guid: &str) {
+        self.cleared_areas.insert((program_guid.to_string(), area_guid.to_string()));
+    }
+
+    /// Show every area cleared with `clear_area` again
+    pub fn restore_areas(&mut self) {
+        self.cleared_areas.clear();
+    }
+
+    /// The images and GIFs used by `programs` that aren't loaded yet. Decode
+    /// them away from the render loop and hand the result to `insert_preloaded`,
+    /// so the frame that first shows them doesn't stall.
+    pub fn media_preload(&self, programs: &[Program], program_dir: &Path) -> MediaPreload {
+        let mut by_kind: HashMap<ContentKind, Vec<&ContentItem>> = HashMap::new();
+        let areas = programs.iter().flat_map(|p| &p.areas);
+        for item in areas.clone().flat_map(|a| &a.resources.items) {
+            by_kind.entry(item.kind()).or_default().push(item);
+        }
+        let mut requests: Vec<_> = by_kind
+            .into_iter()
+            .filter_map(|(kind, items)| {
+                Some((Some(kind), self.renderers.get(&kind)?.preload_request(&items)?))
+            })
+            .collect();
+        let backgrounds = areas.filter_map(|a| a.bg_image.as_deref());
+        requests.extend(self.background_images.preload_files(backgrounds).map(|r| (None, r)));
+        MediaPreload {
+            program_dir: program_dir.to_path_buf(),
+            requests,
+        }
+    }
+
+    /// Cache media decoded by a [`MediaPreload`]
+    pub fn insert_preloaded(&mut self, media: PreloadedMedia) {
+        for (kind, decoded) in media.0 {
+            match kind {
+                Some(kind) => {
+                    if let Some(renderer) = self.renderers.get_mut(&kind) {
+                        renderer.insert_preloaded(decoded);
+                    }
+                }
+                None => self.background_images.insert_images(decoded),
+            }
+        }
     }
 
     /// Reset area states when a new program is loaded
     pub fn reset_for_program(&mut self, program: &Program) {
         self.area_states.clear();
         for area in &program.areas {
-            let items = &area.resources.items;
-            let effect = if !items.is_empty() {
-                get_effect_for_item(&items[0])
-            } else {
-                EffectState::new(0, 0, 0, 0, 50)
+            let state = self.fresh_area_state(area);
+            self.area_states.push(state);
+        }
+    }
+
+    /// Switch from `old` to an updated version of the same program without
+    /// restarting it: areas identical in both (matched by GUID) keep their
+    /// item, effect and scroll position; new or changed areas start over.
+    pub fn update_program(&mut self, old: &Program, new: &Program) {
+        if self.area_states.len() != old.areas.len() {
+            // Never rendered, nothing worth keeping
+            self.reset_for_program(new);
+            return;
+        }
+        let mut states: Vec<Option<AreaState>> =
+            std::mem::take(&mut self.area_states).into_iter().map(Some).collect();
+        let mut area_surfaces = std::mem::take(&mut self.area_surfaces);
+        let mut content_surfaces = std::mem::take(&mut self.content_surfaces);
+        let blank = || Pixmap::new(1, 1).unwrap();
+
+        for area in &new.areas {
+            let previous = old.areas.iter().position(|a| a.guid == area.guid);
+            let kept = previous
+                .filter(|&i| same_area(&old.areas[i], area))
+                .and_then(|i| states[i].take());
+            let state = match kept {
+                Some(state) => state,
+                None => self.fresh_area_state(area),
             };
-            self.area_states.push(AreaState {
-                current_item: 0,
-                effect,
-            });
+            self.area_states.push(state);
+            // Surfaces are re-validated against the state on the next frame
+            let surfaces = |from: &mut Vec<Pixmap>| {
+                previous
+                    .and_then(|i| from.get_mut(i))
+                    .map(|s| std::mem::replace(s, blank()))
+                    .unwrap_or_else(blank)
+            };
+            let area_surface = surfaces(&mut area_surfaces);
+            let content_surface = surfaces(&mut content_surfaces);
+            self.area_surfaces.push(area_surface);
+            self.content_surfaces.push(content_surface);
+        }
+    }
+
+    /// State for an area that starts over from its first item
+    fn fresh_area_state(&self, area: &Area) -> AreaState {
+        let items = &area.resources.items;
+        let mut effect = if !items.is_empty() {
+            get_effect_for_item(&items[0])
+        } else {
+            EffectState::new(0, 0, 0, 0, 50)
+        };
+        // Effects start from now, not from when the player started
+        effect.phase_start_ms = self.last_elapsed_ms;
+        effect.delay_start(items.first().map_or(0, ContentItem::start_delay_ms));
+        AreaState {
+            current_item: 0,
+            effect,
+            rendered_item: None,
+            next_redraw_ms: None,
+            surface_ready: false,
+            render_failed: false,
+            loops_resolved: false,
+            first_cycle: true,
         }
     }
 
-    /// Render a complete frame for the given program.
-    pub fn render_frame(&mut self, program: &Program, program_dir: &Path) -> &[u8] {
-        let elapsed_ms = self.frame * self.ms_per_frame;
+    /// Render a complete frame for the given program. `elapsed_ms` is the
+    /// caller's clock; effects advance with it however many frames get drawn.
+    pub fn render_frame(
+        &mut self,
+        program: &Program,
+        program_dir: &Path,
+        elapsed_ms: u64,
+    ) -> &[u8] {
+        self.last_elapsed_ms = elapsed_ms;
 
         // Initialize area states if needed
         if self.area_states.len() != program.areas.len() {
@@ -86,6 +361,11 @@ impl RenderEngine {
 
         self.framebuffer.fill(Color::BLACK);
 
+        let scroll_groups = self.scroll_group_cycles(program);
+        if let Some(text) = self.renderers.get_mut(&ContentKind::Text) {
+            text.set_scroll_groups(&scroll_groups);
+        }
+
         // Ensure scratch surfaces
         while self.area_surfaces.len() < program.areas.len() {
             self.area_surfaces.push(Pixmap::new(1, 1).unwrap());
@@ -94,38 +374,82 @@ impl RenderEngine {
             self.content_surfaces.push(Pixmap::new(1, 1).unwrap());
         }
 
-        for (i, area) in program.areas.iter().enumerate() {
+        // Composite in z-order (stable, so equal z-indices keep document order)
+        let mut draw_order: Vec<usize> = (0..program.areas.len()).collect();
+        draw_order.sort_by_key(|&i| program.areas[i].z_index);
+
+        for i in draw_order {
+            let area = &program.areas[i];
             let rect = &area.rectangle;
             let w = rect.width;
             let h = rect.height;
             if w == 0 || h == 0 {
                 continue;
             }
+            let cleared = self
+                .cleared_areas
+                .iter()
+                .any(|(p, a)| *p == program.guid && *a == area.guid);
 
             // Ensure surfaces are correct size
+            let area_state = &mut self.area_states[i];
             let surface = &mut self.area_surfaces[i];
             if surface.width() != w || surface.height() != h {
                 *surface = Pixmap::new(w, h).unwrap_or_else(|| Pixmap::new(1, 1).unwrap());
+                area_state.surface_ready = false;
             }
-            surface.fill(Color::TRANSPARENT);
-
             let content_surface = &mut self.content_surfaces[i];
             if content_surface.width() != w || content_surface.height() != h {
                 *content_surface =
                     Pixmap::new(w, h).unwrap_or_else(|| Pixmap::new(1, 1).unwrap());
+                area_state.rendered_item = None;
+            }
+
+            if cleared {
+                if area_state.surface_ready {
+                    surface.fill(Color::TRANSPARENT);
+                    area_state.surface_ready = false;
+                }
+                continue;
             }
 
+            let images = &mut self.background_images;
+            let reported = &mut self.reported_failures;
             let items = &area.resources.items;
             if items.is_empty() {
+                surface.fill(Color::TRANSPARENT);
+                let drawn =
+                    draw_area_background(surface, area, images, reported, program_dir, elapsed_ms);
+                if drawn {
+                    round_corners(surface, area.corner_radius);
+                    composite_area(&mut self.framebuffer, surface, area, elapsed_ms);
+                }
                 continue;
             }
 
             // Update effect state and check if we should advance
-            let area_state = &mut self.area_states[i];
+            area_state.effect.scroll_extent = match area_state.effect.effect_in {
+                23 | 24 | 28 | 29 => h,
+                _ => w,
+            };
+
+            // An item with `@loops` displays for that many loops of its content
+            // rather than its duration; the loop length needs the loaded media
+            if !area_state.loops_resolved {
+                area_state.loops_resolved = true;
+                let item = &items[area_state.current_item];
+                if let Some(loops) = item.loops()
+                    && let Some(renderer) = self.renderers.get_mut(&item.kind())
+                    && let Some(loop_ms) = renderer.loop_duration_ms(item, program_dir)
+                {
+                    area_state.effect.display_duration_ms = loops as u64 * loop_ms;
+                }
+            }
             let should_advance = area_state.effect.update(elapsed_ms);
 
-            if should_advance && items.len() > 1 {
-                // Advance to next content item
+            if should_advance {
+                // Advance to next content item (a lone item starts over, e.g. a
+                // scroll-once text scrolls through again)
                 area_state.current_item = (area_state.current_item + 1) % items.len();
                 let next_item = &items[area_state.current_item];
                 let eff = get_effect_for_item(next_item);
@@ -137,39 +461,70 @@ impl RenderEngine {
                     (eff.display_duration_ms / 100) as u32,
                     elapsed_ms,
                 );
+                area_state.first_cycle &= area_state.current_item != 0;
+                if area_state.first_cycle {
+                    area_state.effect.delay_start(next_item.start_delay_ms());
+                }
+                area_state.loops_resolved = false;
             }
 
             let current_idx = area_state.current_item;
             let item = &items[current_idx];
 
-            // Render content into content surface
-            content_surface.fill(Color::TRANSPARENT);
-            match item {
-                ContentItem::Image(_) => {
-                    self.image_renderer.render(
-                        item, content_surface, 0, 0, w, h, elapsed_ms, program_dir,
-                    );
-                }
-                ContentItem::Text(_) => {
-                    self.text_renderer.render(
-                        item, content_surface, 0, 0, w, h, elapsed_ms, program_dir,
-                    );
-                }
-                ContentItem::Clock(_) => {
-                    self.clock_renderer.render(
-                        item, content_surface, 0, 0, w, h, elapsed_ms, program_dir,
-                    );
-                }
-                ContentItem::Gif(_) => {
-                    self.gif_renderer.render(
-                        item, content_surface, 0, 0, w, h, elapsed_ms, program_dir,
-                    );
+            // A settled area whose content isn't due for a redraw looks exactly
+            // like last frame: reuse its composite
+            let content_due = area_state.rendered_item != Some(current_idx)
+                || area_state.next_redraw_ms.is_some_and(|t| elapsed_ms >= t);
+            let animating = area_state.effect.phase != EffectPhase::Displaying;
+            if area_state.surface_ready && !content_due && !animating {
+                composite_area(&mut self.framebuffer, surface, area, elapsed_ms);
+                continue;
+            }
+
+            surface.fill(Color::TRANSPARENT);
+            draw_area_background(surface, area, images, reported, program_dir, elapsed_ms);
+
+            // Render content into the content surface, unless it still holds an
+            // up-to-date render of this item
+            if content_due && let Some(renderer) = self.renderers.get_mut(&item.kind()) {
+                let cached = area_state.rendered_item == Some(current_idx)
+                    && !area_state.render_failed
+                    && !renderer.is_dirty(item, elapsed_ms);
+                let mut failed = false;
+                let mut panicked = false;
+                if !cached {
+                    content_surface.fill(Color::TRANSPARENT);
+                    // A renderer bug must not take down the rest of the frame
+                    let started = Instant::now();
+                    let rendered = panic::catch_unwind(AssertUnwindSafe(|| {
+                        renderer.render(item, content_surface, 0, 0, w, h, elapsed_ms, program_dir)
+                    }));
+                    self.render_timings.entry(item.kind()).or_default().record(started.elapsed());
+                    panicked = rendered.is_err();
+                    failed = !matches!(rendered, Ok(true));
+                    area_state.rendered_item = Some(current_idx);
+                    area_state.render_failed = failed;
                 }
-                ContentItem::Video(_) => {
-                    self.video_renderer.render(
-                        item, content_surface, 0, 0, w, h, elapsed_ms, program_dir,
+                area_state.next_redraw_ms = if panicked {
+                    // Not retried: it would only panic again. The item gets
+                    // another try when the area cycles back to it.
+                    error!(
+                        "Area '{}': renderer panicked on '{}', skipping it",
+                        area.guid,
+                        item.guid()
                     );
-                }
+                    content_surface.fill(Color::TRANSPARENT);
+                    draw_error_glyph(content_surface);
+                    None
+                } else if failed {
+                    // Mark the spot so a bad filename is visible on the sign
+                    draw_error_glyph(content_surface);
+                    let resource = item.file().map_or(item.guid(), |f| f.name.as_str());
+                    warn_once(reported, resource, &area.guid);
+                    Some(elapsed_ms + FAILED_RENDER_RETRY_MS)
+                } else {
+                    renderer.next_redraw_ms(item, elapsed_ms)
+                };
             }
 
             // Apply transition effect
@@ -188,26 +543,17 @@ impl RenderEngine {
                 w,
                 h,
             );
+            round_corners(surface, area.corner_radius);
+            // A mid-transition composite is only right for this frame; the first
+            // settled frame must redraw at full effect opacity
+            area_state.surface_ready = !animating;
 
-            // Composite area onto framebuffer
-            let alpha = area.alpha as f32 / 255.0;
-            let paint = PixmapPaint {
-                opacity: alpha,
-                ..PixmapPaint::default()
-            };
-            self.framebuffer.draw_pixmap(
-                rect.x,
-                rect.y,
-                surface.as_ref(),
-                &paint,
-                Transform::identity(),
-                None,
-            );
+            composite_area(&mut self.framebuffer, surface, area, elapsed_ms);
         }
 
-        // Apply software brightness
-        if self.brightness < 100 {
-            let factor = self.brightness as f32 / 100.0;
+        // Apply program-level opacity (fades toward the black background)
+        if program.alpha < 255 {
+            let factor = program.alpha as f32 / 255.0;
             let data = self.framebuffer.data_mut();
             for chunk in data.chunks_exact_mut(4) {
                 chunk[0] = (chunk[0] as f32 * factor) as u8;
@@ -216,18 +562,133 @@ impl RenderEngine {
             }
         }
 
+        self.apply_brightness();
+        self.apply_color_correction();
+        self.blend_program_transition(elapsed_ms);
         self.frame += 1;
         self.framebuffer.data()
     }
 
-    pub fn pixels(&self) -> &[u8] {
+    /// Scroll cycle of each text scroll group in `program`: every member's text
+    /// and area width laid end to end, so the group scrolls as one ticker
+    fn scroll_group_cycles(&mut self, program: &Program) -> HashMap<String, u64> {
+        let mut cycles = HashMap::new();
+        let Some(text_renderer) = self.renderers.get_mut(&ContentKind::Text) else {
+            return cycles;
+        };
+        for area in &program.areas {
+            for item in &area.resources.items {
+                if let ContentItem::Text(text) = item
+                    && let Some(group) = &text.scroll_group
+                {
+                    let text_width = text_renderer.content_width(item, area.rectangle.height);
+                    let span = text_width.unwrap_or(0) as u64 + area.rectangle.width as u64;
+                    *cycles.entry(group.clone()).or_insert(0) += span;
+                }
+            }
+        }
+        cycles
+    }
+
+    /// Mix the outgoing program's frame over the new one while a transition runs
+    fn blend_program_transition(&mut self, elapsed_ms: u64) {
+        let Some(transition) = &self.transition else {
+            return;
+        };
+        let elapsed = elapsed_ms.saturating_sub(transition.start_ms);
+        if elapsed >= transition.duration_ms {
+            self.transition = None;
+            return;
+        }
+        let t = elapsed as f32 / transition.duration_ms as f32;
+        let from = transition.from.data();
+        for (dst, &src) in self.framebuffer.data_mut().iter_mut().zip(from) {
+            *dst = (src as f32 + (*dst as f32 - src as f32) * t).round() as u8;
+        }
+    }
+
+    /// Render the boot logo `name` from `program_dir`, scaled to fit the
+    /// screen and centered on black. A missing file leaves the screen black.
+    pub fn render_boot_logo(&mut self, name: &str, program_dir: &Path, elapsed_ms: u64) -> &[u8] {
+        self.last_elapsed_ms = elapsed_ms;
+        self.framebuffer.fill(Color::BLACK);
+        let (w, h) = (self.framebuffer.width() as f32, self.framebuffer.height() as f32);
+        match self.background_images.load_image(name, program_dir, elapsed_ms) {
+            Some(img) => {
+                let scale = (w / img.width() as f32).min(h / img.height() as f32);
+                let x = (w - img.width() as f32 * scale) / 2.0;
+                let y = (h - img.height() as f32 * scale) / 2.0;
+                let transform = Transform::from_scale(scale, scale).post_translate(x, y);
+                self.framebuffer.draw_pixmap(
+                    0,
+                    0,
+                    img.as_ref(),
+                    &PixmapPaint::default(),
+                    transform,
+                    None,
+                );
+            }
+            None => warn_once(&mut self.reported_failures, name, "boot logo"),
+        }
+        self.apply_brightness();
+        self.apply_color_correction();
+        self.frame += 1;
+        self.framebuffer.data()
+    }
+
+    /// Render an empty (black) frame, e.g. for a blank playlist slot
+    pub fn render_blank(&mut self, elapsed_ms: u64) -> &[u8] {
+        self.last_elapsed_ms = elapsed_ms;
+        self.framebuffer.fill(Color::BLACK);
+        self.frame += 1;
+        self.framebuffer.data()
+    }
+
+    /// Render a commissioning pattern instead of a program
+    pub fn render_test_pattern(&mut self, pattern: &TestPattern, elapsed_ms: u64) -> &[u8] {
+        self.last_elapsed_ms = elapsed_ms;
+        testpattern::render(pattern, &mut self.framebuffer, elapsed_ms);
+        self.apply_brightness();
+        self.apply_color_correction();
+        self.frame += 1;
         self.framebuffer.data()
     }
 
-    pub fn save_png(&self, path: &Path) -> Result<()> {
-        self.framebuffer
-            .save_png(path)
-            .map_err(|e| anyhow::anyhow!("Failed to save PNG: {}", e))
+    /// Apply software brightness to the finished frame, stepping any fade in
+    /// progress to the frame's time
+    fn apply_brightness(&mut self) {
+        let target = self.target_brightness as f32;
+        let elapsed = self.last_elapsed_ms.saturating_sub(self.fade_start_ms);
+        self.current_brightness = if elapsed >= self.brightness_fade_ms {
+            target
+        } else {
+            let t = elapsed as f32 / self.brightness_fade_ms as f32;
+            self.fade_from + (target - self.fade_from) * t
+        };
+
+        if self.current_brightness < 100.0 {
+            let factor = self.current_brightness / 100.0;
+            let data = self.framebuffer.data_mut();
+            for chunk in data.chunks_exact_mut(4) {
+                chunk[0] = (chunk[0] as f32 * factor) as u8;
+                chunk[1] = (chunk[1] as f32 * factor) as u8;
+                chunk[2] = (chunk[2] as f32 * factor) as u8;
+            }
+        }
+    }
+
+    fn apply_color_correction(&mut self) {
+        if let Some(correction) = &self.color_correction {
+            correction.apply(&mut self.framebuffer);
+        }
+    }
+
+    pub fn frames_rendered(&self) -> u64 {
+        self.frame
+    }
+
+    pub fn pixels(&self) -> &[u8] {
+        self.framebuffer.data()
     }
 
     pub fn width(&self) -> u32 {
@@ -239,17 +700,779 @@ impl RenderEngine {
     }
 }
 
-/// Extract effect params from a content item
-fn get_effect_for_item(item: &ContentItem) -> EffectState {
-    let eff = match item {
-        ContentItem::Image(i) => i.effect.as_ref(),
-        ContentItem::Text(t) => t.effect.as_ref(),
-        ContentItem::Gif(g) => g.effect.as_ref(),
-        _ => None,
+/// Fill an area surface with its backdrop color and/or image. Returns false if
+/// the area has no background.
+fn draw_area_background(
+    surface: &mut Pixmap,
+    area: &Area,
+    images: &mut ImageRenderer,
+    reported: &mut HashSet<String>,
+    program_dir: &Path,
+    elapsed_ms: u64,
+) -> bool {
+    if let Some(color) = &area.bg_color {
+        let (r, g, b) = parse_color(color);
+        surface.fill(Color::from_rgba8(r, g, b, 255));
+    }
+    if let Some(name) = &area.bg_image {
+        match images.load_image(name, program_dir, elapsed_ms) {
+            Some(img) => {
+                let transform = Transform::from_scale(
+                    surface.width() as f32 / img.width() as f32,
+                    surface.height() as f32 / img.height() as f32,
+                );
+                surface.draw_pixmap(0, 0, img.as_ref(), &PixmapPaint::default(), transform, None);
+            }
+            None => warn_once(reported, name, &area.guid),
+        }
+    }
+    area.bg_color.is_some() || area.bg_image.is_some()
+}
+
+/// Log an unloadable resource the first time it is seen
+fn warn_once(reported: &mut HashSet<String>, resource: &str, area_guid: &str) {
+    if reported.insert(resource.to_string()) {
+        warn!("Area '{}': failed to render '{}'", area_guid, resource);
+    }
+}
+
+/// Red boxed cross in the top-left corner of `target`
+fn draw_error_glyph(target: &mut Pixmap) {
+    let size = ERROR_GLYPH_SIZE.min(target.width()).min(target.height());
+    let width = target.width();
+    let data = target.data_mut();
+    for y in 0..size {
+        for x in 0..size {
+            let edge = x == 0 || y == 0 || x == size - 1 || y == size - 1;
+            if edge || x == y || x + y == size - 1 {
+                let i = ((y * width + x) * 4) as usize;
+                data[i..i + 4].copy_from_slice(&[255, 0, 0, 255]);
+            }
+        }
+    }
+}
+
+/// Clear the pixels of `surface` outside a rounded rectangle with corners of
+/// `radius` px, so the area composites with rounded corners
+fn round_corners(surface: &mut Pixmap, radius: Option<u32>) {
+    let (w, h) = (surface.width(), surface.height());
+    let r = match radius {
+        Some(r) if r > 0 => r.min(w / 2).min(h / 2),
+        _ => return,
     };
+    let rf = r as f32;
+    let data = surface.data_mut();
+    for y in 0..h {
+        // Distance into the corner band from the nearest horizontal edge
+        let dy = if y < r {
+            rf - (y as f32 + 0.5)
+        } else if y >= h - r {
+            (y - (h - r)) as f32 + 0.5
+        } else {
+            continue;
+        };
+        for x in (0..r).chain(w - r..w) {
+            let dx = if x < r {
+                rf - (x as f32 + 0.5)
+            } else {
+                (x - (w - r)) as f32 + 0.5
+            };
+            if dx * dx + dy * dy > rf * rf {
+                let i = ((y * w + x) * 4) as usize;
+                data[i..i + 4].fill(0);
+            }
+        }
+    }
+}
 
-    match eff {
+/// Composite a finished area surface onto the framebuffer at the area's position.
+/// The surface already carries any transition opacity; this is the only place
+/// the area opacity applies, so the two multiply exactly once.
+fn composite_area(framebuffer: &mut Pixmap, surface: &Pixmap, area: &Area, elapsed_ms: u64) {
+    let paint = PixmapPaint {
+        opacity: area_opacity(area, elapsed_ms),
+        ..PixmapPaint::default()
+    };
+    framebuffer.draw_pixmap(
+        area.rectangle.x,
+        area.rectangle.y,
+        surface.as_ref(),
+        &paint,
+        Transform::identity(),
+        None,
+    );
+}
+
+/// Area opacity at `elapsed_ms`: its `alpha`, modulated by any alpha effect
+fn area_opacity(area: &Area, elapsed_ms: u64) -> f32 {
+    let alpha = area.alpha as f32 / 255.0;
+    let speed = area.alpha_speed.unwrap_or(10).max(1) as u64;
+    let period_ms = (10_000 / speed).max(1);
+    let phase = (elapsed_ms % period_ms) as f32 / period_ms as f32;
+    match area.alpha_effect.as_deref() {
+        // Shown for the first half of each cycle
+        Some("blink") => {
+            if phase < 0.5 {
+                alpha
+            } else {
+                0.0
+            }
+        }
+        // Fully shown at the start of each cycle, fully hidden halfway
+        Some("pulse") => alpha * (1.0 + (phase * std::f32::consts::TAU).cos()) / 2.0,
+        _ => alpha,
+    }
+}
+
+/// Whether two versions of an area would play identically. The model has no
+/// `PartialEq`; comparing the serialized form covers every field.
+fn same_area(a: &Area, b: &Area) -> bool {
+    match (serde_json::to_value(a), serde_json::to_value(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Extract effect params from a content item
+fn get_effect_for_item(item: &ContentItem) -> EffectState {
+    match item.effect() {
         Some(e) => EffectState::new(e.effect_in, e.effect_out, e.in_speed, e.out_speed, e.duration),
         None => EffectState::new(0, 0, 0, 0, 50), // default 5 seconds, immediate
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::program::parser::parse_program_xml;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    /// Write a solid-color PNG into `dir` for image content to reference
+    fn solid_png(dir: &Path, name: &str, rgb: [u8; 3]) {
+        let img = image::RgbaImage::from_pixel(4, 4, image::Rgba([rgb[0], rgb[1], rgb[2], 255]));
+        img.save(dir.join(name)).unwrap();
+    }
+
+    fn pixel(engine: &RenderEngine, x: u32, y: u32) -> [u8; 4] {
+        let i = ((y * engine.width() + x) * 4) as usize;
+        engine.pixels()[i..i + 4].try_into().unwrap()
+    }
+
+    fn image_area(guid: &str, z: i32, file: &str) -> String {
+        format!(
+            r#"<area guid="{guid}" zIndex="{z}"><rectangle x="0" y="0" width="8" height="8"/>
+               <resources><image guid="{guid}-img" fit="stretch"><file name="{file}"/></image></resources></area>"#
+        )
+    }
+
+    #[test]
+    fn test_z_index_controls_stacking() {
+        let dir = tempfile::tempdir().unwrap();
+        solid_png(dir.path(), "red.png", [255, 0, 0]);
+        solid_png(dir.path(), "green.png", [0, 255, 0]);
+
+        // Red comes first in the document but has the higher z-index
+        let xml = format!(
+            "<screen><program guid=\"p\">{}{}</program></screen>",
+            image_area("red", 1, "red.png"),
+            image_area("green", 0, "green.png"),
+        );
+        let screen = parse_program_xml(&xml).unwrap();
+        let mut engine = RenderEngine::new(8, 8);
+        engine.render_frame(&screen.programs[0], dir.path(), 0);
+        assert_eq!(pixel(&engine, 4, 4), [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn test_program_alpha_dims_frame() {
+        let dir = tempfile::tempdir().unwrap();
+        solid_png(dir.path(), "white.png", [255, 255, 255]);
+        let xml = format!(
+            "<screen><program guid=\"p\" alpha=\"128\">{}</program></screen>",
+            image_area("white", 0, "white.png"),
+        );
+        let screen = parse_program_xml(&xml).unwrap();
+        let mut engine = RenderEngine::new(8, 8);
+        engine.render_frame(&screen.programs[0], dir.path(), 0);
+        assert_eq!(pixel(&engine, 4, 4), [128, 128, 128, 255]);
+    }
+
+    #[test]
+    fn test_blinking_area_alternates_opacity() {
+        let dir = tempfile::tempdir().unwrap();
+        solid_png(dir.path(), "white.png", [255, 255, 255]);
+        let xml = r#"<screen><program guid="p">
+            <area guid="a" alphaEffect="blink" alphaSpeed="20"><rectangle width="8" height="8"/>
+              <resources><image guid="i" fit="stretch"><file name="white.png"/></image></resources></area>
+            </program></screen>"#;
+        let screen = parse_program_xml(xml).unwrap();
+        let mut engine = RenderEngine::new(8, 8);
+
+        // Two blinks per second: on for 250ms, off for 250ms
+        let mut seen = Vec::new();
+        for t in [0, 100, 250, 400, 500] {
+            engine.render_frame(&screen.programs[0], dir.path(), t);
+            seen.push(pixel(&engine, 4, 4)[0]);
+        }
+        assert_eq!(seen, [255, 255, 0, 0, 255]);
+
+        let mut pulse = screen.programs[0].areas[0].clone();
+        pulse.alpha_effect = Some("pulse".to_string());
+        assert_eq!(area_opacity(&pulse, 0), 1.0);
+        assert!((area_opacity(&pulse, 125) - 0.5).abs() < 0.01);
+        assert!(area_opacity(&pulse, 250) < 0.01);
+    }
+
+    #[test]
+    fn test_area_background_shows_through_text() {
+        let xml = r##"<screen><program guid="p">
+            <area guid="a" bgColor="#0000FF"><rectangle width="32" height="16"/>
+              <resources><text guid="t"><string>I</string><font size="8" color="#FFFFFF"/></text></resources>
+            </area>
+            <area guid="empty" bgColor="#00FF00"><rectangle x="0" y="0" width="2" height="2"/><resources/></area>
+            </program></screen>"##;
+        let screen = parse_program_xml(xml).unwrap();
+        let mut engine = RenderEngine::new(32, 16);
+        engine.render_frame(&screen.programs[0], Path::new("."), 0);
+
+        // Away from the glyph the text is transparent and the backdrop shows
+        assert_eq!(pixel(&engine, 30, 14), [0, 0, 255, 255]);
+        // An area with no content still draws its background
+        assert_eq!(pixel(&engine, 1, 1), [0, 255, 0, 255]);
+    }
+
+    #[test]
+    fn test_clear_area_blanks_only_that_area() {
+        let dir = tempfile::tempdir().unwrap();
+        solid_png(dir.path(), "red.png", [255, 0, 0]);
+        solid_png(dir.path(), "green.png", [0, 255, 0]);
+        let xml = r#"<screen><program guid="p">
+            <area guid="left"><rectangle x="0" y="0" width="4" height="4"/>
+              <resources><image guid="l" fit="stretch"><file name="red.png"/></image></resources></area>
+            <area guid="right"><rectangle x="4" y="0" width="4" height="4"/>
+              <resources><image guid="r" fit="stretch"><file name="green.png"/></image></resources></area>
+            </program></screen>"#;
+        let screen = parse_program_xml(xml).unwrap();
+        let program = &screen.programs[0];
+        let mut engine = RenderEngine::new(8, 4);
+        engine.render_frame(program, dir.path(), 0);
+        assert_eq!(pixel(&engine, 1, 1), [255, 0, 0, 255]);
+
+        engine.clear_area("p", "left");
+        engine.render_frame(program, dir.path(), 100);
+        assert!(engine.area_surfaces[0].data().iter().all(|&b| b == 0));
+        assert_eq!(pixel(&engine, 1, 1), [0, 0, 0, 255]);
+        assert_eq!(pixel(&engine, 5, 1), [0, 255, 0, 255]);
+
+        engine.restore_areas();
+        engine.render_frame(program, dir.path(), 200);
+        assert_eq!(pixel(&engine, 1, 1), [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn test_missing_image_draws_error_glyph() {
+        let dir = tempfile::tempdir().unwrap();
+        let xml = r##"<screen><program guid="p">
+            <area guid="a" bgColor="#0000FF"><rectangle width="16" height="16"/>
+              <resources><image guid="i"><file name="typo.png"/></image></resources></area>
+            </program></screen>"##;
+        let screen = parse_program_xml(xml).unwrap();
+        let mut engine = RenderEngine::new(16, 16);
+        engine.render_frame(&screen.programs[0], dir.path(), 0);
+
+        // Glyph corner and cross, background elsewhere
+        assert_eq!(pixel(&engine, 0, 0), [255, 0, 0, 255]);
+        assert_eq!(pixel(&engine, 3, 3), [255, 0, 0, 255]);
+        assert_eq!(pixel(&engine, 12, 12), [0, 0, 255, 255]);
+        assert!(engine.reported_failures.contains("typo.png"));
+
+        // Once the file turns up it is picked up on a retry
+        solid_png(dir.path(), "typo.png", [0, 255, 0]);
+        engine.render_frame(&screen.programs[0], dir.path(), 500);
+        assert_eq!(pixel(&engine, 0, 0), [255, 0, 0, 255]);
+        engine.render_frame(&screen.programs[0], dir.path(), FAILED_RENDER_RETRY_MS);
+        assert_eq!(pixel(&engine, 0, 0), [0, 255, 0, 255]);
+    }
+
+    #[test]
+    fn test_program_change_cross_fades() {
+        let dir = tempfile::tempdir().unwrap();
+        solid_png(dir.path(), "red.png", [255, 0, 0]);
+        solid_png(dir.path(), "green.png", [0, 255, 0]);
+        let xml = format!(
+            r#"<screen><program guid="red">{}</program>
+               <program guid="green">{}</program></screen>"#,
+            image_area("r", 0, "red.png"),
+            image_area("g", 0, "green.png"),
+        );
+        let screen = parse_program_xml(&xml).unwrap();
+        let (red, green) = (&screen.programs[0], &screen.programs[1]);
+
+        let mut engine = RenderEngine::new(8, 8);
+        engine.set_program_transition(1000);
+        engine.render_frame(red, dir.path(), 0);
+
+        engine.begin_program_transition(1000);
+        engine.reset_for_program(green);
+        engine.render_frame(green, dir.path(), 1000);
+        assert_eq!(pixel(&engine, 4, 4), [255, 0, 0, 255]);
+        engine.render_frame(green, dir.path(), 1500);
+        assert_eq!(pixel(&engine, 4, 4), [128, 128, 0, 255]);
+        engine.render_frame(green, dir.path(), 2000);
+        assert_eq!(pixel(&engine, 4, 4), [0, 255, 0, 255]);
+    }
+
+    #[test]
+    fn test_corner_radius_clears_corners() {
+        let dir = tempfile::tempdir().unwrap();
+        solid_png(dir.path(), "red.png", [255, 0, 0]);
+        let xml = r#"<screen><program guid="p"><area guid="a" cornerRadius="4">
+            <rectangle x="0" y="0" width="16" height="8"/>
+            <resources><image guid="i" fit="stretch"><file name="red.png"/></image></resources>
+            </area></program></screen>"#;
+        let screen = parse_program_xml(xml).unwrap();
+        let mut engine = RenderEngine::new(16, 8);
+        engine.render_frame(&screen.programs[0], dir.path(), 0);
+
+        let surface = engine.area_surfaces[0].data();
+        let alpha = |x: u32, y: u32| surface[((y * 16 + x) * 4 + 3) as usize];
+        for (x, y) in [(0, 0), (15, 0), (0, 7), (15, 7), (1, 0), (0, 1)] {
+            assert_eq!(alpha(x, y), 0, "corner pixel ({x}, {y})");
+        }
+        // Edges between the corners and the middle are untouched
+        for (x, y) in [(4, 0), (0, 4), (8, 4), (15, 4), (3, 3)] {
+            assert_eq!(alpha(x, y), 255, "pixel ({x}, {y})");
+        }
+        assert_eq!(pixel(&engine, 0, 0), [0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn test_scroll_group_shares_offset() {
+        let text_area = |guid: &str, x: u32, text: &str| {
+            format!(
+                r##"<area guid="{guid}"><rectangle x="{x}" y="0" width="64" height="16"/>
+                   <resources><text guid="{guid}-t" singleLine="true" scrollSpeed="0" scrollGroup="ticker">
+                   <string>{text}</string><font size="12" color="#FFFFFF"/></text></resources></area>"##
+            )
+        };
+        let xml = format!(
+            r#"<screen><program guid="p">{}{}</program></screen>"#,
+            text_area("label", 0, "AB"),
+            text_area("data", 64, "LONGER DATA TEXT"),
+        );
+        let screen = parse_program_xml(&xml).unwrap();
+        let program = &screen.programs[0];
+        let mut engine = RenderEngine::new(128, 16);
+
+        // Leftmost lit column of each area
+        let leftmost = |engine: &RenderEngine, from: u32| {
+            (from..from + 64).find(|&x| (0..16).any(|y| pixel(engine, x, y)[0] > 0))
+        };
+        engine.render_frame(program, Path::new("."), 200);
+        let (label_a, data_a) = (leftmost(&engine, 0).unwrap(), leftmost(&engine, 64).unwrap());
+        engine.render_frame(program, Path::new("."), 400);
+        let (label_b, data_b) = (leftmost(&engine, 0).unwrap(), leftmost(&engine, 64).unwrap());
+        // 100 px/sec: both moved 20px left
+        assert_eq!(label_a - label_b, 20);
+        assert_eq!(data_a - data_b, 20);
+
+        // On its own the short label would already be scrolling in again; in
+        // the group it waits for the whole ticker to go by
+        engine.render_frame(program, Path::new("."), 1000);
+        assert_eq!(leftmost(&engine, 0), None);
+    }
+
+    #[test]
+    fn test_gif_loops_control_advance() {
+        let dir = tempfile::tempdir().unwrap();
+        // Two 100ms frames: one loop is 200ms
+        let mut file = std::fs::File::create(dir.path().join("blink.gif")).unwrap();
+        let mut encoder = gif::Encoder::new(&mut file, 1, 1, &[0, 0, 0, 255, 255, 255]).unwrap();
+        for index in [0u8, 1] {
+            let frame = gif::Frame {
+                width: 1,
+                height: 1,
+                delay: 10,
+                buffer: std::borrow::Cow::Owned(vec![index]),
+                ..Default::default()
+            };
+            encoder.write_frame(&frame).unwrap();
+        }
+        drop(encoder);
+        solid_png(dir.path(), "next.png", [0, 255, 0]);
+
+        let xml = r#"<screen><program guid="p"><area guid="a"><rectangle width="8" height="8"/>
+            <resources><gif guid="g" loops="2"><file name="blink.gif"/></gif>
+            <image guid="i" fit="stretch"><file name="next.png"/></image></resources>
+            </area></program></screen>"#;
+        let screen = parse_program_xml(xml).unwrap();
+        let mut engine = RenderEngine::new(8, 8);
+
+        // Two loops take 400ms, well short of the default 5s duration
+        for t in [0, 200, 350] {
+            engine.render_frame(&screen.programs[0], dir.path(), t);
+            assert_eq!(engine.area_states[0].current_item, 0, "at {t}ms");
+        }
+        engine.render_frame(&screen.programs[0], dir.path(), 400);
+        engine.render_frame(&screen.programs[0], dir.path(), 410);
+        assert_eq!(engine.area_states[0].current_item, 1);
+    }
+
+    #[test]
+    fn test_text_lines_play_in_sequence() {
+        let xml = r#"<screen><program guid="p"><area guid="a"><rectangle width="64" height="16"/>
+            <resources><text guid="news"><effect duration="10"/><font size="12"/>
+              <line>First</line><line>Second</line><line>Third</line>
+            </text></resources></area></program></screen>"#;
+        let screen = parse_program_xml(xml).unwrap();
+        let items = &screen.programs[0].areas[0].resources.items;
+        assert_eq!(items.len(), 3);
+        let mut engine = RenderEngine::new(64, 16);
+
+        // One second each, then round again
+        let mut shown: Vec<String> = Vec::new();
+        for t in (0..=3500).step_by(100) {
+            engine.render_frame(&screen.programs[0], Path::new("."), t);
+            let ContentItem::Text(text) = &items[engine.area_states[0].current_item] else {
+                panic!("expected text");
+            };
+            let message = text.string.clone().unwrap();
+            if shown.last() != Some(&message) {
+                shown.push(message);
+            }
+        }
+        assert_eq!(shown, ["First", "Second", "Third", "First"]);
+    }
+
+    /// Static renderer that counts how often it is asked to draw
+    struct CountingRenderer(Arc<AtomicUsize>);
+
+    impl ContentRenderer for CountingRenderer {
+        fn is_dirty(&mut self, _item: &ContentItem, _elapsed_ms: u64) -> bool {
+            false
+        }
+
+        fn render(
+            &mut self,
+            _item: &ContentItem,
+            target: &mut Pixmap,
+            _x: i32,
+            _y: i32,
+            _width: u32,
+            _height: u32,
+            _elapsed_ms: u64,
+            _program_dir: &Path,
+        ) -> bool {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            target.fill(Color::WHITE);
+            true
+        }
+    }
+
+    #[test]
+    fn test_static_content_rendered_once() {
+        let xml = format!(
+            "<screen><program guid=\"p\">{}</program></screen>",
+            image_area("img", 0, "unused.png"),
+        );
+        let screen = parse_program_xml(&xml).unwrap();
+        let renders = Arc::new(AtomicUsize::new(0));
+        let mut engine = RenderEngine::new(8, 8);
+        engine.set_renderer(ContentKind::Image, Box::new(CountingRenderer(renders.clone())));
+
+        for frame in 0..10 {
+            engine.render_frame(&screen.programs[0], Path::new("."), frame * 100);
+            // The cached surface is still composited every frame
+            assert_eq!(pixel(&engine, 4, 4), [255, 255, 255, 255]);
+        }
+        assert_eq!(renders.load(Ordering::SeqCst), 1);
+
+        // A new program invalidates the cache
+        engine.reset_for_program(&screen.programs[0]);
+        engine.render_frame(&screen.programs[0], Path::new("."), 1000);
+        assert_eq!(renders.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_dropped_frames_keep_effect_timing() {
+        let dir = tempfile::tempdir().unwrap();
+        solid_png(dir.path(), "white.png", [255, 255, 255]);
+        // Fade in over 1 second
+        let xml = r#"<screen><program guid="p"><area guid="a"><rectangle width="8" height="8"/>
+            <resources><image guid="i" fit="stretch"><effect in="17" inSpeed="5"/><file name="white.png"/></image></resources>
+            </area></program></screen>"#;
+        let screen = parse_program_xml(xml).unwrap();
+        let mut engine = RenderEngine::new(8, 8);
+
+        engine.render_frame(&screen.programs[0], dir.path(), 0);
+        assert_eq!(engine.area_states[0].effect.progress, 0.0);
+
+        // A slow render ate several frame budgets: only two frames were drawn, but
+        // the fade is as far along as 500ms of real time
+        engine.render_frame(&screen.programs[0], dir.path(), 500);
+        assert!((engine.area_states[0].effect.progress - 0.5).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_render_times_recorded_per_content_type() {
+        let dir = tempfile::tempdir().unwrap();
+        solid_png(dir.path(), "red.png", [255, 0, 0]);
+        let xml = format!(
+            r#"<screen><program guid="p">{}
+              <area guid="t"><rectangle x="8" width="24" height="8"/>
+                <resources><text guid="txt"><string>hi</string></text></resources></area>
+            </program></screen>"#,
+            image_area("red", 0, "red.png"),
+        );
+        let screen = parse_program_xml(&xml).unwrap();
+        let mut engine = RenderEngine::new(32, 8);
+        assert!(engine.render_timings().is_empty());
+
+        engine.render_frame(&screen.programs[0], dir.path(), 0);
+        let timings = engine.render_timings();
+        assert_eq!(timings.len(), 2);
+        assert_eq!(timings[&ContentKind::Image].calls, 1);
+        assert_eq!(timings[&ContentKind::Text].calls, 1);
+        assert!(timings[&ContentKind::Text].max <= timings[&ContentKind::Text].total);
+
+        // Cached content isn't rendered again, so isn't timed again
+        engine.render_frame(&screen.programs[0], dir.path(), 100);
+        assert_eq!(engine.render_timings()[&ContentKind::Image].calls, 1);
+    }
+
+    #[test]
+    fn test_fade_into_translucent_area() {
+        let dir = tempfile::tempdir().unwrap();
+        solid_png(dir.path(), "white.png", [255, 255, 255]);
+        // Half-transparent area fading in over 1 second
+        let xml = r#"<screen><program guid="p"><area guid="a" alpha="128"><rectangle width="8" height="8"/>
+            <resources><image guid="i" fit="stretch"><effect in="17" inSpeed="5"/><file name="white.png"/></image></resources>
+            </area></program></screen>"#;
+        let screen = parse_program_xml(xml).unwrap();
+        let mut engine = RenderEngine::new(8, 8);
+        engine.render_frame(&screen.programs[0], dir.path(), 0);
+        // Halfway: half the content at half the area opacity, over black
+        engine.render_frame(&screen.programs[0], dir.path(), 500);
+        assert_eq!(pixel(&engine, 4, 4), [64, 64, 64, 255]);
+
+        // Fully in: the area opacity alone, not the last faded frame
+        engine.render_frame(&screen.programs[0], dir.path(), 900);
+        engine.render_frame(&screen.programs[0], dir.path(), 1000);
+        assert_eq!(pixel(&engine, 4, 4), [128, 128, 128, 255]);
+    }
+
+    #[test]
+    fn test_start_delay_holds_item_back() {
+        let dir = tempfile::tempdir().unwrap();
+        solid_png(dir.path(), "white.png", [255, 255, 255]);
+        let xml = r#"<screen><program guid="p"><area guid="a"><rectangle width="8" height="8"/>
+            <resources><image guid="i" fit="stretch" startDelay="2000"><effect duration="10"/><file name="white.png"/></image></resources>
+            </area></program></screen>"#;
+        let screen = parse_program_xml(xml).unwrap();
+        let mut engine = RenderEngine::new(8, 8);
+
+        for ms in [0, 1000, 1999] {
+            engine.render_frame(&screen.programs[0], dir.path(), ms);
+            assert_eq!(pixel(&engine, 4, 4), [0, 0, 0, 255], "drawn at {ms}ms");
+        }
+        engine.render_frame(&screen.programs[0], dir.path(), 2000);
+        assert_eq!(pixel(&engine, 4, 4), [255, 255, 255, 255]);
+
+        // Only the first appearance waits: starting over after its 1s display
+        // shows the item again straight away
+        for ms in (2100..=3500).step_by(100) {
+            engine.render_frame(&screen.programs[0], dir.path(), ms);
+            assert_eq!(pixel(&engine, 4, 4), [255, 255, 255, 255], "blank at {ms}ms");
+        }
+    }
+
+    #[test]
+    fn test_effect_time_follows_clock_not_frames() {
+        let dir = tempfile::tempdir().unwrap();
+        solid_png(dir.path(), "white.png", [255, 255, 255]);
+        let xml = r#"<screen><program guid="p"><area guid="a"><rectangle width="8" height="8"/>
+            <resources><image guid="i" fit="stretch"><effect in="17" inSpeed="5"/><file name="white.png"/></image></resources>
+            </area></program></screen>"#;
+        let screen = parse_program_xml(xml).unwrap();
+        let mut engine = RenderEngine::new(8, 8);
+
+        // Many frames at a frozen clock don't move the fade
+        engine.render_frame(&screen.programs[0], dir.path(), 0);
+        for _ in 0..20 {
+            engine.render_frame(&screen.programs[0], dir.path(), 100);
+        }
+        assert!((engine.area_states[0].effect.progress - 0.1).abs() < 0.01);
+        assert_eq!(engine.frames_rendered(), 21);
+
+        // A program loaded later starts its effects from the current clock
+        engine.reset_for_program(&screen.programs[0]);
+        engine.render_frame(&screen.programs[0], dir.path(), 350);
+        assert!((engine.area_states[0].effect.progress - 0.25).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_update_program_keeps_unchanged_areas() {
+        let dir = tempfile::tempdir().unwrap();
+        solid_png(dir.path(), "white.png", [255, 255, 255]);
+        let program = |text: &str| {
+            let xml = format!(
+                r##"<screen><program guid="p">
+                <area guid="logo"><rectangle width="8" height="8"/>
+                  <resources><image guid="i" fit="stretch"><effect in="17" inSpeed="5"/><file name="white.png"/></image></resources></area>
+                <area guid="ticker"><rectangle y="8" width="8" height="8"/>
+                  <resources><text guid="t"><effect in="17" inSpeed="5"/><string>{text}</string><font size="8" color="#FFFFFF"/></text></resources></area>
+                </program></screen>"##
+            );
+            parse_program_xml(&xml).unwrap().programs.remove(0)
+        };
+        let old = program("old news");
+        let new = program("new news");
+        let mut engine = RenderEngine::new(8, 16);
+        engine.render_frame(&old, dir.path(), 0);
+        engine.render_frame(&old, dir.path(), 500);
+
+        engine.update_program(&old, &new);
+        engine.render_frame(&new, dir.path(), 600);
+        // The logo's fade carries on; only the ticker with new text restarts
+        assert!((engine.area_states[0].effect.progress - 0.6).abs() < 0.01);
+        assert_eq!(engine.area_states[0].effect.phase_start_ms, 0);
+        assert_eq!(engine.area_states[1].effect.phase_start_ms, 500);
+        assert!((engine.area_states[1].effect.progress - 0.1).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_brightness_fades_between_levels() {
+        let dir = tempfile::tempdir().unwrap();
+        solid_png(dir.path(), "white.png", [255, 255, 255]);
+        let area = image_area("a", 0, "white.png");
+        let xml = format!(r#"<screen><program guid="p">{area}</program></screen>"#);
+        let screen = parse_program_xml(&xml).unwrap();
+        let program = &screen.programs[0];
+
+        let mut engine = RenderEngine::new(4, 4);
+        engine.render_frame(program, dir.path(), 1000);
+        assert_eq!(pixel(&engine, 1, 1)[0], 255);
+
+        engine.set_brightness(0);
+        let mut levels = Vec::new();
+        for t in [1000, 1125, 1250, 1375, 1500, 1600] {
+            engine.render_frame(program, dir.path(), t);
+            levels.push(pixel(&engine, 1, 1)[0]);
+        }
+        // Full at the change, dark once the fade time has passed, and
+        // strictly in between on the frames during the fade
+        assert_eq!(levels[0], 255);
+        assert!(levels.windows(2).take(4).all(|w| w[1] < w[0]), "{levels:?}");
+        assert!(levels[1..4].iter().all(|&l| l > 0 && l < 255), "{levels:?}");
+        assert_eq!(&levels[4..], &[0, 0]);
+
+        // With no fade the change is immediate
+        engine.set_brightness_fade(0);
+        engine.set_brightness(100);
+        engine.render_frame(program, dir.path(), 1700);
+        assert_eq!(pixel(&engine, 1, 1)[0], 255);
+    }
+
+    /// Renderer that changes every `interval_ms` (or never), counting how often
+    /// the engine consults it
+    struct TimedRenderer {
+        interval_ms: Option<u64>,
+        calls: Arc<AtomicUsize>,
+    }
+
+    impl ContentRenderer for TimedRenderer {
+        fn next_redraw_ms(&mut self, _item: &ContentItem, elapsed_ms: u64) -> Option<u64> {
+            self.interval_ms.map(|interval| elapsed_ms + interval)
+        }
+
+        fn render(
+            &mut self,
+            _item: &ContentItem,
+            target: &mut Pixmap,
+            _x: i32,
+            _y: i32,
+            _width: u32,
+            _height: u32,
+            _elapsed_ms: u64,
+            _program_dir: &Path,
+        ) -> bool {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            target.fill(Color::WHITE);
+            true
+        }
+    }
+
+    struct PanickingRenderer;
+
+    impl ContentRenderer for PanickingRenderer {
+        fn render(
+            &mut self,
+            _item: &ContentItem,
+            _target: &mut Pixmap,
+            _x: i32,
+            _y: i32,
+            _width: u32,
+            _height: u32,
+            _elapsed_ms: u64,
+            _program_dir: &Path,
+        ) -> bool {
+            panic!("bad font metric");
+        }
+    }
+
+    #[test]
+    fn test_panicking_renderer_skips_only_its_area() {
+        let dir = tempfile::tempdir().unwrap();
+        solid_png(dir.path(), "green.png", [0, 255, 0]);
+        let xml = r#"<screen><program guid="p">
+            <area guid="bad"><rectangle x="0" y="0" width="8" height="8"/>
+              <resources><text guid="t"><string>boom</string></text></resources></area>
+            <area guid="good"><rectangle x="8" y="0" width="8" height="8"/>
+              <resources><image guid="i" fit="stretch"><file name="green.png"/></image></resources></area>
+            </program></screen>"#;
+        let screen = parse_program_xml(xml).unwrap();
+        let mut engine = RenderEngine::new(16, 8);
+        engine.set_renderer(ContentKind::Text, Box::new(PanickingRenderer));
+
+        engine.render_frame(&screen.programs[0], dir.path(), 0);
+        assert_eq!(pixel(&engine, 12, 4), [0, 255, 0, 255]);
+        assert_eq!(pixel(&engine, 0, 0), [255, 0, 0, 255]);
+        assert_eq!(engine.frames_rendered(), 1);
+
+        // Later frames keep going without asking the renderer again
+        engine.render_frame(&screen.programs[0], dir.path(), 2000);
+        assert_eq!(pixel(&engine, 12, 4), [0, 255, 0, 255]);
+        assert_eq!(engine.frames_rendered(), 2);
+    }
+
+    #[test]
+    fn test_areas_redraw_on_their_own_schedule() {
+        let xml = r#"<screen><program guid="p">
+            <area guid="still"><rectangle x="0" y="0" width="4" height="8"/>
+              <resources><image guid="i"><file name="still.png"/></image></resources></area>
+            <area guid="anim"><rectangle x="4" y="0" width="4" height="8"/>
+              <resources><gif guid="g"><file name="anim.gif"/></gif></resources></area>
+            </program></screen>"#;
+        let screen = parse_program_xml(xml).unwrap();
+        let still = Arc::new(AtomicUsize::new(0));
+        let anim = Arc::new(AtomicUsize::new(0));
+        let mut engine = RenderEngine::new(8, 8);
+        let renderer = |interval_ms, calls: &Arc<AtomicUsize>| {
+            Box::new(TimedRenderer { interval_ms, calls: calls.clone() })
+        };
+        engine.set_renderer(ContentKind::Image, renderer(None, &still));
+        engine.set_renderer(ContentKind::Gif, renderer(Some(100), &anim));
+
+        // 20 frames at 25fps over 760ms. The animation is due 100ms after each
+        // redraw, which lands on the frames at 0, 120, 240, ..., 720ms
+        for frame in 0..20 {
+            engine.render_frame(&screen.programs[0], Path::new("."), frame * 40);
+            // Both areas are on screen every frame, cached or not
+            assert_eq!(pixel(&engine, 1, 1), [255, 255, 255, 255]);
+            assert_eq!(pixel(&engine, 6, 1), [255, 255, 255, 255]);
+        }
+        assert_eq!(still.load(Ordering::SeqCst), 1);
+        assert_eq!(anim.load(Ordering::SeqCst), 7);
+    }
+}
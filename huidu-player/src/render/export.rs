@@ -0,0 +1,107 @@
+/// Frame export — encodes the premultiplied RGBA framebuffer to an image file.
+/// The encoder is picked from the output path extension (.png, .jpg/.jpeg, .bmp).
+use anyhow::{Context, Result};
+use image::{ImageFormat, RgbImage, RgbaImage};
+use std::path::Path;
+
+/// Save a premultiplied RGBA frame, choosing the format from `path`'s extension.
+/// Paths without an extension are written as PNG.
+pub fn save_frame(path: &Path, data: &[u8], width: u32, height: u32) -> Result<()> {
+    let ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase())
+        .unwrap_or_else(|| "png".to_string());
+
+    match ext.as_str() {
+        "png" => {
+            let img = RgbaImage::from_raw(width, height, demultiply(data))
+                .context("Frame buffer size mismatch")?;
+            img.save_with_format(path, ImageFormat::Png)?;
+        }
+        "jpg" | "jpeg" => {
+            let img = RgbImage::from_raw(width, height, over_black(data))
+                .context("Frame buffer size mismatch")?;
+            img.save_with_format(path, ImageFormat::Jpeg)?;
+        }
+        "bmp" => {
+            let img = RgbImage::from_raw(width, height, over_black(data))
+                .context("Frame buffer size mismatch")?;
+            img.save_with_format(path, ImageFormat::Bmp)?;
+        }
+        other => anyhow::bail!("Unsupported output format: .{}", other),
+    }
+    Ok(())
+}
+
+/// Encode a premultiplied RGBA frame as PNG bytes (for serving over HTTP)
+pub fn encode_png(data: &[u8], width: u32, height: u32) -> Result<Vec<u8>> {
+    let img = RgbaImage::from_raw(width, height, demultiply(data))
+        .context("Frame buffer size mismatch")?;
+    let mut png = Vec::new();
+    img.write_to(&mut std::io::Cursor::new(&mut png), ImageFormat::Png)?;
+    Ok(png)
+}
+
+/// Encode a premultiplied RGBA frame as JPEG bytes (for the MJPEG preview)
+pub fn encode_jpeg(data: &[u8], width: u32, height: u32) -> Result<Vec<u8>> {
+    let img = RgbImage::from_raw(width, height, over_black(data))
+        .context("Frame buffer size mismatch")?;
+    let mut jpeg = Vec::new();
+    img.write_to(&mut std::io::Cursor::new(&mut jpeg), ImageFormat::Jpeg)?;
+    Ok(jpeg)
+}
+
+/// Premultiplied RGBA -> straight RGBA (what PNG expects)
+fn demultiply(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    for px in data.chunks_exact(4) {
+        let a = px[3];
+        if a == 0 {
+            out.extend_from_slice(&[0, 0, 0, 0]);
+        } else {
+            for &c in &px[..3] {
+                out.push(((c as u32 * 255 + a as u32 / 2) / a as u32).min(255) as u8);
+            }
+            out.push(a);
+        }
+    }
+    out
+}
+
+/// Premultiplied RGBA -> RGB composited over black, which is just the color channels
+fn over_black(data: &[u8]) -> Vec<u8> {
+    data.chunks_exact(4).flat_map(|px| [px[0], px[1], px[2]]).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_save_each_format() {
+        let dir = tempfile::tempdir().unwrap();
+        let (w, h) = (6u32, 3u32);
+        let data = [200u8, 100, 50, 255].repeat((w * h) as usize);
+
+        for name in ["frame.png", "frame.jpg", "frame.bmp"] {
+            let path = dir.path().join(name);
+            save_frame(&path, &data, w, h).unwrap();
+            let decoded = image::open(&path).unwrap();
+            assert_eq!((decoded.width(), decoded.height()), (w, h), "{name}");
+        }
+    }
+
+    #[test]
+    fn test_png_is_straight_alpha() {
+        // 50% white, premultiplied
+        assert_eq!(demultiply(&[128, 128, 128, 128]), vec![255, 255, 255, 128]);
+        assert_eq!(demultiply(&[0, 0, 0, 0]), vec![0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_unknown_extension_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(save_frame(&dir.path().join("frame.tga"), &[0; 4], 1, 1).is_err());
+    }
+}
@@ -1,3 +1,8 @@
+pub mod color;
 pub mod effects;
 pub mod engine;
+pub mod export;
+pub mod mono;
 pub mod plugins;
+pub mod rgb565;
+pub mod testpattern;
@@ -0,0 +1,81 @@
+/// 1-bit mono export for single-color LED modules.
+///
+/// Each pixel is reduced to on/off by comparing one channel (or luma) of the
+/// composited frame against a threshold; values at or above the threshold are on.
+///
+/// Packing format: rows are stored top to bottom, each row padded to a whole
+/// number of bytes (`(width + 7) / 8`). Within a byte the leftmost pixel is the
+/// most significant bit, and a set bit means the LED is lit. A 10x2 frame is
+/// therefore 4 bytes: two per row, with the low 6 bits of each row's second
+/// byte always zero.
+use crate::config::MonoChannel;
+
+/// Bytes per packed row for a given width
+pub fn row_bytes(width: u32) -> usize {
+    width.div_ceil(8) as usize
+}
+
+/// Threshold a premultiplied RGBA frame and pack it into the row-major bit format
+pub fn pack(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    channel: MonoChannel,
+    threshold: u8,
+) -> Vec<u8> {
+    let stride = row_bytes(width);
+    let mut out = vec![0u8; stride * height as usize];
+
+    for y in 0..height as usize {
+        for x in 0..width as usize {
+            let i = (y * width as usize + x) * 4;
+            let Some(px) = data.get(i..i + 4) else {
+                return out;
+            };
+            // Premultiplied over a black background is just the color channels
+            let level = match channel {
+                MonoChannel::Red => px[0],
+                MonoChannel::Green => px[1],
+                MonoChannel::Blue => px[2],
+                MonoChannel::Luma => {
+                    ((px[0] as u32 * 299 + px[1] as u32 * 587 + px[2] as u32 * 114) / 1000) as u8
+                }
+            };
+            if level >= threshold {
+                out[y * stride + x / 8] |= 0x80 >> (x % 8);
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_half_black_half_white() {
+        // 10x2: left 5 columns black, right 5 white
+        let (w, h) = (10u32, 2u32);
+        let mut data = Vec::new();
+        for _ in 0..h {
+            for x in 0..w {
+                let v = if x < 5 { 0 } else { 255 };
+                data.extend_from_slice(&[v, v, v, 255]);
+            }
+        }
+
+        let packed = pack(&data, w, h, MonoChannel::Red, 128);
+        assert_eq!(row_bytes(w), 2);
+        assert_eq!(packed, vec![0b0000_0111, 0b1100_0000, 0b0000_0111, 0b1100_0000]);
+    }
+
+    #[test]
+    fn test_channel_selection() {
+        // One pure red pixel and one pure green pixel
+        let data = [255, 0, 0, 255, 0, 255, 0, 255];
+        assert_eq!(pack(&data, 2, 1, MonoChannel::Red, 128), vec![0b1000_0000]);
+        assert_eq!(pack(&data, 2, 1, MonoChannel::Green, 128), vec![0b0100_0000]);
+        assert_eq!(pack(&data, 2, 1, MonoChannel::Luma, 128), vec![0b0100_0000]);
+    }
+}
@@ -1,73 +1,112 @@
 /// Clock content renderer plugin.
 /// Renders digital clock with date/time/week fields.
-use chrono::Local;
+use chrono::{DateTime, Local, Timelike};
+use std::collections::HashMap;
 use std::path::Path;
 use tiny_skia::Pixmap;
 
+use crate::core::clock::{self, SharedClock};
 use crate::program::model::{parse_color, ClockContent, ContentItem};
 use crate::render::plugins::ContentRenderer;
 
+/// A rendered line of text and its color
+type ClockLine = (String, (u8, u8, u8));
+
+/// Time formats beyond HDPlayer's 1-4:
+/// "5" HH:MM and "6" HH:MM:SS with colons shown on even seconds only,
+/// "7" HH:MM:SS.t with tenths of a second
+const TIME_FORMAT_BLINK_HM: &str = "5";
+const TIME_FORMAT_BLINK_HMS: &str = "6";
+const TIME_FORMAT_TENTHS: &str = "7";
+
 pub struct ClockRenderer {
     font: rusttype::Font<'static>,
+    /// Lines of each clock's last render by guid, to tell when the display actually changes
+    last_render: HashMap<String, Vec<ClockLine>>,
+    clock: SharedClock,
 }
 
 impl ClockRenderer {
     pub fn new() -> Self {
+        Self::with_clock(clock::system())
+    }
+
+    /// A renderer showing the time from `clock`
+    pub fn with_clock(clock: SharedClock) -> Self {
         let font_data = include_bytes!("../../../assets/DejaVuSans.ttf");
         let font = rusttype::Font::try_from_bytes(font_data as &[u8])
             .expect("Failed to load built-in font");
-        Self { font }
+        Self {
+            font,
+            last_render: HashMap::new(),
+            clock,
+        }
     }
 
-    fn render_clock(
-        &self,
-        clock: &ClockContent,
-        target: &mut Pixmap,
-        width: u32,
-        height: u32,
-    ) {
-        let now = Local::now();
+    /// True if `lines` differ from the last render of this clock. Dial clocks
+    /// always count as changed since their hands sweep continuously.
+    fn changed(&self, clock: &ClockContent, lines: &[ClockLine]) -> bool {
+        if clock.clock_type == "dial" {
+            return true;
+        }
+        self.last_render.get(&clock.guid).is_none_or(|last| last.as_slice() != lines)
+    }
 
+    /// Format the lines to display for the current time
+    fn clock_lines(&self, clock: &ClockContent) -> Vec<ClockLine> {
+        Self::clock_lines_at(clock, self.clock.now())
+    }
+
+    /// Format the lines to display at `now`
+    fn clock_lines_at(clock: &ClockContent, now: DateTime<Local>) -> Vec<ClockLine> {
         // Collect lines to render with their colors
-        let mut lines: Vec<(String, (u8, u8, u8))> = Vec::new();
+        let mut lines: Vec<ClockLine> = Vec::new();
 
         // Date line
-        if let Some(ref date_field) = clock.date {
-            if date_field.display {
-                let date_str = match date_field.format.as_str() {
-                    "2" => now.format("%m/%d/%Y").to_string(),
-                    "3" => now.format("%d/%m/%Y").to_string(),
-                    "4" => now.format("%b %d, %Y").to_string(),
-                    "5" => now.format("%d %b, %Y").to_string(),
-                    _ => now.format("%Y/%m/%d").to_string(),
-                };
-                lines.push((date_str, parse_color(&date_field.color)));
-            }
+        if let Some(ref date_field) = clock.date
+            && date_field.display
+        {
+            let date_str = match date_field.format.as_str() {
+                "2" => now.format("%m/%d/%Y").to_string(),
+                "3" => now.format("%d/%m/%Y").to_string(),
+                "4" => now.format("%b %d, %Y").to_string(),
+                "5" => now.format("%d %b, %Y").to_string(),
+                _ => now.format("%Y/%m/%d").to_string(),
+            };
+            lines.push((date_str, parse_color(&date_field.color)));
         }
 
         // Week line
-        if let Some(ref week_field) = clock.week {
-            if week_field.display {
-                let week_str = match week_field.format.as_str() {
-                    "2" => now.format("%A").to_string(),
-                    "3" => now.format("%a").to_string(),
-                    _ => now.format("%A").to_string(),
-                };
-                lines.push((week_str, parse_color(&week_field.color)));
-            }
+        if let Some(ref week_field) = clock.week
+            && week_field.display
+        {
+            let week_str = match week_field.format.as_str() {
+                "2" => now.format("%A").to_string(),
+                "3" => now.format("%a").to_string(),
+                _ => now.format("%A").to_string(),
+            };
+            lines.push((week_str, parse_color(&week_field.color)));
         }
 
         // Time line
-        if let Some(ref time_field) = clock.time {
-            if time_field.display {
-                let time_str = match time_field.format.as_str() {
-                    "2" => now.format("%H:%M").to_string(),
-                    "3" => now.format("%I:%M:%S %p").to_string(),
-                    "4" => now.format("%I:%M %p").to_string(),
-                    _ => now.format("%H:%M:%S").to_string(),
-                };
-                lines.push((time_str, parse_color(&time_field.color)));
-            }
+        if let Some(ref time_field) = clock.time
+            && time_field.display
+        {
+            let time_str = match time_field.format.as_str() {
+                "2" => now.format("%H:%M").to_string(),
+                "3" => now.format("%I:%M:%S %p").to_string(),
+                "4" => now.format("%I:%M %p").to_string(),
+                TIME_FORMAT_BLINK_HM => blink_separators(now.format("%H:%M").to_string(), now),
+                TIME_FORMAT_BLINK_HMS => {
+                    blink_separators(now.format("%H:%M:%S").to_string(), now)
+                }
+                TIME_FORMAT_TENTHS => {
+                    let tenths = now.timestamp_subsec_millis().min(999) / 100;
+                    format!("{}.{tenths}", now.format("%H:%M:%S"))
+                }
+                _ => now.format("%H:%M:%S").to_string(),
+            };
+            lines.push((time_str, parse_color(&time_field.color)));
         }
 
         if lines.is_empty() {
@@ -76,7 +115,10 @@ impl ClockRenderer {
                 (255, 255, 255),
             ));
         }
+        lines
+    }
 
+    fn render_clock(&self, lines: &[ClockLine], target: &mut Pixmap, width: u32, height: u32) {
         // Calculate layout
         let font_size = (height as f32 / lines.len() as f32).min(height as f32 * 0.8);
         let scale = rusttype::Scale::uniform(font_size);
@@ -148,6 +190,24 @@ impl ClockRenderer {
 }
 
 impl ContentRenderer for ClockRenderer {
+    fn is_dirty(&mut self, item: &ContentItem, _elapsed_ms: u64) -> bool {
+        match item {
+            ContentItem::Clock(clock) => self.changed(clock, &self.clock_lines(clock)),
+            _ => true,
+        }
+    }
+
+    fn next_redraw_ms(&mut self, item: &ContentItem, elapsed_ms: u64) -> Option<u64> {
+        // Wake at the next wall-clock second (or tenth, when tenths are shown)
+        // so the display doesn't lag it
+        let step = match item {
+            ContentItem::Clock(clock) if shows_tenths(clock) => 100,
+            _ => 1000,
+        };
+        let into_step = self.clock.now().timestamp_subsec_millis().min(999) as u64 % step;
+        Some(elapsed_ms + step - into_step)
+    }
+
     fn render(
         &mut self,
         item: &ContentItem,
@@ -164,7 +224,113 @@ impl ContentRenderer for ClockRenderer {
             _ => return false,
         };
 
-        self.render_clock(clock, target, width, height);
-        true
+        let lines = self.clock_lines(clock);
+        let changed = self.changed(clock, &lines);
+        self.render_clock(&lines, target, width, height);
+        self.last_render.insert(clock.guid.clone(), lines);
+        changed
+    }
+}
+
+/// Blank the colons of a time string on odd seconds, so they blink once a
+/// second in step with the real clock. Spaces keep the layout from shifting.
+fn blink_separators(time: String, now: DateTime<Local>) -> String {
+    if now.second().is_multiple_of(2) {
+        time
+    } else {
+        time.replace(':', " ")
+    }
+}
+
+fn shows_tenths(clock: &ClockContent) -> bool {
+    clock.time.as_ref().is_some_and(|t| t.display && t.format == TIME_FORMAT_TENTHS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::program::parser::parse_program_xml;
+
+    fn clock_item(clock_type: &str) -> ContentItem {
+        clock_item_with_format(clock_type, "2")
+    }
+
+    fn clock_item_with_format(clock_type: &str, format: &str) -> ContentItem {
+        let xml = format!(
+            r#"<screen><program guid="p"><area guid="a"><rectangle width="64" height="16"/>
+               <resources><clock guid="c" type="{clock_type}"><time display="true" format="{format}"/></clock></resources>
+               </area></program></screen>"#
+        );
+        let screen = parse_program_xml(&xml).unwrap();
+        screen.programs[0].areas[0].resources.items[0].clone()
+    }
+
+    #[test]
+    fn test_minute_clock_mostly_unchanged() {
+        let mut renderer = ClockRenderer::new();
+        let item = clock_item("digital");
+        let mut target = Pixmap::new(64, 16).unwrap();
+
+        assert!(renderer.is_dirty(&item, 0));
+        assert!(renderer.render(&item, &mut target, 0, 0, 64, 16, 0, Path::new(".")));
+
+        // A second of 30fps frames; at most one can straddle a minute boundary
+        let mut changed = 0;
+        for frame in 1..=30 {
+            if renderer.is_dirty(&item, frame * 33) {
+                changed += 1;
+                renderer.render(&item, &mut target, 0, 0, 64, 16, frame * 33, Path::new("."));
+            }
+        }
+        assert!(changed <= 1, "{changed} of 30 frames changed");
+    }
+
+    #[test]
+    fn test_clocks_tracked_per_guid() {
+        use chrono::TimeZone;
+
+        let now = Local.with_ymd_and_hms(2024, 5, 1, 12, 34, 56).unwrap();
+        let mut renderer = ClockRenderer::with_clock(clock::MockClock::new(now).shared());
+        let first = clock_item("digital");
+        let ContentItem::Clock(mut clock) = clock_item("digital") else {
+            unreachable!()
+        };
+        clock.guid = "c2".to_string();
+        let second = ContentItem::Clock(clock);
+        let mut target = Pixmap::new(64, 16).unwrap();
+
+        // Two clocks drawn alternately must not mark each other dirty
+        renderer.render(&first, &mut target, 0, 0, 64, 16, 0, Path::new("."));
+        renderer.render(&second, &mut target, 0, 0, 64, 16, 0, Path::new("."));
+        assert!(!renderer.is_dirty(&first, 33));
+        assert!(!renderer.is_dirty(&second, 33));
+    }
+
+    #[test]
+    fn test_dial_clock_always_dirty() {
+        let mut renderer = ClockRenderer::new();
+        let item = clock_item("dial");
+        let mut target = Pixmap::new(64, 16).unwrap();
+        renderer.render(&item, &mut target, 0, 0, 64, 16, 0, Path::new("."));
+        assert!(renderer.is_dirty(&item, 33));
+    }
+
+    #[test]
+    fn test_blinking_colon_alternates_each_second() {
+        use chrono::TimeZone;
+
+        let ContentItem::Clock(clock) = clock_item_with_format("digital", "6") else {
+            unreachable!()
+        };
+        let even = Local.with_ymd_and_hms(2024, 5, 1, 12, 34, 56).unwrap();
+        let odd = even + chrono::Duration::seconds(1);
+        assert_eq!(ClockRenderer::clock_lines_at(&clock, even)[0].0, "12:34:56");
+        assert_eq!(ClockRenderer::clock_lines_at(&clock, odd)[0].0, "12 34 57");
+
+        let ContentItem::Clock(clock) = clock_item_with_format("digital", "7") else {
+            unreachable!()
+        };
+        let later = even + chrono::Duration::milliseconds(340);
+        assert_eq!(ClockRenderer::clock_lines_at(&clock, later)[0].0, "12:34:56.3");
     }
 }
@@ -1,16 +1,21 @@
 /// GIF animation renderer plugin.
-/// Decodes GIF frames and cycles through them with proper timing.
+/// Decodes GIF frames and cycles through them with proper timing. Animated
+/// PNGs decode into the same frame list (`decode_apng`) for the image renderer.
+use image::AnimationDecoder;
 use std::collections::HashMap;
 use std::path::Path;
 use tiny_skia::{Pixmap, PixmapPaint, Transform};
 use tracing::{debug, warn};
 
 use crate::program::model::ContentItem;
-use crate::render::plugins::ContentRenderer;
+use crate::render::plugins::{pixmap_from_rgba, ContentRenderer, Decoded, PreloadRequest};
 
-struct GifData {
+/// Decoded animation frames with their timing
+pub(super) struct GifData {
     frames: Vec<GifFrame>,
     total_duration_ms: u64,
+    /// Times the animation plays before holding its last frame (0 = forever)
+    plays: u32,
 }
 
 struct GifFrame {
@@ -31,103 +36,247 @@ impl GifRenderer {
     }
 
     fn load_gif(&mut self, filename: &str, program_dir: &Path) -> Option<&GifData> {
-        if self.cache.contains_key(filename) {
-            return self.cache.get(filename);
+        if !self.cache.contains_key(filename) {
+            let data = decode_gif(&program_dir.join(filename))?;
+            self.cache.insert(filename.to_string(), data);
         }
+        self.cache.get(filename)
+    }
+}
+
+fn decode_gif(path: &Path) -> Option<GifData> {
+    debug!("Loading GIF: {}", path.display());
+
+    let file = match std::fs::File::open(path) {
+        Ok(f) => f,
+        Err(e) => {
+            debug!("Failed to open GIF {}: {}", path.display(), e);
+            return None;
+        }
+    };
+
+    // Frames are composited as RGBA; the decoder defaults to palette indices
+    let mut options = gif::DecodeOptions::new();
+    options.set_color_output(gif::ColorOutput::RGBA);
+    let mut decoder = match options.read_info(file) {
+        Ok(d) => d,
+        Err(e) => {
+            warn!("Failed to decode GIF {}: {}", path.display(), e);
+            return None;
+        }
+    };
+
+    let width = decoder.width() as u32;
+    let height = decoder.height() as u32;
+
+    let mut frames = Vec::new();
+    let mut cumulative = 0u64;
+
+    // Composite canvas for handling disposal methods
+    let mut canvas = Pixmap::new(width, height).unwrap();
+    canvas.fill(tiny_skia::Color::TRANSPARENT);
 
-        let path = program_dir.join(filename);
-        debug!("Loading GIF: {}", path.display());
+    while let Ok(Some(frame)) = decoder.read_next_frame() {
+        let delay_ms = (frame.delay as u64) * 10; // GIF delay is in centiseconds
+        let delay_ms = if delay_ms == 0 { 100 } else { delay_ms }; // Default 100ms
 
-        let file = match std::fs::File::open(&path) {
+        let fw = frame.width as u32;
+        let fh = frame.height as u32;
+        let fx = frame.left as i32;
+        let fy = frame.top as i32;
+
+        // Create frame pixmap from RGBA buffer
+        if let Some(frame_pixmap) = pixmap_from_rgba(fw, fh, &frame.buffer) {
+
+            // Composite frame onto canvas
+            canvas.draw_pixmap(
+                fx, fy,
+                frame_pixmap.as_ref(),
+                &PixmapPaint::default(),
+                Transform::identity(),
+                None,
+            );
+        }
+
+        // Save a snapshot of the current canvas
+        if let Some(mut snapshot) = Pixmap::new(width, height) {
+            snapshot.data_mut().copy_from_slice(canvas.data());
+            frames.push(GifFrame {
+                pixmap: snapshot,
+                delay_ms,
+                cumulative_ms: cumulative,
+            });
+        }
+
+        cumulative += delay_ms;
+    }
+
+    if frames.is_empty() {
+        warn!("GIF has no frames: {}", path.display());
+        return None;
+    }
+
+    debug!("Loaded GIF: {} frames, {}ms total", frames.len(), cumulative);
+
+    Some(GifData {
+        total_duration_ms: cumulative,
+        frames,
+        plays: 0,
+    })
+}
+
+/// PNG chunk announcing an animation; it comes before the image data
+const APNG_CONTROL_CHUNK: &[u8; 4] = b"acTL";
+
+/// Decode an animated PNG into frames. None for plain PNGs (and anything that
+/// isn't a PNG), which the image renderer draws as stills.
+pub(super) fn decode_apng(path: &Path) -> Option<GifData> {
+    let data = std::fs::read(path).ok()?;
+    let plays = apng_plays(&data)?;
+    debug!("Loading APNG: {}", path.display());
+
+    let decoder = image::codecs::png::PngDecoder::new(std::io::Cursor::new(&data))
+        .and_then(|decoder| decoder.apng());
+    let decoder = match decoder {
+        Ok(d) => d,
+        Err(e) => {
+            warn!("Failed to decode APNG {}: {}", path.display(), e);
+            return None;
+        }
+    };
+
+    // The decoder composites each frame onto the full canvas (blend and dispose ops)
+    let mut frames = Vec::new();
+    let mut cumulative = 0u64;
+    for frame in decoder.into_frames() {
+        let frame = match frame {
             Ok(f) => f,
             Err(e) => {
-                warn!("Failed to open GIF {}: {}", path.display(), e);
-                return None;
+                warn!("Failed to decode APNG frame in {}: {}", path.display(), e);
+                break;
             }
         };
+        let (numer, denom) = frame.delay().numer_denom_ms();
+        let delay_ms = (numer as u64 / denom.max(1) as u64).max(1);
+        let buffer = frame.into_buffer();
+        if let Some(pixmap) = pixmap_from_rgba(buffer.width(), buffer.height(), &buffer) {
+            frames.push(GifFrame { pixmap, delay_ms, cumulative_ms: cumulative });
+            cumulative += delay_ms;
+        }
+    }
 
-        let mut decoder = match gif::DecodeOptions::new().read_info(file) {
-            Ok(d) => d,
-            Err(e) => {
-                warn!("Failed to decode GIF {}: {}", path.display(), e);
-                return None;
-            }
-        };
+    if frames.is_empty() {
+        warn!("APNG has no frames: {}", path.display());
+        return None;
+    }
+    debug!("Loaded APNG: {} frames, {}ms per loop, {} play(s)", frames.len(), cumulative, plays);
+    Some(GifData {
+        total_duration_ms: cumulative,
+        frames,
+        plays,
+    })
+}
 
-        let width = decoder.width() as u32;
-        let height = decoder.height() as u32;
-
-        let mut frames = Vec::new();
-        let mut cumulative = 0u64;
-
-        // Composite canvas for handling disposal methods
-        let mut canvas = Pixmap::new(width, height).unwrap();
-        canvas.fill(tiny_skia::Color::TRANSPARENT);
-
-        while let Ok(Some(frame)) = decoder.read_next_frame() {
-            let delay_ms = (frame.delay as u64) * 10; // GIF delay is in centiseconds
-            let delay_ms = if delay_ms == 0 { 100 } else { delay_ms }; // Default 100ms
-
-            let fw = frame.width as u32;
-            let fh = frame.height as u32;
-            let fx = frame.left as i32;
-            let fy = frame.top as i32;
-
-            // Create frame pixmap from RGBA buffer
-            if let Some(mut frame_pixmap) = Pixmap::new(fw, fh) {
-                let data = frame_pixmap.data_mut();
-                let src = &frame.buffer;
-                for i in 0..(fw * fh) as usize {
-                    let si = i * 4;
-                    if si + 3 < src.len() {
-                        let a = src[si + 3] as f32 / 255.0;
-                        data[si] = (src[si] as f32 * a) as u8;
-                        data[si + 1] = (src[si + 1] as f32 * a) as u8;
-                        data[si + 2] = (src[si + 2] as f32 * a) as u8;
-                        data[si + 3] = src[si + 3];
-                    }
-                }
-
-                // Composite frame onto canvas
-                canvas.draw_pixmap(
-                    fx, fy,
-                    frame_pixmap.as_ref(),
-                    &PixmapPaint::default(),
-                    Transform::identity(),
-                    None,
-                );
-            }
+/// The play count from the animation control chunk, or None if `data` isn't
+/// an animated PNG
+fn apng_plays(data: &[u8]) -> Option<u32> {
+    const SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+    let mut rest = data.strip_prefix(SIGNATURE)?;
+    // Chunks: length, type, data, CRC
+    while rest.len() >= 12 {
+        let len = u32::from_be_bytes(rest[..4].try_into().ok()?) as usize;
+        let kind = &rest[4..8];
+        let body = rest.get(8..8 + len)?;
+        if kind == APNG_CONTROL_CHUNK {
+            return Some(u32::from_be_bytes(body.get(4..8)?.try_into().ok()?));
+        }
+        if kind == b"IDAT" {
+            return None;
+        }
+        rest = rest.get(12 + len..)?;
+    }
+    None
+}
 
-            // Save a snapshot of the current canvas
-            if let Some(mut snapshot) = Pixmap::new(width, height) {
-                snapshot.data_mut().copy_from_slice(canvas.data());
-                frames.push(GifFrame {
-                    pixmap: snapshot,
-                    delay_ms,
-                    cumulative_ms: cumulative,
-                });
-            }
+impl GifData {
+    /// Length of one loop
+    pub(super) fn loop_duration_ms(&self) -> u64 {
+        self.total_duration_ms
+    }
 
-            cumulative += delay_ms;
+    /// Whether a finite animation has played all its loops by `elapsed_ms`
+    fn finished(&self, elapsed_ms: u64) -> bool {
+        self.plays > 0 && elapsed_ms >= self.total_duration_ms * self.plays as u64
+    }
+
+    /// The frame showing `elapsed_ms` into the animation
+    pub(super) fn frame_at(&self, elapsed_ms: u64) -> &Pixmap {
+        if self.finished(elapsed_ms) {
+            return &self.frames[self.frames.len() - 1].pixmap;
         }
+        let loop_time = elapsed_ms % self.total_duration_ms.max(1);
+        let index = self
+            .frames
+            .iter()
+            .rposition(|f| loop_time >= f.cumulative_ms)
+            .unwrap_or(0);
+        &self.frames[index].pixmap
+    }
 
-        if frames.is_empty() {
-            warn!("GIF has no frames: {}", path.display());
+    /// Time (ms) when the frame showing at `elapsed_ms` gives way to the next,
+    /// or None for a still GIF (or a finite animation that has finished)
+    pub(super) fn next_frame_ms(&self, elapsed_ms: u64) -> Option<u64> {
+        if self.frames.len() < 2 || self.total_duration_ms == 0 || self.finished(elapsed_ms) {
             return None;
         }
+        let loop_time = elapsed_ms % self.total_duration_ms;
+        let loop_start = elapsed_ms - loop_time;
+        let frame_end = self
+            .frames
+            .iter()
+            .find(|f| f.cumulative_ms > loop_time)
+            .map_or(self.total_duration_ms, |f| f.cumulative_ms);
+        Some(loop_start + frame_end)
+    }
+}
+
+impl ContentRenderer for GifRenderer {
+    fn next_redraw_ms(&mut self, item: &ContentItem, elapsed_ms: u64) -> Option<u64> {
+        // Redraw when the frame delay is up; not yet loaded means draw now
+        match item {
+            ContentItem::Gif(gif) => match self.cache.get(&gif.file.name) {
+                Some(data) => data.next_frame_ms(elapsed_ms),
+                None => Some(elapsed_ms),
+            },
+            _ => None,
+        }
+    }
+
+    fn preload_request(&self, items: &[&ContentItem]) -> Option<PreloadRequest> {
+        let names = items.iter().filter_map(|item| match item {
+            ContentItem::Gif(gif) => Some(gif.file.name.as_str()),
+            _ => None,
+        });
+        PreloadRequest::missing(names, |name| self.cache.contains_key(name), |path| {
+            Some(Box::new(decode_gif(path)?))
+        })
+    }
 
-        debug!("Loaded GIF: {} frames, {}ms total", frames.len(), cumulative);
+    fn insert_preloaded(&mut self, decoded: Decoded) {
+        for (name, data) in decoded {
+            if let Ok(data) = data.downcast::<GifData>() {
+                self.cache.entry(name).or_insert(*data);
+            }
+        }
+    }
 
-        let data = GifData {
-            total_duration_ms: cumulative,
-            frames,
+    fn loop_duration_ms(&mut self, item: &ContentItem, program_dir: &Path) -> Option<u64> {
+        let ContentItem::Gif(gif) = item else {
+            return None;
         };
-        self.cache.insert(filename.to_string(), data);
-        self.cache.get(filename)
+        Some(self.load_gif(&gif.file.name, program_dir)?.total_duration_ms)
     }
-}
 
-impl ContentRenderer for GifRenderer {
     fn render(
         &mut self,
         item: &ContentItem,
@@ -154,25 +303,17 @@ impl ContentRenderer for GifRenderer {
             return false;
         }
 
-        // Find current frame based on elapsed time
-        let loop_time = elapsed_ms % gif_data.total_duration_ms;
-        let frame_idx = gif_data
-            .frames
-            .iter()
-            .rposition(|f| loop_time >= f.cumulative_ms)
-            .unwrap_or(0);
-
-        let frame = &gif_data.frames[frame_idx];
+        let frame = gif_data.frame_at(elapsed_ms);
 
         // Scale and draw onto target
-        let src_w = frame.pixmap.width() as f32;
-        let src_h = frame.pixmap.height() as f32;
+        let src_w = frame.width() as f32;
+        let src_h = frame.height() as f32;
         let scale_x = width as f32 / src_w;
         let scale_y = height as f32 / src_h;
 
         target.draw_pixmap(
             0, 0,
-            frame.pixmap.as_ref(),
+            frame.as_ref(),
             &PixmapPaint::default(),
             Transform::from_scale(scale_x, scale_y),
             None,
@@ -181,3 +322,79 @@ impl ContentRenderer for GifRenderer {
         true
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gif_data(delays_ms: &[u64]) -> GifData {
+        let mut cumulative = 0;
+        let frames = delays_ms
+            .iter()
+            .map(|&delay_ms| {
+                let frame = GifFrame {
+                    pixmap: Pixmap::new(1, 1).unwrap(),
+                    delay_ms,
+                    cumulative_ms: cumulative,
+                };
+                cumulative += delay_ms;
+                frame
+            })
+            .collect();
+        GifData { frames, total_duration_ms: cumulative, plays: 0 }
+    }
+
+    /// 2x1 APNG whose frames are solid red, green and blue
+    fn write_apng(path: &Path, delays_ms: &[u16], plays: u32) {
+        let file = std::fs::File::create(path).unwrap();
+        let mut encoder = png::Encoder::new(file, 2, 1);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_animated(delays_ms.len() as u32, plays).unwrap();
+        let mut writer = encoder.write_header().unwrap();
+        let colors: [[u8; 4]; 3] = [[255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 255]];
+        for (&delay, color) in delays_ms.iter().zip(colors.iter().cycle()) {
+            writer.set_frame_delay(delay, 1000).unwrap();
+            writer.write_image_data(&color.repeat(2)).unwrap();
+        }
+        writer.finish().unwrap();
+    }
+
+    #[test]
+    fn test_apng_decodes_frames_with_delays() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("spinner.png");
+        write_apng(&path, &[100, 250, 50], 2);
+
+        let data = decode_apng(&path).unwrap();
+        let delays: Vec<u64> = data.frames.iter().map(|f| f.delay_ms).collect();
+        assert_eq!(delays, [100, 250, 50]);
+        assert_eq!(data.loop_duration_ms(), 400);
+        assert_eq!(data.frame_at(0).data()[..4], [255, 0, 0, 255]);
+        assert_eq!(data.frame_at(120).data()[..4], [0, 255, 0, 255]);
+        assert_eq!(data.frame_at(399).data()[..4], [0, 0, 255, 255]);
+        assert_eq!(data.frame_at(400).data()[..4], [255, 0, 0, 255]);
+
+        // Two plays, then it holds the last frame
+        assert_eq!(data.next_frame_ms(750), Some(800));
+        assert_eq!(data.frame_at(5000).data()[..4], [0, 0, 255, 255]);
+        assert_eq!(data.next_frame_ms(800), None);
+
+        // A plain PNG stays on the still-image path
+        let still = dir.path().join("still.png");
+        image::RgbaImage::new(2, 1).save(&still).unwrap();
+        assert!(decode_apng(&still).is_none());
+    }
+
+    #[test]
+    fn test_next_frame_follows_frame_delays() {
+        let data = gif_data(&[100, 300]);
+        assert_eq!(data.next_frame_ms(0), Some(100));
+        assert_eq!(data.next_frame_ms(99), Some(100));
+        assert_eq!(data.next_frame_ms(100), Some(400));
+        // Second loop
+        assert_eq!(data.next_frame_ms(450), Some(500));
+
+        // A single-frame GIF never needs redrawing
+        assert_eq!(gif_data(&[100]).next_frame_ms(0), None);
+    }
+}
@@ -1,60 +1,217 @@
 /// Image content renderer plugin.
-/// Loads PNG/JPG/BMP images and renders them to the area surface.
+/// Loads PNG/JPG/BMP images and renders them to the area surface. Animated
+/// PNGs play through their frames like a GIF.
 use std::collections::HashMap;
 use std::path::Path;
-use tiny_skia::{Pixmap, PixmapPaint, Transform};
+use tiny_skia::{FilterQuality, Paint, Pixmap, PixmapPaint, Rect, Transform};
 use tracing::{debug, warn};
 
-use crate::program::model::ContentItem;
-use crate::render::plugins::ContentRenderer;
+use crate::program::model::{parse_color, ContentItem};
+use crate::render::plugins::gif::{self, GifData};
+use crate::render::plugins::{pixmap_from_rgba, ContentRenderer, Decoded, PreloadRequest};
+
+/// How long (ms) an image that failed to load is left alone before trying again
+const LOAD_RETRY_MS: u64 = 1000;
 
 pub struct ImageRenderer {
     /// Cache of loaded images by filename
     cache: HashMap<String, Pixmap>,
+    /// Images that failed to load, and when (caller's clock, ms); not retried
+    /// for `LOAD_RETRY_MS`, so a bad file isn't re-read every frame
+    failures: HashMap<String, u64>,
+    /// Content files checked for APNG animation: Some for animated ones
+    animations: HashMap<String, Option<Animation>>,
+}
+
+/// An animated PNG and where it is in its playback
+struct Animation {
+    data: GifData,
+    /// When (caller's clock, ms) this showing started
+    start_ms: u64,
+    /// When a frame was last drawn; None before the first
+    last_drawn_ms: Option<u64>,
+}
+
+impl Animation {
+    /// Note a draw at `now_ms` and return the time into the animation. One
+    /// that went undrawn for over a loop (its area moved on and came back)
+    /// starts again from its first frame and play.
+    fn play(&mut self, now_ms: u64) -> u64 {
+        let away = self
+            .last_drawn_ms
+            .is_none_or(|last| now_ms.saturating_sub(last) > self.data.loop_duration_ms());
+        if away {
+            self.start_ms = now_ms;
+        }
+        self.last_drawn_ms = Some(now_ms);
+        now_ms.saturating_sub(self.start_ms)
+    }
 }
 
 impl ImageRenderer {
     pub fn new() -> Self {
         Self {
             cache: HashMap::new(),
+            failures: HashMap::new(),
+            animations: HashMap::new(),
         }
     }
 
-    fn load_image(&mut self, filename: &str, program_dir: &Path) -> Option<&Pixmap> {
-        if self.cache.contains_key(filename) {
-            return self.cache.get(filename);
+    /// The APNG animation in `filename`, or None for still images. Files that
+    /// don't exist yet are checked again on the next call.
+    fn animation(&mut self, filename: &str, program_dir: &Path) -> Option<&mut Animation> {
+        if !self.animations.contains_key(filename) {
+            let path = program_dir.join(filename);
+            if !path.is_file() {
+                return None;
+            }
+            let animation = gif::decode_apng(&path).map(|data| Animation {
+                data,
+                start_ms: 0,
+                last_drawn_ms: None,
+            });
+            self.animations.insert(filename.to_string(), animation);
         }
+        self.animations.get_mut(filename)?.as_mut()
+    }
 
-        let path = program_dir.join(filename);
-        debug!("Loading image: {}", path.display());
-
-        match image::open(&path) {
-            Ok(img) => {
-                let rgba = img.to_rgba8();
-                let (w, h) = (rgba.width(), rgba.height());
-                // tiny-skia expects premultiplied alpha RGBA
-                if let Some(mut pixmap) = Pixmap::new(w, h) {
-                    let data = pixmap.data_mut();
-                    for (i, pixel) in rgba.pixels().enumerate() {
-                        let a = pixel[3] as f32 / 255.0;
-                        data[i * 4] = (pixel[0] as f32 * a) as u8;
-                        data[i * 4 + 1] = (pixel[1] as f32 * a) as u8;
-                        data[i * 4 + 2] = (pixel[2] as f32 * a) as u8;
-                        data[i * 4 + 3] = pixel[3];
-                    }
+    /// The decoded image, loading it on first use. `now_ms` paces retries of
+    /// files that failed to load.
+    pub fn load_image(
+        &mut self,
+        filename: &str,
+        program_dir: &Path,
+        now_ms: u64,
+    ) -> Option<&Pixmap> {
+        if !self.cache.contains_key(filename) {
+            if let Some(&failed_at) = self.failures.get(filename)
+                && now_ms.saturating_sub(failed_at) < LOAD_RETRY_MS
+            {
+                return None;
+            }
+            let path = program_dir.join(filename);
+            match decode_image(&path) {
+                Ok(pixmap) => {
+                    self.failures.remove(filename);
                     self.cache.insert(filename.to_string(), pixmap);
-                    return self.cache.get(filename);
+                }
+                Err(e) => {
+                    // Warn on the first failure only; retries that fail again are routine
+                    if self.failures.insert(filename.to_string(), now_ms).is_none() {
+                        warn!("Image {}: {}", path.display(), e);
+                    } else {
+                        debug!("Image {} still unavailable: {}", path.display(), e);
+                    }
+                    return None;
                 }
             }
-            Err(e) => {
-                warn!("Failed to load image {}: {}", path.display(), e);
+        }
+        self.cache.get(filename)
+    }
+
+    /// Request decoding of the images `filenames` that aren't cached yet
+    pub fn preload_files<'a>(
+        &self,
+        filenames: impl IntoIterator<Item = &'a str>,
+    ) -> Option<PreloadRequest> {
+        PreloadRequest::missing(filenames, |name| self.cache.contains_key(name), |path| {
+            Some(Box::new(decode_image(path).ok()?))
+        })
+    }
+
+    /// Cache images decoded for a `preload_files` request. Ones loaded in the
+    /// meantime are kept.
+    pub fn insert_images(&mut self, decoded: Decoded) {
+        for (name, pixmap) in decoded {
+            if let Ok(pixmap) = pixmap.downcast::<Pixmap>() {
+                self.failures.remove(&name);
+                self.cache.entry(name).or_insert(*pixmap);
             }
         }
-        None
     }
 }
 
+/// Why an image couldn't be loaded
+#[derive(Debug)]
+enum LoadError {
+    /// No such file in the program directory (not uploaded yet, or misnamed)
+    Missing,
+    /// The file exists but doesn't decode as an image
+    Corrupt(String),
+}
+
+impl std::fmt::Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoadError::Missing => write!(f, "file not found"),
+            LoadError::Corrupt(reason) => write!(f, "corrupt or unsupported image: {reason}"),
+        }
+    }
+}
+
+fn decode_image(path: &Path) -> Result<Pixmap, LoadError> {
+    debug!("Loading image: {}", path.display());
+    if !path.is_file() {
+        return Err(LoadError::Missing);
+    }
+    let img = open_upright(path).map_err(|e| LoadError::Corrupt(e.to_string()))?;
+    let rgba = img.to_rgba8();
+    // tiny-skia expects premultiplied alpha RGBA
+    pixmap_from_rgba(rgba.width(), rgba.height(), &rgba)
+        .ok_or_else(|| LoadError::Corrupt("image has no pixels".to_string()))
+}
+
+/// Decode an image and apply its EXIF orientation, so phone photos taken
+/// sideways come out upright
+fn open_upright(path: &Path) -> image::ImageResult<image::DynamicImage> {
+    use image::ImageDecoder;
+
+    let mut decoder = image::ImageReader::open(path)?.with_guessed_format()?.into_decoder()?;
+    let orientation = decoder.orientation()?;
+    let mut img = image::DynamicImage::from_decoder(decoder)?;
+    img.apply_orientation(orientation);
+    Ok(img)
+}
+
 impl ContentRenderer for ImageRenderer {
+    fn is_dirty(&mut self, item: &ContentItem, _elapsed_ms: u64) -> bool {
+        // Still images never change once drawn; animations are paced by
+        // `next_redraw_ms`
+        let ContentItem::Image(img) = item else {
+            return false;
+        };
+        self.animations.get(&img.file.name).is_some_and(Option::is_some)
+    }
+
+    fn next_redraw_ms(&mut self, item: &ContentItem, _elapsed_ms: u64) -> Option<u64> {
+        let ContentItem::Image(img) = item else {
+            return None;
+        };
+        let animation = self.animations.get(&img.file.name)?.as_ref()?;
+        let start = animation.start_ms;
+        let drawn = animation.last_drawn_ms?;
+        Some(start + animation.data.next_frame_ms(drawn - start)?)
+    }
+
+    fn loop_duration_ms(&mut self, item: &ContentItem, program_dir: &Path) -> Option<u64> {
+        let ContentItem::Image(img) = item else {
+            return None;
+        };
+        Some(self.animation(&img.file.name, program_dir)?.data.loop_duration_ms())
+    }
+
+    fn preload_request(&self, items: &[&ContentItem]) -> Option<PreloadRequest> {
+        let names = items.iter().filter_map(|item| match item {
+            ContentItem::Image(img) => Some(img.file.name.as_str()),
+            _ => None,
+        });
+        self.preload_files(names)
+    }
+
+    fn insert_preloaded(&mut self, decoded: Decoded) {
+        self.insert_images(decoded);
+    }
+
     fn render(
         &mut self,
         item: &ContentItem,
@@ -63,7 +220,7 @@ impl ContentRenderer for ImageRenderer {
         y: i32,
         width: u32,
         height: u32,
-        _elapsed_ms: u64,
+        elapsed_ms: u64,
         program_dir: &Path,
     ) -> bool {
         let img_content = match item {
@@ -74,9 +231,15 @@ impl ContentRenderer for ImageRenderer {
         let filename = &img_content.file.name;
         let fit_mode = &img_content.fit;
 
-        let src_pixmap = match self.load_image(filename, program_dir) {
-            Some(p) => p,
-            None => return false,
+        let src_pixmap = match self.animation(filename, program_dir) {
+            Some(animation) => {
+                let play_ms = animation.play(elapsed_ms);
+                animation.data.frame_at(play_ms)
+            }
+            None => match self.load_image(filename, program_dir, elapsed_ms) {
+                Some(p) => p,
+                None => return false,
+            },
         };
 
         let src_w = src_pixmap.width() as f32;
@@ -92,10 +255,12 @@ impl ContentRenderer for ImageRenderer {
                     .post_translate(x as f32, y as f32)
             }
             "fill" => {
-                // Scale to fill, maintaining aspect ratio (may crop)
+                // Scale to fill, maintaining aspect ratio (may crop); the
+                // alignment decides which part of the overflow is kept
                 let scale = (dst_w / src_w).max(dst_h / src_h);
-                let sx = (dst_w - src_w * scale) / 2.0;
-                let sy = (dst_h - src_h * scale) / 2.0;
+                let (ax, ay) = align_factors(&img_content.align);
+                let sx = (dst_w - src_w * scale) * ax;
+                let sy = (dst_h - src_h * scale) * ay;
                 Transform::from_scale(scale, scale).post_translate(x as f32 + sx, y as f32 + sy)
             }
             "center" => {
@@ -105,7 +270,11 @@ impl ContentRenderer for ImageRenderer {
                 Transform::from_translate(x as f32 + sx, y as f32 + sy)
             }
             _ => {
-                // Default: fit (scale to fit, maintaining aspect ratio)
+                // Default: fit (scale to fit, maintaining aspect ratio), with
+                // the margins optionally padded in a solid color
+                if let Some(pad) = &img_content.pad_color {
+                    fill_rect(target, x, y, width, height, pad);
+                }
                 let scale = (dst_w / src_w).min(dst_h / src_h);
                 let sx = (dst_w - src_w * scale) / 2.0;
                 let sy = (dst_h - src_h * scale) / 2.0;
@@ -114,15 +283,225 @@ impl ContentRenderer for ImageRenderer {
         };
 
         // Draw the image onto the target
-        target.draw_pixmap(
-            0,
-            0,
-            src_pixmap.as_ref(),
-            &PixmapPaint::default(),
-            transform,
-            None,
-        );
+        let paint = PixmapPaint {
+            quality: filter_quality(&img_content.filter),
+            ..PixmapPaint::default()
+        };
+        target.draw_pixmap(0, 0, src_pixmap.as_ref(), &paint, transform, None);
 
         true
     }
 }
+
+/// Paint the area rectangle in a solid #RRGGBB color
+fn fill_rect(target: &mut Pixmap, x: i32, y: i32, width: u32, height: u32, color: &str) {
+    let Some(rect) = Rect::from_xywh(x as f32, y as f32, width as f32, height as f32) else {
+        return;
+    };
+    let (r, g, b) = parse_color(color);
+    let mut paint = Paint::default();
+    paint.set_color_rgba8(r, g, b, 255);
+    target.fill_rect(rect, &paint, Transform::identity(), None);
+}
+
+/// Horizontal/vertical anchor (0 = left/top, 0.5 = center, 1 = right/bottom)
+fn align_factors(align: &str) -> (f32, f32) {
+    match align.to_lowercase().replace(['-', '_'], "").as_str() {
+        "top" => (0.5, 0.0),
+        "bottom" => (0.5, 1.0),
+        "left" => (0.0, 0.5),
+        "right" => (1.0, 0.5),
+        "topleft" => (0.0, 0.0),
+        "topright" => (1.0, 0.0),
+        "bottomleft" => (0.0, 1.0),
+        "bottomright" => (1.0, 1.0),
+        _ => (0.5, 0.5),
+    }
+}
+
+/// Sampling used when the image is scaled; anything unknown smooths
+fn filter_quality(filter: &str) -> FilterQuality {
+    match filter.to_lowercase().as_str() {
+        "nearest" => FilterQuality::Nearest,
+        _ => FilterQuality::Bilinear,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::program::model::{FileRef, ImageContent};
+
+    /// A 4x16 image: top row red, everything else blue
+    fn tall_image() -> Pixmap {
+        let mut pixmap = Pixmap::new(4, 16).unwrap();
+        for (i, px) in pixmap.data_mut().chunks_exact_mut(4).enumerate() {
+            px.copy_from_slice(if i < 4 { &[255, 0, 0, 255] } else { &[0, 0, 255, 255] });
+        }
+        pixmap
+    }
+
+    fn fill_item(align: &str) -> ContentItem {
+        ContentItem::Image(ImageContent {
+            guid: "i1".to_string(),
+            name: String::new(),
+            start_delay_ms: 0,
+            fit: "fill".to_string(),
+            align: align.to_string(),
+            filter: "nearest".to_string(),
+            pad_color: None,
+            effect: None,
+            file: FileRef { name: "tall.png".to_string() },
+        })
+    }
+
+    fn render(align: &str) -> Pixmap {
+        let mut renderer = ImageRenderer::new();
+        renderer.cache.insert("tall.png".to_string(), tall_image());
+        let mut target = Pixmap::new(16, 4).unwrap();
+        assert!(renderer.render(&fill_item(align), &mut target, 0, 0, 16, 4, 0, Path::new(".")));
+        target
+    }
+
+    #[test]
+    fn test_premultiply_rounds() {
+        let dir = tempfile::tempdir().unwrap();
+        let img = image::RgbaImage::from_pixel(1, 1, image::Rgba([101, 255, 3, 128]));
+        img.save(dir.path().join("half.png")).unwrap();
+
+        let mut renderer = ImageRenderer::new();
+        let pixmap = renderer.load_image("half.png", dir.path(), 0).unwrap();
+        // 101 * 128 / 255 = 50.7 and 3 * 128 / 255 = 1.5: rounded, not truncated to 50 and 1
+        assert_eq!(pixmap.data(), &[51, 128, 2, 128]);
+    }
+
+    #[test]
+    fn test_exif_orientation_applied() {
+        use image::ImageEncoder;
+
+        // 2x1 red/green, tagged orientation 6 (rotate 90 clockwise to view)
+        let dir = tempfile::tempdir().unwrap();
+        let pixels = [255, 0, 0, 255, 0, 255, 0, 255];
+        let exif = vec![
+            b'I', b'I', 42, 0, 8, 0, 0, 0, // little-endian TIFF header, IFD at 8
+            1, 0, // one entry
+            0x12, 0x01, 3, 0, 1, 0, 0, 0, 6, 0, 0, 0, // Orientation (SHORT) = 6
+            0, 0, 0, 0, // no next IFD
+        ];
+        let file = std::fs::File::create(dir.path().join("phone.png")).unwrap();
+        let mut encoder = image::codecs::png::PngEncoder::new(file);
+        encoder.set_exif_metadata(exif).unwrap();
+        encoder.write_image(&pixels, 2, 1, image::ExtendedColorType::Rgba8).unwrap();
+
+        let mut renderer = ImageRenderer::new();
+        let pixmap = renderer.load_image("phone.png", dir.path(), 0).unwrap();
+        assert_eq!((pixmap.width(), pixmap.height()), (1, 2));
+        assert_eq!(pixmap.data(), &[255, 0, 0, 255, 0, 255, 0, 255]);
+    }
+
+    #[test]
+    fn test_failed_load_retried_after_interval() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut renderer = ImageRenderer::new();
+        assert!(renderer.load_image("late.png", dir.path(), 0).is_none());
+
+        // The upload lands right after the first attempt; it isn't looked for
+        // again until the retry interval has passed
+        image::RgbaImage::new(1, 1).save(dir.path().join("late.png")).unwrap();
+        assert!(renderer.load_image("late.png", dir.path(), 500).is_none());
+        assert!(renderer.load_image("late.png", dir.path(), LOAD_RETRY_MS - 1).is_none());
+        assert!(renderer.load_image("late.png", dir.path(), LOAD_RETRY_MS).is_some());
+
+        // Missing and undecodable files are told apart
+        std::fs::write(dir.path().join("bad.png"), b"not a png").unwrap();
+        assert!(matches!(decode_image(&dir.path().join("gone.png")), Err(LoadError::Missing)));
+        assert!(matches!(decode_image(&dir.path().join("bad.png")), Err(LoadError::Corrupt(_))));
+    }
+
+    #[test]
+    fn test_fill_align_top_keeps_top_row() {
+        // Scaled 4x to 16x64, so the source's top row covers the whole 16x4 area
+        let target = render("top");
+        for y in [0, 3] {
+            let px = target.pixel(8, y).unwrap();
+            assert_eq!((px.red(), px.blue()), (255, 0), "row {y}");
+        }
+
+        // Centered crop shows the middle of the image instead
+        let target = render("center");
+        let px = target.pixel(8, 0).unwrap();
+        assert_eq!((px.red(), px.blue()), (0, 255));
+    }
+
+    #[test]
+    fn test_upscale_filters_differ_at_edge() {
+        // 2x1 black/white image stretched to 16x1
+        let mut src = Pixmap::new(2, 1).unwrap();
+        src.data_mut().copy_from_slice(&[0, 0, 0, 255, 255, 255, 255, 255]);
+
+        let render_with = |filter: &str| {
+            let mut renderer = ImageRenderer::new();
+            renderer.cache.insert("edge.png".to_string(), src.clone());
+            let item = ContentItem::Image(ImageContent {
+                guid: "i1".to_string(),
+                name: String::new(),
+                start_delay_ms: 0,
+                fit: "stretch".to_string(),
+                align: "center".to_string(),
+                filter: filter.to_string(),
+                pad_color: None,
+                effect: None,
+                file: FileRef { name: "edge.png".to_string() },
+            });
+            let mut target = Pixmap::new(16, 1).unwrap();
+            assert!(renderer.render(&item, &mut target, 0, 0, 16, 1, 0, Path::new(".")));
+            target
+        };
+
+        // Nearest keeps a hard edge; bilinear blends across it
+        let nearest = render_with("nearest");
+        assert_eq!(nearest.pixel(7, 0).unwrap().red(), 0);
+        assert_eq!(nearest.pixel(8, 0).unwrap().red(), 255);
+
+        let bilinear = render_with("bilinear");
+        let left = bilinear.pixel(7, 0).unwrap().red();
+        let right = bilinear.pixel(8, 0).unwrap().red();
+        assert!(left > 0 && left < 255, "left edge {left}");
+        assert!(right > 0 && right < 255, "right edge {right}");
+        assert_ne!(nearest.pixel(7, 0), bilinear.pixel(7, 0));
+    }
+
+    #[test]
+    fn test_fit_pad_color_fills_margins() {
+        let render_fit = |pad_color: Option<&str>| {
+            let mut renderer = ImageRenderer::new();
+            renderer.cache.insert("tall.png".to_string(), tall_image());
+            let item = ContentItem::Image(ImageContent {
+                guid: "i1".to_string(),
+                name: String::new(),
+                start_delay_ms: 0,
+                fit: "fit".to_string(),
+                align: "center".to_string(),
+                filter: "nearest".to_string(),
+                pad_color: pad_color.map(str::to_string),
+                effect: None,
+                file: FileRef { name: "tall.png".to_string() },
+            });
+            // 4x16 fit into 16x16: columns 6-9 are picture, the rest margin
+            let mut target = Pixmap::new(16, 16).unwrap();
+            assert!(renderer.render(&item, &mut target, 0, 0, 16, 16, 0, Path::new(".")));
+            target
+        };
+
+        let padded = render_fit(Some("#000000"));
+        for x in [0, 5, 10, 15] {
+            let px = padded.pixel(x, 8).unwrap();
+            assert_eq!((px.red(), px.green(), px.blue(), px.alpha()), (0, 0, 0, 255), "col {x}");
+        }
+        assert_eq!(padded.pixel(8, 8).unwrap().blue(), 255);
+
+        // Without a pad color the margins stay transparent
+        let unpadded = render_fit(None);
+        assert_eq!(unpadded.pixel(0, 8).unwrap().alpha(), 0);
+    }
+}
@@ -4,12 +4,152 @@ pub mod image;
 pub mod text;
 pub mod video;
 
+use std::any::Any;
+use std::collections::HashMap;
+use std::path::Path;
 use tiny_skia::Pixmap;
 
-use crate::program::model::ContentItem;
+use crate::program::model::{ContentItem, ContentKind};
+
+/// Content kinds the player ships a renderer for
+pub const BUILTIN_KINDS: [ContentKind; 5] = [
+    ContentKind::Image,
+    ContentKind::Text,
+    ContentKind::Clock,
+    ContentKind::Gif,
+    ContentKind::Video,
+];
+
+/// The built-in renderer for `kind`
+pub fn builtin_renderer(kind: ContentKind) -> Box<dyn ContentRenderer> {
+    match kind {
+        ContentKind::Image => Box::new(image::ImageRenderer::new()),
+        ContentKind::Text => Box::new(text::TextRenderer::new()),
+        ContentKind::Clock => Box::new(clock::ClockRenderer::new()),
+        ContentKind::Gif => Box::new(gif::GifRenderer::new()),
+        ContentKind::Video => Box::new(video::VideoRenderer::new()),
+    }
+}
+
+/// Build a premultiplied pixmap from straight-alpha RGBA bytes. Channels are
+/// rounded rather than truncated so semi-transparent edges don't darken.
+pub fn pixmap_from_rgba(width: u32, height: u32, rgba: &[u8]) -> Option<Pixmap> {
+    let mut pixmap = Pixmap::new(width, height)?;
+    for (dst, src) in pixmap.data_mut().chunks_exact_mut(4).zip(rgba.chunks_exact(4)) {
+        let a = src[3] as u16;
+        for c in 0..3 {
+            dst[c] = ((src[c] as u16 * a + 127) / 255) as u8;
+        }
+        dst[3] = src[3];
+    }
+    Some(pixmap)
+}
+
+/// Media decoded for a [`PreloadRequest`], by filename. Each renderer
+/// downcasts the values to the type its `decode` produced.
+pub type Decoded = Vec<(String, Box<dyn Any + Send>)>;
+
+/// Media files a renderer wants decoded before a program first shows them
+pub struct PreloadRequest {
+    /// Files in the program directory that aren't cached yet
+    pub names: Vec<String>,
+    /// Decodes one file
+    pub decode: fn(&Path) -> Option<Box<dyn Any + Send>>,
+}
+
+impl PreloadRequest {
+    /// Request `names` that aren't cached yet, or None if there are none
+    pub fn missing<'a>(
+        names: impl IntoIterator<Item = &'a str>,
+        cached: impl Fn(&str) -> bool,
+        decode: fn(&Path) -> Option<Box<dyn Any + Send>>,
+    ) -> Option<Self> {
+        let mut names: Vec<String> =
+            names.into_iter().filter(|name| !cached(name)).map(str::to_string).collect();
+        names.sort_unstable();
+        names.dedup();
+        (!names.is_empty()).then_some(Self { names, decode })
+    }
+
+    /// Decode the files on worker threads, returning the ones that decoded.
+    /// Blocks until all are done, so keep it off the render loop.
+    pub fn decode(&self, program_dir: &Path) -> Decoded {
+        decode_parallel(&self.names, program_dir, self.decode)
+    }
+}
+
+/// Decode the files `names` in `program_dir` on worker threads, returning
+/// the ones that decoded
+fn decode_parallel<T: Send>(
+    names: &[String],
+    program_dir: &Path,
+    decode: fn(&Path) -> Option<T>,
+) -> Vec<(String, T)> {
+    let workers = std::thread::available_parallelism().map_or(2, |n| n.get());
+    let chunk = names.len().div_ceil(workers).max(1);
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = names
+            .chunks(chunk)
+            .map(|batch| {
+                scope.spawn(move || {
+                    let decode_one = |name: &String| {
+                        Some((name.to_string(), decode(&program_dir.join(name))?))
+                    };
+                    batch.iter().filter_map(decode_one).collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        handles.into_iter().flat_map(|h| h.join().unwrap_or_default()).collect()
+    })
+}
 
 /// Trait for content renderer plugins
 pub trait ContentRenderer {
+    /// Whether the item would look different from the last render at
+    /// `elapsed_ms`. When false the engine reuses the previous content surface
+    /// instead of calling `render`.
+    fn is_dirty(&mut self, _item: &ContentItem, _elapsed_ms: u64) -> bool {
+        true
+    }
+
+    /// Earliest time (ms) the item can look different from its render at
+    /// `elapsed_ms`, or None if it never changes. Until then the engine
+    /// reuses the area's last composite without consulting the renderer.
+    /// Defaults to every frame.
+    fn next_redraw_ms(&mut self, _item: &ContentItem, elapsed_ms: u64) -> Option<u64> {
+        Some(elapsed_ms)
+    }
+
+    /// Natural width (px) of the item when laid out `height` px tall, for
+    /// content whose size doesn't come from its area (e.g. scrolling text)
+    fn content_width(&mut self, _item: &ContentItem, _height: u32) -> Option<u32> {
+        None
+    }
+
+    /// Media referenced by `items` (all of this renderer's kind) that isn't
+    /// loaded yet. The engine decodes it off the render loop and passes the
+    /// result to `insert_preloaded`, so it doesn't stall the frame that shows it.
+    fn preload_request(&self, _items: &[&ContentItem]) -> Option<PreloadRequest> {
+        None
+    }
+
+    /// Cache media decoded for this renderer's `preload_request`
+    fn insert_preloaded(&mut self, _decoded: Decoded) {}
+
+    /// Length (ms) of one loop of the item's animation or video, if it has one
+    fn loop_duration_ms(
+        &mut self,
+        _item: &ContentItem,
+        _program_dir: &std::path::Path,
+    ) -> Option<u64> {
+        None
+    }
+
+    /// Scroll cycle length (px) of each scroll group in the current program,
+    /// set by the engine before every frame
+    fn set_scroll_groups(&mut self, _cycles: &HashMap<String, u64>) {}
+
+    #[allow(clippy::too_many_arguments)]
     fn render(
         &mut self,
         item: &ContentItem,
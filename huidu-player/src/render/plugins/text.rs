@@ -1,22 +1,132 @@
 /// Text content renderer plugin.
 /// Renders static and scrolling text using rusttype for font rasterization.
+/// Right-to-left text (Arabic, Hebrew) is bidi-reordered and shaped with
+/// rustybuzz first, so letters take their joined forms and read right to left.
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::path::Path;
 use tiny_skia::Pixmap;
 use tracing::debug;
 
 use crate::program::model::{parse_color, ContentItem, TextContent};
+use crate::render::effects::scroll_speed_px_per_sec;
 use crate::render::plugins::ContentRenderer;
 
 pub struct TextRenderer {
     font: rusttype::Font<'static>,
+    /// The same font, for shaping
+    face: rustybuzz::Face<'static>,
+    /// Cycle length (px) of each scroll group, from the engine
+    scroll_groups: HashMap<String, u64>,
 }
 
 impl TextRenderer {
     pub fn new() -> Self {
-        let font_data = include_bytes!("../../../assets/DejaVuSans.ttf");
-        let font = rusttype::Font::try_from_bytes(font_data as &[u8])
-            .expect("Failed to load built-in font");
-        Self { font }
+        let font_data: &'static [u8] = include_bytes!("../../../assets/DejaVuSans.ttf");
+        let font = rusttype::Font::try_from_bytes(font_data).expect("Failed to load built-in font");
+        let face = rustybuzz::Face::from_slice(font_data, 0).expect("Failed to load built-in font");
+        Self {
+            font,
+            face,
+            scroll_groups: HashMap::new(),
+        }
+    }
+
+    /// Font size and laid-out glyphs of the text, None if there is nothing to draw
+    fn layout(&self, text: &TextContent) -> Option<(f32, Vec<rusttype::PositionedGlyph<'_>>)> {
+        let content = text.string.as_deref().filter(|s| !s.is_empty())?;
+        let font_size = font_size(text);
+        let glyphs = self.layout_str(content, font_size);
+        (!glyphs.is_empty()).then_some((font_size, glyphs))
+    }
+
+    fn layout_str(&self, content: &str, font_size: f32) -> Vec<rusttype::PositionedGlyph<'_>> {
+        let scale = rusttype::Scale::uniform(font_size);
+        let v_metrics = self.font.v_metrics(scale);
+        if !needs_shaping(content) {
+            // Left-to-right text without joining scripts: one glyph per char
+            return self
+                .font
+                .layout(content, scale, rusttype::point(0.0, v_metrics.ascent))
+                .collect();
+        }
+        self.shape(content, scale, v_metrics.ascent)
+    }
+
+    /// Lay out text containing right-to-left scripts: split it into runs in
+    /// visual order, then shape each run so glyphs take their contextual forms
+    fn shape(
+        &self,
+        content: &str,
+        scale: rusttype::Scale,
+        ascent: f32,
+    ) -> Vec<rusttype::PositionedGlyph<'_>> {
+        let px_per_unit = self.font.scale_for_pixel_height(scale.y);
+        let bidi = unicode_bidi::BidiInfo::new(content, None);
+        let mut glyphs = Vec::new();
+        let mut x = 0.0;
+        for paragraph in &bidi.paragraphs {
+            let (levels, runs) = bidi.visual_runs(paragraph, paragraph.range.clone());
+            for run in runs {
+                let mut buffer = rustybuzz::UnicodeBuffer::new();
+                buffer.push_str(&content[run.clone()]);
+                buffer.set_direction(if levels[run.start].is_rtl() {
+                    rustybuzz::Direction::RightToLeft
+                } else {
+                    rustybuzz::Direction::LeftToRight
+                });
+                buffer.guess_segment_properties();
+                // Shaped glyphs come out in visual (left-to-right) order
+                let shaped = rustybuzz::shape(&self.face, &[], buffer);
+                for (info, pos) in shaped.glyph_infos().iter().zip(shaped.glyph_positions()) {
+                    let position = rusttype::point(
+                        x + pos.x_offset as f32 * px_per_unit,
+                        ascent - pos.y_offset as f32 * px_per_unit,
+                    );
+                    let glyph = self.font.glyph(rusttype::GlyphId(info.glyph_id as u16));
+                    glyphs.push(glyph.scaled(scale).positioned(position));
+                    x += pos.x_advance as f32 * px_per_unit;
+                }
+            }
+        }
+        glyphs
+    }
+
+    /// The text as displayed in `width` px: for `overflow="ellipsis"` text that
+    /// doesn't fit, the longest prefix that fits followed by "…"
+    fn displayed_text<'a>(&self, text: &'a TextContent, width: u32) -> Option<Cow<'a, str>> {
+        let content = text.string.as_deref().filter(|s| !s.is_empty())?;
+        if text.overflow != "ellipsis" {
+            return Some(Cow::Borrowed(content));
+        }
+        let font_size = font_size(text);
+        let glyphs = self.layout_str(content, font_size);
+        let width = width as i32;
+        if glyphs_width(&glyphs, font_size) <= width {
+            return Some(Cow::Borrowed(content));
+        }
+
+        let ellipsis_width = glyphs_width(&self.layout_str(ELLIPSIS, font_size), font_size);
+        let keep = if needs_shaping(content) {
+            // Shaped glyphs don't map one-to-one onto chars: measure each prefix
+            let char_count = content.chars().count();
+            (0..char_count)
+                .rev()
+                .find(|&k| {
+                    let prefix: String = content.chars().take(k).collect();
+                    let prefix = self.layout_str(&prefix, font_size);
+                    glyphs_width(&prefix, font_size) + ellipsis_width <= width
+                })
+                .unwrap_or(0)
+        } else {
+            // One glyph per char: glyph k starts where the first k chars end
+            (0..glyphs.len())
+                .rev()
+                .find(|&k| glyphs[k].position().x as i32 + ellipsis_width <= width)
+                .unwrap_or(0)
+        };
+        let prefix: String = content.chars().take(keep).collect();
+        Some(Cow::Owned(format!("{}{ELLIPSIS}", prefix.trim_end())))
     }
 
     fn render_text_content(
@@ -27,47 +137,27 @@ impl TextRenderer {
         height: u32,
         elapsed_ms: u64,
     ) {
-        let content = match &text.string {
-            Some(s) => s.as_str(),
-            None => return,
-        };
-
-        if content.is_empty() {
+        if !text.flash_visible(elapsed_ms) {
             return;
         }
+        let Some(content) = self.displayed_text(text, width) else {
+            return;
+        };
+        let font_size = font_size(text);
+        let glyphs = self.layout_str(&content, font_size);
 
         // Get font properties
-        let font_spec = text.font.as_ref();
-        let font_size = font_spec.map(|f| f.size).unwrap_or(12.0);
-        let (r, g, b) = font_spec
+        let (r, g, b) = text
+            .font
+            .as_ref()
             .map(|f| parse_color(&f.color))
             .unwrap_or((255, 0, 0));
 
-        let scale = rusttype::Scale::uniform(font_size);
-        let v_metrics = self.font.v_metrics(scale);
+        let v_metrics = self.font.v_metrics(rusttype::Scale::uniform(font_size));
         let line_height = (v_metrics.ascent - v_metrics.descent + v_metrics.line_gap).ceil() as i32;
 
-        // Layout glyphs
-        let glyphs: Vec<_> = self
-            .font
-            .layout(content, scale, rusttype::point(0.0, v_metrics.ascent))
-            .collect();
-
-        if glyphs.is_empty() {
-            return;
-        }
-
         // Calculate text bounding box
-        let text_width = glyphs
-            .last()
-            .map(|g| {
-                if let Some(bb) = g.pixel_bounding_box() {
-                    bb.max.x
-                } else {
-                    g.position().x as i32 + (font_size * 0.6) as i32
-                }
-            })
-            .unwrap_or(0);
+        let text_width = glyphs_width(&glyphs, font_size);
 
         // Calculate alignment offset
         let style = text.style.as_ref();
@@ -86,62 +176,203 @@ impl TextRenderer {
             _ => ((height as i32 - line_height) / 2).max(0),
         };
 
-        // Handle single-line scrolling
-        let scroll_offset = if text.single_line && text_width > width as i32 {
-            let total_scroll = text_width + width as i32;
-            let scroll_period_ms = (total_scroll as u64 * 1000) / 50;
-            let progress = (elapsed_ms % scroll_period_ms) as i32;
-            let scroll_px = (progress as f64 * 50.0 / 1000.0) as i32;
-            width as i32 - scroll_px
-        } else {
+        // Handle single-line scrolling. Grouped text always scrolls, on the
+        // group's shared cycle, so the areas read as one ticker.
+        let group_cycle = text.scroll_group.as_ref().and_then(|g| self.scroll_groups.get(g));
+        let needs_scroll = group_cycle.is_some() || text_width > width as i32;
+        let rightward = text.scroll_dir == "right";
+        let scroll_offset = if !(text.scrolls() && needs_scroll) {
             offset_x
+        } else if let Some(&cycle) = group_cycle {
+            scroll_offset(text_width, width as i32, elapsed_ms, text.scroll_speed, rightward, cycle)
+        } else if text.scroll_pause_ms > 0 {
+            paused_scroll_offset(
+                text_width,
+                width as i32,
+                elapsed_ms,
+                text.scroll_speed,
+                rightward,
+                text.scroll_pause_ms,
+            )
+        } else {
+            let cycle = (text_width + width as i32) as u64;
+            scroll_offset(text_width, width as i32, elapsed_ms, text.scroll_speed, rightward, cycle)
         };
 
-        // Get dimensions before mutable borrow
-        let tw = target.width() as i32;
-        let th = target.height() as i32;
-        let data = target.data_mut();
-
-        // Rasterize each glyph
-        for glyph in &glyphs {
-            if let Some(bb) = glyph.pixel_bounding_box() {
-                glyph.draw(|gx, gy, v| {
-                    let px = scroll_offset + bb.min.x + gx as i32;
-                    let py = offset_y + bb.min.y + gy as i32;
-
-                    if px >= 0 && px < tw && py >= 0 && py < th {
-                        let alpha = (v * 255.0) as u8;
-                        if alpha > 0 {
-                            let idx = ((py * tw + px) * 4) as usize;
-                            let a = alpha as f32 / 255.0;
-                            let dst_a = data[idx + 3] as f32 / 255.0;
-                            let out_a = a + dst_a * (1.0 - a);
-                            if out_a > 0.0 {
-                                data[idx] = ((r as f32 * a
-                                    + data[idx] as f32 * dst_a * (1.0 - a))
-                                    / out_a) as u8;
-                                data[idx + 1] = ((g as f32 * a
-                                    + data[idx + 1] as f32 * dst_a * (1.0 - a))
-                                    / out_a) as u8;
-                                data[idx + 2] = ((b as f32 * a
-                                    + data[idx + 2] as f32 * dst_a * (1.0 - a))
-                                    / out_a) as u8;
-                                data[idx + 3] = (out_a * 255.0) as u8;
-                            }
-                        }
-                    }
-                });
-            }
+        // The shadow goes down first so the text covers it
+        let shadow = text.font.as_ref().and_then(|f| {
+            let color = f.shadow_color.as_deref()?;
+            Some((parse_color(color), f.shadow_offset_x, f.shadow_offset_y))
+        });
+        if let Some((color, dx, dy)) = shadow {
+            draw_glyphs(target, &glyphs, scroll_offset + dx, offset_y + dy, color);
         }
+        draw_glyphs(target, &glyphs, scroll_offset, offset_y, (r, g, b));
 
         debug!(
             "Rendered text '{}' ({}x{}) at offset ({}, {})",
-            content, text_width, line_height, scroll_offset, offset_y
+            content,
+            text_width,
+            line_height,
+            scroll_offset,
+            offset_y
         );
     }
 }
 
+/// Blend the glyphs into `target` in one color, with the line's origin at (x, y)
+fn draw_glyphs(
+    target: &mut Pixmap,
+    glyphs: &[rusttype::PositionedGlyph<'_>],
+    x: i32,
+    y: i32,
+    (r, g, b): (u8, u8, u8),
+) {
+    let tw = target.width() as i32;
+    let th = target.height() as i32;
+    let data = target.data_mut();
+
+    for glyph in glyphs {
+        if let Some(bb) = glyph.pixel_bounding_box() {
+            glyph.draw(|gx, gy, v| {
+                let px = x + bb.min.x + gx as i32;
+                let py = y + bb.min.y + gy as i32;
+
+                if px >= 0 && px < tw && py >= 0 && py < th {
+                    let alpha = (v * 255.0) as u8;
+                    if alpha > 0 {
+                        let idx = ((py * tw + px) * 4) as usize;
+                        let a = alpha as f32 / 255.0;
+                        let dst_a = data[idx + 3] as f32 / 255.0;
+                        let out_a = a + dst_a * (1.0 - a);
+                        if out_a > 0.0 {
+                            data[idx] = ((r as f32 * a
+                                + data[idx] as f32 * dst_a * (1.0 - a))
+                                / out_a) as u8;
+                            data[idx + 1] = ((g as f32 * a
+                                + data[idx + 1] as f32 * dst_a * (1.0 - a))
+                                / out_a) as u8;
+                            data[idx + 2] = ((b as f32 * a
+                                + data[idx + 2] as f32 * dst_a * (1.0 - a))
+                                / out_a) as u8;
+                            data[idx + 3] = (out_a * 255.0) as u8;
+                        }
+                    }
+                }
+            });
+        }
+    }
+}
+
+/// Marks text cut short by `overflow="ellipsis"`
+const ELLIPSIS: &str = "…";
+
+/// Whether the text has right-to-left characters, which need bidi reordering
+/// and shaping. Everything else takes rusttype's plain layout.
+fn needs_shaping(content: &str) -> bool {
+    use unicode_bidi::BidiClass::{AL, R};
+    content.chars().any(|c| matches!(unicode_bidi::bidi_class(c), R | AL))
+}
+
+fn font_size(text: &TextContent) -> f32 {
+    text.font.as_ref().map(|f| f.size).unwrap_or(12.0)
+}
+
+/// Pixel width of laid-out text
+fn glyphs_width(glyphs: &[rusttype::PositionedGlyph<'_>], font_size: f32) -> i32 {
+    glyphs
+        .last()
+        .map(|g| {
+            if let Some(bb) = g.pixel_bounding_box() {
+                bb.max.x
+            } else {
+                g.position().x as i32 + (font_size * 0.6) as i32
+            }
+        })
+        .unwrap_or(0)
+}
+
+/// X offset of single-line scrolling text. The text enters from one edge and
+/// leaves by the other, then the cycle repeats every `cycle_px` of scrolling
+/// (text plus area width, or a scroll group's combined width).
+fn scroll_offset(
+    text_width: i32,
+    width: i32,
+    elapsed_ms: u64,
+    speed: u8,
+    rightward: bool,
+    cycle_px: u64,
+) -> i32 {
+    let total_scroll = cycle_px.max(1);
+    let scroll_px = (elapsed_ms * scroll_speed_px_per_sec(speed) / 1000 % total_scroll) as i32;
+    if rightward {
+        scroll_px - text_width
+    } else {
+        width - scroll_px
+    }
+}
+
+/// X offset of single-line scrolling text that pauses at its ends: it holds
+/// for `pause_ms` with its start flush against the leading edge, scrolls until
+/// its end is flush against the trailing edge, holds again, then starts over
+fn paused_scroll_offset(
+    text_width: i32,
+    width: i32,
+    elapsed_ms: u64,
+    speed: u8,
+    rightward: bool,
+    pause_ms: u64,
+) -> i32 {
+    let travel_px = (text_width - width).max(0) as u64;
+    let px_per_sec = scroll_speed_px_per_sec(speed).max(1);
+    let travel_ms = travel_px * 1000 / px_per_sec;
+    let t = elapsed_ms % (2 * pause_ms + travel_ms).max(1);
+    let moved = if t < pause_ms {
+        0
+    } else {
+        ((t - pause_ms) * px_per_sec / 1000).min(travel_px)
+    } as i32;
+    if rightward {
+        // Reads from the end back to the start
+        width - text_width + moved
+    } else {
+        -moved
+    }
+}
+
 impl ContentRenderer for TextRenderer {
+    fn is_dirty(&mut self, item: &ContentItem, _elapsed_ms: u64) -> bool {
+        // Only scrolling or flashing text changes; anything else is static.
+        // Flashing text is only asked at its toggles (see next_redraw_ms).
+        matches!(item, ContentItem::Text(t) if t.scrolls() || t.flash)
+    }
+
+    fn next_redraw_ms(&mut self, item: &ContentItem, elapsed_ms: u64) -> Option<u64> {
+        let ContentItem::Text(text) = item else {
+            return None;
+        };
+        if text.scrolls() {
+            Some(elapsed_ms)
+        } else if text.flash {
+            let half = text.flash_half_period_ms();
+            Some((elapsed_ms / half + 1) * half)
+        } else {
+            None
+        }
+    }
+
+    fn content_width(&mut self, item: &ContentItem, _height: u32) -> Option<u32> {
+        let ContentItem::Text(text) = item else {
+            return None;
+        };
+        let (font_size, glyphs) = self.layout(text)?;
+        Some(glyphs_width(&glyphs, font_size).max(0) as u32)
+    }
+
+    fn set_scroll_groups(&mut self, cycles: &HashMap<String, u64>) {
+        self.scroll_groups.clone_from(cycles);
+    }
+
     fn render(
         &mut self,
         item: &ContentItem,
@@ -162,3 +393,159 @@ impl ContentRenderer for TextRenderer {
         true
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scroll_speed_level_sets_pace() {
+        let delta = |speed| {
+            scroll_offset(400, 100, 1000, speed, false, 500)
+                - scroll_offset(400, 100, 0, speed, false, 500)
+        };
+        // Level 0 is 100 px/sec, level 8 is 8 px/sec
+        assert_eq!(delta(0), -100);
+        assert_eq!(delta(8), -8);
+        assert!(delta(0).abs() > delta(8).abs());
+    }
+
+    #[test]
+    fn test_ellipsis_overflow_truncates() {
+        let screen = crate::program::parser::parse_program_xml(
+            r#"<screen><program guid="p"><area guid="a"><rectangle width="60" height="16"/>
+              <resources><text guid="t" singleLine="true" overflow="ellipsis">
+                <string>A long single-line label that cannot fit</string><font size="12"/>
+              </text></resources></area></program></screen>"#,
+        )
+        .unwrap();
+        let item = &screen.programs[0].areas[0].resources.items[0];
+        let ContentItem::Text(text) = item else {
+            panic!("expected text");
+        };
+        let mut renderer = TextRenderer::new();
+
+        let shown = renderer.displayed_text(text, 60).unwrap();
+        assert!(shown.ends_with('…'), "{shown}");
+        assert!(shown.starts_with("A long"), "{shown}");
+        let width = glyphs_width(&renderer.layout_str(&shown, 12.0), 12.0);
+        assert!(width <= 60, "{shown} is {width}px");
+
+        // Fits: left alone, and nothing scrolls
+        assert_eq!(renderer.displayed_text(text, 1000).unwrap(), text.string.as_deref().unwrap());
+        assert!(!renderer.is_dirty(item, 0));
+        assert_eq!(renderer.next_redraw_ms(item, 0), None);
+    }
+
+    #[test]
+    fn test_flash_toggles_text() {
+        let screen = crate::program::parser::parse_program_xml(
+            r##"<screen><program guid="p"><area guid="a"><rectangle width="64" height="16"/>
+              <resources><text guid="t" flash="true" flashSpeed="10">
+                <string>ALERT</string><font size="12" color="#FFFFFF"/>
+              </text></resources></area></program></screen>"##,
+        )
+        .unwrap();
+        let item = &screen.programs[0].areas[0].resources.items[0];
+        let mut renderer = TextRenderer::new();
+        let drawn = |renderer: &mut TextRenderer, elapsed_ms| {
+            let mut target = Pixmap::new(64, 16).unwrap();
+            renderer.render(item, &mut target, 0, 0, 64, 16, elapsed_ms, Path::new("."));
+            target.data().chunks_exact(4).any(|px| px[3] > 0)
+        };
+
+        // One blink per second: shown for 500ms, hidden for 500ms
+        assert!(drawn(&mut renderer, 100));
+        assert!(!drawn(&mut renderer, 600));
+        assert!(drawn(&mut renderer, 1100));
+
+        // Redrawn at each toggle, not every frame
+        assert!(renderer.is_dirty(item, 100));
+        assert_eq!(renderer.next_redraw_ms(item, 100), Some(500));
+        assert_eq!(renderer.next_redraw_ms(item, 600), Some(1000));
+    }
+
+    #[test]
+    fn test_shadow_drawn_at_offset() {
+        let render = |font: &str| {
+            let xml = format!(
+                r#"<screen><program guid="p"><area guid="a"><rectangle width="64" height="24"/>
+                  <resources><text guid="t"><string>HI</string>{font}</text></resources>
+                  </area></program></screen>"#
+            );
+            let screen = crate::program::parser::parse_program_xml(&xml).unwrap();
+            let item = &screen.programs[0].areas[0].resources.items[0];
+            let mut target = Pixmap::new(64, 24).unwrap();
+            TextRenderer::new().render(item, &mut target, 0, 0, 64, 24, 0, Path::new("."));
+            target
+        };
+        // Bottom-most row holding a pixel of exactly this color
+        let lowest_row = |target: &Pixmap, rgb: [u8; 3]| {
+            let pixels = target.data().chunks_exact(4).enumerate();
+            pixels.filter(|(_, px)| px[..3] == rgb).map(|(i, _)| i / 64).max()
+        };
+
+        let plain = render(r##"<font size="12" color="#FF0000"/>"##);
+        assert_eq!(lowest_row(&plain, [0, 0, 255]), None);
+
+        let shadowed = render(
+            r##"<font size="12" color="#FF0000" shadowColor="#0000FF" shadowOffsetX="2" shadowOffsetY="3"/>"##,
+        );
+        let text_bottom = lowest_row(&shadowed, [255, 0, 0]).unwrap();
+        assert_eq!(lowest_row(&shadowed, [0, 0, 255]), Some(text_bottom + 3));
+    }
+
+    #[test]
+    fn test_arabic_is_shaped_right_to_left() {
+        let renderer = TextRenderer::new();
+        // "bayt" (house): beh, yeh, teh, all joining on both sides
+        let glyphs = renderer.layout_str("بيت", 16.0);
+        assert_eq!(glyphs.len(), 3);
+        assert!(glyphs.iter().all(|g| g.id().0 != 0), "missing glyphs");
+
+        let isolated = |c: char| renderer.font.glyph(c).id();
+        // Read right to left: beh is drawn rightmost, in its initial form, and
+        // yeh in the middle in its medial form, rather than standalone
+        let mut by_x: Vec<_> = glyphs.iter().collect();
+        by_x.sort_by(|a, b| a.position().x.total_cmp(&b.position().x));
+        let (beh, yeh) = (isolated('ب'), isolated('ي'));
+        assert!(by_x[2].id() != beh && by_x[2].id() != isolated('ت'), "{:?}", by_x[2].id());
+        assert!(by_x[1].id() != yeh && by_x[1].id() != beh, "{:?}", by_x[1].id());
+        // A letter on its own keeps the standalone form
+        assert_eq!(renderer.layout_str("ب", 16.0)[0].id(), isolated('ب'));
+
+        // Latin text keeps the plain one-glyph-per-char layout
+        assert!(!needs_shaping("SALE 50%"));
+        assert!(needs_shaping("SALE بيت"));
+    }
+
+    #[test]
+    fn test_scroll_pauses_at_ends() {
+        // 300px text in a 100px area at 50 px/sec: 1s pause, 4s travel, 1s pause
+        let offset = |t| paused_scroll_offset(300, 100, t, 3, false, 1000);
+        for t in [0, 500, 999] {
+            assert_eq!(offset(t), 0, "at {t}ms");
+        }
+        assert_eq!(offset(2000), -50);
+        for t in [5000, 5500, 5999] {
+            assert_eq!(offset(t), -200, "at {t}ms");
+        }
+        assert_eq!(offset(6000), 0);
+
+        let rightward = |t| paused_scroll_offset(300, 100, t, 3, true, 1000);
+        assert_eq!(rightward(500), -200);
+        assert_eq!(rightward(5500), 0);
+    }
+
+    #[test]
+    fn test_scroll_directions_loop() {
+        // 400px text through a 100px area at 50 px/sec: a cycle is 10 seconds
+        assert_eq!(scroll_offset(400, 100, 0, 3, false, 500), 100);
+        assert_eq!(scroll_offset(400, 100, 9_980, 3, false, 500), -399);
+        assert_eq!(scroll_offset(400, 100, 10_000, 3, false, 500), 100);
+
+        assert_eq!(scroll_offset(400, 100, 0, 3, true, 500), -400);
+        assert_eq!(scroll_offset(400, 100, 9_980, 3, true, 500), 99);
+        assert_eq!(scroll_offset(400, 100, 10_000, 3, true, 500), -400);
+    }
+}
@@ -1,24 +1,38 @@
 /// Video content renderer plugin.
 /// Displays the first frame of a video as a still image.
 /// Full video decoding would require gstreamer or ffmpeg integration.
+///
+/// Extracted frames are kept as JPEGs in `<program_dir>/.thumbnails`, named by
+/// video filename and modification time, so restarts don't re-run ffmpeg for
+/// every video and a replaced video gets a fresh thumbnail.
 use std::collections::HashMap;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process::Command;
-use tiny_skia::{Pixmap, PixmapPaint, Transform};
+use std::time::UNIX_EPOCH;
+use tiny_skia::{Color, Pixmap, PixmapPaint, Transform};
 use tracing::{debug, warn};
 
-use crate::program::model::ContentItem;
-use crate::render::plugins::ContentRenderer;
+use crate::program::model::{parse_color, ContentItem, VideoContent};
+use crate::render::plugins::{pixmap_from_rgba, ContentRenderer};
+
+/// Writes the first frame of the video at the first path to an image file at
+/// the second; returns false if nothing was written
+type FrameExtractor = Box<dyn Fn(&Path, &Path) -> bool + Send>;
 
 pub struct VideoRenderer {
     /// Cache first frame thumbnails
     thumbnails: HashMap<String, Option<Pixmap>>,
+    extract: FrameExtractor,
+    /// Cache of video durations (ms) from ffprobe
+    durations: HashMap<String, Option<u64>>,
 }
 
 impl VideoRenderer {
     pub fn new() -> Self {
         Self {
             thumbnails: HashMap::new(),
+            extract: Box::new(ffmpeg_first_frame),
+            durations: HashMap::new(),
         }
     }
 
@@ -30,7 +44,7 @@ impl VideoRenderer {
         self.thumbnails.get(filename).and_then(|t| t.as_ref())
     }
 
-    /// Try to extract the first frame using ffmpeg CLI
+    /// Load the first frame from the disk cache, extracting it if missing or stale
     fn extract_first_frame(&self, filename: &str, program_dir: &Path) -> Option<Pixmap> {
         let video_path = program_dir.join(filename);
         if !video_path.exists() {
@@ -38,51 +52,32 @@ impl VideoRenderer {
             return None;
         }
 
-        // Try ffmpeg to extract first frame as PNG to temp file
-        let temp_path = std::env::temp_dir().join(format!("huidu_thumb_{}.png", md5_hash(filename)));
-
-        let result = Command::new("ffmpeg")
-            .args([
-                "-y",
-                "-i", &video_path.to_string_lossy(),
-                "-vframes", "1",
-                "-f", "image2",
-                &temp_path.to_string_lossy(),
-            ])
-            .stdout(std::process::Stdio::null())
-            .stderr(std::process::Stdio::null())
-            .status();
-
-        match result {
-            Ok(status) if status.success() => {
-                debug!("Extracted video thumbnail: {}", temp_path.display());
-                // Load the extracted PNG
-                match image::open(&temp_path) {
-                    Ok(img) => {
-                        let rgba = img.to_rgba8();
-                        let (w, h) = (rgba.width(), rgba.height());
-                        if let Some(mut pixmap) = Pixmap::new(w, h) {
-                            let data = pixmap.data_mut();
-                            for (i, pixel) in rgba.pixels().enumerate() {
-                                let a = pixel[3] as f32 / 255.0;
-                                data[i * 4] = (pixel[0] as f32 * a) as u8;
-                                data[i * 4 + 1] = (pixel[1] as f32 * a) as u8;
-                                data[i * 4 + 2] = (pixel[2] as f32 * a) as u8;
-                                data[i * 4 + 3] = pixel[3];
-                            }
-                            let _ = std::fs::remove_file(&temp_path);
-                            return Some(pixmap);
-                        }
-                    }
-                    Err(e) => warn!("Failed to load extracted frame: {}", e),
-                }
-                let _ = std::fs::remove_file(&temp_path);
-            }
-            Ok(_) => {
-                debug!("ffmpeg failed to extract frame from {}", filename);
+        let cache_path = thumbnail_path(program_dir, filename, &video_path);
+        if let Some(path) = &cache_path
+            && let Some(pixmap) = load_pixmap(path)
+        {
+            debug!("Using cached video thumbnail: {}", path.display());
+            return Some(pixmap);
+        }
+
+        // Without an mtime there's nothing to key the cache on; go via a temp file
+        let out_path = cache_path.clone().unwrap_or_else(|| {
+            std::env::temp_dir().join(format!("huidu_thumb_{}.jpg", md5_hash(filename)))
+        });
+        if let Some(parent) = out_path.parent() {
+            let _ = std::fs::create_dir_all(parent);
+        }
+
+        if (self.extract)(&video_path, &out_path) {
+            debug!("Extracted video thumbnail: {}", out_path.display());
+            let pixmap = load_pixmap(&out_path);
+            if cache_path.is_some() && pixmap.is_some() {
+                remove_stale_thumbnails(&out_path, filename);
+            } else {
+                let _ = std::fs::remove_file(&out_path);
             }
-            Err(_) => {
-                debug!("ffmpeg not available, video thumbnail extraction disabled");
+            if pixmap.is_some() {
+                return pixmap;
             }
         }
 
@@ -126,11 +121,177 @@ impl VideoRenderer {
     }
 }
 
+/// Draw one video frame into the area. Shared by the thumbnail path and any
+/// future decoded-frame path so both place the frame the same way.
+fn draw_video_frame(video: &VideoContent, frame: &Pixmap, target: &mut Pixmap, width: u32, height: u32) {
+    let fit = video.fit_mode();
+    if matches!(fit, "fit" | "center") {
+        // These can leave parts of the area uncovered. Physical signs show
+        // solid bars, so paint them rather than leaving the area background
+        // to bleed through
+        let (r, g, b) = parse_color(&video.letterbox_color);
+        target.fill(Color::from_rgba8(r, g, b, 255));
+    }
+
+    let (sx, sy, offset_x, offset_y) = placement(fit, frame.width(), frame.height(), width, height);
+    target.draw_pixmap(
+        0, 0,
+        frame.as_ref(),
+        &PixmapPaint::default(),
+        Transform::from_scale(sx, sy).post_translate(offset_x, offset_y),
+        None,
+    );
+}
+
+/// Scale factors and offset of a `frame_w` x `frame_h` frame in the area for a
+/// fit mode, matching the image fit modes: stretch distorts to cover the area,
+/// fit scales to fit whole, fill scales to cover and crops, center doesn't scale.
+/// Everything but stretch is centered.
+fn placement(
+    fit: &str,
+    frame_w: u32,
+    frame_h: u32,
+    width: u32,
+    height: u32,
+) -> (f32, f32, f32, f32) {
+    let scale_x = width as f32 / frame_w as f32;
+    let scale_y = height as f32 / frame_h as f32;
+
+    let (sx, sy) = match fit {
+        "stretch" => (scale_x, scale_y),
+        "fill" => {
+            let s = scale_x.max(scale_y);
+            (s, s)
+        }
+        "center" => (1.0, 1.0),
+        _ => {
+            let s = scale_x.min(scale_y);
+            (s, s)
+        }
+    };
+
+    let offset_x = (width as f32 - frame_w as f32 * sx) / 2.0;
+    let offset_y = (height as f32 - frame_h as f32 * sy) / 2.0;
+    (sx, sy, offset_x, offset_y)
+}
+
+/// Extract the first frame with the ffmpeg CLI
+fn ffmpeg_first_frame(video_path: &Path, out_path: &Path) -> bool {
+    let result = Command::new("ffmpeg")
+        .args([
+            "-y",
+            "-i", &video_path.to_string_lossy(),
+            "-vframes", "1",
+            "-q:v", "2",
+            "-f", "image2",
+            &out_path.to_string_lossy(),
+        ])
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .status();
+
+    match result {
+        Ok(status) if status.success() => true,
+        Ok(_) => {
+            debug!("ffmpeg failed to extract frame from {}", video_path.display());
+            false
+        }
+        Err(_) => {
+            debug!("ffmpeg not available, video thumbnail extraction disabled");
+            false
+        }
+    }
+}
+
+/// Duration of a video in ms, read with the ffprobe CLI
+fn ffprobe_duration_ms(video_path: &Path) -> Option<u64> {
+    let output = Command::new("ffprobe")
+        .args([
+            "-v", "error",
+            "-show_entries", "format=duration",
+            "-of", "default=noprint_wrappers=1:nokey=1",
+        ])
+        .arg(video_path)
+        .stderr(std::process::Stdio::null())
+        .output();
+
+    match output {
+        Ok(output) if output.status.success() => {
+            let secs: f64 = String::from_utf8_lossy(&output.stdout).trim().parse().ok()?;
+            (secs > 0.0).then(|| (secs * 1000.0).round() as u64)
+        }
+        Ok(_) => {
+            debug!("ffprobe failed to read duration of {}", video_path.display());
+            None
+        }
+        Err(_) => {
+            debug!("ffprobe not available, video loop counts disabled");
+            None
+        }
+    }
+}
+
+/// Cache file for a video's thumbnail: `<md5(filename)>_<mtime>.jpg`
+fn thumbnail_path(program_dir: &Path, filename: &str, video_path: &Path) -> Option<PathBuf> {
+    let modified = std::fs::metadata(video_path).ok()?.modified().ok()?;
+    let mtime = modified.duration_since(UNIX_EPOCH).ok()?.as_secs();
+    Some(
+        program_dir
+            .join(".thumbnails")
+            .join(format!("{}_{}.jpg", md5_hash(filename), mtime)),
+    )
+}
+
+/// Delete thumbnails of older versions of `filename`, keeping `current`
+fn remove_stale_thumbnails(current: &Path, filename: &str) {
+    let Some(dir) = current.parent() else {
+        return;
+    };
+    let prefix = format!("{}_", md5_hash(filename));
+    for entry in std::fs::read_dir(dir).into_iter().flatten().flatten() {
+        let path = entry.path();
+        let name = entry.file_name();
+        if path != current && name.to_string_lossy().starts_with(&prefix) {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+fn load_pixmap(path: &Path) -> Option<Pixmap> {
+    match image::open(path) {
+        Ok(img) => {
+            let rgba = img.to_rgba8();
+            pixmap_from_rgba(rgba.width(), rgba.height(), &rgba)
+        }
+        Err(e) => {
+            if path.exists() {
+                warn!("Failed to load video thumbnail {}: {}", path.display(), e);
+            }
+            None
+        }
+    }
+}
+
 fn md5_hash(s: &str) -> String {
     format!("{:x}", md5::compute(s.as_bytes()))
 }
 
 impl ContentRenderer for VideoRenderer {
+    fn next_redraw_ms(&mut self, _item: &ContentItem, _elapsed_ms: u64) -> Option<u64> {
+        // Only the still first frame is shown
+        None
+    }
+
+    fn loop_duration_ms(&mut self, item: &ContentItem, program_dir: &Path) -> Option<u64> {
+        let ContentItem::Video(video) = item else {
+            return None;
+        };
+        *self
+            .durations
+            .entry(video.file.name.clone())
+            .or_insert_with(|| ffprobe_duration_ms(&program_dir.join(&video.file.name)))
+    }
+
     fn render(
         &mut self,
         item: &ContentItem,
@@ -152,27 +313,112 @@ impl ContentRenderer for VideoRenderer {
             None => return false,
         };
 
-        let scale_x = width as f32 / thumb.width() as f32;
-        let scale_y = height as f32 / thumb.height() as f32;
+        draw_video_frame(video, thumb, target, width, height);
+        true
+    }
+}
 
-        let (sx, sy) = if video.aspect_ratio {
-            let s = scale_x.min(scale_y);
-            (s, s)
-        } else {
-            (scale_x, scale_y)
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::program::model::FileRef;
+
+    #[test]
+    fn test_letterbox_bars_use_fill_color() {
+        let mut renderer = VideoRenderer::new();
+        let mut thumb = Pixmap::new(16, 4).unwrap();
+        thumb.fill(Color::WHITE);
+        renderer.thumbnails.insert("wide.mp4".to_string(), Some(thumb));
+
+        let item = ContentItem::Video(VideoContent {
+            guid: "v1".to_string(),
+            name: String::new(),
+            start_delay_ms: 0,
+            aspect_ratio: true,
+            fit: None,
+            letterbox_color: "#000000".to_string(),
+            loops: None,
+            file: FileRef { name: "wide.mp4".to_string() },
+        });
+
+        let mut target = Pixmap::new(8, 8).unwrap();
+        assert!(renderer.render(&item, &mut target, 0, 0, 8, 8, 0, Path::new(".")));
+
+        let px = |x: u32, y: u32| target.pixel(x, y).unwrap();
+        // 16x4 scaled to 8x2, centered vertically: rows 3-4 are picture, the rest bars
+        for y in [0, 1, 2, 5, 6, 7] {
+            let bar = px(4, y);
+            assert_eq!((bar.red(), bar.green(), bar.blue(), bar.alpha()), (0, 0, 0, 255), "row {y}");
+        }
+        let picture = px(4, 3);
+        assert_eq!((picture.red(), picture.alpha()), (255, 255));
+    }
+
+    #[test]
+    fn test_fit_mode_placement() {
+        // A 16x4 frame in an 8x8 area
+        assert_eq!(placement("stretch", 16, 4, 8, 8), (0.5, 2.0, 0.0, 0.0));
+        assert_eq!(placement("fit", 16, 4, 8, 8), (0.5, 0.5, 0.0, 3.0));
+        assert_eq!(placement("fill", 16, 4, 8, 8), (2.0, 2.0, -12.0, 0.0));
+        assert_eq!(placement("center", 16, 4, 8, 8), (1.0, 1.0, -4.0, 2.0));
+    }
+
+    #[test]
+    fn test_aspect_ratio_is_fit_alias() {
+        let mut video = VideoContent {
+            guid: "v1".to_string(),
+            name: String::new(),
+            start_delay_ms: 0,
+            aspect_ratio: true,
+            fit: None,
+            letterbox_color: "#000000".to_string(),
+            loops: None,
+            file: FileRef { name: "a.mp4".to_string() },
         };
+        assert_eq!(video.fit_mode(), "fit");
+        video.aspect_ratio = false;
+        assert_eq!(video.fit_mode(), "stretch");
+        video.fit = Some("fill".to_string());
+        assert_eq!(video.fit_mode(), "fill");
+    }
 
-        let offset_x = ((width as f32 - thumb.width() as f32 * sx) / 2.0) as i32;
-        let offset_y = ((height as f32 - thumb.height() as f32 * sy) / 2.0) as i32;
+    #[test]
+    fn test_thumbnail_persists_across_restarts() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
 
-        target.draw_pixmap(
-            offset_x, offset_y,
-            thumb.as_ref(),
-            &PixmapPaint::default(),
-            Transform::from_scale(sx, sy),
-            None,
-        );
+        let dir = tempfile::tempdir().unwrap();
+        let video = dir.path().join("clip.mp4");
+        std::fs::write(&video, b"not really a video").unwrap();
 
-        true
+        let calls = Arc::new(AtomicUsize::new(0));
+        let renderer = || {
+            let calls = calls.clone();
+            let mut renderer = VideoRenderer::new();
+            renderer.extract = Box::new(move |_video: &Path, out: &Path| {
+                calls.fetch_add(1, Ordering::SeqCst);
+                image::RgbImage::from_pixel(4, 2, image::Rgb([200, 0, 0])).save(out).is_ok()
+            });
+            renderer
+        };
+
+        let mut first = renderer();
+        let thumb = first.get_thumbnail("clip.mp4", dir.path()).unwrap();
+        assert_eq!((thumb.width(), thumb.height()), (4, 2));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // A fresh renderer (as after a restart) reads the JPEG back from disk
+        let mut second = renderer();
+        let thumb = second.get_thumbnail("clip.mp4", dir.path()).unwrap();
+        assert_eq!((thumb.width(), thumb.height()), (4, 2));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // Replacing the video makes the cached frame stale
+        let later = std::time::SystemTime::now() + std::time::Duration::from_secs(60);
+        std::fs::File::options().write(true).open(&video).unwrap().set_modified(later).unwrap();
+        let mut third = renderer();
+        assert!(third.get_thumbnail("clip.mp4", dir.path()).is_some());
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        assert_eq!(std::fs::read_dir(dir.path().join(".thumbnails")).unwrap().count(), 1);
     }
 }
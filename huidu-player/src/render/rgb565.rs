@@ -0,0 +1,63 @@
+/// RGB565 conversion for panel drivers (HUB75 modules and most Huidu
+/// receiving cards) that take 16-bit packed pixels.
+///
+/// Word layout: red in the top 5 bits, green in the middle 6, blue in the low 5.
+/// The frame is premultiplied RGBA shown over black, so a pixel's displayed
+/// color is its channels as stored: a half-transparent white pixel is mid gray
+/// on the panel. Dividing by alpha would brighten it back to white, so the
+/// channels are not un-premultiplied.
+use crate::config::ByteOrder;
+
+/// Pack one premultiplied RGBA pixel, rounding each channel to the nearest level
+fn pack(r: u8, g: u8, b: u8) -> u16 {
+    let r5 = (r as u16 * 31 + 127) / 255;
+    let g6 = (g as u16 * 63 + 127) / 255;
+    let b5 = (b as u16 * 31 + 127) / 255;
+    (r5 << 11) | (g6 << 5) | b5
+}
+
+/// Convert premultiplied RGBA pixels in `src` to RGB565 words in `dst`.
+/// Converts as many pixels as both buffers hold.
+pub fn convert_rgba_to_rgb565(src: &[u8], dst: &mut [u16]) {
+    for (px, word) in src.chunks_exact(4).zip(dst.iter_mut()) {
+        *word = pack(px[0], px[1], px[2]);
+    }
+}
+
+/// Serialize RGB565 words to bytes in the order the panel expects
+pub fn to_bytes(words: &[u16], order: ByteOrder, out: &mut Vec<u8>) {
+    out.clear();
+    out.reserve(words.len() * 2);
+    for &word in words {
+        out.extend_from_slice(&match order {
+            ByteOrder::Little => word.to_le_bytes(),
+            ByteOrder::Big => word.to_be_bytes(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_known_colors() {
+        let src = [
+            255, 0, 0, 255, // red
+            0, 255, 0, 255, // green
+            0, 0, 255, 255, // blue
+            255, 255, 255, 255, // white
+            128, 128, 128, 128, // white at ~50% alpha, premultiplied: gray over black
+            0, 0, 0, 0, // transparent
+        ];
+        let mut dst = [0u16; 6];
+        convert_rgba_to_rgb565(&src, &mut dst);
+        assert_eq!(dst, [0xF800, 0x07E0, 0x001F, 0xFFFF, 0x8410, 0x0000]);
+
+        let mut bytes = Vec::new();
+        to_bytes(&dst[..1], ByteOrder::Little, &mut bytes);
+        assert_eq!(bytes, [0x00, 0xF8]);
+        to_bytes(&dst[..1], ByteOrder::Big, &mut bytes);
+        assert_eq!(bytes, [0xF8, 0x00]);
+    }
+}
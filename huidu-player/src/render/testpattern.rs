@@ -0,0 +1,114 @@
+/// Built-in commissioning patterns, rendered in place of programs when a new
+/// panel is being checked for dead pixels, color order and geometry.
+use tiny_skia::Pixmap;
+
+use crate::program::model::parse_color;
+
+/// SMPTE-style bar order, brightest to darkest
+const BARS: [[u8; 3]; 8] = [
+    [255, 255, 255],
+    [255, 255, 0],
+    [0, 255, 255],
+    [0, 255, 0],
+    [255, 0, 255],
+    [255, 0, 0],
+    [0, 0, 255],
+    [0, 0, 0],
+];
+
+/// Spacing of the grid pattern's lines in pixels
+const GRID_SPACING: u32 = 8;
+
+/// Gradient scroll speed in px/sec
+const GRADIENT_SPEED: u64 = 20;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TestPattern {
+    /// Eight vertical color bars
+    Bars,
+    /// White lines every few pixels plus a border, on black
+    Grid,
+    /// Horizontal gray ramp that scrolls over time
+    Gradient,
+    /// The whole panel in one color
+    Solid(u8, u8, u8),
+}
+
+impl std::str::FromStr for TestPattern {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lower = s.to_lowercase();
+        match lower.as_str() {
+            "bars" => Ok(TestPattern::Bars),
+            "grid" => Ok(TestPattern::Grid),
+            "gradient" => Ok(TestPattern::Gradient),
+            _ => match lower.strip_prefix("solid:") {
+                Some(color) if color.trim_start_matches('#').len() == 6 => {
+                    let (r, g, b) = parse_color(color);
+                    Ok(TestPattern::Solid(r, g, b))
+                }
+                _ => Err(format!(
+                    "Unknown test pattern: {s} (expected bars, grid, gradient or solid:#rrggbb)"
+                )),
+            },
+        }
+    }
+}
+
+/// Fill `target` with the pattern as it looks `elapsed_ms` into playback
+pub fn render(pattern: &TestPattern, target: &mut Pixmap, elapsed_ms: u64) {
+    let width = target.width();
+    let height = target.height();
+    let offset = (elapsed_ms * GRADIENT_SPEED / 1000) as u32;
+
+    for (i, px) in target.data_mut().chunks_exact_mut(4).enumerate() {
+        let x = i as u32 % width;
+        let y = i as u32 / width;
+        let [r, g, b] = match *pattern {
+            TestPattern::Bars => BARS[(x as usize * BARS.len()) / width as usize],
+            TestPattern::Grid => {
+                let line = x.is_multiple_of(GRID_SPACING)
+                    || y.is_multiple_of(GRID_SPACING)
+                    || x == width - 1
+                    || y == height - 1;
+                if line { [255, 255, 255] } else { [0, 0, 0] }
+            }
+            TestPattern::Gradient => {
+                let level = ((x + offset % width) % width * 255 / (width - 1).max(1)) as u8;
+                [level, level, level]
+            }
+            TestPattern::Solid(r, g, b) => [r, g, b],
+        };
+        // Opaque, so premultiplied and straight color are the same
+        px.copy_from_slice(&[r, g, b, 255]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_bars_have_eight_colors_across_width() {
+        for width in [8u32, 64, 100] {
+            let mut target = Pixmap::new(width, 4).unwrap();
+            render(&TestPattern::Bars, &mut target, 0);
+            let colors: HashSet<_> = (0..width)
+                .map(|x| {
+                    let px = target.pixel(x, 2).unwrap();
+                    (px.red(), px.green(), px.blue())
+                })
+                .collect();
+            assert_eq!(colors.len(), BARS.len(), "width {width}");
+        }
+    }
+
+    #[test]
+    fn test_parse_patterns() {
+        assert_eq!("Bars".parse(), Ok(TestPattern::Bars));
+        assert_eq!("solid:#FF8000".parse(), Ok(TestPattern::Solid(255, 128, 0)));
+        assert!("solid:red".parse::<TestPattern>().is_err());
+        assert!("plaid".parse::<TestPattern>().is_err());
+    }
+}
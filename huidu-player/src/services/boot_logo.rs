@@ -0,0 +1,75 @@
+/// Boot logo service.
+/// Remembers which uploaded image (a file in the program directory) the
+/// player shows at startup until the first program loads.
+use anyhow::{bail, Result};
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use tracing::{info, warn};
+
+/// File in the state directory holding the boot logo's filename
+const BOOT_LOGO_FILE: &str = "boot_logo";
+
+/// Cheap to clone — clones share the same setting.
+#[derive(Clone)]
+pub struct BootLogoService {
+    name: Arc<RwLock<Option<String>>>,
+    state_dir: PathBuf,
+}
+
+impl BootLogoService {
+    /// Create the service, restoring any logo saved in `state_dir`
+    pub fn new(state_dir: PathBuf) -> Self {
+        let name = std::fs::read_to_string(state_dir.join(BOOT_LOGO_FILE))
+            .ok()
+            .map(|saved| saved.trim().to_string())
+            .filter(|saved| validate_name(saved).is_ok());
+        Self {
+            name: Arc::new(RwLock::new(name)),
+            state_dir,
+        }
+    }
+
+    /// Filename of the boot logo, if one is set
+    pub fn get(&self) -> Option<String> {
+        self.name.read().unwrap().clone()
+    }
+
+    /// Use `name` (a file in the program directory) as the boot logo
+    pub fn set(&self, name: &str) -> Result<()> {
+        let name = name.trim();
+        validate_name(name)?;
+        *self.name.write().unwrap() = Some(name.to_string());
+
+        let path = self.state_dir.join(BOOT_LOGO_FILE);
+        let saved =
+            std::fs::create_dir_all(&self.state_dir).and_then(|_| std::fs::write(&path, name));
+        if let Err(e) = saved {
+            warn!("Failed to persist boot logo to {}: {}", path.display(), e);
+        }
+        info!("Boot logo set to {}", name);
+        Ok(())
+    }
+
+    /// Go back to a black screen at startup
+    pub fn clear(&self) {
+        *self.name.write().unwrap() = None;
+        let path = self.state_dir.join(BOOT_LOGO_FILE);
+        if let Err(e) = std::fs::remove_file(&path)
+            && e.kind() != std::io::ErrorKind::NotFound
+        {
+            warn!("Failed to remove {}: {}", path.display(), e);
+        }
+        info!("Boot logo cleared");
+    }
+}
+
+/// The logo must be a plain filename inside the program directory
+fn validate_name(name: &str) -> Result<()> {
+    if name.is_empty() || name == "." || name == ".." {
+        bail!("Boot logo name must be a filename");
+    }
+    if name.contains(['/', '\\']) || name.chars().any(char::is_control) {
+        bail!("Boot logo name must be a plain filename: {name}");
+    }
+    Ok(())
+}
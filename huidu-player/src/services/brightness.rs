@@ -2,6 +2,8 @@
 /// Manages brightness level and scheduled brightness changes.
 use serde::{Deserialize, Serialize};
 
+use crate::core::clock::SharedClock;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BrightnessScheduleEntry {
     pub hour: u8,
@@ -14,13 +16,16 @@ pub struct BrightnessService {
     current_level: u8,
     /// Brightness schedule (time-of-day based)
     schedule: Vec<BrightnessScheduleEntry>,
+    clock: SharedClock,
 }
 
 impl BrightnessService {
-    pub fn new() -> Self {
+    /// A service whose schedule follows `clock`
+    pub fn new(clock: SharedClock) -> Self {
         Self {
             current_level: 100,
             schedule: Vec::new(),
+            clock,
         }
     }
 
@@ -38,6 +43,13 @@ impl BrightnessService {
         tracing::info!("Brightness schedule updated: {} entries", self.schedule.len());
     }
 
+    /// Back to full brightness with no schedule
+    pub fn reset(&mut self) {
+        self.current_level = 100;
+        self.schedule.clear();
+        tracing::info!("Brightness reset");
+    }
+
     pub fn get_schedule(&self) -> &[BrightnessScheduleEntry] {
         &self.schedule
     }
@@ -48,7 +60,7 @@ impl BrightnessService {
             return;
         }
 
-        let now = chrono::Local::now();
+        let now = self.clock.now();
         let current_minutes = now.format("%H").to_string().parse::<u16>().unwrap_or(0) * 60
             + now.format("%M").to_string().parse::<u16>().unwrap_or(0);
 
@@ -66,11 +78,11 @@ impl BrightnessService {
             best = self.schedule.last();
         }
 
-        if let Some(entry) = best {
-            if entry.level != self.current_level {
-                self.current_level = entry.level;
-                tracing::debug!("Brightness auto-adjusted to {}", self.current_level);
-            }
+        if let Some(entry) = best
+            && entry.level != self.current_level
+        {
+            self.current_level = entry.level;
+            tracing::debug!("Brightness auto-adjusted to {}", self.current_level);
         }
     }
 
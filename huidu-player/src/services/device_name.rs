@@ -0,0 +1,84 @@
+/// Device name service.
+/// Holds the operator-chosen name reported in discovery and GetDeviceInfo,
+/// persisted so a rename survives restarts.
+use anyhow::{bail, Result};
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use tracing::{info, warn};
+
+/// Name reported until the operator renames the device
+pub const DEFAULT_DEVICE_NAME: &str = "BoxPlayer";
+
+/// Longest accepted name in bytes (it is sent null-terminated in discovery packets)
+const MAX_NAME_LEN: usize = 64;
+
+/// File in the state directory holding the device name
+const DEVICE_NAME_FILE: &str = "device_name";
+
+/// Cheap to clone — clones share the same name, so the discovery broadcaster
+/// sees a rename made over the SDK.
+#[derive(Clone)]
+pub struct DeviceNameService {
+    name: Arc<RwLock<String>>,
+    state_dir: PathBuf,
+}
+
+impl DeviceNameService {
+    /// Create the service, restoring any name saved in `state_dir`
+    pub fn new(state_dir: PathBuf) -> Self {
+        let name = std::fs::read_to_string(state_dir.join(DEVICE_NAME_FILE))
+            .ok()
+            .map(|saved| saved.trim().to_string())
+            .filter(|saved| validate_name(saved).is_ok())
+            .unwrap_or_else(|| DEFAULT_DEVICE_NAME.to_string());
+        Self {
+            name: Arc::new(RwLock::new(name)),
+            state_dir,
+        }
+    }
+
+    pub fn get(&self) -> String {
+        self.name.read().unwrap().clone()
+    }
+
+    /// Rename the device and persist the new name
+    pub fn set(&self, name: &str) -> Result<()> {
+        let name = name.trim();
+        validate_name(name)?;
+        *self.name.write().unwrap() = name.to_string();
+
+        let path = self.state_dir.join(DEVICE_NAME_FILE);
+        let saved =
+            std::fs::create_dir_all(&self.state_dir).and_then(|_| std::fs::write(&path, name));
+        if let Err(e) = saved {
+            warn!("Failed to persist device name to {}: {}", path.display(), e);
+        }
+        info!("Device name set to {}", name);
+        Ok(())
+    }
+
+    /// Go back to the default name and forget the saved one
+    pub fn reset(&self) {
+        *self.name.write().unwrap() = DEFAULT_DEVICE_NAME.to_string();
+        let path = self.state_dir.join(DEVICE_NAME_FILE);
+        if let Err(e) = std::fs::remove_file(&path)
+            && e.kind() != std::io::ErrorKind::NotFound
+        {
+            warn!("Failed to remove {}: {}", path.display(), e);
+        }
+        info!("Device name reset to {}", DEFAULT_DEVICE_NAME);
+    }
+}
+
+fn validate_name(name: &str) -> Result<()> {
+    if name.is_empty() {
+        bail!("Device name must not be empty");
+    }
+    if name.len() > MAX_NAME_LEN {
+        bail!("Device name longer than {MAX_NAME_LEN} bytes");
+    }
+    if name.chars().any(char::is_control) {
+        bail!("Device name contains control characters");
+    }
+    Ok(())
+}
@@ -0,0 +1,211 @@
+/// Device lock service.
+/// When a password is set, SDK sessions must present it before they may change
+/// programs or settings. Only a salted Argon2 hash of the password is kept,
+/// persisted so the lock survives restarts, and discovery advertises whether
+/// it is on. Repeated wrong passwords make the device refuse every attempt for
+/// a growing while.
+use anyhow::{anyhow, bail, Result};
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+/// Longest accepted password in bytes
+const MAX_PASSWORD_LEN: usize = 64;
+
+/// File in the state directory holding the password hash
+const DEVICE_LOCK_FILE: &str = "device_lock";
+
+/// Wrong passwords allowed before attempts are refused for a while
+const FREE_ATTEMPTS: u32 = 3;
+
+/// Refusal period after the first failure beyond the free attempts; it doubles
+/// with each further failure
+const BACKOFF_BASE: Duration = Duration::from_secs(1);
+
+/// Longest refusal period
+const BACKOFF_MAX: Duration = Duration::from_secs(300);
+
+/// Wrong passwords since the last correct one
+#[derive(Default)]
+struct Failures {
+    count: u32,
+    /// Attempts before this instant are refused without checking
+    retry_at: Option<Instant>,
+}
+
+/// Cheap to clone — clones share the same lock, so discovery sees a password
+/// set over the SDK.
+#[derive(Clone)]
+pub struct LockService {
+    /// Argon2 hash of the password in PHC string form; None when unlocked
+    hash: Arc<RwLock<Option<String>>>,
+    /// Last password that verified. Hashing is slow on purpose, and the web UI
+    /// sends the password with every request.
+    verified: Arc<RwLock<Option<String>>>,
+    /// Shared by every session and the web UI, so reconnecting doesn't reset it
+    failures: Arc<RwLock<Failures>>,
+    state_dir: PathBuf,
+}
+
+impl LockService {
+    /// Create the service, restoring any lock saved in `state_dir`
+    pub fn new(state_dir: PathBuf) -> Self {
+        let hash = std::fs::read_to_string(state_dir.join(DEVICE_LOCK_FILE))
+            .ok()
+            .map(|saved| saved.trim().to_string())
+            .filter(|saved| PasswordHash::new(saved).is_ok());
+        Self {
+            hash: Arc::new(RwLock::new(hash)),
+            verified: Arc::new(RwLock::new(None)),
+            failures: Arc::new(RwLock::new(Failures::default())),
+            state_dir,
+        }
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.hash.read().unwrap().is_some()
+    }
+
+    /// Whether `password` unlocks the device. Anything does when it isn't locked.
+    /// While backing off after repeated wrong passwords, everything is refused.
+    pub fn verify(&self, password: &str) -> bool {
+        let Some(hash) = self.hash.read().unwrap().clone() else {
+            return true;
+        };
+        if let Some(retry_at) = self.failures.read().unwrap().retry_at
+            && Instant::now() < retry_at
+        {
+            warn!("Password attempt refused while backing off");
+            return false;
+        }
+        if self.verified.read().unwrap().as_deref() == Some(password) {
+            return true;
+        }
+        let ok = PasswordHash::new(&hash).is_ok_and(|parsed| {
+            Argon2::default().verify_password(password.as_bytes(), &parsed).is_ok()
+        });
+        if ok {
+            *self.verified.write().unwrap() = Some(password.to_string());
+            *self.failures.write().unwrap() = Failures::default();
+        } else {
+            self.record_failure();
+        }
+        ok
+    }
+
+    fn record_failure(&self) {
+        let mut failures = self.failures.write().unwrap();
+        failures.count += 1;
+        if let Some(excess) = failures.count.checked_sub(FREE_ATTEMPTS + 1) {
+            let delay = BACKOFF_BASE
+                .saturating_mul(1 << excess.min(16))
+                .min(BACKOFF_MAX);
+            warn!("{} wrong passwords, refusing attempts for {:?}", failures.count, delay);
+            failures.retry_at = Some(Instant::now() + delay);
+        }
+    }
+
+    /// Lock the device with `password`, replacing any previous one
+    pub fn set(&self, password: &str) -> Result<()> {
+        validate_password(password)?;
+        // A v4 UUID is 16 bytes from the OS random source
+        let salt = SaltString::encode_b64(uuid::Uuid::new_v4().as_bytes())
+            .map_err(|e| anyhow!("Failed to encode salt: {e}"))?;
+        let hash = Argon2::default()
+            .hash_password(password.as_bytes(), &salt)
+            .map_err(|e| anyhow!("Failed to hash password: {e}"))?
+            .to_string();
+        *self.hash.write().unwrap() = Some(hash.clone());
+        *self.verified.write().unwrap() = Some(password.to_string());
+        *self.failures.write().unwrap() = Failures::default();
+
+        let path = self.state_dir.join(DEVICE_LOCK_FILE);
+        let saved =
+            std::fs::create_dir_all(&self.state_dir).and_then(|_| std::fs::write(&path, hash));
+        if let Err(e) = saved {
+            warn!("Failed to persist device lock to {}: {}", path.display(), e);
+        }
+        info!("Device lock enabled");
+        Ok(())
+    }
+
+    /// Remove the password and forget the saved one
+    pub fn clear(&self) {
+        *self.hash.write().unwrap() = None;
+        *self.verified.write().unwrap() = None;
+        *self.failures.write().unwrap() = Failures::default();
+        let path = self.state_dir.join(DEVICE_LOCK_FILE);
+        if let Err(e) = std::fs::remove_file(&path)
+            && e.kind() != std::io::ErrorKind::NotFound
+        {
+            warn!("Failed to remove {}: {}", path.display(), e);
+        }
+        info!("Device lock disabled");
+    }
+}
+
+fn validate_password(password: &str) -> Result<()> {
+    if password.is_empty() {
+        bail!("Password must not be empty");
+    }
+    if password.len() > MAX_PASSWORD_LEN {
+        bail!("Password longer than {MAX_PASSWORD_LEN} bytes");
+    }
+    if password.chars().any(char::is_control) {
+        bail!("Password contains control characters");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_password_is_salted_and_verified() {
+        let dir = tempfile::tempdir().unwrap();
+        let first = LockService::new(dir.path().join("a"));
+        let second = LockService::new(dir.path().join("b"));
+        first.set("s3cret").unwrap();
+        second.set("s3cret").unwrap();
+
+        let saved = |name: &str| {
+            std::fs::read_to_string(dir.path().join(name).join(DEVICE_LOCK_FILE)).unwrap()
+        };
+        assert!(saved("a").starts_with("$argon2"));
+        assert_ne!(saved("a"), saved("b"));
+
+        // A restart verifies against the saved hash
+        let restarted = LockService::new(dir.path().join("a"));
+        assert!(restarted.is_locked());
+        assert!(!restarted.verify("guess"));
+        assert!(restarted.verify("s3cret"));
+    }
+
+    #[test]
+    fn test_wrong_passwords_back_off() {
+        let dir = tempfile::tempdir().unwrap();
+        let lock = LockService::new(dir.path().to_path_buf());
+        lock.set("s3cret").unwrap();
+        let restarted = LockService::new(dir.path().to_path_buf());
+
+        for _ in 0..=FREE_ATTEMPTS {
+            assert!(!restarted.verify("guess"));
+        }
+        // Even the right password is refused until the back-off ends
+        assert!(!restarted.verify("s3cret"));
+
+        restarted.failures.write().unwrap().retry_at = Some(Instant::now());
+        assert!(restarted.verify("s3cret"));
+        assert_eq!(restarted.failures.read().unwrap().count, 0);
+
+        // A password verified earlier (and cached) gets no way around it
+        for _ in 0..=FREE_ATTEMPTS {
+            assert!(!lock.verify("guess"));
+        }
+        assert!(!lock.verify("s3cret"));
+    }
+}
@@ -2,10 +2,17 @@
 use std::path::PathBuf;
 use std::sync::Arc;
 use tokio::sync::{mpsc, RwLock};
+use tokio::time::Duration;
 use tracing::info;
 
+use crate::config::PlayerConfig;
+use crate::core::clock::{self, SharedClock};
 use crate::core::player::PlayerCommand;
+use crate::services::boot_logo::BootLogoService;
 use crate::services::brightness::BrightnessService;
+use crate::services::device_name::DeviceNameService;
+use crate::services::lock::LockService;
+use crate::services::relay::{RelayService, SysfsGpio};
 use crate::services::screen_schedule::ScreenScheduleService;
 use crate::services::storage::StorageService;
 use crate::services::time_sync::TimeSyncService;
@@ -16,16 +23,59 @@ pub struct ServicesState {
     pub brightness: BrightnessService,
     pub screen_schedule: ScreenScheduleService,
     pub storage: StorageService,
+    pub time_sync: TimeSyncService,
+    pub relay: RelayService,
+    pub device_name: DeviceNameService,
+    pub boot_logo: BootLogoService,
+    pub lock: LockService,
+    /// Time source for the schedules and time queries
+    pub clock: SharedClock,
 }
 
 impl ServicesState {
-    pub fn new(program_dir: PathBuf) -> Self {
+    pub fn new(config: &PlayerConfig) -> Self {
+        Self::with_clock(config, clock::system())
+    }
+
+    /// Services that read the time from `clock`
+    pub fn with_clock(config: &PlayerConfig, clock: SharedClock) -> Self {
+        let storage = StorageService::new(config.program_dir.clone());
+        let time_sync = TimeSyncService::new(
+            Duration::from_secs(config.ntp_interval_secs),
+            storage.state_dir(),
+        );
+        let device_name = DeviceNameService::new(storage.state_dir());
+        let boot_logo = BootLogoService::new(storage.state_dir());
+        let lock = LockService::new(storage.state_dir());
         Self {
-            brightness: BrightnessService::new(),
-            screen_schedule: ScreenScheduleService::new(),
-            storage: StorageService::new(program_dir),
+            brightness: BrightnessService::new(clock.clone()),
+            screen_schedule: ScreenScheduleService::new(clock.clone()),
+            storage,
+            time_sync,
+            relay: RelayService::new(Box::new(SysfsGpio::new(config.relay_gpios.clone()))),
+            device_name,
+            boot_logo,
+            lock,
+            clock,
         }
     }
+
+    /// Delete every program and uploaded file, switch the relays off and
+    /// return each setting to its default, forgetting the persisted ones. The
+    /// device GUID is kept so the unit stays recognizable to HDPlayer.
+    pub fn factory_reset(&mut self) -> anyhow::Result<()> {
+        self.brightness.reset();
+        self.screen_schedule.set_schedule(Vec::new());
+        let relays = self.relay.all_off();
+        self.time_sync.reset_server();
+        self.device_name.reset();
+        self.boot_logo.clear();
+        self.lock.clear();
+        self.storage.clear()?;
+        relays?;
+        info!("Factory reset complete");
+        Ok(())
+    }
 }
 
 /// Start all background services
@@ -43,10 +93,9 @@ pub async fn start_services(
         ScreenScheduleService::run(state_clone, tx).await;
     });
 
-    // NTP time sync (runs every 6 hours)
-    tokio::spawn(async move {
-        TimeSyncService::run().await;
-    });
+    // NTP time sync (runs every `ntp_interval_secs`)
+    let time_sync = state.read().await.time_sync.clone();
+    tokio::spawn(time_sync.run());
 
     // USB disk watcher
     let tx = player_tx.clone();
@@ -1,5 +1,9 @@
+pub mod boot_logo;
 pub mod brightness;
+pub mod device_name;
+pub mod lock;
 pub mod manager;
+pub mod relay;
 pub mod screen_schedule;
 pub mod storage;
 pub mod time_sync;
@@ -0,0 +1,120 @@
+/// Relay/GPIO output service.
+/// Numbered on/off outputs (fans, door chimes, ...) switched through a backend.
+use anyhow::{Context, Result};
+use tracing::info;
+
+/// Hardware behind the relay outputs
+pub trait RelayBackend: Send + Sync {
+    /// Number of outputs
+    fn count(&self) -> usize;
+    /// Drive output `index` on or off
+    fn write(&mut self, index: usize, on: bool) -> Result<()>;
+}
+
+pub struct RelayService {
+    backend: Box<dyn RelayBackend>,
+    /// Last state written to each output
+    states: Vec<bool>,
+}
+
+impl RelayService {
+    pub fn new(backend: Box<dyn RelayBackend>) -> Self {
+        let states = vec![false; backend.count()];
+        Self { backend, states }
+    }
+
+    pub fn states(&self) -> &[bool] {
+        &self.states
+    }
+
+    pub fn set(&mut self, index: usize, on: bool) -> Result<()> {
+        if index >= self.states.len() {
+            anyhow::bail!("Relay {} does not exist ({} outputs)", index, self.states.len());
+        }
+        self.backend.write(index, on)?;
+        self.states[index] = on;
+        info!("Relay {} {}", index, if on { "ON" } else { "OFF" });
+        Ok(())
+    }
+
+    /// Switch every output off. All are tried; the first failure is returned.
+    pub fn all_off(&mut self) -> Result<()> {
+        let mut result = Ok(());
+        for index in 0..self.states.len() {
+            let off = self.set(index, false);
+            if result.is_ok() {
+                result = off;
+            }
+        }
+        result
+    }
+}
+
+/// Linux sysfs GPIO outputs (`/sys/class/gpio/gpioN/value`), one pin per relay
+pub struct SysfsGpio {
+    pins: Vec<u32>,
+}
+
+impl SysfsGpio {
+    const GPIO_ROOT: &'static str = "/sys/class/gpio";
+
+    /// Export the pins and configure them as outputs
+    pub fn new(pins: Vec<u32>) -> Self {
+        for pin in &pins {
+            let dir = format!("{}/gpio{}", Self::GPIO_ROOT, pin);
+            if !std::path::Path::new(&dir).exists()
+                && let Err(e) =
+                    std::fs::write(format!("{}/export", Self::GPIO_ROOT), pin.to_string())
+            {
+                tracing::warn!("Failed to export GPIO {}: {}", pin, e);
+                continue;
+            }
+            if let Err(e) = std::fs::write(format!("{}/direction", dir), "out") {
+                tracing::warn!("Failed to configure GPIO {} as output: {}", pin, e);
+            }
+        }
+        Self { pins }
+    }
+}
+
+impl RelayBackend for SysfsGpio {
+    fn count(&self) -> usize {
+        self.pins.len()
+    }
+
+    fn write(&mut self, index: usize, on: bool) -> Result<()> {
+        let pin = self.pins[index];
+        std::fs::write(
+            format!("{}/gpio{}/value", Self::GPIO_ROOT, pin),
+            if on { "1" } else { "0" },
+        )
+        .with_context(|| format!("Failed to write GPIO {}", pin))
+    }
+}
+
+/// In-memory outputs for tests
+#[cfg(test)]
+pub struct MockRelays {
+    pub outputs: std::sync::Arc<std::sync::Mutex<Vec<bool>>>,
+}
+
+#[cfg(test)]
+impl MockRelays {
+    pub fn new(count: usize) -> Self {
+        Self {
+            outputs: std::sync::Arc::new(std::sync::Mutex::new(vec![false; count])),
+        }
+    }
+}
+
+#[cfg(test)]
+impl RelayBackend for MockRelays {
+    fn count(&self) -> usize {
+        self.outputs.lock().unwrap().len()
+    }
+
+    fn write(&mut self, index: usize, on: bool) -> Result<()> {
+        self.outputs.lock().unwrap()[index] = on;
+        Ok(())
+    }
+}
@@ -1,12 +1,13 @@
 /// Screen on/off scheduling service.
 /// Turns the screen on/off based on configured time ranges.
-use chrono::Local;
+use chrono::{Datelike, NaiveTime, Weekday};
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use tokio::sync::{mpsc, RwLock};
 use tokio::time::{self, Duration};
 use tracing::debug;
 
+use crate::core::clock::SharedClock;
 use crate::core::player::PlayerCommand;
 use crate::services::manager::ServicesState;
 
@@ -14,22 +15,90 @@ use crate::services::manager::ServicesState;
 pub struct ScreenScheduleEntry {
     /// Time to turn on (HH:MM:SS)
     pub on_time: String,
-    /// Time to turn off (HH:MM:SS)
+    /// Time to turn off (HH:MM:SS). Earlier than `on_time` means the window
+    /// runs past midnight; equal to it means all day.
     pub off_time: String,
     /// Days of week enabled (comma-separated: "Mon,Tue,Wed,Thu,Fri,Sat,Sun")
     pub days: String,
+    /// Whether the screen is on or off inside the window
+    #[serde(default)]
+    pub action: SwitchAction,
+    /// Overlapping windows resolve to the highest priority; on a tie, off wins
+    #[serde(default)]
+    pub priority: u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum SwitchAction {
+    #[default]
+    On,
+    Off,
+}
+
+impl SwitchAction {
+    /// Parse the `type` attribute; anything but off/false/0 turns on
+    pub fn parse(s: &str) -> Self {
+        match s.trim().to_lowercase().as_str() {
+            "off" | "false" | "0" => SwitchAction::Off,
+            _ => SwitchAction::On,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SwitchAction::On => "on",
+            SwitchAction::Off => "off",
+        }
+    }
+}
+
+impl ScreenScheduleEntry {
+    /// Whether the entry applies on this weekday. `days` is matched token by
+    /// token, case-insensitively, as short or full day names ("Mon", "monday")
+    fn runs_on(&self, day: Weekday) -> bool {
+        self.days.trim().is_empty()
+            || self
+                .days
+                .split(',')
+                .any(|token| token.trim().parse::<Weekday>() == Ok(day))
+    }
+
+    /// Whether the window is active at `time` on `day`. When `off_time` is
+    /// earlier than `on_time` the window runs past midnight, and its early
+    /// morning part belongs to the day it started on: "Fri 22:00-06:00" is
+    /// still on at 03:00 Saturday.
+    fn is_active(&self, time: NaiveTime, day: Weekday) -> bool {
+        let (Some(on), Some(off)) = (parse_time(&self.on_time), parse_time(&self.off_time)) else {
+            return false;
+        };
+        if off < on {
+            (time >= on && self.runs_on(day)) || (time < off && self.runs_on(day.pred()))
+        } else {
+            self.runs_on(day) && (on == off || (time >= on && time < off))
+        }
+    }
+}
+
+/// Parse HH:MM:SS, or HH:MM
+fn parse_time(s: &str) -> Option<NaiveTime> {
+    NaiveTime::parse_from_str(s, "%H:%M:%S")
+        .or_else(|_| NaiveTime::parse_from_str(s, "%H:%M"))
+        .ok()
 }
 
 pub struct ScreenScheduleService {
     entries: Vec<ScreenScheduleEntry>,
     last_state: Option<bool>,
+    clock: SharedClock,
 }
 
 impl ScreenScheduleService {
-    pub fn new() -> Self {
+    /// A service that reads the time from `clock`
+    pub fn new(clock: SharedClock) -> Self {
         Self {
             entries: Vec::new(),
             last_state: None,
+            clock,
         }
     }
 
@@ -45,27 +114,40 @@ impl ScreenScheduleService {
 
     /// Check if screen should be on right now
     pub fn should_be_on(&self) -> Option<bool> {
+        let now = self.clock.now();
+        self.should_be_on_at(now.time(), now.weekday())
+    }
+
+    /// The power state to switch to, when the schedule calls for a different
+    /// one than at the last check
+    pub fn transition(&mut self) -> Option<bool> {
+        let on = self.should_be_on()?;
+        if self.last_state == Some(on) {
+            return None;
+        }
+        self.last_state = Some(on);
+        Some(on)
+    }
+
+    /// Resolve the schedule at a given time of day and weekday. Among the
+    /// windows covering it, the highest priority wins and off beats on at equal
+    /// priority. Outside every window the screen is off, unless the schedule
+    /// only has off windows (then they just carve blackouts out of "on").
+    pub fn should_be_on_at(&self, time: NaiveTime, day: Weekday) -> Option<bool> {
         if self.entries.is_empty() {
             return None; // No schedule, don't override
         }
 
-        let now = Local::now();
-        let current_time = now.format("%H:%M:%S").to_string();
-        let day_name = now.format("%a").to_string(); // Mon, Tue, etc.
-
-        for entry in &self.entries {
-            // Check if today is in the enabled days
-            if !entry.days.is_empty() && !entry.days.contains(&day_name) {
-                continue;
-            }
+        let winner = self
+            .entries
+            .iter()
+            .filter(|entry| entry.is_active(time, day))
+            .max_by_key(|entry| (entry.priority, entry.action == SwitchAction::Off));
 
-            // Check if current time is within on/off range
-            if current_time >= entry.on_time && current_time < entry.off_time {
-                return Some(true);
-            }
+        match winner {
+            Some(entry) => Some(entry.action == SwitchAction::On),
+            None => Some(!self.entries.iter().any(|entry| entry.action == SwitchAction::On)),
         }
-
-        Some(false)
     }
 
     /// Background task that checks schedule every 30 seconds
@@ -78,18 +160,10 @@ impl ScreenScheduleService {
         loop {
             interval.tick().await;
 
-            let should_on = {
-                let state = state.read().await;
-                state.screen_schedule.should_be_on()
-            };
-
-            if let Some(on) = should_on {
-                let mut state = state.write().await;
-                if state.screen_schedule.last_state != Some(on) {
-                    state.screen_schedule.last_state = Some(on);
-                    debug!("Screen schedule: turning {}", if on { "ON" } else { "OFF" });
-                    let _ = player_tx.send(PlayerCommand::ScreenPower(on)).await;
-                }
+            let transition = state.write().await.screen_schedule.transition();
+            if let Some(on) = transition {
+                debug!("Screen schedule: turning {}", if on { "ON" } else { "OFF" });
+                let _ = player_tx.send(PlayerCommand::ScreenPower(on)).await;
             }
 
             // Also check brightness schedule
@@ -100,3 +174,121 @@ impl ScreenScheduleService {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::clock::{self, MockClock};
+    use chrono::{Local, TimeZone};
+
+    fn entry(
+        on: &str,
+        off: &str,
+        days: &str,
+        action: SwitchAction,
+        priority: u8,
+    ) -> ScreenScheduleEntry {
+        ScreenScheduleEntry {
+            on_time: on.to_string(),
+            off_time: off.to_string(),
+            days: days.to_string(),
+            action,
+            priority,
+        }
+    }
+
+    fn at(h: u32, m: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(h, m, 0).unwrap()
+    }
+
+    #[test]
+    fn test_mock_clock_drives_schedule_transition() {
+        // Wednesday 1 May 2024, a minute before opening
+        let clock = MockClock::new(Local.with_ymd_and_hms(2024, 5, 1, 8, 59, 0).unwrap());
+        let mut schedule = ScreenScheduleService::new(clock.shared());
+        schedule.set_schedule(vec![entry("09:00", "17:00", "Mon,Wed", SwitchAction::On, 0)]);
+
+        assert_eq!(schedule.transition(), Some(false));
+        clock.advance(chrono::Duration::seconds(59));
+        assert_eq!(schedule.transition(), None);
+
+        clock.advance(chrono::Duration::seconds(1));
+        assert_eq!(schedule.transition(), Some(true));
+        assert_eq!(schedule.transition(), None);
+
+        clock.set(Local.with_ymd_and_hms(2024, 5, 1, 17, 0, 0).unwrap());
+        assert_eq!(schedule.transition(), Some(false));
+        // Thursday isn't scheduled
+        clock.set(Local.with_ymd_and_hms(2024, 5, 2, 10, 0, 0).unwrap());
+        assert_eq!(schedule.transition(), None);
+    }
+
+    #[test]
+    fn test_window_crossing_midnight() {
+        let mut schedule = ScreenScheduleService::new(clock::system());
+        schedule.set_schedule(vec![entry("22:00:00", "06:00:00", "", SwitchAction::On, 0)]);
+
+        assert_eq!(schedule.should_be_on_at(at(23, 0), Weekday::Mon), Some(true));
+        assert_eq!(schedule.should_be_on_at(at(3, 0), Weekday::Tue), Some(true));
+        assert_eq!(schedule.should_be_on_at(at(6, 0), Weekday::Tue), Some(false));
+        assert_eq!(schedule.should_be_on_at(at(12, 0), Weekday::Tue), Some(false));
+    }
+
+    #[test]
+    fn test_off_overrides_overlapping_on() {
+        let mut schedule = ScreenScheduleService::new(clock::system());
+        schedule.set_schedule(vec![
+            entry("09:00:00", "17:00:00", "Mon,Tue,Wed,Thu,Fri", SwitchAction::On, 0),
+            // Holiday: off all day
+            entry("00:00:00", "00:00:00", "Wed", SwitchAction::Off, 0),
+        ]);
+        assert_eq!(schedule.should_be_on_at(at(10, 0), Weekday::Tue), Some(true));
+        assert_eq!(schedule.should_be_on_at(at(10, 0), Weekday::Wed), Some(false));
+
+        // A higher-priority on window beats the off one
+        let mut entries = schedule.get_schedule().to_vec();
+        entries.push(entry("12:00:00", "13:00:00", "Wed", SwitchAction::On, 1));
+        schedule.set_schedule(entries);
+        assert_eq!(schedule.should_be_on_at(at(12, 30), Weekday::Wed), Some(true));
+        assert_eq!(schedule.should_be_on_at(at(14, 0), Weekday::Wed), Some(false));
+    }
+
+    #[test]
+    fn test_off_only_schedule_defaults_on() {
+        let mut schedule = ScreenScheduleService::new(clock::system());
+        schedule.set_schedule(vec![entry("01:00:00", "05:00:00", "", SwitchAction::Off, 0)]);
+        assert_eq!(schedule.should_be_on_at(at(3, 0), Weekday::Sun), Some(false));
+        assert_eq!(schedule.should_be_on_at(at(12, 0), Weekday::Sun), Some(true));
+    }
+
+    #[test]
+    fn test_days_match_whole_tokens() {
+        let weekdays = entry("09:00:00", "17:00:00", "Mon,Wed,Fri", SwitchAction::On, 0);
+        assert!(weekdays.runs_on(Weekday::Mon));
+        assert!(weekdays.runs_on(Weekday::Fri));
+        assert!(!weekdays.runs_on(Weekday::Tue));
+
+        // Case and spacing don't matter, partial words don't match
+        assert!(entry("", "", " wed , SAT", SwitchAction::On, 0).runs_on(Weekday::Sat));
+        assert!(!entry("", "", "Mond", SwitchAction::On, 0).runs_on(Weekday::Mon));
+        assert!(!entry("", "", "Tues,Thurs", SwitchAction::On, 0).runs_on(Weekday::Tue));
+        assert!(entry("", "", "Sunday", SwitchAction::On, 0).runs_on(Weekday::Sun));
+    }
+
+    #[test]
+    fn test_daytime_and_overnight_windows() {
+        let mut schedule = ScreenScheduleService::new(clock::system());
+        schedule.set_schedule(vec![entry("09:00:00", "17:00:00", "", SwitchAction::On, 0)]);
+        assert_eq!(schedule.should_be_on_at(at(12, 0), Weekday::Thu), Some(true));
+        assert_eq!(schedule.should_be_on_at(at(8, 59), Weekday::Thu), Some(false));
+        assert_eq!(schedule.should_be_on_at(at(17, 0), Weekday::Thu), Some(false));
+        assert_eq!(schedule.should_be_on_at(at(23, 0), Weekday::Thu), Some(false));
+
+        // Friday night display: the 03:00 part falls on Saturday
+        schedule.set_schedule(vec![entry("22:00", "06:00", "Fri", SwitchAction::On, 0)]);
+        assert_eq!(schedule.should_be_on_at(at(23, 0), Weekday::Fri), Some(true));
+        assert_eq!(schedule.should_be_on_at(at(3, 0), Weekday::Sat), Some(true));
+        assert_eq!(schedule.should_be_on_at(at(3, 0), Weekday::Fri), Some(false));
+        assert_eq!(schedule.should_be_on_at(at(23, 0), Weekday::Sat), Some(false));
+    }
+}
@@ -1,9 +1,28 @@
 /// Program persistence service.
-/// Saves and loads program state to/from disk.
+/// Saves and loads program state to/from disk. Each received program is kept
+/// in its own file under the state directory, named after its GUID, with an
+/// index recording the order they arrived in, so a restart restores them all.
+use std::collections::HashSet;
 use std::path::PathBuf;
 use tracing::{info, warn};
 
-use crate::program::model::Screen;
+use crate::program::model::{Program, Screen};
+
+/// Program file written before programs were saved individually; migrated
+/// into the saved set on the next save
+const LEGACY_PROGRAM_FILE: &str = "current_program.xml";
+
+/// Subdirectory of the state directory holding one file per saved program
+const SAVED_PROGRAMS_DIR: &str = "programs";
+
+/// File in the saved programs directory listing their GUIDs, one per line
+const INDEX_FILE: &str = "index";
+
+/// A saved program: its GUID and the `<screen>` holding it as received
+struct SavedProgram {
+    guid: String,
+    xml: String,
+}
 
 pub struct StorageService {
     program_dir: PathBuf,
@@ -14,34 +33,215 @@ impl StorageService {
         Self { program_dir }
     }
 
-    /// Save screen XML to the program directory
-    pub fn save_program(&self, _screen: &Screen, xml: &str) -> anyhow::Result<()> {
-        std::fs::create_dir_all(&self.program_dir)?;
+    /// Save each program of a received screen, replacing any saved program
+    /// with the same GUID and appending new ones to the saved set. Each is
+    /// kept as its element from the received `xml`, verbatim.
+    pub fn save_program(&self, screen: &Screen, xml: &str) -> anyhow::Result<()> {
+        if let Some(program) = screen.programs.iter().find(|p| !is_valid_guid(&p.guid)) {
+            anyhow::bail!("Invalid program GUID {:?}", program.guid);
+        }
+        let received = program_elements(xml);
+        let mut saved = self.saved_entries();
+        for program in &screen.programs {
+            let xml = match received.iter().find(|(guid, _)| *guid == program.guid) {
+                Some((_, element)) => format!("<screen>{element}</screen>"),
+                // Not found as written (e.g. malformed around it): save it as parsed
+                None => quick_xml::se::to_string_with_root(
+                    "screen",
+                    &Screen {
+                        timestamps: String::new(),
+                        programs: vec![program.clone()],
+                    },
+                )?,
+            };
+            match saved.iter_mut().find(|entry| entry.guid == program.guid) {
+                Some(entry) => entry.xml = xml,
+                None => saved.push(SavedProgram {
+                    guid: program.guid.clone(),
+                    xml,
+                }),
+            }
+        }
+        self.write_saved_programs(&saved)?;
+        info!("Saved {} program(s), {} in total", screen.programs.len(), saved.len());
+        Ok(())
+    }
+
+    /// Save a received screen like `save_program` and return what should play:
+    /// every saved program, or just the received ones if saving failed
+    pub fn save_and_merge(&self, screen: Screen, xml: &str) -> Screen {
+        if let Err(e) = self.save_program(&screen, xml) {
+            warn!("Failed to save program: {}", e);
+            return screen;
+        }
+        self.load_current_program().unwrap_or(screen)
+    }
 
-        // Save as current_program.xml
-        let path = self.program_dir.join("current_program.xml");
-        std::fs::write(&path, xml)?;
-        info!("Saved program to {}", path.display());
+    /// Remove programs by GUID from the saved set, deleting their files and
+    /// dropping them from the index
+    pub fn remove_programs(&self, guids: &[String]) -> anyhow::Result<()> {
+        let mut saved = self.saved_entries();
+        let before = saved.len();
+        saved.retain(|entry| !guids.contains(&entry.guid));
+        if saved.len() == before {
+            return Ok(());
+        }
+        self.write_saved_programs(&saved)?;
+        info!("Removed {} saved program(s), {} left", before - saved.len(), saved.len());
         Ok(())
     }
 
-    /// Load the most recent program from disk
+    /// Remove programs by GUID like `remove_programs`, then delete the media
+    /// files they used that no remaining program uses, saved or placed in the
+    /// program directory as a file. Files named in `keep` (e.g. the boot logo)
+    /// stay. Returns the names of the files deleted.
+    pub fn remove_programs_and_media(
+        &self,
+        guids: &[String],
+        keep: &[String],
+    ) -> anyhow::Result<Vec<String>> {
+        let Some(screen) = self.load_current_program() else {
+            return Ok(Vec::new());
+        };
+        let (removed, remaining): (Vec<_>, Vec<_>) =
+            screen.programs.iter().partition(|p| guids.contains(&p.guid));
+        let file_programs = self.file_programs();
+        let in_use: HashSet<&str> = remaining
+            .into_iter()
+            .chain(&file_programs)
+            .flat_map(|p| p.media_files())
+            .chain(keep.iter().map(String::as_str))
+            .collect();
+        let orphaned: HashSet<&str> = removed
+            .iter()
+            .flat_map(|p| p.media_files())
+            .filter(|name| !in_use.contains(name) && is_plain_filename(name))
+            .collect();
+
+        self.remove_programs(guids)?;
+        let mut deleted = Vec::new();
+        for name in orphaned {
+            match self.delete_file(name) {
+                Ok(()) => deleted.push(name.to_string()),
+                Err(e) => warn!("Failed to delete unused media {}: {}", name, e),
+            }
+        }
+        deleted.sort();
+        Ok(deleted)
+    }
+
+    /// Load the saved programs, in the order they were first received.
+    /// None when nothing is saved.
     pub fn load_current_program(&self) -> Option<Screen> {
-        let path = self.program_dir.join("current_program.xml");
-        if !path.exists() {
+        let programs = self.saved_programs();
+        if programs.is_empty() {
             return None;
         }
+        Some(Screen {
+            timestamps: String::new(),
+            programs,
+        })
+    }
+
+    fn saved_programs(&self) -> Vec<Program> {
+        let mut programs = Vec::new();
+        for entry in self.saved_entries() {
+            match crate::program::parser::parse_program_xml(&entry.xml) {
+                Ok(screen) => programs.extend(screen.programs),
+                Err(e) => warn!("Failed to restore saved program {}: {}", entry.guid, e),
+            }
+        }
+        programs
+    }
 
-        match crate::program::parser::parse_program_file(&path) {
-            Ok(screen) => {
-                info!("Restored program from {}", path.display());
-                Some(screen)
+    /// Programs listed in the index, falling back to the legacy single file
+    /// when nothing has been saved individually yet
+    fn saved_entries(&self) -> Vec<SavedProgram> {
+        let dir = self.saved_programs_dir();
+        let Ok(index) = std::fs::read_to_string(dir.join(INDEX_FILE)) else {
+            return self.legacy_entries();
+        };
+        let mut saved = Vec::new();
+        for guid in index.lines().filter(|line| !line.is_empty()) {
+            let path = dir.join(program_file_name(guid));
+            match std::fs::read_to_string(&path) {
+                Ok(xml) => saved.push(SavedProgram {
+                    guid: guid.to_string(),
+                    xml,
+                }),
+                Err(e) => warn!("Failed to restore saved program {}: {}", guid, e),
+            }
+        }
+        saved
+    }
+
+    /// Programs in files placed directly in the program directory, which the
+    /// player loads alongside the saved ones (the legacy file is saved state)
+    fn file_programs(&self) -> Vec<Program> {
+        let Ok(entries) = std::fs::read_dir(&self.program_dir) else {
+            return Vec::new();
+        };
+        let mut programs = Vec::new();
+        for path in entries.flatten().map(|entry| entry.path()) {
+            let is_program = path.extension().is_some_and(|e| e == "xml")
+                || crate::program::parser::is_json_program(&path);
+            if !is_program || path.file_name().is_some_and(|n| n == LEGACY_PROGRAM_FILE) {
+                continue;
+            }
+            if let Ok(screen) = crate::program::parser::parse_program_file(&path) {
+                programs.extend(screen.programs);
             }
+        }
+        programs
+    }
+
+    fn legacy_entries(&self) -> Vec<SavedProgram> {
+        let path = self.program_dir.join(LEGACY_PROGRAM_FILE);
+        if !path.exists() {
+            return Vec::new();
+        }
+        match std::fs::read_to_string(&path) {
+            Ok(xml) => program_elements(&xml)
+                .into_iter()
+                .filter(|(guid, _)| is_valid_guid(guid))
+                .map(|(guid, element)| SavedProgram {
+                    guid,
+                    xml: format!("<screen>{element}</screen>"),
+                })
+                .collect(),
             Err(e) => {
                 warn!("Failed to restore program: {}", e);
-                None
+                Vec::new()
+            }
+        }
+    }
+
+    /// Make `saved` the saved set: write a file per program and the index,
+    /// then delete files no longer listed and the legacy single file
+    fn write_saved_programs(&self, saved: &[SavedProgram]) -> anyhow::Result<()> {
+        let dir = self.saved_programs_dir();
+        std::fs::create_dir_all(&dir)?;
+        let mut keep = HashSet::from([INDEX_FILE.to_string()]);
+        let mut index = String::new();
+        for entry in saved {
+            let name = program_file_name(&entry.guid);
+            std::fs::write(dir.join(&name), &entry.xml)?;
+            index.push_str(&entry.guid);
+            index.push('\n');
+            keep.insert(name);
+        }
+        std::fs::write(dir.join(INDEX_FILE), index)?;
+
+        for entry in std::fs::read_dir(&dir)?.flatten() {
+            if entry.file_name().to_str().is_none_or(|name| !keep.contains(name)) {
+                std::fs::remove_file(entry.path())?;
             }
         }
+        self.delete_file(LEGACY_PROGRAM_FILE)
+    }
+
+    fn saved_programs_dir(&self) -> PathBuf {
+        self.state_dir().join(SAVED_PROGRAMS_DIR)
     }
 
     /// Get the program directory path
@@ -49,15 +249,21 @@ impl StorageService {
         &self.program_dir
     }
 
+    /// Directory for device state that must survive program clears
+    /// (a subdirectory, so `clear` and `list_files` leave it alone)
+    pub fn state_dir(&self) -> PathBuf {
+        self.program_dir.join(".state")
+    }
+
     /// List all files in the program directory
     pub fn list_files(&self) -> Vec<String> {
         let mut files = Vec::new();
         if let Ok(entries) = std::fs::read_dir(&self.program_dir) {
             for entry in entries.flatten() {
-                if entry.path().is_file() {
-                    if let Some(name) = entry.file_name().to_str() {
-                        files.push(name.to_string());
-                    }
+                if entry.path().is_file()
+                    && let Some(name) = entry.file_name().to_str()
+                {
+                    files.push(name.to_string());
                 }
             }
         }
@@ -74,7 +280,7 @@ impl StorageService {
         Ok(())
     }
 
-    /// Delete all files in the program directory
+    /// Delete all files in the program directory and every saved program
     pub fn clear(&self) -> anyhow::Result<()> {
         if let Ok(entries) = std::fs::read_dir(&self.program_dir) {
             for entry in entries.flatten() {
@@ -83,7 +289,175 @@ impl StorageService {
                 }
             }
         }
+        let saved = self.saved_programs_dir();
+        if saved.exists() {
+            std::fs::remove_dir_all(&saved)?;
+        }
         info!("Cleared program directory");
         Ok(())
     }
 }
+
+/// Whether `name` is a file directly in the program directory, so a program
+/// can't point garbage collection elsewhere
+fn is_plain_filename(name: &str) -> bool {
+    !name.is_empty() && name != "." && name != ".." && !name.contains(['/', '\\'])
+}
+
+/// Whether `guid` can be saved. It is a line of the index, so it must not be
+/// empty or contain line breaks or other control characters.
+fn is_valid_guid(guid: &str) -> bool {
+    !guid.is_empty() && !guid.chars().any(char::is_control)
+}
+
+/// Each `<program>` element in `xml` with its GUID, as written. Stops at the
+/// first malformed spot, keeping the elements before it.
+fn program_elements(xml: &str) -> Vec<(String, &str)> {
+    use quick_xml::events::Event;
+
+    let mut reader = quick_xml::Reader::from_str(xml);
+    let mut elements = Vec::new();
+    let mut open: Option<(String, usize)> = None;
+    loop {
+        let start = reader.buffer_position() as usize;
+        let event = match reader.read_event() {
+            Ok(Event::Eof) | Err(_) => break,
+            Ok(event) => event,
+        };
+        let end = reader.buffer_position() as usize;
+        match event {
+            Event::Start(ref tag) | Event::Empty(ref tag)
+                if open.is_none() && tag.local_name().as_ref() == b"program" =>
+            {
+                let guid = tag
+                    .try_get_attribute("guid")
+                    .ok()
+                    .flatten()
+                    .and_then(|attr| attr.unescape_value().ok())
+                    .unwrap_or_default()
+                    .into_owned();
+                if matches!(event, Event::Empty(_)) {
+                    elements.push((guid, &xml[start..end]));
+                } else {
+                    open = Some((guid, start));
+                }
+            }
+            Event::End(tag) if tag.local_name().as_ref() == b"program" => {
+                if let Some((guid, start)) = open.take() {
+                    elements.push((guid, &xml[start..end]));
+                }
+            }
+            _ => {}
+        }
+    }
+    elements
+}
+
+/// File a saved program is kept in. GUIDs come from the sender, so they are
+/// hashed rather than used as a path.
+fn program_file_name(guid: &str) -> String {
+    format!("{:x}.xml", md5::compute(guid.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TWO_PROGRAMS: &str = r#"<screen>
+      <program guid="p1" name="One"><area guid="a1"><rectangle width="8" height="8"/><resources/></area></program>
+      <program guid="p2" name="Two"><area guid="a2"><rectangle width="8" height="8"/><resources/></area></program>
+    </screen>"#;
+
+    #[test]
+    fn test_remove_programs_keeps_others() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = StorageService::new(dir.path().to_path_buf());
+        let screen = crate::program::parser::parse_program_xml(TWO_PROGRAMS).unwrap();
+        storage.save_program(&screen, TWO_PROGRAMS).unwrap();
+
+        storage.remove_programs(&["p1".to_string()]).unwrap();
+        let restored = storage.load_current_program().unwrap();
+        assert_eq!(restored.programs.len(), 1);
+        assert_eq!(restored.programs[0].guid, "p2");
+
+        storage.remove_programs(&["p2".to_string()]).unwrap();
+        assert!(storage.load_current_program().is_none());
+    }
+
+    #[test]
+    fn test_remove_program_deletes_only_unshared_media() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = StorageService::new(dir.path().to_path_buf());
+        let image = |guid: &str, file: &str| {
+            format!(r#"<image guid="{guid}"><file name="{file}"/></image>"#)
+        };
+        let xml = format!(
+            r#"<screen>
+              <program guid="p1"><area guid="a1"><rectangle width="8" height="8"/>
+                <resources>{}{}</resources></area></program>
+              <program guid="p2"><area guid="a2"><rectangle width="8" height="8"/>
+                <resources>{}</resources></area></program>
+            </screen>"#,
+            image("i1", "shared.png"),
+            image("i2", "only-p1.png") + &image("i4", "in-file.png") + &image("i5", "kept.png"),
+            image("i3", "shared.png"),
+        );
+        let files = ["shared.png", "only-p1.png", "in-file.png", "kept.png", "unrelated.png"];
+        for file in files {
+            std::fs::write(dir.path().join(file), b"png").unwrap();
+        }
+        // A program file placed in the directory also uses one of p1's files
+        let placed = format!(
+            r#"<screen><program guid="f1"><area guid="a3"><rectangle width="8" height="8"/>
+              <resources>{}</resources></area></program></screen>"#,
+            image("i6", "in-file.png"),
+        );
+        std::fs::write(dir.path().join("placed.xml"), placed).unwrap();
+        let screen = crate::program::parser::parse_program_xml(&xml).unwrap();
+        storage.save_program(&screen, &xml).unwrap();
+
+        let keep = ["kept.png".to_string()];
+        let deleted = storage.remove_programs_and_media(&["p1".to_string()], &keep).unwrap();
+        assert_eq!(deleted, ["only-p1.png"]);
+        assert!(!dir.path().join("only-p1.png").exists());
+        assert!(dir.path().join("shared.png").exists());
+        assert!(dir.path().join("in-file.png").exists());
+        assert!(dir.path().join("kept.png").exists());
+        assert!(dir.path().join("unrelated.png").exists());
+        assert_eq!(storage.load_current_program().unwrap().programs[0].guid, "p2");
+    }
+
+    #[test]
+    fn test_programs_saved_verbatim() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = StorageService::new(dir.path().to_path_buf());
+        let xml = format!(
+            r#"<sdk guid="s"><in method="AddProgram">{TWO_PROGRAMS}</in></sdk>"#
+        );
+        let screen = crate::program::parser::parse_program_xml(&xml).unwrap();
+        storage.save_program(&screen, &xml).unwrap();
+
+        let saved = dir.path().join(".state").join(SAVED_PROGRAMS_DIR);
+        let p2 = std::fs::read_to_string(saved.join(program_file_name("p2"))).unwrap();
+        let element = r#"<program guid="p2" name="Two"><area guid="a2">"#;
+        assert!(p2.starts_with(&format!("<screen>{element}")), "{p2}");
+        assert_eq!(p2.matches("<program").count(), 1);
+
+        // Removing one program leaves the other's file as it was
+        storage.remove_programs(&["p1".to_string()]).unwrap();
+        let after = std::fs::read_to_string(saved.join(program_file_name("p2"))).unwrap();
+        assert_eq!(after, p2);
+    }
+
+    #[test]
+    fn test_guid_with_line_break_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = StorageService::new(dir.path().to_path_buf());
+        let xml = r#"<screen><program guid="p1&#10;p2"/></screen>"#;
+        let screen = crate::program::parser::parse_program_xml(xml).unwrap();
+        assert_eq!(screen.programs[0].guid, "p1\np2");
+
+        assert!(storage.save_program(&screen, xml).is_err());
+        assert!(storage.load_current_program().is_none());
+    }
+}
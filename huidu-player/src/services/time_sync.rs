@@ -1,60 +1,123 @@
 /// NTP time synchronization service.
 /// Periodically syncs system clock via NTP.
+use anyhow::{bail, Result};
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::Mutex;
 use tokio::time::{self, Duration};
 use tracing::{debug, info, warn};
 
-pub struct TimeSyncService;
+/// Delay before the first sync after startup (lets the network come up)
+const INITIAL_SYNC_DELAY: Duration = Duration::from_secs(10);
+
+/// Default interval between periodic syncs
+pub const DEFAULT_SYNC_INTERVAL_SECS: u64 = 6 * 3600;
+
+/// NTP server used until one is set with SetNtpServer
+pub const DEFAULT_NTP_SERVER: &str = "pool.ntp.org";
+
+/// File in the state directory holding the configured NTP server
+const NTP_SERVER_FILE: &str = "ntp_server";
+
+/// Sets the system clock from the given NTP server, returning once the attempt is over
+type NtpSync = Arc<dyn Fn(&str) + Send + Sync>;
+
+/// Cheap to clone — clones share the same in-progress lock and server setting.
+#[derive(Clone)]
+pub struct TimeSyncService {
+    interval: Duration,
+    /// Held while a sync runs so periodic and on-demand syncs never overlap
+    sync_lock: Arc<Mutex<()>>,
+    /// NTP server host, persisted under `state_dir`
+    server: Arc<std::sync::Mutex<String>>,
+    state_dir: PathBuf,
+    sync: NtpSync,
+}
 
 impl TimeSyncService {
-    /// Run NTP sync in background (every 6 hours)
-    pub async fn run() {
-        // Initial sync after 10 seconds
-        time::sleep(Duration::from_secs(10)).await;
-        Self::sync_once().await;
-
-        // Then every 6 hours
-        let mut interval = time::interval(Duration::from_secs(6 * 3600));
-        loop {
-            interval.tick().await;
-            Self::sync_once().await;
+    /// Create the service, restoring any NTP server saved in `state_dir`
+    pub fn new(interval: Duration, state_dir: PathBuf) -> Self {
+        let server = std::fs::read_to_string(state_dir.join(NTP_SERVER_FILE))
+            .ok()
+            .map(|saved| saved.trim().to_string())
+            .filter(|saved| validate_server(saved).is_ok())
+            .unwrap_or_else(|| DEFAULT_NTP_SERVER.to_string());
+        Self {
+            interval,
+            sync_lock: Arc::new(Mutex::new(())),
+            server: Arc::new(std::sync::Mutex::new(server)),
+            state_dir,
+            sync: Arc::new(system_sync),
         }
     }
 
-    async fn sync_once() {
-        debug!("Attempting NTP time sync");
+    /// Sync with `sync` instead of the system tools
+    #[cfg(test)]
+    pub fn with_sync(mut self, sync: impl Fn(&str) + Send + Sync + 'static) -> Self {
+        self.sync = Arc::new(sync);
+        self
+    }
 
-        // On Linux, try ntpdate or systemctl
-        #[cfg(unix)]
-        {
-            let result = tokio::process::Command::new("ntpdate")
-                .args(["-u", "pool.ntp.org"])
-                .output()
-                .await;
+    /// NTP server host used for syncs
+    pub fn server(&self) -> String {
+        self.server.lock().unwrap().clone()
+    }
 
-            match result {
-                Ok(output) if output.status.success() => {
-                    info!("NTP sync successful");
-                }
-                Ok(output) => {
-                    let stderr = String::from_utf8_lossy(&output.stderr);
-                    debug!("ntpdate failed: {}", stderr);
-                    // Try timedatectl as fallback
-                    let _ = tokio::process::Command::new("timedatectl")
-                        .args(["set-ntp", "true"])
-                        .output()
-                        .await;
-                }
-                Err(_) => {
-                    debug!("ntpdate not available");
-                }
-            }
+    /// Change the NTP server and persist it. The new server is used from the next sync.
+    pub fn set_server(&self, host: &str) -> Result<()> {
+        let host = host.trim();
+        validate_server(host)?;
+        *self.server.lock().unwrap() = host.to_string();
+
+        let path = self.state_dir.join(NTP_SERVER_FILE);
+        let saved =
+            std::fs::create_dir_all(&self.state_dir).and_then(|_| std::fs::write(&path, host));
+        if let Err(e) = saved {
+            warn!("Failed to persist NTP server to {}: {}", path.display(), e);
         }
+        info!("NTP server set to {}", host);
+        Ok(())
+    }
 
-        // On Windows, time sync is handled by the OS
-        #[cfg(windows)]
+    /// Go back to the default NTP server and forget the saved one
+    pub fn reset_server(&self) {
+        *self.server.lock().unwrap() = DEFAULT_NTP_SERVER.to_string();
+        let path = self.state_dir.join(NTP_SERVER_FILE);
+        if let Err(e) = std::fs::remove_file(&path)
+            && e.kind() != std::io::ErrorKind::NotFound
         {
-            debug!("NTP sync skipped on Windows (OS handles it)");
+            warn!("Failed to remove {}: {}", path.display(), e);
+        }
+        info!("NTP server reset to {}", DEFAULT_NTP_SERVER);
+    }
+
+    /// Run NTP sync in background (every `interval`)
+    pub async fn run(self) {
+        time::sleep(INITIAL_SYNC_DELAY).await;
+        self.sync_now().await;
+
+        let mut interval = time::interval(self.interval);
+        // The first tick completes immediately; we just synced
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            self.sync_now().await;
+        }
+    }
+
+    /// Sync immediately (e.g. from the SyncNtp SDK command).
+    /// Returns false without syncing if another sync is already in progress.
+    pub async fn sync_now(&self) -> bool {
+        let Ok(_guard) = self.sync_lock.try_lock() else {
+            debug!("NTP sync already in progress, skipping");
+            return false;
+        };
+        let (sync, server) = (self.sync.clone(), self.server());
+        debug!("Attempting NTP time sync against {}", server);
+        if let Err(e) = tokio::task::spawn_blocking(move || sync(&server)).await {
+            warn!("NTP sync task failed: {}", e);
         }
+        true
     }
 
     /// Manually set device time (from SetTimeInfo SDK command)
@@ -71,7 +134,67 @@ impl TimeSyncService {
 
         #[cfg(windows)]
         {
-            warn!("Cannot set system time on Windows without admin privileges");
+            tracing::warn!("Cannot set system time on Windows without admin privileges");
+        }
+    }
+}
+
+/// Sync with ntpdate, falling back to turning on systemd's own NTP client
+fn system_sync(server: &str) {
+    // On Linux, try ntpdate or systemctl
+    #[cfg(unix)]
+    {
+        let result = std::process::Command::new("ntpdate").args(["-u", server]).output();
+
+        match result {
+            Ok(output) if output.status.success() => {
+                info!("NTP sync successful");
+            }
+            Ok(output) => {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                debug!("ntpdate failed: {}", stderr);
+                // Try timedatectl as fallback
+                let _ = std::process::Command::new("timedatectl")
+                    .args(["set-ntp", "true"])
+                    .output();
+            }
+            Err(_) => {
+                debug!("ntpdate not available");
+            }
         }
     }
+
+    // On Windows, time sync is handled by the OS
+    #[cfg(windows)]
+    {
+        let _ = server;
+        debug!("NTP sync skipped on Windows (OS handles it)");
+    }
+}
+
+/// A server host is passed to ntpdate as an argument, so it must be a single
+/// word that can't be mistaken for an option
+fn validate_server(host: &str) -> Result<()> {
+    if host.is_empty() {
+        bail!("NTP server must not be empty");
+    }
+    if host.starts_with('-') || host.chars().any(|c| c.is_whitespace() || c.is_control()) {
+        bail!("Invalid NTP server: {host:?}");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_sync_now_does_not_overlap() {
+        let dir = tempfile::tempdir().unwrap();
+        let service = TimeSyncService::new(Duration::from_secs(60), dir.path().to_path_buf());
+        let other = service.clone();
+
+        let _guard = service.sync_lock.lock().await;
+        assert!(!other.sync_now().await);
+    }
 }
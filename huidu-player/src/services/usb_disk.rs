@@ -6,7 +6,7 @@ use tokio::time::{self, Duration};
 use tracing::{debug, info, warn};
 
 use crate::core::player::PlayerCommand;
-use crate::program::parser;
+use crate::program::{bundle, parser};
 
 pub struct UsbDiskService;
 
@@ -57,24 +57,27 @@ impl UsbDiskService {
 
             for mount in &mount_points {
                 let path = Path::new(mount);
-                if path.exists() {
-                    if let Ok(entries) = std::fs::read_dir(path) {
-                        for entry in entries.flatten() {
-                            let p = entry.path();
-                            // Look for program.xml or *.xml in the root
-                            if p.is_dir() {
-                                let prog_xml = p.join("program.xml");
-                                if prog_xml.exists() {
-                                    results.push(p);
-                                    continue;
-                                }
-                                // Check for any .xml files
-                                if let Ok(files) = std::fs::read_dir(&p) {
-                                    for f in files.flatten() {
-                                        if f.path().extension().is_some_and(|e| e == "xml") {
-                                            results.push(p);
-                                            break;
-                                        }
+                if path.exists()
+                    && let Ok(entries) = std::fs::read_dir(path)
+                {
+                    for entry in entries.flatten() {
+                        let p = entry.path();
+                        // Look for program.xml or *.xml in the root
+                        if p.is_dir() {
+                            let prog_xml = p.join("program.xml");
+                            if prog_xml.exists() {
+                                results.push(p);
+                                continue;
+                            }
+                            // Check for any .xml files or packed bundles
+                            if let Ok(files) = std::fs::read_dir(&p) {
+                                for f in files.flatten() {
+                                    let path = f.path();
+                                    if path.extension().is_some_and(|e| e == "xml")
+                                        || bundle::is_bundle(&path)
+                                    {
+                                        results.push(p);
+                                        break;
                                     }
                                 }
                             }
@@ -108,17 +111,20 @@ impl UsbDiskService {
         program_dir: &Path,
         player_tx: &mpsc::Sender<PlayerCommand>,
     ) -> anyhow::Result<()> {
-        // Find XML files on USB
+        // Find XML files and packed bundles on USB
         let mut xml_files = Vec::new();
+        let mut bundles = Vec::new();
         for entry in std::fs::read_dir(usb_path)? {
             let entry = entry?;
             let path = entry.path();
             if path.extension().is_some_and(|e| e == "xml") {
                 xml_files.push(path);
+            } else if bundle::is_bundle(&path) {
+                bundles.push(path);
             }
         }
 
-        if xml_files.is_empty() {
+        if xml_files.is_empty() && bundles.is_empty() {
             anyhow::bail!("No XML program files found on USB");
         }
 
@@ -129,7 +135,7 @@ impl UsbDiskService {
         for entry in std::fs::read_dir(usb_path)? {
             let entry = entry?;
             let src = entry.path();
-            if src.is_file() {
+            if src.is_file() && !bundles.contains(&src) {
                 let filename = src.file_name().unwrap();
                 let dst = program_dir.join(filename);
                 info!("Copying {} -> {}", src.display(), dst.display());
@@ -137,6 +143,17 @@ impl UsbDiskService {
             }
         }
 
+        // Bundles go straight into the program directory; their XML loads
+        // unless the drive also has loose program XML
+        for path in &bundles {
+            match bundle::unpack(path, program_dir) {
+                Ok(files) => xml_files.extend(
+                    files.into_iter().filter(|f| f.extension().is_some_and(|e| e == "xml")),
+                ),
+                Err(e) => warn!("Failed to unpack {}: {}", path.display(), e),
+            }
+        }
+
         // Parse and load the first XML program
         for xml_file in &xml_files {
             let dst = program_dir.join(xml_file.file_name().unwrap());